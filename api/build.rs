@@ -0,0 +1,11 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Builds do not depend on a protoc installation
+    if std::env::var_os("PROTOC").is_none() {
+        // SAFETY: build scripts are single threaded
+        unsafe { std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?) };
+    }
+    tonic_prost_build::configure()
+        .build_client(false)
+        .compile_protos(&["proto/hecate.proto"], &["proto"])?;
+    Ok(())
+}
@@ -0,0 +1,8 @@
+SELECT ca.ancestor_concept_id, count(*) AS total
+FROM cdm.concept_ancestor ca
+         JOIN cdm.concept c ON c.concept_id = ca.descendant_concept_id
+WHERE ca.ancestor_concept_id = ANY($1::int[])
+  AND ca.min_levels_of_separation > 0
+  AND ($2::int IS NULL OR ca.min_levels_of_separation <= $2)
+  AND ($3::text IS NULL OR c.domain_id = $3)
+GROUP BY ca.ancestor_concept_id
@@ -0,0 +1,5 @@
+SELECT count(*) AS total
+FROM hecate.vocabulary_diff_change
+WHERE diff_id = $1
+  AND change = $2
+  AND ($3::text[] IS NULL OR vocabulary_id = ANY($3::text[]))
@@ -0,0 +1,7 @@
+CREATE SCHEMA IF NOT EXISTS hecate;
+
+CREATE TABLE IF NOT EXISTS hecate.schema_migrations
+(
+    version    TEXT PRIMARY KEY,
+    applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
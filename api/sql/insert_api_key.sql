@@ -0,0 +1,3 @@
+INSERT INTO hecate.api_key (name, key_hash, scopes)
+VALUES ($1, $2, $3)
+RETURNING id, name, scopes, created_at, last_used_at, revoked_at
@@ -0,0 +1,5 @@
+INSERT INTO hecate.concept_set_version (concept_set_id, version, expression, expression_hash, created_by, note,
+                                        created_at)
+SELECT s.id, $2, $3, $4, $5, $6, $7
+FROM hecate.concept_set AS s
+WHERE s.uid = $1
@@ -0,0 +1,11 @@
+INSERT INTO hecate.recommendation_feedback (concept_set_id, concept_id, source_concept_id, source_text,
+                                            similarity_score, verdict, vocabulary_version, created_by, created_at)
+SELECT COALESCE((SELECT s.id FROM hecate.concept_set AS s WHERE s.uid = $1), $2), $3, $4, $5, $6, $7, $8, $9, $10
+WHERE NOT EXISTS (SELECT 1
+                  FROM hecate.recommendation_feedback AS f
+                  WHERE f.concept_id = $3
+                    AND f.verdict = $7
+                    AND f.created_at = $10
+                    AND f.source_concept_id IS NOT DISTINCT FROM $4
+                    AND f.source_text IS NOT DISTINCT FROM $5
+                    AND f.created_by IS NOT DISTINCT FROM $9)
@@ -0,0 +1,4 @@
+INSERT INTO @codeset_table (codeset_id, concept_id)
+SELECT @codeset_id AS codeset_id, c.concept_id
+FROM @vocabulary_database_schema.concept c
+WHERE c.concept_id IN (@concept_ids);
@@ -0,0 +1,10 @@
+WITH concept_set AS (
+    INSERT INTO hecate.concept_set (name, description, tags, created_by)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, name, description, tags, created_by, latest_version, created_at, updated_at),
+     version AS (
+         INSERT INTO hecate.concept_set_version (concept_set_id, version, expression, expression_hash, created_by, note)
+             SELECT id, 1, $5, $6, $4, $7
+             FROM concept_set)
+SELECT id, name, description, tags, created_by, latest_version, created_at, updated_at
+FROM concept_set
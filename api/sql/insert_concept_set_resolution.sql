@@ -0,0 +1,4 @@
+INSERT INTO hecate.concept_set_resolution (concept_set_id, version, vocabulary_version, concept_ids,
+                                           invalid_concept_ids)
+VALUES ($1, $2, $3, $4, $5)
+RETURNING id, concept_set_id, version, vocabulary_version, concept_ids, invalid_concept_ids, resolved_at
@@ -0,0 +1,2 @@
+INSERT INTO hecate.concept_set_version (concept_set_id, version, expression, expression_hash, created_by, note)
+VALUES ($1, $2, $3, $4, $5, $6)
@@ -0,0 +1,7 @@
+INSERT INTO hecate.idempotency_key (key, owner, method, path, request_hash)
+VALUES ($1, $2, $3, $4, $5)
+ON CONFLICT (key, owner, method, path) DO UPDATE
+    SET claimed_at = now()
+    WHERE idempotency_key.status_code IS NULL
+      AND idempotency_key.request_hash = EXCLUDED.request_hash
+      AND idempotency_key.claimed_at < now() - make_interval(secs => $6)
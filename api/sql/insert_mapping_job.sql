@@ -0,0 +1,4 @@
+INSERT INTO hecate.mapping_job (id, source_codes, warnings, created_by)
+VALUES ($1, $2, $3, $4)
+RETURNING id, status, progress, source_codes, mapped_codes, unmatched_codes, failed_codes, warnings, error, created_by,
+    created_at, started_at, finished_at
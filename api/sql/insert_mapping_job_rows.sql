@@ -0,0 +1,4 @@
+INSERT INTO hecate.mapping_job_row (job_id, row_number, source_code, source_name, frequency, candidates, error)
+SELECT $1, row_number, source_code, source_name, frequency, candidates, error
+FROM unnest($2::int[], $3::text[], $4::text[], $5::bigint[], $6::jsonb[], $7::text[])
+         AS row (row_number, source_code, source_name, frequency, candidates, error)
@@ -0,0 +1,3 @@
+INSERT INTO hecate.mapping_review_comment (job_id, row_number, body, created_by)
+VALUES ($1, $2, $3, $4)
+RETURNING id, job_id, row_number, body, created_by, created_at
@@ -0,0 +1,5 @@
+INSERT INTO hecate.recommendation_feedback (concept_set_id, concept_id, source_concept_id, source_text,
+                                            similarity_score, verdict, vocabulary_version, created_by)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+RETURNING id, concept_set_id, concept_id, source_concept_id, source_text, similarity_score, verdict,
+    vocabulary_version, created_by, created_at
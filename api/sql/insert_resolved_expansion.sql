@@ -0,0 +1,3 @@
+INSERT INTO hecate.resolved_expansion (expression_hash, vocabulary_version, expression, expansion, resolved_count)
+VALUES ($1, $2, $3, $4, $5)
+ON CONFLICT (expression_hash, vocabulary_version) DO NOTHING
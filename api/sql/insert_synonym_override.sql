@@ -0,0 +1,3 @@
+INSERT INTO hecate.synonym_override (alias, alias_lower, concept_id, source, created_by, note)
+VALUES ($1, lower($1), $2, 'manual', $3, $4)
+RETURNING id
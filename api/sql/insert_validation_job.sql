@@ -0,0 +1,3 @@
+INSERT INTO hecate.validation_job (id, profile, created_by)
+VALUES ($1, $2, $3)
+RETURNING id, status, stage, progress, profile, result, error, created_by, created_at, started_at, finished_at
@@ -0,0 +1,3 @@
+INSERT INTO hecate.vocabulary_diff (from_schema, from_version, to_schema, to_version)
+VALUES ($1, $2, $3, $4)
+RETURNING id
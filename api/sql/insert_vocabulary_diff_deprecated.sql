@@ -0,0 +1,19 @@
+INSERT INTO hecate.vocabulary_diff_change (diff_id, change, concept_id, concept_name, domain_id, vocabulary_id,
+                                           concept_class_id, standard_concept, concept_code, invalid_reason,
+                                           previous_maps_to, maps_to)
+SELECT $1::bigint AS diff_id,
+       'deprecated' AS change,
+       c.concept_id,
+       c.concept_name,
+       c.domain_id,
+       c.vocabulary_id,
+       c.concept_class_id,
+       c.standard_concept,
+       c.concept_code,
+       c.invalid_reason,
+       NULL::int[] AS previous_maps_to,
+       NULL::int[] AS maps_to
+FROM {to}.concept c
+         JOIN {from}.concept p ON p.concept_id = c.concept_id
+WHERE p.invalid_reason IS NULL
+  AND c.invalid_reason IS NOT NULL
@@ -0,0 +1,30 @@
+INSERT INTO hecate.vocabulary_diff_change (diff_id, change, concept_id, concept_name, domain_id, vocabulary_id,
+                                           concept_class_id, standard_concept, concept_code, invalid_reason,
+                                           previous_maps_to, maps_to)
+WITH before AS (SELECT concept_id_1 AS concept_id, array_agg(concept_id_2 ORDER BY concept_id_2) AS maps_to
+                FROM {from}.concept_relationship
+                WHERE relationship_id = 'Maps to'
+                  AND invalid_reason IS NULL
+                GROUP BY concept_id_1),
+     after AS (SELECT concept_id_1 AS concept_id, array_agg(concept_id_2 ORDER BY concept_id_2) AS maps_to
+               FROM {to}.concept_relationship
+               WHERE relationship_id = 'Maps to'
+                 AND invalid_reason IS NULL
+               GROUP BY concept_id_1)
+SELECT $1::bigint AS diff_id,
+       'remapped'   AS change,
+       c.concept_id,
+       c.concept_name,
+       c.domain_id,
+       c.vocabulary_id,
+       c.concept_class_id,
+       c.standard_concept,
+       c.concept_code,
+       c.invalid_reason,
+       b.maps_to AS previous_maps_to,
+       a.maps_to AS maps_to
+FROM before b
+         FULL JOIN after a ON a.concept_id = b.concept_id
+         JOIN {to}.concept c ON c.concept_id = COALESCE(a.concept_id, b.concept_id)
+WHERE a.maps_to IS DISTINCT FROM b.maps_to
+  AND EXISTS (SELECT 1 FROM {from}.concept p WHERE p.concept_id = c.concept_id)
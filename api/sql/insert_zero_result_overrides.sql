@@ -0,0 +1,6 @@
+INSERT INTO hecate.synonym_override (alias, alias_lower, concept_id, zero_result_query_id, source, created_by)
+SELECT q.query, q.query_lower, c.concept_id, q.id, 'zero_result', $3
+FROM hecate.zero_result_query AS q
+         CROSS JOIN unnest($2::int[]) AS c(concept_id)
+WHERE q.id = $1
+ON CONFLICT (alias_lower, concept_id) DO NOTHING
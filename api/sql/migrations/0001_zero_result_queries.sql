@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS hecate.zero_result_query
+(
+    id          BIGSERIAL PRIMARY KEY,
+    query_lower TEXT        NOT NULL UNIQUE,
+    query       TEXT        NOT NULL,
+    filters     JSONB,
+    occurrences INTEGER     NOT NULL DEFAULT 1,
+    status      TEXT        NOT NULL DEFAULT 'open',
+    first_seen  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_seen   TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS hecate.synonym_override
+(
+    id                   BIGSERIAL PRIMARY KEY,
+    alias_lower          TEXT        NOT NULL,
+    concept_id           INTEGER     NOT NULL,
+    zero_result_query_id BIGINT REFERENCES hecate.zero_result_query (id) ON DELETE SET NULL,
+    created_at           TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (alias_lower, concept_id)
+);
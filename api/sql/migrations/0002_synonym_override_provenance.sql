@@ -0,0 +1,13 @@
+ALTER TABLE hecate.synonym_override
+    ADD COLUMN IF NOT EXISTS alias      TEXT,
+    ADD COLUMN IF NOT EXISTS source     TEXT NOT NULL DEFAULT 'zero_result',
+    ADD COLUMN IF NOT EXISTS created_by TEXT,
+    ADD COLUMN IF NOT EXISTS note       TEXT,
+    ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+UPDATE hecate.synonym_override
+SET alias = alias_lower
+WHERE alias IS NULL;
+
+ALTER TABLE hecate.synonym_override
+    ALTER COLUMN alias SET NOT NULL;
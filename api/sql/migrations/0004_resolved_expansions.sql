@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS hecate.resolved_expansion
+(
+    expression_hash    TEXT        NOT NULL,
+    vocabulary_version TEXT        NOT NULL,
+    expression         JSONB       NOT NULL,
+    expansion          JSONB       NOT NULL,
+    resolved_count     INTEGER     NOT NULL,
+    hits               INTEGER     NOT NULL DEFAULT 0,
+    created_at         TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_used_at       TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (expression_hash, vocabulary_version)
+);
@@ -0,0 +1,26 @@
+CREATE TABLE IF NOT EXISTS hecate.concept_set
+(
+    id             BIGSERIAL PRIMARY KEY,
+    name           TEXT        NOT NULL,
+    description    TEXT,
+    tags           TEXT[]      NOT NULL DEFAULT '{}',
+    created_by     TEXT,
+    latest_version INTEGER     NOT NULL DEFAULT 1,
+    created_at     TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at     TIMESTAMPTZ NOT NULL DEFAULT now(),
+    deleted_at     TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS concept_set_tags_idx ON hecate.concept_set USING GIN (tags);
+
+CREATE TABLE IF NOT EXISTS hecate.concept_set_version
+(
+    concept_set_id  BIGINT      NOT NULL REFERENCES hecate.concept_set (id),
+    version         INTEGER     NOT NULL,
+    expression      JSONB       NOT NULL,
+    expression_hash TEXT        NOT NULL,
+    created_by      TEXT,
+    note            TEXT,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (concept_set_id, version)
+);
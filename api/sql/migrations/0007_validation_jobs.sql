@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS hecate.validation_job
+(
+    id          UUID PRIMARY KEY,
+    status      TEXT             NOT NULL DEFAULT 'queued',
+    stage       TEXT,
+    progress    DOUBLE PRECISION NOT NULL DEFAULT 0,
+    profile     TEXT             NOT NULL,
+    result      JSONB,
+    error       TEXT,
+    created_by  TEXT,
+    created_at  TIMESTAMPTZ      NOT NULL DEFAULT now(),
+    started_at  TIMESTAMPTZ,
+    finished_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS validation_job_created_at_idx ON hecate.validation_job (created_at);
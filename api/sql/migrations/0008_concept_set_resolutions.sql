@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS hecate.concept_set_resolution
+(
+    id                  BIGSERIAL PRIMARY KEY,
+    concept_set_id      BIGINT      NOT NULL REFERENCES hecate.concept_set (id),
+    version             INTEGER     NOT NULL,
+    vocabulary_version  TEXT,
+    concept_ids         INTEGER[]   NOT NULL,
+    invalid_concept_ids INTEGER[]   NOT NULL,
+    resolved_at         TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS concept_set_resolution_concept_set_idx
+    ON hecate.concept_set_resolution (concept_set_id, resolved_at);
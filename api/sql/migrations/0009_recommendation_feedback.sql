@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS hecate.recommendation_feedback
+(
+    id                 BIGSERIAL PRIMARY KEY,
+    concept_set_id     BIGINT,
+    concept_id         INTEGER     NOT NULL,
+    source_concept_id  INTEGER,
+    source_text        TEXT,
+    similarity_score   REAL,
+    verdict            TEXT        NOT NULL CHECK (verdict IN ('accepted', 'rejected')),
+    vocabulary_version TEXT,
+    created_by         TEXT,
+    created_at         TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS recommendation_feedback_concept_set_idx
+    ON hecate.recommendation_feedback (concept_set_id, created_at);
+CREATE INDEX IF NOT EXISTS recommendation_feedback_concept_idx
+    ON hecate.recommendation_feedback (concept_id);
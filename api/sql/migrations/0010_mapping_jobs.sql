@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS hecate.mapping_job
+(
+    id           UUID PRIMARY KEY,
+    status       TEXT             NOT NULL DEFAULT 'queued',
+    progress     DOUBLE PRECISION NOT NULL DEFAULT 0,
+    source_codes INTEGER          NOT NULL,
+    mapped_codes INTEGER          NOT NULL DEFAULT 0,
+    result       JSONB,
+    error        TEXT,
+    created_by   TEXT,
+    created_at   TIMESTAMPTZ      NOT NULL DEFAULT now(),
+    started_at   TIMESTAMPTZ,
+    finished_at  TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS mapping_job_created_at_idx ON hecate.mapping_job (created_at);
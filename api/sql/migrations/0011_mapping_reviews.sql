@@ -0,0 +1,25 @@
+CREATE TABLE IF NOT EXISTS hecate.mapping_review
+(
+    job_id      UUID        NOT NULL REFERENCES hecate.mapping_job (id) ON DELETE CASCADE,
+    row_number  INTEGER     NOT NULL,
+    status      TEXT        NOT NULL DEFAULT 'unreviewed'
+        CHECK (status IN ('unreviewed', 'approved', 'flagged')),
+    concept_id  INTEGER,
+    assigned_to TEXT,
+    updated_by  TEXT,
+    updated_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (job_id, row_number)
+);
+
+CREATE TABLE IF NOT EXISTS hecate.mapping_review_comment
+(
+    id         BIGSERIAL PRIMARY KEY,
+    job_id     UUID        NOT NULL REFERENCES hecate.mapping_job (id) ON DELETE CASCADE,
+    row_number INTEGER     NOT NULL,
+    body       TEXT        NOT NULL,
+    created_by TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS mapping_review_comment_row_idx
+    ON hecate.mapping_review_comment (job_id, row_number, created_at);
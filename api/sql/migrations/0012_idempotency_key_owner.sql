@@ -0,0 +1,8 @@
+ALTER TABLE hecate.idempotency_key
+    ADD COLUMN IF NOT EXISTS owner TEXT NOT NULL DEFAULT '';
+
+ALTER TABLE hecate.idempotency_key
+    DROP CONSTRAINT IF EXISTS idempotency_key_pkey;
+
+ALTER TABLE hecate.idempotency_key
+    ADD PRIMARY KEY (key, owner, method, path);
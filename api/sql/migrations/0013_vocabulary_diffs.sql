@@ -0,0 +1,30 @@
+CREATE TABLE IF NOT EXISTS hecate.vocabulary_diff
+(
+    id           BIGSERIAL PRIMARY KEY,
+    from_schema  TEXT        NOT NULL,
+    from_version TEXT        NOT NULL,
+    to_schema    TEXT        NOT NULL,
+    to_version   TEXT        NOT NULL,
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (from_schema, from_version, to_schema, to_version)
+);
+
+CREATE TABLE IF NOT EXISTS hecate.vocabulary_diff_change
+(
+    diff_id          BIGINT  NOT NULL REFERENCES hecate.vocabulary_diff (id) ON DELETE CASCADE,
+    change           TEXT    NOT NULL CHECK (change IN ('new', 'deprecated', 'remapped')),
+    concept_id       INTEGER NOT NULL,
+    concept_name     TEXT    NOT NULL,
+    domain_id        TEXT    NOT NULL,
+    vocabulary_id    TEXT    NOT NULL,
+    concept_class_id TEXT    NOT NULL,
+    standard_concept TEXT,
+    concept_code     TEXT    NOT NULL,
+    invalid_reason   TEXT,
+    previous_maps_to INTEGER[],
+    maps_to          INTEGER[],
+    PRIMARY KEY (diff_id, change, concept_id)
+);
+
+CREATE INDEX IF NOT EXISTS vocabulary_diff_change_vocabulary_idx
+    ON hecate.vocabulary_diff_change (diff_id, change, vocabulary_id, concept_id);
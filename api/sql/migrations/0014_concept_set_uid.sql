@@ -0,0 +1,4 @@
+ALTER TABLE hecate.concept_set
+    ADD COLUMN IF NOT EXISTS uid UUID NOT NULL DEFAULT gen_random_uuid();
+
+CREATE UNIQUE INDEX IF NOT EXISTS concept_set_uid_idx ON hecate.concept_set (uid);
@@ -0,0 +1,37 @@
+CREATE TABLE IF NOT EXISTS hecate.mapping_job_row
+(
+    job_id      UUID    NOT NULL REFERENCES hecate.mapping_job (id) ON DELETE CASCADE,
+    row_number  INTEGER NOT NULL,
+    source_code TEXT    NOT NULL,
+    source_name TEXT    NOT NULL,
+    frequency   BIGINT,
+    candidates  JSONB   NOT NULL DEFAULT '[]',
+    error       TEXT,
+    PRIMARY KEY (job_id, row_number)
+);
+
+ALTER TABLE hecate.mapping_job
+    ADD COLUMN IF NOT EXISTS unmatched_codes INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS failed_codes    INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS warnings        TEXT[]  NOT NULL DEFAULT '{}';
+
+-- Results of earlier jobs were stored as a single JSONB value on the job
+INSERT INTO hecate.mapping_job_row (job_id, row_number, source_code, source_name, frequency, candidates)
+SELECT job.id,
+       (code ->> 'row_number')::int,
+       COALESCE(code ->> 'source_code', ''),
+       code ->> 'source_name',
+       (code ->> 'frequency')::bigint,
+       COALESCE(code -> 'candidates', '[]')
+FROM hecate.mapping_job AS job,
+     jsonb_array_elements(job.result -> 'codes') AS code
+WHERE job.result IS NOT NULL
+ON CONFLICT DO NOTHING;
+
+UPDATE hecate.mapping_job
+SET unmatched_codes = COALESCE((result ->> 'unmatched')::int, 0),
+    warnings        = ARRAY(SELECT jsonb_array_elements_text(result -> 'warnings'))
+WHERE result IS NOT NULL;
+
+ALTER TABLE hecate.mapping_job
+    DROP COLUMN IF EXISTS result;
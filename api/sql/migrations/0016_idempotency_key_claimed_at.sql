@@ -0,0 +1,4 @@
+-- When the request holding the key started, so a claim whose request never finished can be
+-- taken over by a retry
+ALTER TABLE hecate.idempotency_key
+    ADD COLUMN IF NOT EXISTS claimed_at TIMESTAMPTZ NOT NULL DEFAULT now();
@@ -0,0 +1,15 @@
+SELECT c.concept_id,
+       c.concept_name,
+       c.domain_id,
+       c.vocabulary_id,
+       c.concept_class_id,
+       c.standard_concept,
+       c.concept_code,
+       c.invalid_reason,
+       ca.min_levels_of_separation,
+       ca.max_levels_of_separation
+FROM cdm.concept_ancestor ca
+         JOIN cdm.concept c ON c.concept_id = ca.ancestor_concept_id
+WHERE ca.descendant_concept_id = $1
+  AND ca.min_levels_of_separation > 0
+ORDER BY ca.min_levels_of_separation, c.concept_name
@@ -0,0 +1,8 @@
+SELECT id,
+       name,
+       scopes,
+       created_at,
+       last_used_at,
+       revoked_at
+FROM hecate.api_key
+ORDER BY revoked_at NULLS FIRST, name, id
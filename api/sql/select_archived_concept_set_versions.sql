@@ -0,0 +1,10 @@
+SELECT s.uid AS concept_set_uid,
+       v.version,
+       v.expression,
+       v.expression_hash,
+       v.created_by,
+       v.note,
+       v.created_at
+FROM hecate.concept_set_version AS v
+         JOIN hecate.concept_set AS s ON s.id = v.concept_set_id
+ORDER BY s.id, v.version
@@ -0,0 +1,11 @@
+SELECT s.uid,
+       s.name,
+       s.description,
+       s.tags,
+       s.created_by,
+       s.latest_version,
+       s.created_at,
+       s.updated_at,
+       s.deleted_at
+FROM hecate.concept_set AS s
+ORDER BY s.id
@@ -0,0 +1,13 @@
+SELECT s.uid                                             AS concept_set_uid,
+       CASE WHEN s.id IS NULL THEN f.concept_set_id END AS concept_set_id,
+       f.concept_id,
+       f.source_concept_id,
+       f.source_text,
+       f.similarity_score,
+       f.verdict,
+       f.vocabulary_version,
+       f.created_by,
+       f.created_at
+FROM hecate.recommendation_feedback AS f
+         LEFT JOIN hecate.concept_set AS s ON s.id = f.concept_set_id
+ORDER BY f.id
@@ -0,0 +1,11 @@
+SELECT o.alias,
+       o.concept_id,
+       o.source,
+       o.created_by,
+       o.note,
+       q.query_lower AS zero_result_query,
+       o.created_at,
+       o.updated_at
+FROM hecate.synonym_override AS o
+         LEFT JOIN hecate.zero_result_query AS q ON q.id = o.zero_result_query_id
+ORDER BY o.alias_lower, o.concept_id
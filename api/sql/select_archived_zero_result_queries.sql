@@ -0,0 +1,8 @@
+SELECT q.query,
+       q.filters,
+       q.occurrences,
+       q.status,
+       q.first_seen,
+       q.last_seen
+FROM hecate.zero_result_query AS q
+ORDER BY q.query_lower
@@ -0,0 +1,4 @@
+SELECT ancestor_concept_id, descendant_concept_id as concept_id
+FROM cdm.concept_ancestor
+WHERE ancestor_concept_id = ANY($1::int[])
+  AND min_levels_of_separation > 0
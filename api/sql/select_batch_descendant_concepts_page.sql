@@ -0,0 +1,35 @@
+SELECT page.ancestor_concept_id,
+       page.concept_id,
+       page.concept_name,
+       page.domain_id,
+       page.vocabulary_id,
+       page.concept_class_id,
+       page.standard_concept,
+       page.concept_code,
+       page.invalid_reason,
+       page.min_levels_of_separation,
+       page.max_levels_of_separation
+FROM (SELECT ca.ancestor_concept_id,
+             c.concept_id,
+             c.concept_name,
+             c.domain_id,
+             c.vocabulary_id,
+             c.concept_class_id,
+             c.standard_concept,
+             c.concept_code,
+             c.invalid_reason,
+             ca.min_levels_of_separation,
+             ca.max_levels_of_separation,
+             row_number() OVER (
+                 PARTITION BY ca.ancestor_concept_id
+                 ORDER BY ca.min_levels_of_separation, c.concept_name, c.concept_id
+                 ) AS position
+      FROM cdm.concept_ancestor ca
+               JOIN cdm.concept c ON c.concept_id = ca.descendant_concept_id
+      WHERE ca.ancestor_concept_id = ANY($1::int[])
+        AND ca.min_levels_of_separation > 0
+        AND ($2::int IS NULL OR ca.min_levels_of_separation <= $2)
+        AND ($3::text IS NULL OR c.domain_id = $3)) AS page
+WHERE page.position > $4
+  AND page.position <= $4 + $5
+ORDER BY page.ancestor_concept_id, page.position
@@ -0,0 +1,5 @@
+SELECT cr.concept_id_2 as source_concept_id, cr.concept_id_1 as mapped_concept_id
+FROM cdm.concept_relationship cr
+WHERE cr.concept_id_2 = ANY($1::int[])
+  AND cr.relationship_id = 'Maps to'
+  AND cr.invalid_reason IS NULL
@@ -0,0 +1,16 @@
+SELECT p.concept_id_1 AS source_concept_id,
+       c.concept_id,
+       c.concept_name,
+       c.domain_id,
+       c.vocabulary_id,
+       c.concept_class_id,
+       c.standard_concept,
+       c.concept_code,
+       c.invalid_reason,
+       c.valid_start_date,
+       c.valid_end_date
+FROM cdm.phoebe AS p
+         JOIN cdm.concept AS c ON p.concept_id_2 = c.concept_id
+WHERE p.concept_id_1 = ANY($1::int[])
+  AND c.invalid_reason IS NULL
+ORDER BY p.concept_id_1, c.concept_name
@@ -0,0 +1,4 @@
+SELECT concept_class_id,
+       concept_class_name
+FROM cdm.concept_class
+ORDER BY concept_class_id
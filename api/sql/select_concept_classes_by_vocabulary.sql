@@ -0,0 +1,19 @@
+WITH classes AS (SELECT concept_class_id,
+                        count(*)                                       AS concept_count,
+                        count(*) FILTER (WHERE standard_concept = 'S') AS standard_count
+                 FROM cdm.concept
+                 WHERE vocabulary_id = $1
+                 GROUP BY concept_class_id)
+SELECT cl.concept_class_id,
+       cl.concept_count,
+       cl.standard_count,
+       (SELECT jsonb_agg(jsonb_build_object('concept_id', s.concept_id, 'concept_name', s.concept_name))
+        FROM (SELECT c.concept_id, c.concept_name
+              FROM cdm.concept c
+              WHERE c.vocabulary_id = $1
+                AND c.concept_class_id = cl.concept_class_id
+                AND c.invalid_reason IS NULL
+              ORDER BY c.standard_concept NULLS LAST, c.concept_id
+              LIMIT $2) s) AS sample_concepts
+FROM classes cl
+ORDER BY cl.concept_count DESC
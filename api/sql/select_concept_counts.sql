@@ -0,0 +1,6 @@
+SELECT vocabulary_id,
+       domain_id,
+       concept_class_id,
+       count(*) AS concept_count
+FROM cdm.concept
+GROUP BY vocabulary_id, domain_id, concept_class_id
@@ -0,0 +1,12 @@
+SELECT 'descendant' AS link, ancestor_concept_id AS concept_id
+FROM cdm.concept_ancestor
+WHERE descendant_concept_id = $1
+  AND ancestor_concept_id = ANY($2::int[])
+  AND min_levels_of_separation > 0
+UNION ALL
+SELECT 'mapped' AS link, concept_id_2 AS concept_id
+FROM cdm.concept_relationship
+WHERE concept_id_1 = $1
+  AND concept_id_2 = ANY($2::int[])
+  AND relationship_id = 'Maps to'
+  AND invalid_reason IS NULL
@@ -0,0 +1,9 @@
+SELECT c.concept_name,
+       max(ts_rank_cd(to_tsvector('simple', c.concept_name), q, 32)) AS rank
+FROM cdm.concept c,
+     websearch_to_tsquery('simple', $1) q
+WHERE to_tsvector('simple', c.concept_name) @@ q
+  AND c.invalid_reason IS NULL
+GROUP BY c.concept_name
+ORDER BY rank DESC
+LIMIT $2
@@ -0,0 +1,11 @@
+SELECT id,
+       name,
+       description,
+       tags,
+       created_by,
+       latest_version,
+       created_at,
+       updated_at
+FROM hecate.concept_set
+WHERE id = $1
+  AND deleted_at IS NULL
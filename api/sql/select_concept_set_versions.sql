@@ -0,0 +1,13 @@
+SELECT v.concept_set_id,
+       v.version,
+       v.expression,
+       v.expression_hash,
+       v.created_by,
+       v.note,
+       v.created_at
+FROM hecate.concept_set_version AS v
+         JOIN hecate.concept_set AS s ON s.id = v.concept_set_id
+WHERE v.concept_set_id = $1
+  AND s.deleted_at IS NULL
+  AND ($2::int IS NULL OR v.version = $2)
+ORDER BY v.version DESC
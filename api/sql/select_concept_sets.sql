@@ -0,0 +1,14 @@
+SELECT id,
+       name,
+       description,
+       tags,
+       created_by,
+       latest_version,
+       created_at,
+       updated_at
+FROM hecate.concept_set
+WHERE deleted_at IS NULL
+  AND ($1::text IS NULL OR $1 = ANY (tags))
+  AND ($2::text IS NULL OR name ILIKE '%' || $2 || '%')
+ORDER BY updated_at DESC, id DESC
+LIMIT $3
@@ -0,0 +1,8 @@
+SELECT cs.concept_synonym_name,
+       cs.language_concept_id,
+       l.concept_name AS language_name
+FROM cdm.concept_synonym cs
+         LEFT JOIN cdm.concept l ON l.concept_id = cs.language_concept_id
+WHERE cs.concept_id = $1
+  AND ($2::int[] IS NULL OR cs.language_concept_id = ANY($2::int[]))
+ORDER BY cs.language_concept_id, cs.concept_synonym_name
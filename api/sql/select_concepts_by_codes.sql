@@ -0,0 +1,14 @@
+SELECT c.concept_id,
+       c.concept_name,
+       c.domain_id,
+       c.vocabulary_id,
+       c.concept_class_id,
+       c.standard_concept,
+       c.concept_code,
+       c.invalid_reason,
+       c.valid_start_date,
+       c.valid_end_date
+FROM cdm.concept c
+         JOIN unnest($1::text[], $2::text[]) AS k(vocabulary_id, concept_code)
+              ON c.concept_code = k.concept_code AND lower(c.vocabulary_id) = lower(k.vocabulary_id)
+ORDER BY c.vocabulary_id, c.concept_code, c.concept_id
@@ -0,0 +1,10 @@
+SELECT DISTINCT ca.ancestor_concept_id AS concept_id
+FROM cdm.concept_ancestor AS ca
+WHERE ca.descendant_concept_id = ANY($1)
+  AND ca.min_levels_of_separation > 0
+  AND ca.ancestor_concept_id = ANY($2)
+  AND NOT EXISTS (SELECT 1
+                  FROM cdm.concept_ancestor AS d
+                  WHERE d.ancestor_concept_id = ca.ancestor_concept_id
+                    AND d.descendant_concept_id <> ALL ($2))
+ORDER BY ca.ancestor_concept_id
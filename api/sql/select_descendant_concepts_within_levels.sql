@@ -0,0 +1,5 @@
+SELECT ancestor_concept_id, descendant_concept_id as concept_id
+FROM cdm.concept_ancestor
+WHERE ancestor_concept_id = ANY($1)
+  AND min_levels_of_separation > 0
+  AND min_levels_of_separation <= $2
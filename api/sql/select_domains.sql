@@ -0,0 +1,4 @@
+SELECT domain_id,
+       domain_name
+FROM cdm.domain
+ORDER BY domain_id
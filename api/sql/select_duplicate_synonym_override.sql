@@ -0,0 +1,6 @@
+SELECT other.id
+FROM hecate.synonym_override other
+LEFT JOIN hecate.synonym_override current ON current.id = $1
+WHERE other.alias_lower = lower(COALESCE($2, current.alias))
+  AND other.concept_id = COALESCE($3, current.concept_id)
+  AND other.id IS DISTINCT FROM $1
@@ -0,0 +1,6 @@
+SELECT ancestor_concept_id AS parent_concept_id, descendant_concept_id AS child_concept_id
+FROM cdm.concept_ancestor
+WHERE ancestor_concept_id = ANY($1::int[])
+  AND descendant_concept_id = ANY($1::int[])
+  AND min_levels_of_separation = 1
+ORDER BY ancestor_concept_id, descendant_concept_id
@@ -0,0 +1,11 @@
+SELECT descendant_concept_id AS concept_id
+FROM cdm.concept_ancestor
+WHERE ancestor_concept_id = ANY($1::int[])
+  AND descendant_concept_id = ANY($2::int[])
+  AND min_levels_of_separation > 0
+UNION
+SELECT ancestor_concept_id AS concept_id
+FROM cdm.concept_ancestor
+WHERE descendant_concept_id = ANY($1::int[])
+  AND ancestor_concept_id = ANY($2::int[])
+  AND min_levels_of_separation > 0
@@ -0,0 +1,9 @@
+SELECT request_hash,
+       status_code,
+       content_type,
+       response_body
+FROM hecate.idempotency_key
+WHERE key = $1
+  AND owner = $2
+  AND method = $3
+  AND path = $4
@@ -0,0 +1,17 @@
+SELECT min(n.name)                          AS concept_name,
+       lower(n.name)                        AS concept_name_lower,
+       array_agg(DISTINCT n.concept_id)     AS concept_ids
+FROM (SELECT c.concept_id, c.concept_name AS name
+      FROM cdm.concept c
+      WHERE c.invalid_reason IS NULL
+        AND (cardinality($1::text[]) = 0 OR c.vocabulary_id = ANY ($1))
+      UNION
+      SELECT s.concept_id, s.concept_synonym_name AS name
+      FROM cdm.concept_synonym s
+               JOIN cdm.concept c ON c.concept_id = s.concept_id
+      WHERE $2
+        AND c.invalid_reason IS NULL
+        AND (cardinality($1::text[]) = 0 OR c.vocabulary_id = ANY ($1))) n
+WHERE trim(n.name) <> ''
+GROUP BY lower(n.name)
+ORDER BY lower(n.name)
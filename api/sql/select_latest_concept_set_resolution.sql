@@ -0,0 +1,11 @@
+SELECT id,
+       concept_set_id,
+       version,
+       vocabulary_version,
+       concept_ids,
+       invalid_concept_ids,
+       resolved_at
+FROM hecate.concept_set_resolution
+WHERE concept_set_id = $1
+ORDER BY resolved_at DESC, id DESC
+LIMIT 1
@@ -0,0 +1,14 @@
+SELECT cr.concept_id_1 AS source_concept_id,
+       cr.relationship_id,
+       c.concept_id,
+       c.concept_name,
+       c.vocabulary_id,
+       c.domain_id,
+       c.standard_concept
+FROM cdm.concept_relationship AS cr
+         JOIN cdm.concept AS c ON c.concept_id = cr.concept_id_2
+WHERE cr.concept_id_1 = ANY($1)
+  AND cr.relationship_id = ANY($2)
+  AND cr.invalid_reason IS NULL
+  AND c.invalid_reason IS NULL
+ORDER BY cr.concept_id_1, cr.relationship_id, c.concept_name
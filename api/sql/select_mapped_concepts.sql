@@ -0,0 +1,16 @@
+SELECT c.concept_id,
+       c.concept_name,
+       c.domain_id,
+       c.vocabulary_id,
+       c.concept_class_id,
+       c.standard_concept,
+       c.concept_code,
+       c.invalid_reason,
+       c.valid_start_date,
+       c.valid_end_date
+FROM cdm.concept_relationship cr
+         JOIN cdm.concept c ON c.concept_id = cr.concept_id_2
+WHERE cr.concept_id_1 = $1
+  AND cr.relationship_id = $2
+  AND cr.invalid_reason IS NULL
+ORDER BY c.vocabulary_id, c.concept_name, c.concept_id
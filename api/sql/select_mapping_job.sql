@@ -0,0 +1,15 @@
+SELECT id,
+       status,
+       progress,
+       source_codes,
+       mapped_codes,
+       unmatched_codes,
+       failed_codes,
+       warnings,
+       error,
+       created_by,
+       created_at,
+       started_at,
+       finished_at
+FROM hecate.mapping_job
+WHERE id = $1
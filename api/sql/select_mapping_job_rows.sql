@@ -0,0 +1,12 @@
+SELECT r.row_number,
+       r.source_code,
+       r.source_name,
+       r.frequency,
+       r.candidates,
+       r.error
+FROM hecate.mapping_job_row AS r
+         LEFT JOIN hecate.mapping_review AS v ON v.job_id = r.job_id AND v.row_number = r.row_number
+WHERE r.job_id = $1
+  AND ($2::text IS NULL OR COALESCE(v.status, 'unreviewed') = $2)
+  AND ($3::text IS NULL OR v.assigned_to = $3)
+ORDER BY r.row_number
@@ -0,0 +1,10 @@
+SELECT id,
+       job_id,
+       row_number,
+       body,
+       created_by,
+       created_at
+FROM hecate.mapping_review_comment
+WHERE job_id = $1
+  AND row_number = $2
+ORDER BY created_at, id
@@ -0,0 +1,10 @@
+SELECT job_id,
+       row_number,
+       status,
+       concept_id,
+       assigned_to,
+       updated_by,
+       updated_at
+FROM hecate.mapping_review
+WHERE job_id = $1
+ORDER BY row_number
@@ -0,0 +1,11 @@
+SELECT e.expression_hash,
+       e.vocabulary_version,
+       e.expression,
+       e.expansion,
+       e.resolved_count,
+       e.hits,
+       e.created_at,
+       e.last_used_at
+FROM hecate.resolved_expansion AS e
+WHERE e.expression_hash = $1
+  AND e.vocabulary_version = $2
@@ -0,0 +1,16 @@
+SELECT o.id,
+       o.alias,
+       o.alias_lower,
+       o.concept_id,
+       c.concept_name,
+       o.source,
+       o.created_by,
+       o.note,
+       o.zero_result_query_id,
+       o.created_at,
+       o.updated_at
+FROM hecate.synonym_override AS o
+         LEFT JOIN cdm.concept AS c ON c.concept_id = o.concept_id
+WHERE ($1::text IS NULL OR o.alias_lower LIKE '%' || lower($1) || '%')
+  AND ($2::int IS NULL OR o.concept_id = $2)
+ORDER BY o.alias_lower, o.concept_id
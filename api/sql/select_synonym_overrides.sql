@@ -0,0 +1,2 @@
+SELECT alias_lower, concept_id
+FROM hecate.synonym_override
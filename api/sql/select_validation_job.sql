@@ -0,0 +1,13 @@
+SELECT id,
+       status,
+       stage,
+       progress,
+       profile,
+       result,
+       error,
+       created_by,
+       created_at,
+       started_at,
+       finished_at
+FROM hecate.validation_job
+WHERE id = $1
@@ -0,0 +1,5 @@
+SELECT vocabulary_id,
+       vocabulary_name,
+       vocabulary_version
+FROM cdm.vocabulary
+ORDER BY vocabulary_id
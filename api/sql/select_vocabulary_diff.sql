@@ -0,0 +1,6 @@
+SELECT id
+FROM hecate.vocabulary_diff
+WHERE from_schema = $1
+  AND from_version = $2
+  AND to_schema = $3
+  AND to_version = $4
@@ -0,0 +1,16 @@
+SELECT concept_id,
+       concept_name,
+       domain_id,
+       vocabulary_id,
+       concept_class_id,
+       standard_concept,
+       concept_code,
+       invalid_reason,
+       previous_maps_to,
+       maps_to
+FROM hecate.vocabulary_diff_change
+WHERE diff_id = $1
+  AND change = $2
+  AND ($3::text[] IS NULL OR vocabulary_id = ANY($3::text[]))
+ORDER BY concept_id
+OFFSET $4 LIMIT $5
@@ -0,0 +1,25 @@
+SELECT c.concept_id,
+       c.concept_name,
+       c.domain_id,
+       c.concept_class_id,
+       c.standard_concept,
+       (SELECT count(*)
+        FROM cdm.concept_ancestor d
+        WHERE d.ancestor_concept_id = c.concept_id
+          AND d.min_levels_of_separation > 0) AS descendant_count
+FROM cdm.concept c
+WHERE c.vocabulary_id = $1
+  AND c.standard_concept IN ('S', 'C')
+  AND c.invalid_reason IS NULL
+  AND EXISTS (SELECT 1
+              FROM cdm.concept_ancestor ca
+              WHERE ca.ancestor_concept_id = c.concept_id
+                AND ca.min_levels_of_separation > 0)
+  AND NOT EXISTS (SELECT 1
+                  FROM cdm.concept_ancestor ca
+                           JOIN cdm.concept p ON p.concept_id = ca.ancestor_concept_id
+                  WHERE ca.descendant_concept_id = c.concept_id
+                    AND ca.min_levels_of_separation > 0
+                    AND p.vocabulary_id = c.vocabulary_id)
+ORDER BY descendant_count DESC, c.concept_name
+LIMIT $2
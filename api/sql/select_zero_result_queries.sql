@@ -0,0 +1,16 @@
+SELECT q.id,
+       q.query,
+       q.query_lower,
+       q.filters,
+       q.occurrences,
+       q.status,
+       q.first_seen,
+       q.last_seen,
+       COALESCE(array_agg(o.concept_id) FILTER (WHERE o.concept_id IS NOT NULL), '{}') AS override_concept_ids
+FROM hecate.zero_result_query AS q
+         LEFT JOIN hecate.synonym_override AS o ON o.alias_lower = q.query_lower
+WHERE $1::text IS NULL
+   OR q.status = $1
+GROUP BY q.id
+ORDER BY q.occurrences DESC, q.last_seen DESC
+LIMIT $2
@@ -0,0 +1,5 @@
+UPDATE hecate.api_key
+SET last_used_at = now()
+WHERE key_hash = $1
+  AND revoked_at IS NULL
+RETURNING id, name, scopes, created_at, last_used_at, revoked_at
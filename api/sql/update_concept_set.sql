@@ -0,0 +1,8 @@
+UPDATE hecate.concept_set
+SET name           = COALESCE($2, name),
+    description    = COALESCE($3, description),
+    tags           = COALESCE($4, tags),
+    latest_version = $5,
+    updated_at     = now()
+WHERE id = $1
+RETURNING id, name, description, tags, created_by, latest_version, created_at, updated_at
@@ -0,0 +1,8 @@
+UPDATE hecate.idempotency_key
+SET status_code   = $5,
+    content_type  = $6,
+    response_body = $7
+WHERE key = $1
+  AND owner = $2
+  AND method = $3
+  AND path = $4
@@ -0,0 +1,8 @@
+UPDATE hecate.mapping_job
+SET status          = $2,
+    progress        = CASE WHEN $2 = 'succeeded' THEN 1 ELSE progress END,
+    unmatched_codes = $3,
+    failed_codes    = $4,
+    error           = $5,
+    finished_at     = now()
+WHERE id = $1
@@ -0,0 +1,7 @@
+UPDATE hecate.mapping_job
+SET status       = 'running',
+    progress     = $2,
+    mapped_codes = $3,
+    started_at   = COALESCE(started_at, now())
+WHERE id = $1
+  AND status IN ('queued', 'running')
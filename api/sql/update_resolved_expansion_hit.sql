@@ -0,0 +1,6 @@
+UPDATE hecate.resolved_expansion
+SET hits         = hits + 1,
+    last_used_at = now()
+WHERE expression_hash = $1
+  AND vocabulary_version = $2
+RETURNING expansion
@@ -0,0 +1,7 @@
+UPDATE hecate.synonym_override
+SET alias       = COALESCE($2, alias),
+    alias_lower = lower(COALESCE($2, alias)),
+    concept_id  = COALESCE($3, concept_id),
+    note        = COALESCE($4, note),
+    updated_at  = now()
+WHERE id = $1
@@ -0,0 +1,7 @@
+UPDATE hecate.validation_job
+SET status      = $2,
+    progress    = CASE WHEN $2 = 'succeeded' THEN 1 ELSE progress END,
+    result      = $3,
+    error       = $4,
+    finished_at = now()
+WHERE id = $1
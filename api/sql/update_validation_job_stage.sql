@@ -0,0 +1,7 @@
+UPDATE hecate.validation_job
+SET status     = 'running',
+    stage      = $2,
+    progress   = $3,
+    started_at = COALESCE(started_at, now())
+WHERE id = $1
+  AND status IN ('queued', 'running')
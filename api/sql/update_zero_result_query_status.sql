@@ -0,0 +1,3 @@
+UPDATE hecate.zero_result_query
+SET status = $2
+WHERE id = $1
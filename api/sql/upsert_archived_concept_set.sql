@@ -0,0 +1,13 @@
+INSERT INTO hecate.concept_set (uid, name, description, tags, created_by, latest_version, created_at, updated_at,
+                                deleted_at)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+ON CONFLICT (uid) DO UPDATE
+    SET name           = EXCLUDED.name,
+        description    = EXCLUDED.description,
+        tags           = EXCLUDED.tags,
+        created_by     = EXCLUDED.created_by,
+        latest_version = EXCLUDED.latest_version,
+        created_at     = EXCLUDED.created_at,
+        updated_at     = EXCLUDED.updated_at,
+        deleted_at     = EXCLUDED.deleted_at
+RETURNING id
@@ -0,0 +1,12 @@
+INSERT INTO hecate.synonym_override (alias, alias_lower, concept_id, source, created_by, note, zero_result_query_id,
+                                     created_at, updated_at)
+VALUES ($1, lower($1), $2, $3, $4, $5,
+        (SELECT q.id FROM hecate.zero_result_query AS q WHERE q.query_lower = $6), $7, $8)
+ON CONFLICT (alias_lower, concept_id) DO UPDATE
+    SET alias                = EXCLUDED.alias,
+        source               = EXCLUDED.source,
+        created_by           = EXCLUDED.created_by,
+        note                 = EXCLUDED.note,
+        zero_result_query_id = COALESCE(EXCLUDED.zero_result_query_id,
+                                        hecate.synonym_override.zero_result_query_id),
+        updated_at           = EXCLUDED.updated_at
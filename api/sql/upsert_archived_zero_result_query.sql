@@ -0,0 +1,8 @@
+INSERT INTO hecate.zero_result_query (query_lower, query, filters, occurrences, status, first_seen, last_seen)
+VALUES (lower($1), $1, $2, $3, $4, $5, $6)
+ON CONFLICT (query_lower) DO UPDATE
+    SET occurrences = GREATEST(hecate.zero_result_query.occurrences, EXCLUDED.occurrences),
+        status      = EXCLUDED.status,
+        filters     = COALESCE(EXCLUDED.filters, hecate.zero_result_query.filters),
+        first_seen  = LEAST(hecate.zero_result_query.first_seen, EXCLUDED.first_seen),
+        last_seen   = GREATEST(hecate.zero_result_query.last_seen, EXCLUDED.last_seen)
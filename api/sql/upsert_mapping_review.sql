@@ -0,0 +1,9 @@
+INSERT INTO hecate.mapping_review (job_id, row_number, status, concept_id, assigned_to, updated_by)
+VALUES ($1, $2, COALESCE($3, 'unreviewed'), $4, NULLIF($5, ''), $6)
+ON CONFLICT (job_id, row_number) DO UPDATE
+    SET status      = COALESCE($3, mapping_review.status),
+        concept_id  = COALESCE($4, mapping_review.concept_id),
+        assigned_to = CASE WHEN $5 IS NULL THEN mapping_review.assigned_to ELSE NULLIF($5, '') END,
+        updated_by  = $6,
+        updated_at  = now()
+RETURNING job_id, row_number, status, concept_id, assigned_to, updated_by, updated_at
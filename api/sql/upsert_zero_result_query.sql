@@ -0,0 +1,7 @@
+INSERT INTO hecate.zero_result_query (query_lower, query, filters)
+VALUES ($1, $2, $3)
+ON CONFLICT (query_lower) DO UPDATE
+    SET occurrences = hecate.zero_result_query.occurrences + 1,
+        query       = EXCLUDED.query,
+        filters     = EXCLUDED.filters,
+        last_seen   = now()
@@ -1,27 +1,207 @@
-use crate::domain::SearchResponse;
-use crate::embeddings::fetch_embeddings;
-use crate::errors::PgError;
+use crate::code_systems;
+use crate::code_systems::CodeSystemMatch;
+use crate::codesets::{CodesetSqlOptions, SqlDialect, render_codeset_sql};
+use crate::curation;
+use crate::debug::SearchExplanation;
+use crate::domain::{
+    Concept, ConceptSynonym, HierarchyConcept, RelatedConcept, ResolvedConcept, SearchDiagnostics,
+    SearchResponse, SearchResults,
+};
+use crate::errors::{EmbeddingError, PgError, SearchError};
+use crate::expansions;
+use crate::import::{self, CsvLayout, ImportResult};
+use crate::ndjson;
+use crate::profiles::{ValidationProfile, ValidationRule};
+use crate::search::{CachedSearch, GroupBy, MatchMode, SearchFilters, SortKey, SortOrder};
+use crate::slo::SHED_RECOMMENDATIONS_WARNING;
+use crate::snapshot;
+use crate::tabular::{self, FormatParameter};
 use crate::umls::get_umls_definition_from_nlm;
 use crate::utils::deserialize_string_or_vec;
-use crate::validation;
+use crate::validation::{self, ConceptSetAnalysis};
+use crate::workbook;
 use crate::{StateWrapper, db};
+use actix_web::http::header::HeaderValue;
 use actix_web::web::{Data, Json, Query};
-use actix_web::{Error, HttpResponse, get, post, web};
-use log::info;
-use qdrant_client::qdrant::condition::ConditionOneOf;
-use qdrant_client::qdrant::point_id::PointIdOptions;
-use qdrant_client::qdrant::{
-    Condition, Filter, GetPointsBuilder, PointId, QueryPointsBuilder, RecommendInputBuilder,
-    RetrievedPoint, ScoredPoint, ScrollPointsBuilder, SearchPointsBuilder,
-};
-use qdrant_client::{Qdrant, qdrant};
-use serde::Deserialize;
+use actix_web::{Error, HttpRequest, HttpResponse, get, post, web};
+use futures::{StreamExt, TryStreamExt, stream};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::convert::Infallible;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::instrument;
+use utoipa::{IntoParams, ToSchema};
 
-pub const COLLECTION_NAME: &str = "meddra";
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct Parameters {
+    /// The search text. "Quoted phrases", terms joined by AND or OR and -excluded terms
+    /// constrain the concept names of the results, the remaining words are searched as usual.
+    #[param(example = "diabetes")]
+    pub(crate) q: String,
+    /// Only concepts of these vocabularies, comma-separated, e.g. `SNOMED,ICD10CM`.
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    #[param(value_type = Option<String>)]
+    pub(crate) vocabulary_id: Option<Vec<String>>,
+    /// `S` for standard, `C` for classification concepts, empty for non-standard ones.
+    standard_concept: Option<String>,
+    /// Only concepts of these domains, comma-separated, e.g. `Condition,Drug`.
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    #[param(value_type = Option<String>)]
+    pub(crate) domain_id: Option<Vec<String>>,
+    /// Only concepts of these classes, comma-separated, e.g. `Disorder,Clinical Finding`.
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    #[param(value_type = Option<String>)]
+    concept_class_id: Option<Vec<String>>,
+    /// Results per page, 100 when omitted.
+    #[param(minimum = 1)]
+    pub(crate) limit: Option<u64>,
+    /// Wrap the results in `SearchResults`, which also carries facets and, when nothing was
+    /// found, diagnostics.
+    #[serde(default)]
+    envelope: bool,
+    /// An auxiliary code system to search instead of the OMOP vocabulary, see `/api/systems`.
+    /// Returns `CodeSystemMatch`es; the concept filters and the envelope do not apply.
+    system: Option<String>,
+    /// Results to skip, superseded by `cursor`.
+    offset: Option<u64>,
+    /// The `next_cursor` (or `X-Next-Cursor` header) of the previous page. Only valid for the
+    /// same query and filters.
+    cursor: Option<String>,
+    /// How `q` is matched against concept names.
+    #[serde(default, rename = "match")]
+    match_mode: MatchMode,
+    /// What a result stands for: a name with every concept carrying it, or a single concept.
+    #[serde(default)]
+    group_by: GroupBy,
+    /// Sort the grouped results across all pages instead of ranking them by relevance.
+    sort: Option<SortKey>,
+    /// Direction of `sort`, descending for `score` and ascending for the other keys when
+    /// omitted.
+    order: Option<SortOrder>,
+    /// Least similarity of embedding matches, `SEARCH__SCORE_THRESHOLD` when omitted.
+    #[param(minimum = 0, maximum = 1)]
+    score_threshold: Option<f32>,
+    /// Points to request from Qdrant, the configured candidate limits when omitted.
+    #[param(minimum = 1, maximum = 2000)]
+    candidates: Option<u64>,
+    /// Return the enveloped results with the trace of the search, bypassing the search cache.
+    /// Takes precedence over NDJSON and CSV output.
+    #[serde(default)]
+    debug: bool,
+    /// Concepts to leave out of the results, such as those already in a concept set,
+    /// comma-separated. Names left without concepts are dropped.
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    #[param(value_type = Option<String>, example = "201826,4193704")]
+    exclude_concept_ids: Option<Vec<String>>,
+}
 
-#[derive(Deserialize)]
-struct Parameters {
-    q: String,
+impl Parameters {
+    pub(crate) fn filters(&self) -> SearchFilters {
+        SearchFilters {
+            vocabulary_id: self.vocabulary_id.clone(),
+            standard_concept: self.standard_concept.clone(),
+            domain_id: self.domain_id.clone(),
+            concept_class_id: self.concept_class_id.clone(),
+            match_mode: self.match_mode,
+            group_by: self.group_by,
+            score_threshold: self.score_threshold,
+            candidates: self.candidates,
+        }
+    }
+
+    /// Ties a cursor to the query, filters and sort order it was issued for.
+    fn fingerprint(&self) -> String {
+        let canonical = serde_json::to_vec(&(self.q.trim(), self.filters(), self.sort, self.order))
+            .unwrap_or_default();
+        Sha256::digest(&canonical)
+            .iter()
+            .take(6)
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Where the requested page starts, `None` when the cursor belongs to another search.
+    fn page_offset(&self) -> Option<u64> {
+        let Some(cursor) = &self.cursor else {
+            return Some(self.offset.unwrap_or(0));
+        };
+        let (offset, fingerprint) = cursor.split_once('.')?;
+        (fingerprint == self.fingerprint())
+            .then(|| offset.parse().ok())
+            .flatten()
+    }
+
+    fn cursor_at(&self, offset: u64) -> String {
+        format!("{}.{}", offset, self.fingerprint())
+    }
+
+    /// The concept ids in `exclude_concept_ids`, or the values that are not concept ids.
+    fn excluded_concept_ids(&self) -> Result<HashSet<i32>, Vec<String>> {
+        let values = self.exclude_concept_ids.as_deref().unwrap_or_default();
+        let invalid: Vec<String> = values
+            .iter()
+            .filter(|value| value.parse::<i32>().is_err())
+            .cloned()
+            .collect();
+        if !invalid.is_empty() {
+            return Err(invalid);
+        }
+        Ok(values
+            .iter()
+            .filter_map(|value| value.parse().ok())
+            .collect())
+    }
+}
+
+/// The results without the excluded concepts, dropping names left without concepts. Applied
+/// after the search cache, so editing a concept set does not make every search a cache miss.
+fn without_concepts(results: &[SearchResponse], excluded: &HashSet<i32>) -> Vec<SearchResponse> {
+    results
+        .iter()
+        .filter_map(|result| {
+            let concepts: Vec<Concept> = result
+                .concepts
+                .iter()
+                .filter(|concept| !excluded.contains(&concept.concept_id))
+                .cloned()
+                .collect();
+            (!concepts.is_empty()).then(|| SearchResponse {
+                concepts,
+                ..result.clone()
+            })
+        })
+        .collect()
+}
+
+const DEFAULT_DESCENDANT_LIMIT: i64 = 100;
+const MAX_DESCENDANT_LIMIT: i64 = 1000;
+/// Terms searched per batch request, and searches run at the same time.
+const MAX_BATCH_TERMS: usize = 1000;
+const BATCH_CONCURRENCY: usize = 8;
+/// Concepts looked up per query while streaming a resolved codeset as NDJSON.
+const RESOLVED_ROWS_PER_QUERY: usize = 1000;
+
+/// The JSON bodies `/api/search` answers with, depending on `envelope`, `debug` and `system`.
+/// Only documents the response, the handler serializes each shape directly.
+#[allow(dead_code)]
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+enum SearchPage {
+    Results(Vec<SearchResponse>),
+    Envelope(Box<SearchResults>),
+    System(Vec<CodeSystemMatch>),
+}
+
+/// The filters of `/api/search`, applied to every term. Filters take a single value or a list.
+#[derive(Deserialize, ToSchema)]
+struct BatchSearchRequest {
+    /// At most 1000 terms.
+    terms: Vec<String>,
     #[serde(default, deserialize_with = "deserialize_string_or_vec")]
     vocabulary_id: Option<Vec<String>>,
     standard_concept: Option<String>,
@@ -29,110 +209,597 @@ struct Parameters {
     domain_id: Option<Vec<String>>,
     #[serde(default, deserialize_with = "deserialize_string_or_vec")]
     concept_class_id: Option<Vec<String>>,
+    /// Results per term, 100 when omitted.
+    #[schema(minimum = 1)]
+    limit: Option<u64>,
+    #[serde(default, rename = "match")]
+    match_mode: MatchMode,
+    #[serde(default)]
+    group_by: GroupBy,
+    /// Sort the grouped results instead of ranking them by relevance.
+    sort: Option<SortKey>,
+    order: Option<SortOrder>,
+    /// Least similarity of embedding matches, `SEARCH__SCORE_THRESHOLD` when omitted.
+    score_threshold: Option<f32>,
+    /// Points to request from Qdrant, the configured candidate limits when omitted.
+    candidates: Option<u64>,
+}
+
+impl BatchSearchRequest {
+    fn filters(&self) -> SearchFilters {
+        SearchFilters {
+            vocabulary_id: self.vocabulary_id.clone(),
+            standard_concept: self.standard_concept.clone(),
+            domain_id: self.domain_id.clone(),
+            concept_class_id: self.concept_class_id.clone(),
+            match_mode: self.match_mode,
+            group_by: self.group_by,
+            score_threshold: self.score_threshold,
+            candidates: self.candidates,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct RelationshipParameters {
+    /// Only these relationships, comma-separated, e.g. `Maps to` or `Subsumes`.
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    #[param(value_type = Option<String>)]
+    relationship_id: Option<Vec<String>>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum MappingDirection {
+    /// The standard concepts a concept maps to.
+    #[default]
+    ToStandard,
+    /// The source concepts mapping to a standard concept.
+    FromStandard,
+}
+
+impl MappingDirection {
+    fn relationship_id(self) -> &'static str {
+        match self {
+            MappingDirection::ToStandard => "Maps to",
+            MappingDirection::FromStandard => "Mapped from",
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct MappingParameters {
+    /// The concepts the concept maps to, or those mapping to it.
+    #[serde(default)]
+    #[param(inline)]
+    direction: MappingDirection,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SynonymParameters {
+    /// Language concepts to keep synonyms of, comma-separated, e.g. 4180186 for English.
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    #[param(value_type = Option<String>)]
+    language_concept_id: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DescendantParameters {
+    /// Only descendants at most this many levels below the concept, all levels when omitted.
+    max_levels: Option<i32>,
+    domain_id: Option<String>,
+    #[serde(default)]
+    offset: i64,
+    /// Descendants per page, 100 when omitted and at most 1000. With NDJSON all of them past
+    /// `offset` when omitted.
+    #[param(maximum = 1000)]
+    limit: Option<i64>,
+}
+
+/// A page of a concept's descendants.
+#[derive(Serialize, ToSchema)]
+struct DescendantPage {
+    total: i64,
+    offset: i64,
+    has_more: bool,
+    concepts: Vec<HierarchyConcept>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ConceptSetImportParameters {
+    /// The layout of the CSV, detected from its header when omitted.
+    layout: Option<CsvLayout>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CodesetSqlRequest {
+    /// The concept set expression, as JSON text.
+    concept_set: String,
+    #[serde(flatten)]
+    options: CodesetSqlOptions,
+}
+
+/// The SQL that fills a circe codeset table with a resolved concept set.
+#[derive(Serialize, ToSchema)]
+struct CodesetSql {
+    dialect: SqlDialect,
+    codeset_id: i32,
+    concept_count: usize,
+    sql: String,
+    warnings: Vec<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct ConceptSetResolveRequest {
+    /// The concept set expression, as JSON text.
+    concept_set: String,
+    /// Resolved concepts to skip, in ascending concept id order.
+    #[serde(default)]
+    offset: u64,
+    /// All resolved concepts when omitted.
     limit: Option<u64>,
 }
 
-#[derive(Deserialize)]
+/// A page of the concepts a concept set resolves to, in ascending concept id order.
+#[derive(Serialize, ToSchema)]
+struct ResolvedConceptSet {
+    total: u64,
+    offset: u64,
+    has_more: bool,
+    concepts: Vec<ResolvedConcept>,
+    /// Address of the stored expansion, see `/api/expansions/{hash}`.
+    expansion_hash: Option<String>,
+    warnings: Vec<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct ConceptSetCompareRequest {
+    /// The earlier version, e.g. of the cohort's current definition, as JSON text.
+    base: String,
+    /// The revision, as JSON text.
+    revised: String,
+}
+
+/// How the resolved concepts of a revision differ from those of the base version.
+#[derive(Serialize, ToSchema)]
+struct ConceptSetComparison {
+    base_count: usize,
+    revised_count: usize,
+    added: Vec<ResolvedConcept>,
+    removed: Vec<ResolvedConcept>,
+    /// The concept ids both versions resolve to.
+    shared: Vec<i32>,
+    /// The shared concepts over all concepts of either version, 1 for two empty sets.
+    jaccard: f64,
+    warnings: Vec<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
 struct ConceptSetValidationRequest {
+    /// The concept set expression, or a concept set with its expression, as JSON text.
     concept_set: String,
+    #[serde(default)]
+    profile: ValidationProfile,
 }
 
+/// A validation profile with the rules it checks.
+#[derive(Serialize, ToSchema)]
+struct ValidationProfileSummary {
+    id: ValidationProfile,
+    description: &'static str,
+    rules: &'static [ValidationRule],
+    /// The domains recommendations are limited to, those of the concept set when absent.
+    recommendation_domains: Option<&'static [&'static str]>,
+}
+
+#[utoipa::path(
+    tag = "search",
+    summary = "Search for concepts",
+    description = "Semantic search for medical concepts by text, with optional filters on vocabulary, domain, \
+        concept class and standard status.",
+    params(Parameters, FormatParameter),
+    responses(
+        (status = 200, description = "A page of results, a `SearchResults` envelope with `envelope=true` or \
+            `debug=true`, or `CodeSystemMatch`es with `system`",
+            headers(
+                ("X-Total-Count" = u64, description = "Results across all pages"),
+                ("X-Next-Cursor" = String, description = "Cursor of the next page, absent on the last page"),
+                ("X-Suggested-Query" = String, description = "The `suggested_query` of a search without results, \
+                    when the envelope is not used"),
+            ),
+            content(
+                (SearchPage = "application/json"),
+                (SearchResponse = "application/x-ndjson"),
+                (String = "text/csv"),
+                (String = "text/tab-separated-values"),
+            )
+        ),
+        (status = 400, description = "A cursor issued for another search, or a vocabulary_id or domain_id that \
+            does not exist; the body lists the `invalid` and the `valid` values"),
+        (status = 503, description = "The embedding service returned vectors of an unexpected model or \
+            dimension, the request was not sent to the vector store"),
+    )
+)]
 #[get("/api/search")]
+#[instrument(skip_all, fields(q = %parameters.q))]
 async fn search(
+    req: HttpRequest,
     parameters: Query<Parameters>,
     state: Data<StateWrapper>,
-) -> Result<Json<Vec<SearchResponse>>, Error> {
-    let client = &state.qdrant_client;
-    let input = parameters.q.trim();
-    let lowercase_input = input.to_lowercase();
-    info!("Received search request for {:?}", &input);
-    let opt_existing = state.concept_index.get(lowercase_input.as_str());
-    let mut to_return: Vec<SearchResponse> = Vec::new();
-    let mut ids: Vec<String> = Vec::new();
-    if opt_existing.is_none() {
-        info!("Nothing found in search index");
-        let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
-        let numeric_id = input.parse::<i32>();
-        let concepts = match numeric_id {
-            Ok(_) => db::get_concept_name_by_number(&pg_client, numeric_id.unwrap()).await?,
-            Err(_) => db::get_concept_name_by_string(&pg_client, input.to_string()).await?,
-        };
+) -> Result<HttpResponse, Error> {
+    let parameters = parameters.into_inner();
+    // An empty page would point the next cursor back at the same offset
+    let limit = state
+        .config
+        .demo
+        .clamp_limit(parameters.limit.unwrap_or(100).max(1));
+    if let Some(code_system) =
+        code_systems::find_system(&state.config, parameters.system.as_deref())?
+    {
+        let matches = code_systems::search(&state, code_system, &parameters.q, limit).await?;
+        if let Some(format) = tabular::requested(&req) {
+            let rows = tabular::code_system_rows(matches).into_iter();
+            return Ok(tabular::respond(
+                HttpResponse::Ok(),
+                format,
+                tabular::CODE_SYSTEM_COLUMNS,
+                stream::iter(rows.map(Ok::<_, Infallible>)),
+            ));
+        }
+        return Ok(HttpResponse::Ok().json(matches));
+    }
+    // A misspelled filter would otherwise silently return nothing
+    state
+        .vocabulary_catalog
+        .validate_vocabulary_ids(parameters.vocabulary_id.as_deref())?;
+    state
+        .vocabulary_catalog
+        .validate_domain_ids(parameters.domain_id.as_deref())?;
+    parameters.filters().validate_tuning()?;
+    let excluded = match parameters.excluded_concept_ids() {
+        Ok(excluded) => excluded,
+        Err(invalid) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid concept ids in exclude_concept_ids: {}", invalid.join(", "))
+            })));
+        }
+    };
+    let Some(offset) = parameters.page_offset() else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "The cursor does not belong to this query and these filters"
+        })));
+    };
+    if parameters.debug {
+        return explain_search(&state, &parameters, &excluded, offset, limit).await;
+    }
+    let cached = cached_search(&state, &parameters.q, parameters.filters()).await?;
+    // The cached results stay in relevance order, excluding and sorting work on a copy
+    let mut remaining =
+        (!excluded.is_empty()).then(|| without_concepts(&cached.results, &excluded));
+    if let Some(sort) = parameters.sort {
+        let remaining = remaining.get_or_insert_with(|| cached.results.clone());
+        sort.sort(remaining, parameters.order);
+    }
+    let all_results = remaining.as_deref().unwrap_or(&cached.results);
+    let total = all_results.len() as u64;
+    let page_end = offset.saturating_add(limit).min(total);
+    let results: Vec<SearchResponse> =
+        all_results[offset.min(total) as usize..page_end as usize].to_vec();
+    let next_cursor = (page_end < total).then(|| parameters.cursor_at(page_end));
+    // Results that were all excluded are not a search without results
+    let found_nothing = cached.results.is_empty();
+    let mut suggested_query = None;
+    if found_nothing {
+        curation::capture_zero_result_query(
+            &state,
+            parameters.q.trim(),
+            serde_json::to_value(parameters.filters()).unwrap_or_default(),
+        );
+        suggested_query = snapshot::current(&state)
+            .spelling
+            .as_ref()
+            .and_then(|spelling| spelling.suggest(&parameters.q));
+    }
+    if ndjson::accepted(&req) {
+        let mut response = HttpResponse::Ok();
+        response.insert_header(("X-Total-Count", total));
+        if let Some(next_cursor) = &next_cursor {
+            response.insert_header(("X-Next-Cursor", next_cursor.as_str()));
+        }
+        let rows = stream::iter(results.into_iter().map(Ok::<_, Infallible>));
+        return Ok(ndjson::respond(response, rows));
+    }
+    if let Some(format) = tabular::requested(&req) {
+        let mut response = HttpResponse::Ok();
+        response.insert_header(("X-Total-Count", total));
+        if let Some(next_cursor) = &next_cursor {
+            response.insert_header(("X-Next-Cursor", next_cursor.as_str()));
+        }
+        let rows = tabular::search_rows(results).into_iter();
+        return Ok(tabular::respond(
+            response,
+            format,
+            tabular::SEARCH_COLUMNS,
+            stream::iter(rows.map(Ok::<_, Infallible>)),
+        ));
+    }
+    if !parameters.envelope {
+        let mut response = HttpResponse::Ok();
+        response.insert_header(("X-Total-Count", total));
+        if let Some(next_cursor) = &next_cursor {
+            response.insert_header(("X-Next-Cursor", next_cursor.as_str()));
+        }
+        if let Some(suggested_query) = &suggested_query
+            && let Ok(value) = HeaderValue::from_str(suggested_query)
+        {
+            response.insert_header(("X-Suggested-Query", value));
+        }
+        return Ok(response.json(results));
+    }
+    let diagnostics = if found_nothing {
+        let mut diagnostics = cached.diagnostics.clone();
+        diagnostics.suggest_relaxations();
+        Some(diagnostics)
+    } else {
+        None
+    };
+    Ok(HttpResponse::Ok().json(SearchResults {
+        results,
+        total,
+        has_more: next_cursor.is_some(),
+        next_cursor,
+        suggested_query,
+        facets: cached.facets.clone(),
+        diagnostics,
+        debug: None,
+    }))
+}
+
+/// The full result list of a search, from the in-memory cache, the shared cache or a fresh run
+/// of the pipeline, in that order. Every page groups the full candidate list so totals and page
+/// boundaries stay stable.
+pub(crate) async fn cached_search(
+    state: &StateWrapper,
+    query: &str,
+    filters: SearchFilters,
+) -> Result<Arc<CachedSearch>, SearchError> {
+    let generation = state.shared_cache.search_generation();
+    let cache_key = state
+        .search_pipeline
+        .cache_key(state, query, &filters, generation);
+    if let Some(cached) = state
+        .metrics
+        .search_cache
+        .record(state.search_cache.get(&cache_key))
+    {
+        return Ok(cached);
+    }
+    if let Some(cached) = state
+        .shared_cache
+        .get::<CachedSearch>("search", &cache_key)
+        .await
+    {
+        let cached = Arc::new(cached);
+        state.search_cache.insert(cache_key, cached.clone());
+        return Ok(cached);
+    }
+    let mut diagnostics = SearchDiagnostics::new(state.config.search.score_threshold);
+    let results = state
+        .search_pipeline
+        .run(state, query, filters, u64::MAX, &mut diagnostics)
+        .await?;
+    let cached = Arc::new(CachedSearch {
+        results,
+        facets: diagnostics.facets.take(),
+        diagnostics,
+    });
+    state
+        .shared_cache
+        .insert("search", &cache_key, cached.as_ref())
+        .await;
+    state.search_cache.insert(cache_key, cached.clone());
+    Ok(cached)
+}
 
-        if !concepts.is_empty() {
-            for c in concepts {
-                let lower = c.to_lowercase();
-                info!("{}", lower);
-                let res = state.concept_index.get(lower.as_str());
-                if let Some(item) = res {
-                    item.iter().for_each(|x| ids.push(x.to_string()))
-                } else {
-                    let results: Vec<RetrievedPoint> =
-                        find_by_concept_name_lower(client, lower, COLLECTION_NAME).await;
-                    results.iter().for_each(|x| {
-                        if let PointIdOptions::Uuid(id) =
-                            x.clone().id.unwrap().point_id_options.unwrap()
-                        {
-                            ids.push(id.to_string());
-                        }
-                    });
+/// `/api/search?debug=true`: the requested page in the envelope along with the diagnostics and
+/// the trace of every stage, SQL statement, Qdrant and embedding call. Runs past the search
+/// cache, a cached search has nothing to trace.
+async fn explain_search(
+    state: &StateWrapper,
+    parameters: &Parameters,
+    excluded: &HashSet<i32>,
+    offset: u64,
+    limit: u64,
+) -> Result<HttpResponse, Error> {
+    let mut diagnostics = SearchDiagnostics::new(state.config.search.score_threshold);
+    let started = Instant::now();
+    let (results, trace) = state
+        .search_pipeline
+        .run_traced(
+            state,
+            &parameters.q,
+            parameters.filters(),
+            u64::MAX,
+            &mut diagnostics,
+        )
+        .await;
+    let results = results?;
+    let elapsed = started.elapsed();
+    let found_nothing = results.is_empty();
+    let mut results = without_concepts(&results, excluded);
+    if let Some(sort) = parameters.sort {
+        sort.sort(&mut results, parameters.order);
+    }
+    let total = results.len() as u64;
+    let page_end = offset.saturating_add(limit).min(total);
+    let next_cursor = (page_end < total).then(|| parameters.cursor_at(page_end));
+    let suggested_query = if found_nothing {
+        snapshot::current(state)
+            .spelling
+            .as_ref()
+            .and_then(|spelling| spelling.suggest(&parameters.q))
+    } else {
+        None
+    };
+    let facets = diagnostics.facets.take();
+    diagnostics.suggest_relaxations();
+    Ok(HttpResponse::Ok().json(SearchResults {
+        results: results[offset.min(total) as usize..page_end as usize].to_vec(),
+        total,
+        has_more: next_cursor.is_some(),
+        next_cursor,
+        suggested_query,
+        facets,
+        diagnostics: Some(diagnostics),
+        debug: Some(SearchExplanation {
+            elapsed_ms: elapsed.as_secs_f64() * 1000.0,
+            trace,
+        }),
+    }))
+}
+
+/// Searches many terms with shared filters. The distinct terms are embedded up front in as few
+/// requests as the embedding service allows, instead of once per term.
+#[utoipa::path(
+    tag = "search",
+    summary = "Search many terms",
+    description = "Searches every term with the same filters and returns the results keyed by term, as sent. \
+        At most 1000 terms per request.",
+    responses(
+        (status = 200, description = "Ranked results per term", body = HashMap<String, Vec<SearchResponse>>),
+        (status = 400, description = "Too many terms or an unknown vocabulary_id or domain_id"),
+        (status = 503, description = "The embedding service returned vectors of an unexpected model or dimension"),
+    )
+)]
+#[post("/api/search/batch")]
+#[instrument(skip_all, fields(terms = request.terms.len()))]
+async fn search_batch(
+    request: Json<BatchSearchRequest>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let request = request.into_inner();
+    if request.terms.len() > MAX_BATCH_TERMS {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("At most {} terms can be searched per request", MAX_BATCH_TERMS)
+        })));
+    }
+    let limit = state
+        .config
+        .demo
+        .clamp_limit(request.limit.unwrap_or(100).max(1));
+    state
+        .vocabulary_catalog
+        .validate_vocabulary_ids(request.vocabulary_id.as_deref())?;
+    state
+        .vocabulary_catalog
+        .validate_domain_ids(request.domain_id.as_deref())?;
+    request.filters().validate_tuning()?;
+    info!(
+        "Received batch search request for {} terms",
+        request.terms.len()
+    );
+
+    let terms: Vec<String> = request
+        .terms
+        .iter()
+        .map(|term| term.trim().to_string())
+        .filter(|term| !term.is_empty())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let filters = request.filters();
+    let (sort, order) = (request.sort, request.order);
+    let mut embeddings: HashMap<String, Vec<f32>> = HashMap::new();
+    // Exact and prefix matches never embed the query
+    if matches!(filters.match_mode, MatchMode::Auto | MatchMode::Semantic) {
+        let started = Instant::now();
+        let embedded = state.embedder.embed_batch(terms.clone()).await;
+        state
+            .metrics
+            .record_dependency("embedding", "embed_batch", started.elapsed());
+        match embedded {
+            Ok(vectors) => embeddings.extend(terms.iter().cloned().zip(vectors)),
+            Err(e) => {
+                if let Some(mismatch) = e.downcast_ref::<EmbeddingError>() {
+                    return Err(mismatch.clone().into());
                 }
+                // Each search embeds its own term again if it gets that far
+                warn!(
+                    "Batch embedding failed, falling back to per-term embedding: {}",
+                    e
+                );
             }
-        } else {
-            let limit = parameters.limit.unwrap_or(100);
-            // Request more results from qdrant to account for filtering
-            let search_limit = 250;
-            let recommendations = recommend(input.to_string(), client, search_limit).await;
-            for sp in recommendations {
-                let mut concept: SearchResponse = SearchResponse::from(sp);
-                // Apply filters after retrieval due to performance issues with filtering in qdrant
-                concept.concepts = filter_concepts(concept.concepts, &parameters);
-                if concept.concepts.is_empty() {
-                    continue;
+        }
+    }
+
+    let results: Vec<(String, Vec<SearchResponse>)> = stream::iter(terms)
+        .map(|term| {
+            let embedding = embeddings.remove(&term);
+            let filters = filters.clone();
+            let state = &state;
+            async move {
+                let mut diagnostics = SearchDiagnostics::new(state.config.search.score_threshold);
+                let mut results = state
+                    .search_pipeline
+                    .run_with_embedding(
+                        state,
+                        &term,
+                        embedding,
+                        filters.clone(),
+                        limit,
+                        &mut diagnostics,
+                    )
+                    .await?;
+                if let Some(sort) = sort {
+                    sort.sort(&mut results, order);
                 }
-                // case desensification
-                let mut contains_case_insensitive_exact_match = false;
-                to_return = to_return
-                    .into_iter()
-                    .map(|mut every| {
-                        if every.concept_name_lower.eq(&concept.concept_name_lower) {
-                            every.append_concepts(&mut concept.concepts);
-                            contains_case_insensitive_exact_match = true;
-                            every
-                        } else {
-                            every
-                        }
-                    })
-                    .collect();
-                if !contains_case_insensitive_exact_match {
-                    to_return.push(concept);
+                if results.is_empty() {
+                    curation::capture_zero_result_query(
+                        state,
+                        &term,
+                        serde_json::to_value(&filters).unwrap_or_default(),
+                    );
                 }
+                Ok::<_, SearchError>((term, results))
             }
-            // Sort by score descending and apply limit
-            to_return.sort_by(|a, b| {
-                b.score
-                    .partial_cmp(&a.score)
-                    .unwrap_or(std::cmp::Ordering::Equal)
-            });
-            if to_return.len() > limit as usize {
-                to_return.truncate(limit as usize);
-            }
-            return Ok(Json(to_return));
-        }
-    } else {
-        opt_existing
-            .unwrap()
-            .iter()
-            .for_each(|x| ids.push(x.to_string()));
-    }
-    let mut points: Vec<PointId> = Vec::new();
-    let mut recs = RecommendInputBuilder::default();
-    for id in ids {
-        points.push(PointId::from(id.as_str()));
-        recs = recs.add_positive(PointId::from(id.as_str()));
-    }
-    create_response_from_vector_db_ids(client, to_return, recs, points, &parameters).await
+        })
+        .buffered(BATCH_CONCURRENCY)
+        .try_collect()
+        .await?;
+
+    // Terms are answered as sent, duplicates and surrounding whitespace included
+    let by_term: HashMap<String, Vec<SearchResponse>> = results.into_iter().collect();
+    let response: serde_json::Map<String, serde_json::Value> = request
+        .terms
+        .iter()
+        .map(|term| {
+            let results = by_term
+                .get(term.trim())
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            (
+                term.clone(),
+                serde_json::to_value(results).unwrap_or_default(),
+            )
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(response))
 }
 
+#[utoipa::path(
+    tag = "concepts",
+    summary = "Get a concept by id",
+    params(("id" = i32, Path, description = "Concept id", example = 201826)),
+    responses(
+        (status = 200, description = "The concept, as a one-element array", body = Vec<Concept>),
+        (status = 404, description = "Concept not found"),
+    )
+)]
 #[get("/api/concepts/{id}")]
 async fn get_concept_by_id(
     path: web::Path<i32>,
@@ -145,18 +812,240 @@ async fn get_concept_by_id(
     Ok(HttpResponse::Ok().json([concept]))
 }
 
+/// The concept's relationships, all of them or only those with the given `relationship_id`s,
+/// e.g. `Maps to`.
+#[utoipa::path(
+    tag = "concepts",
+    summary = "Get the relationships of a concept",
+    description = "All relationships of the concept, or only those with the given `relationship_id`s.",
+    params(("id" = i32, Path, description = "Concept id", example = 201826), RelationshipParameters),
+    responses(
+        (status = 200, description = "The concept's relationships", body = Vec<RelatedConcept>),
+        (status = 404, description = "Concept not found"),
+    )
+)]
 #[get("/api/concepts/{id}/relationships")]
 async fn get_concept_relationships(
     path: web::Path<i32>,
+    parameters: Query<RelationshipParameters>,
     state: Data<StateWrapper>,
 ) -> Result<HttpResponse, Error> {
     let id = path.into_inner();
     info!("Get concept {} relationships", &id);
     let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
-    let concept = db::get_concept_relationships(&pg_client, id).await?;
+    let concept =
+        db::get_concept_relationships(&pg_client, id, parameters.relationship_id.as_deref())
+            .await?;
     Ok(HttpResponse::Ok().json(concept))
 }
 
+/// The concepts a concept maps to through `Maps to`, or with `direction=from_standard` the
+/// concepts mapping to it, with full concept details.
+#[utoipa::path(
+    tag = "concepts",
+    summary = "Get the standard mappings of a concept",
+    description = "The concepts a concept maps to through `Maps to`, or with `direction=from_standard` the \
+        concepts mapping to it (`Mapped from`), as full concept rows. Standard concepts map to themselves.",
+    params(("id" = i32, Path, description = "Concept id", example = 44821244), MappingParameters),
+    responses(
+        (status = 200, description = "The mapped concepts", body = Vec<Concept>),
+        (status = 400, description = "Unknown direction"),
+        (status = 404, description = "Concept not found"),
+    )
+)]
+#[get("/api/concepts/{id}/mappings")]
+async fn get_concept_mappings(
+    path: web::Path<i32>,
+    parameters: Query<MappingParameters>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    info!("Get concept {} mappings {:?}", &id, parameters.direction);
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let concepts =
+        db::get_mapped_concepts(&pg_client, id, parameters.direction.relationship_id()).await?;
+    if concepts.is_empty() && db::get_concepts_by_ids(&pg_client, &[id]).await?.is_empty() {
+        return Err(PgError::NotFound.into());
+    }
+    Ok(HttpResponse::Ok().json(concepts))
+}
+
+/// The concept's synonyms from `concept_synonym`, optionally only in the given languages.
+#[utoipa::path(
+    tag = "concepts",
+    summary = "Get the synonyms of a concept",
+    params(("id" = i32, Path, description = "Concept id", example = 201826), SynonymParameters),
+    responses(
+        (status = 200, description = "The concept's synonyms with their language", body = Vec<ConceptSynonym>),
+        (status = 400, description = "language_concept_id is not a list of concept ids"),
+        (status = 404, description = "Concept not found"),
+    )
+)]
+#[get("/api/concepts/{id}/synonyms")]
+async fn get_concept_synonyms(
+    path: web::Path<i32>,
+    parameters: Query<SynonymParameters>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    info!("Get concept {} synonyms", &id);
+    let language_concept_ids: Option<Vec<i32>> = match parameters
+        .language_concept_id
+        .as_ref()
+        .map(|ids| ids.iter().map(|id| id.parse::<i32>()).collect())
+        .transpose()
+    {
+        Ok(ids) => ids,
+        Err(_) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "language_concept_id must be concept IDs"
+            })));
+        }
+    };
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let synonyms =
+        db::get_concept_synonyms(&pg_client, id, language_concept_ids.as_deref()).await?;
+    if synonyms.is_empty() && db::get_concepts_by_ids(&pg_client, &[id]).await?.is_empty() {
+        return Err(PgError::NotFound.into());
+    }
+    Ok(HttpResponse::Ok().json(synonyms))
+}
+
+/// The concept's ancestors with their levels of separation, nearest first, e.g. for breadcrumbs.
+#[utoipa::path(
+    tag = "concepts",
+    summary = "Get the ancestors of a concept",
+    description = "The ancestors from `concept_ancestor`, nearest first, with the levels of separation along \
+        the shortest and longest path.",
+    params(("id" = i32, Path, description = "Concept id", example = 201826)),
+    responses(
+        (status = 200, description = "The concept's ancestors, empty for top-level concepts", body = Vec<HierarchyConcept>),
+        (status = 404, description = "Concept not found"),
+    )
+)]
+#[get("/api/concepts/{id}/ancestors")]
+async fn get_concept_ancestors(
+    path: web::Path<i32>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    info!("Get concept {} ancestors", &id);
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let ancestors = db::get_ancestor_concepts(&pg_client, id).await?;
+    // Top-level concepts have no ancestors, unknown ones are not found
+    if ancestors.is_empty() && db::get_concepts_by_ids(&pg_client, &[id]).await?.is_empty() {
+        return Err(PgError::NotFound.into());
+    }
+    Ok(HttpResponse::Ok().json(ancestors))
+}
+
+/// The concept's descendants as full concept rows, nearest first and a page at a time, as
+/// hierarchies like SNOMED's Clinical finding have far too many to return at once.
+#[utoipa::path(
+    tag = "concepts",
+    summary = "Get the descendants of a concept",
+    description = "The descendants from `concept_ancestor` as full concept rows, nearest first, a page at a time.",
+    params(("id" = i32, Path, description = "Concept id", example = 441840), DescendantParameters),
+    responses(
+        (status = 200, description = "A page of descendants, or with Accept `application/x-ndjson` one descendant \
+            per line with the total in `X-Total-Count`",
+            content(
+                (DescendantPage = "application/json"),
+                (HierarchyConcept = "application/x-ndjson"),
+            )
+        ),
+        (status = 404, description = "Concept not found"),
+    )
+)]
+#[get("/api/concepts/{id}/descendants")]
+async fn get_concept_descendants(
+    req: HttpRequest,
+    path: web::Path<i32>,
+    parameters: Query<DescendantParameters>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    info!("Get concept {} descendants", &id);
+    let offset = parameters.offset.max(0);
+    let limit = parameters
+        .limit
+        .unwrap_or(DEFAULT_DESCENDANT_LIMIT)
+        .clamp(1, MAX_DESCENDANT_LIMIT);
+    let limit = state.config.demo.clamp_limit(limit as u64) as i64;
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    if ndjson::accepted(&req) {
+        return stream_concept_descendants(id, &parameters, &state, pg_client).await;
+    }
+    let (total, concepts) = db::get_descendant_concepts_page(
+        &pg_client,
+        id,
+        parameters.max_levels,
+        parameters.domain_id.as_deref(),
+        offset,
+        limit,
+    )
+    .await?;
+    if total == 0 && db::get_concepts_by_ids(&pg_client, &[id]).await?.is_empty() {
+        return Err(PgError::NotFound.into());
+    }
+    Ok(HttpResponse::Ok().json(DescendantPage {
+        total,
+        offset,
+        has_more: offset + (concepts.len() as i64) < total,
+        concepts,
+    }))
+}
+
+/// The NDJSON variant of the descendant listing: every descendant past `offset` unless a `limit`
+/// is given, streamed from Postgres as the rows arrive.
+async fn stream_concept_descendants(
+    id: i32,
+    parameters: &DescendantParameters,
+    state: &StateWrapper,
+    pg_client: deadpool_postgres::Client,
+) -> Result<HttpResponse, Error> {
+    let offset = parameters.offset.max(0);
+    let limit = parameters.limit.map(|limit| limit.max(1));
+    let limit = if state.config.demo.enabled {
+        let limit = limit.unwrap_or(MAX_DESCENDANT_LIMIT) as u64;
+        Some(state.config.demo.clamp_limit(limit) as i64)
+    } else {
+        limit
+    };
+    let total = db::count_descendant_concepts(
+        &pg_client,
+        id,
+        parameters.max_levels,
+        parameters.domain_id.as_deref(),
+    )
+    .await?;
+    if total == 0 && db::get_concepts_by_ids(&pg_client, &[id]).await?.is_empty() {
+        return Err(PgError::NotFound.into());
+    }
+    let rows = db::stream_descendant_concepts(
+        pg_client,
+        id,
+        parameters.max_levels,
+        parameters.domain_id.clone(),
+        offset,
+        limit,
+    )
+    .await?;
+    let mut response = HttpResponse::Ok();
+    response.insert_header(("X-Total-Count", total));
+    Ok(ndjson::respond(response, rows))
+}
+
+#[utoipa::path(
+    tag = "concepts",
+    summary = "Get the PHOEBE recommendations of a concept",
+    description = "Concepts that co-occur with the concept in observational data, from the `Concept recommended` \
+        relationships of PHOEBE.",
+    params(("id" = i32, Path, description = "Concept id", example = 201826)),
+    responses(
+        (status = 200, description = "The recommended concepts", body = Vec<RelatedConcept>),
+    )
+)]
 #[get("/api/concepts/{id}/phoebe")]
 async fn get_concept_phoebe(
     path: web::Path<i32>,
@@ -169,6 +1058,17 @@ async fn get_concept_phoebe(
     Ok(HttpResponse::Ok().json(concept))
 }
 
+#[utoipa::path(
+    tag = "concepts",
+    summary = "Get the definition of a concept",
+    description = "The UMLS definition of the concept's name, or `No definition available`.",
+    params(("id" = i32, Path, description = "Concept id", example = 201826)),
+    responses(
+        (status = 200, description = "The definition", body = String),
+        (status = 404, description = "Concept not found"),
+        (status = 502, description = "The UMLS API refused the request, e.g. for a missing or invalid API key"),
+    )
+)]
 #[get("/api/concepts/{id}/definition")]
 async fn get_concept_definition(
     path: web::Path<i32>,
@@ -178,218 +1078,564 @@ async fn get_concept_definition(
     info!("Get concept {} definition", &id);
     let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
     let concept = db::get_concept_by_id(&pg_client, id).await?;
-    let def = get_umls_definition_from_nlm(concept.concept_name)
-        .await
-        .unwrap()
-        .unwrap_or("No definition available".parse()?);
+    let cached = state
+        .shared_cache
+        .get::<String>("umls", &concept.concept_name)
+        .await;
+    let def = match cached {
+        Some(def) => def,
+        None => {
+            let Ok(def) = get_umls_definition_from_nlm(concept.concept_name.clone()).await else {
+                return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                    "error": "The UMLS API refused the request"
+                })));
+            };
+            if let Some(def) = &def {
+                state
+                    .shared_cache
+                    .insert("umls", &concept.concept_name, def)
+                    .await;
+            }
+            def.unwrap_or("No definition available".parse()?)
+        }
+    };
     Ok(HttpResponse::Ok().json(def))
 }
-
-async fn create_response_from_vector_db_ids(
-    client: &Qdrant,
-    mut to_return: Vec<SearchResponse>,
-    recs: RecommendInputBuilder,
-    points: Vec<PointId>,
-    parameters: &Parameters,
-) -> Result<Json<Vec<SearchResponse>>, Error> {
-    let search_result = retrieve_point_from_db(client, points, COLLECTION_NAME).await;
-    let limit = parameters.limit.unwrap_or(100);
-    // Request more results from qdrant to account for filtering
-    let query_points_builder = QueryPointsBuilder::new(COLLECTION_NAME)
-        .with_payload(true)
-        .score_threshold(0.50)
-        .limit(500)
-        .query(recs.build());
-    let neighbours = client.query(query_points_builder).await.unwrap().result;
-    for retrieved_point in search_result {
-        let mut concept = SearchResponse::from(retrieved_point);
-        // Apply filters after retrieval due to performance issues with filtering in qdrant
-        concept.concepts = filter_concepts(concept.concepts, parameters);
-        if concept.concepts.is_empty() {
-            continue;
-        }
-        let mut didwehit = false;
-        to_return = to_return
-            .into_iter()
-            .map(|mut every| {
-                if every.concept_name_lower.eq(&concept.concept_name_lower) {
-                    every.append_concepts(&mut concept.concepts);
-                    didwehit = true;
-                    every
-                } else {
-                    every
-                }
-            })
-            .collect();
-        if !didwehit {
-            to_return.push(concept);
-        }
-    }
-    for scored_point in neighbours {
-        let mut concept = SearchResponse::from(scored_point);
-        // Apply filters after retrieval due to performance issues with filtering in qdrant
-        concept.concepts = filter_concepts(concept.concepts, parameters);
-        if concept.concepts.is_empty() {
-            continue;
-        }
-        let mut didwehit = false;
-        to_return = to_return
-            .into_iter()
-            .map(|mut every| {
-                if every.concept_name_lower.eq(&concept.concept_name_lower) {
-                    every.append_concepts(&mut concept.concepts);
-                    didwehit = true;
-                    every
-                } else {
-                    every
-                }
-            })
-            .collect();
-        if !didwehit {
-            to_return.push(concept);
-        }
+#[utoipa::path(
+    tag = "concept sets",
+    summary = "Analyze a concept set",
+    description = "Validates a concept set expression against the vocabulary and the rules of the profile, \
+        resolves it and recommends further concepts.",
+    responses(
+        (status = 200, description = "The analysis; problems with the concept set are reported in `errors` and \
+            `warnings`", body = ConceptSetAnalysis),
+        (status = 400, description = "The demo instance refuses concept sets above its size limit"),
+    )
+)]
+#[post("/api/conceptsets/analyze")]
+#[instrument(skip_all)]
+async fn analyze_concept_set(
+    request: Json<ConceptSetValidationRequest>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    info!("Received concept set analysis request");
+    let concept_set = &request.concept_set;
+    if let Some(response) = demo_size_limit(&state, concept_set) {
+        return Ok(response);
     }
 
-    // Sort by score descending and apply limit
-    to_return.sort_by(|a, b| {
-        b.score
-            .partial_cmp(&a.score)
-            .unwrap_or(std::cmp::Ordering::Equal)
-    });
-    if to_return.len() > limit as usize {
-        to_return.truncate(limit as usize);
-    }
-
-    Ok(Json(to_return))
-}
-
-async fn find_by_concept_name_lower(
-    client: &Qdrant,
-    concept_name_lower: String,
-    collection: &str,
-) -> Vec<RetrievedPoint> {
-    client
-        .scroll(
-            ScrollPointsBuilder::new(collection).filter(Filter::must([Condition {
-                condition_one_of: Some(ConditionOneOf::Field(qdrant::FieldCondition {
-                    key: "concept_name_lower".to_string(),
-                    r#match: Some(qdrant::Match {
-                        match_value: Some(concept_name_lower.to_string().into()),
-                    }),
-                    range: None,
-                    geo_bounding_box: None,
-                    geo_radius: None,
-                    values_count: None,
-                    geo_polygon: None,
-                    datetime_range: None,
-                    is_empty: None,
-                    is_null: None,
-                })),
-            }])),
-        )
-        .await
-        .unwrap()
-        .result
-}
-
-async fn retrieve_point_from_db(
-    client: &Qdrant,
-    points: Vec<PointId>,
-    collection: &str,
-) -> Vec<RetrievedPoint> {
-    client
-        .get_points(
-            GetPointsBuilder::new(collection, points)
-                .with_vectors(false)
-                .with_payload(true),
-        )
-        .await
-        .unwrap()
-        .result
+    let analysis_result = analyze_with_state(
+        &state,
+        concept_set,
+        request.profile,
+        validation::Progress::default(),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(analysis_result.to_json()))
 }
 
-async fn recommend(input: String, client: &Qdrant, limit: u64) -> Vec<ScoredPoint> {
-    let vector = fetch_embeddings(input).await.unwrap().embedding;
-    client
-        .search_points(SearchPointsBuilder::new(COLLECTION_NAME, vector, limit).with_payload(true))
-        .await
-        .unwrap()
-        .result
+/// The demo instance refuses to analyze concept sets above its size limit.
+fn demo_size_limit(state: &StateWrapper, concept_set: &str) -> Option<HttpResponse> {
+    demo_size_error(state, concept_set)
+        .map(|error| HttpResponse::BadRequest().json(serde_json::json!({ "error": error })))
 }
 
-fn filter_concepts(
-    concepts: Vec<crate::domain::Concept>,
-    parameters: &Parameters,
-) -> Vec<crate::domain::Concept> {
-    concepts
-        .into_iter()
-        .filter(|concept| {
-            // Filter by vocabulary_id
-            if let Some(vocab_ids) = &parameters.vocabulary_id {
-                if !vocab_ids
-                    .iter()
-                    .any(|id| id.eq_ignore_ascii_case(&concept.vocabulary_id))
-                {
-                    return false;
-                }
-            }
-
-            // Filter by standard_concept
-            if let Some(std_concept) = &parameters.standard_concept {
-                match concept.standard_concept.as_ref() {
-                    Some(sc) if sc == std_concept => {}
-                    None if std_concept.is_empty() => {}
-                    _ => return false,
-                }
-            }
-
-            // Filter by domain_id
-            if let Some(domain_ids) = &parameters.domain_id {
-                if !domain_ids
-                    .iter()
-                    .any(|id| id.eq_ignore_ascii_case(&concept.domain_id))
-                {
-                    return false;
-                }
-            }
-
-            // Filter by concept_class_id
-            if let Some(class_ids) = &parameters.concept_class_id {
-                if !class_ids
-                    .iter()
-                    .any(|id| id.eq_ignore_ascii_case(&concept.concept_class_id))
-                {
-                    return false;
-                }
-            }
+/// Why the demo instance refuses to analyze the concept set, if it does.
+pub(crate) fn demo_size_error(state: &StateWrapper, concept_set: &str) -> Option<String> {
+    if state.config.demo.enabled
+        && let Ok(expression) = validation::parse_concept_set(concept_set)
+        && expression.items.len() > state.config.demo.max_concept_set_items
+    {
+        return Some(format!(
+            "The demo instance analyzes concept sets of at most {} items",
+            state.config.demo.max_concept_set_items
+        ));
+    }
+    None
+}
 
-            true
-        })
-        .collect()
+fn server_sent_event(event: &str, data: &serde_json::Value) -> web::Bytes {
+    web::Bytes::from(format!("event: {}\ndata: {}\n\n", event, data))
 }
 
-#[post("/api/conceptsets/analyze")]
-async fn analyze_concept_set(
+/// The analyze endpoint as server-sent events: a `progress` event as each stage starts, so UIs
+/// can show what a large concept set is waiting on, then the analysis as a `result` event.
+#[utoipa::path(
+    tag = "concept sets",
+    summary = "Analyze a concept set with progress events",
+    description = "Server-sent events: `progress` with `stage` and `progress` (0 to 1) as each stage starts, \
+        then `result` with the analysis, or `error`.",
+    request_body = ConceptSetValidationRequest,
+    responses(
+        (status = 200, description = "The event stream", body = String, content_type = "text/event-stream"),
+        (status = 400, description = "The demo instance refuses concept sets above its size limit"),
+    )
+)]
+#[post("/api/conceptsets/analyze/stream")]
+async fn analyze_concept_set_stream(
     request: Json<ConceptSetValidationRequest>,
     state: Data<StateWrapper>,
 ) -> Result<HttpResponse, Error> {
-    info!("Received concept set analysis request");
-    let concept_set = &request.concept_set;
+    info!("Received streaming concept set analysis request");
+    if let Some(response) = demo_size_limit(&state, &request.concept_set) {
+        return Ok(response);
+    }
+    let request = request.into_inner();
+    let (events, receiver) = tokio::sync::mpsc::unbounded_channel();
+    actix_web::rt::spawn(async move {
+        let (progress, mut stages) = validation::Progress::channel();
+        let analysis = async {
+            let mut result =
+                analyze_with_state(&state, &request.concept_set, request.profile, progress).await?;
+            // Ends the progress events once the analysis is done
+            result.progress = validation::Progress::default();
+            Ok::<_, Error>(result)
+        };
+        let forward_stages = async {
+            while let Some(stage) = stages.recv().await {
+                let data = serde_json::json!({ "stage": stage, "progress": stage.fraction() });
+                // A client that went away only misses the events
+                let _ = events.send(server_sent_event("progress", &data));
+            }
+        };
+        let (outcome, _) = futures::join!(analysis, forward_stages);
+        let event = match outcome {
+            Ok(result) => server_sent_event("result", &result.to_json()),
+            Err(e) => server_sent_event("error", &serde_json::json!({ "error": e.to_string() })),
+        };
+        let _ = events.send(event);
+    });
+    let body = stream::unfold(receiver, |mut receiver| async move {
+        receiver
+            .recv()
+            .await
+            .map(|event| (Ok::<_, Error>(event), receiver))
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        // Keeps nginx from holding the events back until the analysis is done
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(body))
+}
 
+/// Analyzes a concept set against the current index snapshot and vocabulary, as the analyze
+/// endpoint, saved concept sets and validation jobs do.
+pub(crate) async fn analyze_with_state(
+    state: &StateWrapper,
+    concept_set: &str,
+    profile: ValidationProfile,
+    progress: validation::Progress,
+) -> Result<validation::ValidationResult, Error> {
     let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let recommendation_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
 
-    let analysis_result = validation::analyze_concept_set(
+    // Recommendations are the expensive part and give way first when search is over budget
+    let shed_recommendations = state.latency.shed_recommendations();
+    let snapshot = snapshot::current(state);
+    let mut analysis_result = validation::analyze_concept_set(
         concept_set,
         &pg_client,
-        Some(&state.qdrant_client),
-        Some(&state.concept_index),
+        &recommendation_client,
+        (!shed_recommendations).then_some(&state.qdrant_client),
+        &state.qdrant_read_options,
+        Some(&snapshot),
+        profile,
+        state.config.recommendation_budget(),
+        &state.recommendation_cache,
+        &state.metrics.recommendation_cache,
+        state.vocabulary_catalog.vocabulary_version.as_deref(),
+        progress,
     )
     .await
     .unwrap_or_else(|e| {
         let mut error_result = validation::ValidationResult::new();
+        error_result.profile = profile;
         error_result.add_error(format!("Database error during analysis: {}", e));
         error_result
     });
+    if shed_recommendations {
+        analysis_result.add_warning(SHED_RECOMMENDATIONS_WARNING.to_string());
+    }
+    Ok(analysis_result)
+}
 
-    Ok(HttpResponse::Ok().json(analysis_result.to_json()))
+#[utoipa::path(
+    tag = "concept sets",
+    summary = "List the validation profiles",
+    responses((status = 200, description = "The profiles", body = Vec<ValidationProfileSummary>))
+)]
+#[get("/api/conceptsets/profiles")]
+async fn get_validation_profiles() -> Result<HttpResponse, Error> {
+    let profiles: Vec<ValidationProfileSummary> = ValidationProfile::ALL
+        .into_iter()
+        .map(|profile| ValidationProfileSummary {
+            id: profile,
+            description: profile.description(),
+            rules: profile.rules(),
+            recommendation_domains: profile.expected_domains(),
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(profiles))
+}
+
+#[utoipa::path(
+    tag = "concept sets",
+    summary = "Import concept sets from CSV",
+    description = "Builds ATLAS concept set expressions from the CSV layouts of the R tooling around ATLAS, \
+        with the concept details filled in from the vocabulary.",
+    params(ConceptSetImportParameters),
+    request_body(content = String, content_type = "text/csv"),
+    responses(
+        (status = 200, description = "The concept sets found in the CSV", body = ImportResult),
+        (status = 400, description = "The CSV could not be read"),
+    )
+)]
+#[post("/api/conceptsets/import")]
+async fn import_concept_sets(
+    parameters: Query<ConceptSetImportParameters>,
+    body: String,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    info!("Received concept set CSV import request");
+    let parsed = match import::parse_csv(&body, parameters.layout) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+        }
+    };
+
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let imported = import::build_concept_sets(parsed, &pg_client).await?;
+    Ok(HttpResponse::Ok().json(imported))
+}
+
+#[utoipa::path(
+    tag = "concept sets",
+    summary = "Export a resolved concept set as codeset SQL",
+    responses(
+        (status = 200, description = "The SQL", body = CodesetSql),
+        (status = 400, description = "The concept set could not be parsed"),
+    )
+)]
+#[post("/api/conceptsets/export/sql")]
+async fn export_codeset_sql(
+    request: Json<CodesetSqlRequest>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    info!("Received codeset SQL export request");
+    let expression = match validation::parse_concept_set(&request.concept_set) {
+        Ok(expression) => expression,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+        }
+    };
+
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let mut result = validation::ValidationResult::new();
+    let resolved = expansions::resolve_concept_set(
+        &expression,
+        &pg_client,
+        state.vocabulary_catalog.vocabulary_version.as_deref(),
+        &mut result,
+    )
+    .await
+    .resolved_concept_ids();
+    if resolved.is_empty() {
+        result.add_warning("The concept set resolves to no concepts".to_string());
+    }
+
+    Ok(HttpResponse::Ok().json(CodesetSql {
+        dialect: request.options.dialect,
+        codeset_id: request.options.codeset_id,
+        concept_count: resolved.len(),
+        sql: render_codeset_sql(&request.options, &resolved),
+        warnings: result.warnings,
+    }))
+}
+
+/// The analysis as an Excel workbook with a sheet per section, the way coders review concept
+/// sets.
+#[utoipa::path(
+    tag = "concept sets",
+    summary = "Export a concept set analysis as an Excel workbook",
+    description = "The analysis with a sheet per section, the way coders review concept sets.",
+    responses(
+        (status = 200, description = "The workbook, as an attachment", body = Vec<u8>,
+            content_type = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+        (status = 400, description = "The concept set could not be parsed, the body is the analysis", body = ConceptSetAnalysis),
+    )
+)]
+#[post("/api/conceptsets/export/xlsx")]
+#[instrument(skip_all)]
+async fn export_concept_set_xlsx(
+    request: Json<ConceptSetValidationRequest>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    info!("Received concept set workbook export request");
+    if let Some(response) = demo_size_limit(&state, &request.concept_set) {
+        return Ok(response);
+    }
+    let analysis_result = analyze_with_state(
+        &state,
+        &request.concept_set,
+        request.profile,
+        validation::Progress::default(),
+    )
+    .await?;
+    // Without a summary the concept set did not parse, there is nothing to put on the sheets
+    if analysis_result.concept_summary.is_none() {
+        return Ok(HttpResponse::BadRequest().json(analysis_result.to_json()));
+    }
+
+    let concept_ids: BTreeSet<i32> = analysis_result
+        .concept_summary
+        .iter()
+        .flat_map(|summary| {
+            [
+                &summary.included_concepts,
+                &summary.included_descendants,
+                &summary.included_mapped,
+                &summary.excluded_concepts,
+                &summary.excluded_descendants,
+                &summary.excluded_mapped,
+            ]
+        })
+        .flatten()
+        .copied()
+        .collect();
+    let concept_ids: Vec<i32> = concept_ids.into_iter().collect();
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let concepts: HashMap<i32, Concept> = db::get_concepts_by_ids(&pg_client, &concept_ids)
+        .await?
+        .into_iter()
+        .map(|concept| (concept.concept_id, concept))
+        .collect();
+
+    let workbook = match workbook::render(&analysis_result, &concepts) {
+        Ok(workbook) => workbook,
+        Err(e) => {
+            warn!("Could not render concept set workbook: {}", e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Could not render the workbook: {}", e)
+            })));
+        }
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(workbook::XLSX)
+        .insert_header((
+            actix_web::http::header::CONTENT_DISPOSITION,
+            "attachment; filename=\"concept_set.xlsx\"",
+        ))
+        .body(workbook))
+}
+
+/// The concepts a concept set resolves to, with the same expansion as analysis, for ETL code
+/// that needs the codeset itself rather than counts.
+#[utoipa::path(
+    tag = "concept sets",
+    summary = "Resolve a concept set",
+    description = "The concepts a concept set resolves to, with the descendant and mapped expansion of the \
+        analysis, for ETL code that needs the codeset itself.",
+    params(FormatParameter),
+    responses(
+        (status = 200, description = "A page of the resolved concepts, or with Accept `application/x-ndjson` or a \
+            `format` one concept per line with the total in `X-Total-Count`",
+            headers(("X-Expansion-Hash" = String, description = "Address of the stored expansion, with NDJSON and \
+                delimited output")),
+            content(
+                (ResolvedConceptSet = "application/json"),
+                (ResolvedConcept = "application/x-ndjson"),
+                (String = "text/csv"),
+                (String = "text/tab-separated-values"),
+            )
+        ),
+        (status = 400, description = "The concept set could not be parsed"),
+        (status = 500, description = "Line-per-concept output of a concept set that could not be resolved completely"),
+    )
+)]
+#[post("/api/conceptsets/resolve")]
+async fn resolve_concept_set(
+    req: HttpRequest,
+    request: Json<ConceptSetResolveRequest>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    info!("Received concept set resolve request");
+    let expression = match validation::parse_concept_set(&request.concept_set) {
+        Ok(expression) => expression,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+        }
+    };
+
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let mut result = validation::ValidationResult::new();
+    let resolved = expansions::resolve_concept_set(
+        &expression,
+        &pg_client,
+        state.vocabulary_catalog.vocabulary_version.as_deref(),
+        &mut result,
+    )
+    .await
+    .resolved_concept_ids();
+    let lookups_failed = !result.warnings.is_empty();
+    if resolved.is_empty() {
+        result.add_warning("The concept set resolves to no concepts".to_string());
+    }
+
+    let total = resolved.len() as u64;
+    let limit = state
+        .config
+        .demo
+        .clamp_limit(request.limit.unwrap_or(total));
+    let page_start = request.offset.min(total);
+    let page_end = page_start.saturating_add(limit).min(total);
+    let page = &resolved[page_start as usize..page_end as usize];
+    let format = tabular::requested(&req);
+    if ndjson::accepted(&req) || format.is_some() {
+        // A line-per-concept codeset has no place for warnings, and a partial one must not
+        // pass for complete
+        if lookups_failed {
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "The concept set could not be resolved completely",
+                "warnings": result.warnings,
+            })));
+        }
+        let mut response = HttpResponse::Ok();
+        response.insert_header(("X-Total-Count", total));
+        if let Some(expansion_hash) = &result.expansion_hash {
+            response.insert_header(("X-Expansion-Hash", expansion_hash.as_str()));
+        }
+        let rows = resolved_rows(page, pg_client);
+        return Ok(match format {
+            Some(format) => tabular::respond(response, format, tabular::RESOLVED_COLUMNS, rows),
+            None => ndjson::respond(response, rows),
+        });
+    }
+    let concepts = resolved_concepts(db::get_concepts_by_ids(&pg_client, page).await?);
+
+    Ok(HttpResponse::Ok().json(ResolvedConceptSet {
+        total,
+        offset: page_start,
+        has_more: page_end < total,
+        concepts,
+        expansion_hash: result.expansion_hash,
+        warnings: result.warnings,
+    }))
+}
+
+/// The resolved concepts a chunk of IDs at a time, so large codesets are never held at once.
+fn resolved_rows(
+    concept_ids: &[i32],
+    pg_client: deadpool_postgres::Client,
+) -> impl futures::Stream<Item = Result<ResolvedConcept, PgError>> + 'static {
+    let pg_client = Rc::new(pg_client);
+    let chunks: Vec<Vec<i32>> = concept_ids
+        .chunks(RESOLVED_ROWS_PER_QUERY)
+        .map(<[i32]>::to_vec)
+        .collect();
+    stream::iter(chunks)
+        .then(move |chunk| {
+            let pg_client = pg_client.clone();
+            async move { db::get_concepts_by_ids(&pg_client, &chunk).await }
+        })
+        .map_ok(|concepts| stream::iter(resolved_concepts(concepts).into_iter().map(Ok)))
+        .try_flatten()
+}
+
+pub(crate) fn resolved_concepts(concepts: Vec<Concept>) -> Vec<ResolvedConcept> {
+    let mut concepts: Vec<ResolvedConcept> = concepts
+        .into_iter()
+        .map(|concept| ResolvedConcept {
+            concept_id: concept.concept_id,
+            concept_name: concept.concept_name,
+            vocabulary_id: concept.vocabulary_id,
+            domain_id: concept.domain_id,
+        })
+        .collect();
+    concepts.sort_by_key(|concept| concept.concept_id);
+    concepts
+}
+
+/// Resolves two versions of a concept set and lists the concepts the revision adds and removes,
+/// with the Jaccard overlap of the two resolved sets.
+#[utoipa::path(
+    tag = "concept sets",
+    summary = "Compare two versions of a concept set",
+    description = "Resolves both versions and lists the concepts the revision adds and removes, with the \
+        Jaccard overlap of the two resolved sets.",
+    responses(
+        (status = 200, description = "The concepts the revision adds and removes", body = ConceptSetComparison),
+        (status = 400, description = "A concept set could not be parsed"),
+    )
+)]
+#[post("/api/conceptsets/compare")]
+async fn compare_concept_sets(
+    request: Json<ConceptSetCompareRequest>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    info!("Received concept set comparison request");
+    let (base, revised) = match (
+        validation::parse_concept_set(&request.base),
+        validation::parse_concept_set(&request.revised),
+    ) {
+        (Ok(base), Ok(revised)) => (base, revised),
+        (Err(e), _) | (_, Err(e)) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+        }
+    };
+
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let vocabulary_version = state.vocabulary_catalog.vocabulary_version.as_deref();
+    let mut base_result = validation::ValidationResult::new();
+    let base_ids: BTreeSet<i32> =
+        expansions::resolve_concept_set(&base, &pg_client, vocabulary_version, &mut base_result)
+            .await
+            .resolved_concept_ids()
+            .into_iter()
+            .collect();
+    let mut revised_result = validation::ValidationResult::new();
+    let revised_ids: BTreeSet<i32> = expansions::resolve_concept_set(
+        &revised,
+        &pg_client,
+        vocabulary_version,
+        &mut revised_result,
+    )
+    .await
+    .resolved_concept_ids()
+    .into_iter()
+    .collect();
+
+    let added: Vec<i32> = revised_ids.difference(&base_ids).copied().collect();
+    let removed: Vec<i32> = base_ids.difference(&revised_ids).copied().collect();
+    let shared: Vec<i32> = base_ids.intersection(&revised_ids).copied().collect();
+    let union = base_ids.union(&revised_ids).count();
+    let jaccard = if union == 0 {
+        1.0
+    } else {
+        shared.len() as f64 / union as f64
+    };
+    let added = resolved_concepts(db::get_concepts_by_ids(&pg_client, &added).await?);
+    let removed = resolved_concepts(db::get_concepts_by_ids(&pg_client, &removed).await?);
+    let warnings: Vec<String> = base_result
+        .warnings
+        .iter()
+        .map(|warning| format!("Base: {}", warning))
+        .chain(
+            revised_result
+                .warnings
+                .iter()
+                .map(|warning| format!("Revised: {}", warning)),
+        )
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ConceptSetComparison {
+        base_count: base_ids.len(),
+        revised_count: revised_ids.len(),
+        added,
+        removed,
+        shared,
+        jaccard,
+        warnings,
+    }))
 }
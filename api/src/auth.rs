@@ -0,0 +1,307 @@
+use crate::config::{AuthConfig, Scope};
+use crate::domain::ApiKey;
+use crate::errors::PgError;
+use crate::oidc;
+use crate::rate_limit;
+use crate::{StateWrapper, db};
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::http::header::{AUTHORIZATION, HeaderName, WWW_AUTHENTICATE};
+use actix_web::middleware::Next;
+use actix_web::web::{Data, Json};
+use actix_web::{Error, HttpMessage, HttpResponse, delete, get, post, web};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+pub const API_KEY_HEADER: HeaderName = HeaderName::from_static("x-api-key");
+
+/// Generated keys are recognizable in logs and secret scanners by this prefix.
+const KEY_PREFIX: &str = "hk_";
+
+/// Routes answered without a key: health checks, Prometheus scrapes and the API documentation.
+const PUBLIC_PATHS: &[&str] = &[
+    "/api/health",
+    "/api/ready",
+    "/metrics",
+    "/api/metrics",
+    "/api/docs",
+    "/api/openapi.json",
+    "/api/openapi.yaml",
+];
+
+/// Swagger UI and its bundled assets, public like the specification they render.
+const DOCS_PREFIX: &str = "/api/docs/";
+
+/// The caller of an authenticated request, available to handlers as a request extension.
+#[derive(Debug, Clone, Serialize)]
+pub struct Identity {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+impl Identity {
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&Scope::Admin) || self.scopes.contains(&scope)
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CreateApiKeyRequest {
+    name: String,
+    scopes: Vec<Scope>,
+}
+
+/// A created key. `key` is not shown again, only its hash is stored.
+#[derive(Serialize, ToSchema)]
+struct CreatedApiKey {
+    id: i64,
+    name: String,
+    scopes: Vec<String>,
+    created_at: DateTime<Utc>,
+    key: String,
+}
+
+/// Health checks and documentation, answered for anyone and exempt from rate limits.
+pub fn is_public(path: &str) -> bool {
+    PUBLIC_PATHS.contains(&path) || path.starts_with(DOCS_PREFIX)
+}
+
+/// The scope a route needs, `None` for public routes.
+fn required_scope(path: &str) -> Option<Scope> {
+    if is_public(path) {
+        None
+    } else if path.starts_with("/api/admin") {
+        Some(Scope::Admin)
+    } else if path.starts_with("/api/conceptsets")
+        || path.starts_with("/api/expand")
+        || path.starts_with("/api/validate")
+        || path.starts_with("/api/recommendations/feedback")
+        || path.starts_with("/api/mapping")
+    {
+        Some(Scope::Validate)
+    } else {
+        Some(Scope::Read)
+    }
+}
+
+fn hash_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn generate_key() -> String {
+    format!(
+        "{}{}{}",
+        KEY_PREFIX,
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+/// The key sent as `Authorization: Bearer <key>` or `X-API-Key: <key>`, or an OIDC token sent
+/// as the bearer.
+fn presented_key(req: &ServiceRequest) -> Option<String> {
+    let headers = req.headers();
+    if let Some(key) = headers.get(API_KEY_HEADER) {
+        return key.to_str().ok().map(str::to_string);
+    }
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|key| key.trim().to_string())
+}
+
+/// Finds the key among the configured ones, then among the active keys in `hecate.api_key`.
+async fn authenticate(state: &StateWrapper, key: &str) -> Result<Option<Identity>, PgError> {
+    let key_hash = hash_key(key);
+    let configured = state
+        .config
+        .auth
+        .api_keys
+        .iter()
+        .find(|configured| hash_key(&configured.key) == key_hash);
+    if let Some(configured) = configured {
+        return Ok(Some(Identity {
+            name: configured.name.clone(),
+            scopes: configured.scopes.clone(),
+        }));
+    }
+    let pg_client = state.pg_pool.get().await?;
+    let stored = db::use_api_key(&pg_client, &key_hash).await?;
+    Ok(stored.map(|stored| Identity {
+        name: stored.name,
+        scopes: stored
+            .scopes
+            .iter()
+            .filter_map(|scope| Scope::parse(scope))
+            .collect(),
+    }))
+}
+
+/// The caller an API key or OIDC token belongs to, or why it is not accepted.
+pub async fn identify(
+    state: &StateWrapper,
+    key: &str,
+) -> Result<Result<Identity, String>, PgError> {
+    if let Some(verifier) = &state.oidc
+        && oidc::looks_like_jwt(key)
+    {
+        return Ok(verifier.verify(key).await.map_err(|e| e.to_string()));
+    }
+    Ok(authenticate(state, key)
+        .await?
+        .ok_or_else(|| "Unknown or revoked API key".to_string()))
+}
+
+/// Answers 401, or 429 once the client's address has failed authentication more often than its
+/// rate limit allows.
+fn unauthorized(state: &StateWrapper, req: ServiceRequest, error: &str) -> ServiceResponse {
+    if let Some(response) = rate_limit::throttle_unauthenticated(state, &req) {
+        return req.into_response(response);
+    }
+    let response = HttpResponse::Unauthorized()
+        .insert_header((WWW_AUTHENTICATE, "Bearer"))
+        .json(serde_json::json!({ "error": error }));
+    req.into_response(response)
+}
+
+/// Middleware requiring an API key or OIDC token with the route's scope when `AUTH__ENABLED` is
+/// set.
+pub async fn require_auth(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let state = match req.app_data::<Data<StateWrapper>>() {
+        Some(state) if state.config.auth.enabled => state.clone(),
+        _ => return next.call(req).await.map(|res| res.map_into_boxed_body()),
+    };
+    let scope = match required_scope(req.path()) {
+        Some(scope) if req.method() != Method::OPTIONS => scope,
+        _ => return next.call(req).await.map(|res| res.map_into_boxed_body()),
+    };
+    let Some(key) = presented_key(&req) else {
+        return Ok(unauthorized(
+            &state,
+            req,
+            "An API key or bearer token is required",
+        ));
+    };
+    let identity = match identify(&state, &key).await? {
+        Ok(identity) => identity,
+        Err(reason) => {
+            info!("Rejecting {} {}: {}", req.method(), req.path(), reason);
+            return Ok(unauthorized(&state, req, &reason));
+        }
+    };
+    if !identity.has_scope(scope) {
+        info!(
+            "Rejecting {} {} for {}: missing scope {}",
+            req.method(),
+            req.path(),
+            identity.name,
+            scope.as_str()
+        );
+        let response = HttpResponse::Forbidden().json(serde_json::json!({
+            "error": format!("This endpoint requires the {} scope", scope.as_str())
+        }));
+        return Ok(req.into_response(response));
+    }
+    req.extensions_mut().insert(identity);
+    next.call(req).await.map(|res| res.map_into_boxed_body())
+}
+
+/// Logs how authentication is set up, warning when no key or token could reach the admin API.
+pub fn log_configuration(config: &AuthConfig) {
+    if !config.enabled {
+        return;
+    }
+    info!(
+        "Authentication required, {} API keys configured in the environment",
+        config.api_keys.len()
+    );
+    if config.oidc.issuer.is_none()
+        && !config
+            .api_keys
+            .iter()
+            .any(|key| key.scopes.contains(&Scope::Admin))
+    {
+        warn!("No admin API key is configured, only keys already stored in hecate.api_key work");
+    }
+}
+
+/// The keys stored in the database, without their hashes. Configured keys are not listed.
+#[utoipa::path(
+    tag = "admin",
+    summary = "List API keys",
+    description = "Keys stored in the database, without their hashes. Keys configured in the environment are \
+        not listed.",
+    responses((status = 200, description = "Stored API keys", body = Vec<ApiKey>))
+)]
+#[get("/api/admin/api-keys")]
+async fn list_api_keys(state: Data<StateWrapper>) -> Result<HttpResponse, Error> {
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let keys = db::get_api_keys(&pg_client).await?;
+    Ok(HttpResponse::Ok().json(keys))
+}
+
+/// Creates a key and returns it. This is the only time the key is shown, only its hash is kept.
+#[utoipa::path(
+    tag = "admin",
+    summary = "Create an API key",
+    description = "Returns the new key. It is not shown again, only its hash is stored.",
+    responses(
+        (status = 201, description = "The created key", body = CreatedApiKey),
+        (status = 400, description = "Missing name or scopes"),
+    )
+)]
+#[post("/api/admin/api-keys")]
+async fn create_api_key(
+    request: Json<CreateApiKeyRequest>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let name = request.name.trim();
+    if name.is_empty() || request.scopes.is_empty() {
+        return Ok(HttpResponse::BadRequest()
+            .json(serde_json::json!({ "error": "A name and at least one scope are required" })));
+    }
+    info!("Creating API key {:?}", name);
+    let key = generate_key();
+    let scopes: Vec<&str> = request.scopes.iter().map(Scope::as_str).collect();
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let stored = db::insert_api_key(&pg_client, name, &hash_key(&key), &scopes).await?;
+    Ok(HttpResponse::Created().json(CreatedApiKey {
+        id: stored.id,
+        name: stored.name,
+        scopes: stored.scopes,
+        created_at: stored.created_at,
+        key,
+    }))
+}
+
+#[utoipa::path(
+    tag = "admin",
+    summary = "Revoke an API key",
+    params(("id" = i64, Path, description = "API key id")),
+    responses(
+        (status = 204, description = "Revoked"),
+        (status = 404, description = "No active key with this id"),
+    )
+)]
+#[delete("/api/admin/api-keys/{id}")]
+async fn revoke_api_key(
+    path: web::Path<i64>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    info!("Revoking API key {}", id);
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    db::revoke_api_key(&pg_client, id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,67 @@
+use crate::StateWrapper;
+use crate::snapshot;
+use actix_web::web::{Data, Query};
+use actix_web::{Error, HttpResponse, get};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+const DEFAULT_SUGGESTIONS: usize = 10;
+const MAX_SUGGESTIONS: usize = 50;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct AutocompleteParameters {
+    /// The typed prefix, case-insensitive.
+    q: String,
+    #[param(default = 10, maximum = 50)]
+    limit: Option<usize>,
+}
+
+/// A concept name or synonym, lowercase as the concept index stores it. Searching it returns
+/// the concepts.
+#[derive(Debug, Serialize, ToSchema)]
+struct Suggestion {
+    name: String,
+}
+
+/// Names and synonyms starting with `q`, shortest first, looked up in the concept index without
+/// touching the vector store or the database.
+#[utoipa::path(
+    tag = "search",
+    summary = "Suggest concept names as the user types",
+    description = "Concept names and synonyms starting with `q`, shortest first, read from the in-memory \
+        concept index. Meant to be called on every keystroke; pass the chosen suggestion to /api/search for \
+        the concepts. Matching is on the start of the name only.",
+    params(AutocompleteParameters),
+    responses(
+        (status = 200, description = "Suggestions, empty for an empty prefix", body = Vec<Suggestion>),
+        (status = 503, description = "The concept index is still loading"),
+    )
+)]
+#[get("/api/autocomplete")]
+async fn autocomplete(
+    parameters: Query<AutocompleteParameters>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let prefix = parameters.q.trim_start().to_lowercase();
+    if prefix.is_empty() {
+        return Ok(HttpResponse::Ok().json(Vec::<Suggestion>::new()));
+    }
+    let limit = parameters
+        .limit
+        .unwrap_or(DEFAULT_SUGGESTIONS)
+        .clamp(1, MAX_SUGGESTIONS);
+
+    let snapshot = snapshot::current(&state);
+    let Some(concept_index) = snapshot.concept_index() else {
+        return Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "The concept index is not loaded yet"
+        })));
+    };
+    let suggestions: Vec<Suggestion> = concept_index
+        .shortest_names_with_prefix(&prefix, limit)
+        .into_iter()
+        .map(|name| Suggestion { name })
+        .collect();
+    Ok(HttpResponse::Ok().json(suggestions))
+}
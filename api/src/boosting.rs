@@ -0,0 +1,142 @@
+use crate::StateWrapper;
+use crate::domain::{Concept, SearchResponse};
+use actix_web::web::Data;
+use actix_web::{Error, HttpResponse, get, post};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::error::Error as StdError;
+use std::fs;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+/// A score adjustment applied to every search result containing a concept that matches all of
+/// the rule's conditions. Conditions left out match anything.
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct BoostingRule {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub vocabulary_id: Option<String>,
+    pub domain_id: Option<String>,
+    pub concept_class_id: Option<String>,
+    /// `S`, `C`, or an empty string for non-standard concepts.
+    pub standard_concept: Option<String>,
+    /// Match concepts that are (or are not) invalid.
+    pub invalid: Option<bool>,
+    pub boost: f64,
+}
+
+impl BoostingRule {
+    fn matches(&self, concept: &Concept) -> bool {
+        let matches_field = |rule: &Option<String>, value: &str| {
+            rule.as_ref().is_none_or(|r| r.eq_ignore_ascii_case(value))
+        };
+        matches_field(&self.vocabulary_id, &concept.vocabulary_id)
+            && matches_field(&self.domain_id, &concept.domain_id)
+            && matches_field(&self.concept_class_id, &concept.concept_class_id)
+            && matches_field(
+                &self.standard_concept,
+                concept.standard_concept.as_deref().unwrap_or_default(),
+            )
+            && self
+                .invalid
+                .is_none_or(|invalid| invalid == concept.invalid_reason.is_some())
+    }
+}
+
+/// Ranking adjustments loaded from the file named by `BOOSTING_RULES_PATH`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, ToSchema)]
+pub struct BoostingRules {
+    #[serde(default)]
+    pub rules: Vec<BoostingRule>,
+}
+
+impl BoostingRules {
+    pub fn load(path: Option<&str>) -> Result<BoostingRules, Box<dyn StdError>> {
+        let Some(path) = path else {
+            return Ok(BoostingRules::default());
+        };
+        let rules: BoostingRules = serde_json::from_str(&fs::read_to_string(path)?)?;
+        info!("{} boosting rules loaded from {}", rules.rules.len(), path);
+        Ok(rules)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn StdError>> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        info!("{} boosting rules written to {}", self.rules.len(), path);
+        Ok(())
+    }
+
+    fn concept_boost(&self, concept: &Concept) -> f64 {
+        self.rules
+            .iter()
+            .filter(|rule| rule.matches(concept))
+            .map(|rule| rule.boost)
+            .sum()
+    }
+
+    /// Adjusts the score of each result by the boost of its best matching concept.
+    pub fn apply(&self, results: &mut [SearchResponse]) {
+        if self.rules.is_empty() {
+            return;
+        }
+        for result in results.iter_mut() {
+            let boost = result
+                .concepts
+                .iter()
+                .map(|concept| self.concept_boost(concept))
+                .reduce(f64::max)
+                .unwrap_or_default();
+            if let Some(score) = result.score.as_mut() {
+                *score += boost;
+            }
+        }
+    }
+}
+
+pub fn current_rules(state: &StateWrapper) -> Arc<BoostingRules> {
+    state.boosting_rules.read().unwrap().clone()
+}
+
+/// The number of rules a reload loaded.
+#[derive(Serialize, ToSchema)]
+struct ReloadedRules {
+    rules: usize,
+}
+
+#[utoipa::path(
+    tag = "admin",
+    summary = "Get the boosting rules",
+    description = "The ranking adjustments applied to search results, as loaded from `BOOSTING_RULES_PATH`.",
+    responses((status = 200, description = "The active boosting rules", body = BoostingRules))
+)]
+#[get("/api/admin/boosting")]
+async fn get_boosting_rules(state: Data<StateWrapper>) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok().json(current_rules(&state).as_ref()))
+}
+
+/// Re-reads the rules file so ranking can be tuned without a restart.
+#[utoipa::path(
+    tag = "admin",
+    summary = "Reload the boosting rules",
+    description = "Re-reads `BOOSTING_RULES_PATH` and clears the search caches, so ranking can be tuned \
+        without a restart.",
+    responses(
+        (status = 200, description = "The number of rules loaded", body = ReloadedRules),
+        (status = 400, description = "The rules file could not be loaded"),
+    )
+)]
+#[post("/api/admin/boosting/reload")]
+async fn reload_boosting_rules(state: Data<StateWrapper>) -> Result<HttpResponse, Error> {
+    match BoostingRules::load(state.config.boosting_rules_path.as_deref()) {
+        Ok(rules) => {
+            let loaded = rules.rules.len();
+            *state.boosting_rules.write().unwrap() = Arc::new(rules);
+            state.search_cache.invalidate_all();
+            state.shared_cache.invalidate_searches().await;
+            Ok(HttpResponse::Ok().json(ReloadedRules { rules: loaded }))
+        }
+        Err(e) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Could not load boosting rules: {}", e)
+        }))),
+    }
+}
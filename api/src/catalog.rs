@@ -0,0 +1,302 @@
+use crate::StateWrapper;
+use crate::db;
+use crate::domain::{ConceptClass, Domain, Vocabulary};
+use crate::errors::{ApiError, PgError};
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::web::Data;
+use actix_web::{Error, HttpResponse, get};
+use deadpool_postgres::Pool;
+use log::info;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use utoipa::ToSchema;
+
+/// The vocabularies, domains and concept classes present in the loaded CDM with their concept
+/// counts, read once at startup. Used for the listing endpoints and to reject search filters
+/// that can never match.
+#[derive(Debug, Default)]
+pub struct VocabularyCatalog {
+    pub vocabularies: Vec<Vocabulary>,
+    pub domains: Vec<Domain>,
+    pub concept_classes: Vec<ConceptClass>,
+    pub vocabulary_counts: HashMap<String, i64>,
+    pub domain_counts: HashMap<String, i64>,
+    pub concept_class_counts: HashMap<String, i64>,
+    /// Identifies the vocabulary release, `None` when the catalog could not be loaded.
+    pub vocabulary_version: Option<String>,
+}
+
+pub const VOCABULARY_VERSION_HEADER: HeaderName = HeaderName::from_static("x-vocab-version");
+
+/// The release recorded on the `None` vocabulary, as Athena downloads carry it. Otherwise a
+/// digest over all vocabulary versions, which changes whenever any of them does.
+pub fn release_version(vocabularies: &[Vocabulary]) -> Option<String> {
+    if vocabularies.is_empty() {
+        return None;
+    }
+    if let Some(version) = vocabularies
+        .iter()
+        .find(|v| v.vocabulary_id == "None")
+        .and_then(|v| v.vocabulary_version.clone())
+    {
+        return Some(version);
+    }
+    let mut versions: Vec<String> = vocabularies
+        .iter()
+        .map(|v| {
+            format!(
+                "{}={}",
+                v.vocabulary_id,
+                v.vocabulary_version.as_deref().unwrap_or_default()
+            )
+        })
+        .collect();
+    versions.sort();
+    let digest: String = Sha256::digest(versions.join(";").as_bytes())
+        .iter()
+        .take(8)
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    Some(format!("digest:{}", digest))
+}
+
+impl VocabularyCatalog {
+    pub async fn load(pg_pool: &Pool) -> Result<VocabularyCatalog, PgError> {
+        let pg_client = pg_pool.get().await?;
+        let vocabularies = db::get_vocabularies(&pg_client).await?;
+        let domains = db::get_domains(&pg_client).await?;
+        let concept_classes = db::get_concept_classes(&pg_client).await?;
+        let mut vocabulary_counts = HashMap::new();
+        let mut domain_counts = HashMap::new();
+        let mut concept_class_counts = HashMap::new();
+        for count in db::get_concept_counts(&pg_client).await? {
+            *vocabulary_counts.entry(count.vocabulary_id).or_default() += count.concept_count;
+            *domain_counts.entry(count.domain_id).or_default() += count.concept_count;
+            *concept_class_counts
+                .entry(count.concept_class_id)
+                .or_default() += count.concept_count;
+        }
+        info!(
+            "{} vocabularies, {} domains and {} concept classes loaded",
+            vocabularies.len(),
+            domains.len(),
+            concept_classes.len()
+        );
+        let vocabulary_version = release_version(&vocabularies);
+        info!("Vocabulary version {:?}", vocabulary_version);
+        Ok(VocabularyCatalog {
+            vocabularies,
+            domains,
+            concept_classes,
+            vocabulary_counts,
+            domain_counts,
+            concept_class_counts,
+            vocabulary_version,
+        })
+    }
+
+    pub fn validate_vocabulary_ids(&self, values: Option<&[String]>) -> Result<(), ApiError> {
+        let known: Vec<&str> = self
+            .vocabularies
+            .iter()
+            .map(|v| v.vocabulary_id.as_str())
+            .collect();
+        validate("vocabulary_id", values, &known)
+    }
+
+    pub fn validate_domain_ids(&self, values: Option<&[String]>) -> Result<(), ApiError> {
+        let known: Vec<&str> = self.domains.iter().map(|d| d.domain_id.as_str()).collect();
+        validate("domain_id", values, &known)
+    }
+
+    /// The filter values as the vocabulary spells them, for lookups that match case-sensitively
+    /// such as Qdrant payload filters. `None` when the catalog is not loaded or a value is not
+    /// in it, the caller then has to filter case-insensitively itself.
+    pub fn canonical_vocabulary_ids(&self, values: &[String]) -> Option<Vec<String>> {
+        canonicalize(
+            values,
+            self.vocabularies.iter().map(|v| v.vocabulary_id.as_str()),
+        )
+    }
+
+    pub fn canonical_domain_ids(&self, values: &[String]) -> Option<Vec<String>> {
+        canonicalize(values, self.domains.iter().map(|d| d.domain_id.as_str()))
+    }
+
+    pub fn canonical_concept_class_ids(&self, values: &[String]) -> Option<Vec<String>> {
+        canonicalize(
+            values,
+            self.concept_classes
+                .iter()
+                .map(|c| c.concept_class_id.as_str()),
+        )
+    }
+}
+
+fn canonicalize<'a>(
+    values: &[String],
+    known: impl Iterator<Item = &'a str> + Clone,
+) -> Option<Vec<String>> {
+    values
+        .iter()
+        .map(|value| {
+            known
+                .clone()
+                .find(|k| k.eq_ignore_ascii_case(value))
+                .map(str::to_string)
+        })
+        .collect()
+}
+
+/// Filters match case-insensitively, so validation does too. An empty catalog means it could
+/// not be loaded and nothing is rejected.
+fn validate(
+    parameter: &'static str,
+    values: Option<&[String]>,
+    known: &[&str],
+) -> Result<(), ApiError> {
+    let Some(values) = values else {
+        return Ok(());
+    };
+    if known.is_empty() {
+        return Ok(());
+    }
+    let invalid: Vec<String> = values
+        .iter()
+        .filter(|value| !known.iter().any(|k| k.eq_ignore_ascii_case(value)))
+        .cloned()
+        .collect();
+    if invalid.is_empty() {
+        return Ok(());
+    }
+    Err(ApiError::InvalidFilter {
+        parameter,
+        invalid,
+        valid: known.iter().map(|k| k.to_string()).collect(),
+    })
+}
+
+/// A listed vocabulary, domain or concept class with the number of concepts in it.
+#[derive(Serialize, ToSchema)]
+struct Counted<'a, T> {
+    #[serde(flatten)]
+    item: &'a T,
+    concept_count: i64,
+}
+
+fn with_counts<'a, T>(
+    items: &'a [T],
+    counts: &HashMap<String, i64>,
+    id: impl Fn(&T) -> &str,
+) -> Vec<Counted<'a, T>> {
+    items
+        .iter()
+        .map(|item| Counted {
+            concept_count: counts.get(id(item)).copied().unwrap_or_default(),
+            item,
+        })
+        .collect()
+}
+
+/// The loaded vocabulary release with the API version and the version of each vocabulary.
+#[derive(Serialize, ToSchema)]
+struct Versions<'a> {
+    #[schema(example = "v5.0 31-AUG-24")]
+    vocabulary_version: Option<&'a str>,
+    api_version: &'static str,
+    vocabularies: BTreeMap<&'a str, Option<&'a str>>,
+}
+
+#[utoipa::path(
+    tag = "vocabularies",
+    summary = "List vocabularies",
+    description = "Vocabularies in the loaded CDM, valid values for the `vocabulary_id` search filter.",
+    responses((status = 200, description = "Available vocabularies", body = [Counted<Vocabulary>]))
+)]
+#[get("/api/vocabularies")]
+async fn list_vocabularies(state: Data<StateWrapper>) -> Result<HttpResponse, Error> {
+    let catalog = &state.vocabulary_catalog;
+    Ok(HttpResponse::Ok().json(with_counts(
+        &catalog.vocabularies,
+        &catalog.vocabulary_counts,
+        |vocabulary| &vocabulary.vocabulary_id,
+    )))
+}
+
+#[utoipa::path(
+    tag = "vocabularies",
+    summary = "List domains",
+    description = "Domains in the loaded CDM, valid values for the `domain_id` search filter.",
+    responses((status = 200, description = "Available domains", body = [Counted<Domain>]))
+)]
+#[get("/api/domains")]
+async fn list_domains(state: Data<StateWrapper>) -> Result<HttpResponse, Error> {
+    let catalog = &state.vocabulary_catalog;
+    Ok(HttpResponse::Ok().json(with_counts(
+        &catalog.domains,
+        &catalog.domain_counts,
+        |domain| &domain.domain_id,
+    )))
+}
+
+#[utoipa::path(
+    tag = "vocabularies",
+    summary = "List concept classes",
+    description = "Concept classes in the loaded CDM with their concept counts, valid values for the \
+        `concept_class_id` search filter.",
+    responses((status = 200, description = "Available concept classes", body = [Counted<ConceptClass>]))
+)]
+#[get("/api/concept-classes")]
+async fn list_concept_classes(state: Data<StateWrapper>) -> Result<HttpResponse, Error> {
+    let catalog = &state.vocabulary_catalog;
+    Ok(HttpResponse::Ok().json(with_counts(
+        &catalog.concept_classes,
+        &catalog.concept_class_counts,
+        |concept_class| &concept_class.concept_class_id,
+    )))
+}
+
+/// The loaded vocabulary release, as in the `X-Vocab-Version` header, and the versions of the
+/// individual vocabularies.
+#[utoipa::path(
+    tag = "vocabularies",
+    summary = "Get the vocabulary version",
+    description = "The loaded vocabulary release, also sent on every response as the `X-Vocab-Version` header, \
+        with the API version and the version of each vocabulary.",
+    responses((status = 200, description = "Versions", body = Versions))
+)]
+#[get("/api/version")]
+async fn get_version(state: Data<StateWrapper>) -> Result<HttpResponse, Error> {
+    let catalog = &state.vocabulary_catalog;
+    let vocabularies: BTreeMap<&str, Option<&str>> = catalog
+        .vocabularies
+        .iter()
+        .map(|v| (v.vocabulary_id.as_str(), v.vocabulary_version.as_deref()))
+        .collect();
+    Ok(HttpResponse::Ok().json(Versions {
+        vocabulary_version: catalog.vocabulary_version.as_deref(),
+        api_version: env!("CARGO_PKG_VERSION"),
+        vocabularies,
+    }))
+}
+
+/// Middleware adding the vocabulary release to every response, so clients can tell when results
+/// come from a newer vocabulary than the ones they kept.
+pub async fn vocabulary_version_header(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let version = req
+        .app_data::<Data<StateWrapper>>()
+        .and_then(|state| state.vocabulary_catalog.vocabulary_version.as_deref())
+        .and_then(|version| HeaderValue::from_str(version).ok());
+    let mut res = next.call(req).await?.map_into_boxed_body();
+    if let Some(version) = version {
+        res.headers_mut().insert(VOCABULARY_VERSION_HEADER, version);
+    }
+    Ok(res)
+}
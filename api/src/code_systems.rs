@@ -0,0 +1,504 @@
+use crate::StateWrapper;
+use crate::config::{CodeSystemConfig, Configs};
+use crate::domain::{SearchDiagnostics, SearchResponse};
+use crate::errors::{ApiError, EmbeddingError};
+use crate::qdrant::{WithReadOptions, ensure_collection, stable_point_id};
+use crate::search::SearchFilters;
+use actix_web::web::{Data, Path, Payload, Query};
+use actix_web::{Error, HttpResponse, get, post};
+use log::{info, warn};
+use qdrant_client::Payload as PointPayload;
+use qdrant_client::qdrant::{
+    Condition, Filter, GetPointsBuilder, PointId, PointStruct, ScrollPointsBuilder,
+    SearchPointsBuilder, UpsertPointsBuilder,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use utoipa::{IntoParams, ToSchema};
+
+/// The `system` of the standard OMOP vocabulary, searched by the regular pipeline.
+pub const OMOP_SYSTEM: &str = "omop";
+
+/// Codes embedded per OpenAI request and upserted per Qdrant call during ingest.
+const INGEST_BATCH_SIZE: usize = 256;
+const MAX_CATALOG_BYTES: usize = 64 * 1024 * 1024;
+const DEFAULT_MAPPING_CANDIDATES: u64 = 10;
+
+/// A code of an auxiliary code system, stored as the payload of its point with an additional
+/// `name_lower` for exact name lookups.
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct CodeSystemMatch {
+    pub system: String,
+    pub code: String,
+    pub name: String,
+    #[serde(default)]
+    pub score: Option<f64>,
+    /// The catalog's remaining columns, such as units or specimen types.
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl CodeSystemMatch {
+    fn from_payload(
+        payload: std::collections::HashMap<String, qdrant_client::qdrant::Value>,
+        score: f64,
+    ) -> Option<Self> {
+        let payload = serde_json::to_value(payload).ok()?;
+        let mut code: CodeSystemMatch = serde_json::from_value(payload).ok()?;
+        code.score = Some(score);
+        Some(code)
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct MappingParameters {
+    #[param(default = 10)]
+    limit: Option<u64>,
+}
+
+/// A searchable code system with the Qdrant collection it is kept in.
+#[derive(Serialize, ToSchema)]
+struct CodeSystem<'a> {
+    id: &'a str,
+    name: &'a str,
+    collection: &'a str,
+}
+
+/// The outcome of a catalog ingest. `warnings` lists the catalog lines that were skipped.
+#[derive(Serialize, ToSchema)]
+struct IngestedCatalog<'a> {
+    system: &'a str,
+    collection: &'a str,
+    ingested: usize,
+    warnings: Vec<String>,
+}
+
+/// A local code with the standard concepts it could map to.
+#[derive(Serialize, ToSchema)]
+struct MappingCandidates {
+    source: CodeSystemMatch,
+    candidates: Vec<SearchResponse>,
+}
+
+/// Resolves a `system` parameter to its configuration, `None` for the OMOP vocabulary.
+pub fn find_system<'a>(
+    config: &'a Configs,
+    system: Option<&str>,
+) -> Result<Option<&'a CodeSystemConfig>, ApiError> {
+    let Some(system) = system.filter(|system| !system.eq_ignore_ascii_case(OMOP_SYSTEM)) else {
+        return Ok(None);
+    };
+    config
+        .code_systems
+        .iter()
+        .find(|code_system| code_system.id == system)
+        .map(Some)
+        .ok_or_else(|| ApiError::InvalidFilter {
+            parameter: "system",
+            invalid: vec![system.to_string()],
+            valid: std::iter::once(OMOP_SYSTEM.to_string())
+                .chain(config.code_systems.iter().map(|system| system.id.clone()))
+                .collect(),
+        })
+}
+
+/// Points are keyed by system and code so re-ingesting a catalog updates codes in place.
+fn point_id(system: &str, code: &str) -> PointId {
+    stable_point_id(&format!("{}\u{0}{}", system, code))
+        .to_string()
+        .into()
+}
+
+fn canonical_column(header: &str) -> Option<&'static str> {
+    let normalized: String = header
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    match normalized.as_str() {
+        "code" | "localcode" | "sourcecode" | "conceptcode" => Some("code"),
+        "name" | "description" | "display" | "sourcename" | "conceptname" => Some("name"),
+        _ => None,
+    }
+}
+
+/// Reads a catalog with a code and a name column, every other column becomes an attribute.
+/// Rows without a code or name are skipped with a warning, later duplicates of a code win.
+fn parse_catalog(
+    content: &str,
+    system: &str,
+) -> Result<(Vec<CodeSystemMatch>, Vec<String>), String> {
+    let header_line = content.lines().next().unwrap_or_default();
+    let delimiter = if header_line.contains('\t') {
+        b'\t'
+    } else {
+        b','
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(content.as_bytes());
+
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| format!("Could not read CSV header: {}", e))?
+        .iter()
+        .map(str::to_string)
+        .collect();
+    let columns: Vec<Option<&'static str>> = headers
+        .iter()
+        .map(|header| canonical_column(header))
+        .collect();
+    if !columns.contains(&Some("code")) || !columns.contains(&Some("name")) {
+        return Err("CSV needs a code and a name column".to_string());
+    }
+
+    let mut codes: BTreeMap<String, CodeSystemMatch> = BTreeMap::new();
+    let mut warnings = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        // Header is line 1
+        let line = index + 2;
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                warnings.push(format!("Skipping line {}: {}", line, e));
+                continue;
+            }
+        };
+        let mut code = CodeSystemMatch {
+            system: system.to_string(),
+            code: String::new(),
+            name: String::new(),
+            score: None,
+            attributes: BTreeMap::new(),
+        };
+        for ((column, header), value) in columns.iter().zip(&headers).zip(record.iter()) {
+            match column {
+                Some("code") => code.code = value.to_string(),
+                Some("name") => code.name = value.to_string(),
+                _ if !value.is_empty() => {
+                    code.attributes.insert(header.clone(), value.to_string());
+                }
+                _ => {}
+            }
+        }
+        if code.code.is_empty() || code.name.is_empty() {
+            warnings.push(format!("Skipping line {}: missing code or name", line));
+            continue;
+        }
+        codes.insert(code.code.clone(), code);
+    }
+    Ok((codes.into_values().collect(), warnings))
+}
+
+fn to_point(code: &CodeSystemMatch, vector: Vec<f32>) -> PointStruct {
+    let payload = serde_json::json!({
+        "system": code.system,
+        "code": code.code,
+        "name": code.name,
+        "name_lower": code.name.to_lowercase(),
+        "attributes": code.attributes,
+    });
+    PointStruct::new(
+        point_id(&code.system, &code.code),
+        vector,
+        PointPayload::try_from(payload).unwrap(),
+    )
+}
+
+/// Codes matching the query exactly, by code or by name.
+async fn find_exact(
+    state: &StateWrapper,
+    code_system: &CodeSystemConfig,
+    query: &str,
+) -> Vec<CodeSystemMatch> {
+    let filter = Filter::should([
+        Condition::matches("code", query.to_string()),
+        Condition::matches("name_lower", query.to_lowercase()),
+    ]);
+    match state
+        .qdrant_client
+        .scroll(
+            ScrollPointsBuilder::new(code_system.collection.as_str())
+                .filter(filter)
+                .with_payload(true)
+                .limit(10)
+                .with_read_options(&state.qdrant_read_options),
+        )
+        .await
+    {
+        Ok(response) => response
+            .result
+            .into_iter()
+            .filter_map(|point| CodeSystemMatch::from_payload(point.payload, 1.0))
+            .collect(),
+        Err(e) => {
+            warn!(
+                "Exact lookup in code system {} failed: {}",
+                code_system.id, e
+            );
+            Vec::new()
+        }
+    }
+}
+
+/// Searches one auxiliary code system: exact code and name matches first, then the nearest
+/// neighbours of the query embedding above the usual score threshold.
+pub async fn search(
+    state: &StateWrapper,
+    code_system: &CodeSystemConfig,
+    query: &str,
+    limit: u64,
+) -> Result<Vec<CodeSystemMatch>, EmbeddingError> {
+    let mut matches = find_exact(state, code_system, query.trim()).await;
+    if matches.len() as u64 >= limit {
+        matches.truncate(limit as usize);
+        return Ok(matches);
+    }
+
+    let vector = match state.embedder.embed(query.to_string()).await {
+        Ok(vector) => vector,
+        Err(e) => {
+            if let Some(mismatch) = e.downcast_ref::<EmbeddingError>() {
+                return Err(mismatch.clone());
+            }
+            warn!("Embedding search failed for {:?}: {}", query, e);
+            return Ok(matches);
+        }
+    };
+    let response = state
+        .qdrant_client
+        .search_points(
+            SearchPointsBuilder::new(code_system.collection.as_str(), vector, limit)
+                .with_payload(true)
+                .score_threshold(state.config.search.score_threshold)
+                .with_read_options(&state.qdrant_read_options),
+        )
+        .await;
+    let points = match response {
+        Ok(response) => response.result,
+        Err(e) => {
+            warn!(
+                "Embedding search in code system {} failed: {}",
+                code_system.id, e
+            );
+            return Ok(matches);
+        }
+    };
+
+    let mut seen: HashSet<String> = matches.iter().map(|code| code.code.clone()).collect();
+    for point in points {
+        if let Some(code) = CodeSystemMatch::from_payload(point.payload, point.score as f64)
+            && seen.insert(code.code.clone())
+        {
+            matches.push(code);
+        }
+    }
+    matches.truncate(limit as usize);
+    Ok(matches)
+}
+
+/// The searchable code systems, starting with the OMOP vocabulary.
+#[utoipa::path(
+    tag = "code systems",
+    summary = "List code systems",
+    description = "The OMOP vocabulary plus every configured auxiliary code system, each with its Qdrant \
+        collection.",
+    responses((status = 200, description = "Code systems", body = Vec<CodeSystem>))
+)]
+#[get("/api/systems")]
+async fn list_systems(state: Data<StateWrapper>) -> Result<HttpResponse, Error> {
+    let mut systems = vec![CodeSystem {
+        id: OMOP_SYSTEM,
+        name: "OMOP Standardized Vocabularies",
+        collection: &state.config.qdrant_collection,
+    }];
+    systems.extend(state.config.code_systems.iter().map(|system| CodeSystem {
+        id: &system.id,
+        name: &system.name,
+        collection: &system.collection,
+    }));
+    Ok(HttpResponse::Ok().json(systems))
+}
+
+/// Loads a catalog CSV into the code system's collection, creating it on first use. Codes
+/// already in the collection are replaced, codes missing from the catalog are kept.
+#[utoipa::path(
+    tag = "admin",
+    summary = "Ingest a code system catalog",
+    description = "Loads a CSV with a code and a name column into the code system's collection, creating it on \
+        first use. The remaining columns are kept as attributes. Codes already in the collection are replaced, \
+        codes missing from the catalog are kept.",
+    params(("system" = String, Path, description = "Id of a configured auxiliary code system")),
+    request_body(content = String, content_type = "text/csv"),
+    responses(
+        (status = 200, description = "The number of codes ingested", body = IngestedCatalog),
+        (status = 400, description = "Unknown code system, the OMOP vocabulary, or a malformed catalog"),
+        (status = 413, description = "The catalog exceeds 64 MiB"),
+        (status = 502, description = "Embedding or the Qdrant upsert failed, with the codes ingested until then"),
+    )
+)]
+#[post("/api/admin/systems/{system}/ingest")]
+async fn ingest_code_system(
+    path: Path<String>,
+    payload: Payload,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let system = path.into_inner();
+    let Some(code_system) = find_system(&state.config, Some(&system))? else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "The OMOP vocabulary is loaded by the vocabulary ingest, not through this endpoint"
+        })));
+    };
+    let Ok(body) = payload.to_bytes_limited(MAX_CATALOG_BYTES).await else {
+        return Ok(HttpResponse::PayloadTooLarge().json(serde_json::json!({
+            "error": format!("Catalogs are limited to {} bytes", MAX_CATALOG_BYTES)
+        })));
+    };
+    let body = body?;
+    let (codes, warnings) = match std::str::from_utf8(&body)
+        .map_err(|e| format!("Catalog is not valid UTF-8: {}", e))
+        .and_then(|content| parse_catalog(content, &code_system.id))
+    {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+        }
+    };
+    info!(
+        "Ingesting {} codes into code system {} ({})",
+        codes.len(),
+        code_system.id,
+        code_system.collection
+    );
+
+    if let Err(e) = ensure_collection(
+        &state.qdrant_client,
+        &code_system.collection,
+        state.embedder.dimensions(),
+        &["code", "name_lower"],
+    )
+    .await
+    {
+        return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+            "error": format!("Could not prepare collection {}: {}", code_system.collection, e)
+        })));
+    }
+    let mut ingested = 0;
+    for batch in codes.chunks(INGEST_BATCH_SIZE) {
+        let names = batch.iter().map(|code| code.name.clone()).collect();
+        let vectors = match state.embedder.embed_batch(names).await {
+            Ok(vectors) => vectors,
+            Err(e) => {
+                return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                    "error": format!("Embedding failed after {} codes: {}", ingested, e),
+                    "ingested": ingested,
+                })));
+            }
+        };
+        let points: Vec<PointStruct> = batch
+            .iter()
+            .zip(vectors)
+            .map(|(code, vector)| to_point(code, vector))
+            .collect();
+        if let Err(e) = state
+            .qdrant_client
+            .upsert_points(
+                UpsertPointsBuilder::new(code_system.collection.as_str(), points).wait(true),
+            )
+            .await
+        {
+            return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                "error": format!("Upsert failed after {} codes: {}", ingested, e),
+                "ingested": ingested,
+            })));
+        }
+        ingested += batch.len();
+    }
+
+    Ok(HttpResponse::Ok().json(IngestedCatalog {
+        system: &code_system.id,
+        collection: &code_system.collection,
+        ingested,
+        warnings,
+    }))
+}
+
+/// Standard concepts a local code could map to, found by running the regular search pipeline
+/// on the code's name.
+#[utoipa::path(
+    tag = "code systems",
+    summary = "Suggest standard concepts for a local code",
+    description = "Runs the regular search pipeline, restricted to standard concepts, on the name of a code from \
+        an auxiliary code system.",
+    params(
+        ("system" = String, Path, description = "Id of a configured auxiliary code system"),
+        ("code" = String, Path, description = "The local code"),
+        MappingParameters,
+    ),
+    responses(
+        (status = 200, description = "The local code and its candidate standard concepts", body = MappingCandidates),
+        (status = 400, description = "Unknown code system, or the OMOP vocabulary"),
+        (status = 404, description = "The code is not in the code system"),
+    )
+)]
+#[get("/api/systems/{system}/codes/{code}/mapping-candidates")]
+async fn get_mapping_candidates(
+    path: Path<(String, String)>,
+    parameters: Query<MappingParameters>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let (system, code) = path.into_inner();
+    let Some(code_system) = find_system(&state.config, Some(&system))? else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Mapping candidates are only available for auxiliary code systems"
+        })));
+    };
+    let response = state
+        .qdrant_client
+        .get_points(
+            GetPointsBuilder::new(
+                code_system.collection.as_str(),
+                vec![point_id(&code_system.id, &code)],
+            )
+            .with_payload(true)
+            .with_vectors(false)
+            .with_read_options(&state.qdrant_read_options),
+        )
+        .await;
+    let source = match response {
+        Ok(response) => response
+            .result
+            .into_iter()
+            .find_map(|point| CodeSystemMatch::from_payload(point.payload, 1.0)),
+        Err(e) => {
+            warn!(
+                "Lookup of {} in code system {} failed: {}",
+                code, code_system.id, e
+            );
+            None
+        }
+    };
+    let Some(source) = source else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("Code {} not found in code system {}", code, code_system.id)
+        })));
+    };
+
+    let limit = state
+        .config
+        .demo
+        .clamp_limit(parameters.limit.unwrap_or(DEFAULT_MAPPING_CANDIDATES));
+    let filters = SearchFilters {
+        standard_concept: Some("S".to_string()),
+        ..Default::default()
+    };
+    let mut diagnostics = SearchDiagnostics::new(state.config.search.score_threshold);
+    let candidates = state
+        .search_pipeline
+        .run(&state, &source.name, filters, limit, &mut diagnostics)
+        .await?;
+    Ok(HttpResponse::Ok().json(MappingCandidates { source, candidates }))
+}
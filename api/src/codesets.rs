@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Rows per `IN (...)` list. Oracle rejects lists longer than 1000 entries, and keeping the
+/// other dialects at the same size keeps the generated SQL readable.
+const CONCEPT_IDS_PER_STATEMENT: usize = 1000;
+
+/// Target database platforms, named after their SqlRender dialect identifiers.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SqlDialect {
+    #[default]
+    SqlServer,
+    Postgresql,
+    Oracle,
+    Redshift,
+    Snowflake,
+    Bigquery,
+    Spark,
+}
+
+impl SqlDialect {
+    /// Name of the temporary codeset table, as circe-be generates it for the dialect.
+    fn default_codeset_table(&self) -> &'static str {
+        match self {
+            SqlDialect::SqlServer => "#Codesets",
+            _ => "Codesets",
+        }
+    }
+
+    fn create_codeset_table(&self, table: &str) -> String {
+        match self {
+            SqlDialect::SqlServer => format!(
+                "CREATE TABLE {} (codeset_id int NOT NULL, concept_id bigint NOT NULL);",
+                table
+            ),
+            SqlDialect::Postgresql | SqlDialect::Redshift => format!(
+                "CREATE TEMP TABLE {} (codeset_id int NOT NULL, concept_id bigint NOT NULL);",
+                table
+            ),
+            SqlDialect::Oracle => format!(
+                "CREATE GLOBAL TEMPORARY TABLE {} (codeset_id NUMBER(10) NOT NULL, concept_id NUMBER(19) NOT NULL) ON COMMIT PRESERVE ROWS;",
+                table
+            ),
+            SqlDialect::Snowflake => format!(
+                "CREATE TEMPORARY TABLE {} (codeset_id INT NOT NULL, concept_id BIGINT NOT NULL);",
+                table
+            ),
+            SqlDialect::Bigquery => format!(
+                "CREATE TEMP TABLE {} (codeset_id INT64 NOT NULL, concept_id INT64 NOT NULL);",
+                table
+            ),
+            SqlDialect::Spark => format!(
+                "CREATE TABLE {} (codeset_id INT, concept_id BIGINT);",
+                table
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CodesetSqlOptions {
+    #[serde(default)]
+    pub dialect: SqlDialect,
+    #[serde(default)]
+    pub codeset_id: i32,
+    /// Defaults to the circe temp table name for the dialect.
+    pub codeset_table: Option<String>,
+    /// Defaults to the SqlRender parameter, so the output can still be rendered by the caller.
+    pub vocabulary_database_schema: Option<String>,
+    #[serde(default)]
+    pub include_create_table: bool,
+}
+
+/// Renders the circe-style `codeset_id, concept_id` population SQL for a resolved concept set.
+pub fn render_codeset_sql(options: &CodesetSqlOptions, concept_ids: &[i32]) -> String {
+    let template = include_str!("../sql/insert_codeset.sql");
+    let codeset_table = options
+        .codeset_table
+        .as_deref()
+        .unwrap_or_else(|| options.dialect.default_codeset_table());
+    let vocabulary_database_schema = options
+        .vocabulary_database_schema
+        .as_deref()
+        .unwrap_or("@vocabulary_database_schema");
+
+    let mut statements = Vec::new();
+    if options.include_create_table {
+        statements.push(options.dialect.create_codeset_table(codeset_table));
+    }
+    for chunk in concept_ids.chunks(CONCEPT_IDS_PER_STATEMENT) {
+        let concept_ids = chunk
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        statements.push(
+            template
+                .trim_end()
+                .replace("@codeset_table", codeset_table)
+                .replace("@codeset_id", &options.codeset_id.to_string())
+                .replace("@vocabulary_database_schema", vocabulary_database_schema)
+                .replace("@concept_ids", &concept_ids),
+        );
+    }
+    statements.join("\n\n") + "\n"
+}
@@ -0,0 +1,145 @@
+use crate::StateWrapper;
+use crate::db;
+use crate::domain::{Concept, LinkedConcept};
+use crate::errors::PgError;
+use actix_web::web::{Data, Json};
+use actix_web::{Error, HttpResponse, get, post, web};
+use deadpool_postgres::Client;
+use log::info;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+const MAX_BATCH_CODES: usize = 1000;
+
+/// A code of a vocabulary, as in `concept.concept_code`.
+#[derive(Deserialize, ToSchema)]
+#[schema(as = VocabularyCode)]
+struct SourceCode {
+    vocabulary_id: String,
+    concept_code: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CodeLookupRequest {
+    codes: Vec<SourceCode>,
+}
+
+/// The concepts carrying a source code and the standard concepts they map to, what an ETL needs
+/// to fill a `*_concept_id` and `*_source_concept_id` pair.
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct CodeLookup {
+    pub(crate) vocabulary_id: String,
+    pub(crate) concept_code: String,
+    pub(crate) concepts: Vec<Concept>,
+    pub(crate) maps_to: Vec<LinkedConcept>,
+}
+
+/// Looks up the codes in one round trip each for the concepts and their mappings, keeping the
+/// order they were requested in.
+pub(crate) async fn lookup_codes(
+    codes: Vec<(String, String)>,
+    pg_client: &Client,
+) -> Result<Vec<CodeLookup>, PgError> {
+    let concepts = db::get_concepts_by_codes(pg_client, &codes).await?;
+    let concept_ids: Vec<i32> = concepts.iter().map(|concept| concept.concept_id).collect();
+    let maps_to = db::get_linked_concepts(pg_client, &concept_ids, &["Maps to"]).await?;
+
+    Ok(codes
+        .into_iter()
+        .map(|(vocabulary_id, concept_code)| {
+            let concepts: Vec<Concept> = concepts
+                .iter()
+                .filter(|concept| {
+                    concept.concept_code == concept_code
+                        && concept.vocabulary_id.eq_ignore_ascii_case(&vocabulary_id)
+                })
+                .cloned()
+                .collect();
+            let maps_to = maps_to
+                .iter()
+                .filter(|linked| {
+                    concepts
+                        .iter()
+                        .any(|concept| concept.concept_id == linked.source_concept_id)
+                })
+                .cloned()
+                .collect();
+            CodeLookup {
+                vocabulary_id,
+                concept_code,
+                concepts,
+                maps_to,
+            }
+        })
+        .collect())
+}
+
+/// The concepts with a code in a vocabulary, e.g. `ICD10CM/E11.9`, for ETL code lookups that
+/// should not go through fuzzy search.
+#[utoipa::path(
+    tag = "concepts",
+    summary = "Look up concepts by source code",
+    description = "The concepts with a code in a vocabulary, with the standard concepts they map to. The \
+        vocabulary ID matches case-insensitively, the code exactly.",
+    params(
+        ("vocabulary_id" = String, Path, example = "ICD10CM"),
+        ("code" = String, Path, example = "E11.9"),
+    ),
+    responses(
+        (status = 200, description = "Concepts with the code", body = CodeLookup),
+        (status = 400, description = "Unknown vocabulary"),
+        (status = 404, description = "No concept has the code"),
+    )
+)]
+#[get("/api/concepts/code/{vocabulary_id}/{code}")]
+async fn get_concepts_by_code(
+    path: web::Path<(String, String)>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let (vocabulary_id, code) = path.into_inner();
+    info!("Get concepts with code {} in {}", code, vocabulary_id);
+    state
+        .vocabulary_catalog
+        .validate_vocabulary_ids(Some(std::slice::from_ref(&vocabulary_id)))?;
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let lookup = lookup_codes(vec![(vocabulary_id, code)], &pg_client)
+        .await?
+        .pop()
+        .filter(|lookup| !lookup.concepts.is_empty())
+        .ok_or(PgError::NotFound)?;
+    Ok(HttpResponse::Ok().json(lookup))
+}
+
+/// Batch variant of the code lookup. Answers with one entry per requested code, in order, with
+/// no concepts for codes that are not in the vocabulary.
+#[utoipa::path(
+    tag = "concepts",
+    summary = "Look up concepts by source code in batch",
+    description = "One entry per requested code, in request order; codes not in the vocabulary have no \
+        concepts. At most 1000 codes per request.",
+    responses(
+        (status = 200, description = "Concepts per code", body = Vec<CodeLookup>),
+        (status = 400, description = "Too many codes"),
+    )
+)]
+#[post("/api/concepts/code")]
+async fn get_concepts_by_codes(
+    request: Json<CodeLookupRequest>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let request = request.into_inner();
+    if request.codes.len() > MAX_BATCH_CODES {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("At most {} codes can be looked up per request", MAX_BATCH_CODES)
+        })));
+    }
+    info!("Looking up {} codes", request.codes.len());
+    let codes: Vec<(String, String)> = request
+        .codes
+        .into_iter()
+        .map(|code| (code.vocabulary_id, code.concept_code))
+        .collect();
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let lookups = lookup_codes(codes, &pg_client).await?;
+    Ok(HttpResponse::Ok().json(lookups))
+}
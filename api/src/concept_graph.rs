@@ -0,0 +1,226 @@
+use crate::StateWrapper;
+use crate::db;
+use crate::domain::{Concept, ConceptClassSummary, HierarchyConcept, HierarchyEdge, HierarchyRoot};
+use crate::errors::PgError;
+use actix_web::web::{Data, Query};
+use actix_web::{Error, HttpResponse, get, web};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+const DEFAULT_SAMPLE_CONCEPTS: i64 = 5;
+const MAX_SAMPLE_CONCEPTS: i64 = 50;
+const DEFAULT_ROOT_LIMIT: i64 = 100;
+const MAX_ROOT_LIMIT: i64 = 1000;
+const DEFAULT_HIERARCHY_LEVELS: i32 = 1;
+const MAX_HIERARCHY_LEVELS: i32 = 5;
+/// Descendants in a hierarchy tree, nearest first; wide hierarchies are cut off here.
+const MAX_HIERARCHY_DESCENDANTS: i64 = 500;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ConceptClassParameters {
+    /// Sample concepts per class.
+    #[param(default = 5, minimum = 0, maximum = 50)]
+    samples: Option<i64>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct RootParameters {
+    #[param(default = 100, maximum = 1000)]
+    limit: Option<i64>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct HierarchyParameters {
+    /// Levels of ancestors above the concept.
+    #[param(default = 1, minimum = 0, maximum = 5)]
+    up: Option<i32>,
+    /// Levels of descendants below the concept.
+    #[param(default = 1, minimum = 0, maximum = 5)]
+    down: Option<i32>,
+}
+
+/// A concept in a hierarchy tree, `level` steps below the requested concept, or above it when
+/// negative.
+#[derive(Debug, Serialize, ToSchema)]
+struct HierarchyNode {
+    concept_id: i32,
+    concept_name: String,
+    domain_id: String,
+    vocabulary_id: String,
+    concept_class_id: String,
+    standard_concept: Option<String>,
+    level: i32,
+}
+
+impl HierarchyNode {
+    fn from_concept(level: i32, concept: Concept) -> Self {
+        HierarchyNode {
+            concept_id: concept.concept_id,
+            concept_name: concept.concept_name,
+            domain_id: concept.domain_id,
+            vocabulary_id: concept.vocabulary_id,
+            concept_class_id: concept.concept_class_id,
+            standard_concept: concept.standard_concept,
+            level,
+        }
+    }
+
+    fn from_hierarchy(level: i32, concept: HierarchyConcept) -> Self {
+        HierarchyNode {
+            concept_id: concept.concept_id,
+            concept_name: concept.concept_name,
+            domain_id: concept.domain_id,
+            vocabulary_id: concept.vocabulary_id,
+            concept_class_id: concept.concept_class_id,
+            standard_concept: concept.standard_concept,
+            level,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct HierarchyTree {
+    concept_id: i32,
+    nodes: Vec<HierarchyNode>,
+    edges: Vec<HierarchyEdge>,
+    /// Whether descendants were left out to keep the tree renderable.
+    truncated: bool,
+}
+
+/// Concept classes of a vocabulary with their member counts and a few example concepts, as an
+/// entry point for exploring an unfamiliar vocabulary.
+#[utoipa::path(
+    tag = "vocabularies",
+    summary = "List concept classes of a vocabulary",
+    description = "Concept classes in the vocabulary with member counts and sample concepts.",
+    params(("vocabulary_id" = String, Path, example = "SNOMED"), ConceptClassParameters),
+    responses(
+        (status = 200, description = "Concept classes, most populated first", body = Vec<ConceptClassSummary>),
+        (status = 400, description = "Unknown vocabulary, the body lists the valid ones"),
+    )
+)]
+#[get("/api/vocabularies/{vocabulary_id}/concept-classes")]
+async fn get_concept_classes(
+    path: web::Path<String>,
+    parameters: Query<ConceptClassParameters>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let vocabulary_id = path.into_inner();
+    state
+        .vocabulary_catalog
+        .validate_vocabulary_ids(Some(std::slice::from_ref(&vocabulary_id)))?;
+    let samples = parameters
+        .samples
+        .unwrap_or(DEFAULT_SAMPLE_CONCEPTS)
+        .clamp(0, MAX_SAMPLE_CONCEPTS);
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let classes =
+        db::get_concept_classes_by_vocabulary(&pg_client, &vocabulary_id, samples).await?;
+    Ok(HttpResponse::Ok().json(classes))
+}
+
+/// Top-level concepts of a vocabulary's hierarchy: valid standard or classification concepts
+/// that have descendants but no ancestor in the same vocabulary.
+#[utoipa::path(
+    tag = "vocabularies",
+    summary = "List hierarchy roots of a vocabulary",
+    description = "Valid standard and classification concepts with descendants but no ancestor in the same \
+        vocabulary.",
+    params(("vocabulary_id" = String, Path, example = "SNOMED"), RootParameters),
+    responses(
+        (status = 200, description = "Root concepts, largest subtree first", body = Vec<HierarchyRoot>),
+        (status = 400, description = "Unknown vocabulary, the body lists the valid ones"),
+    )
+)]
+#[get("/api/vocabularies/{vocabulary_id}/roots")]
+async fn get_vocabulary_roots(
+    path: web::Path<String>,
+    parameters: Query<RootParameters>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let vocabulary_id = path.into_inner();
+    state
+        .vocabulary_catalog
+        .validate_vocabulary_ids(Some(std::slice::from_ref(&vocabulary_id)))?;
+    let limit = parameters
+        .limit
+        .unwrap_or(DEFAULT_ROOT_LIMIT)
+        .clamp(1, MAX_ROOT_LIMIT);
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let roots = db::get_vocabulary_roots(&pg_client, &vocabulary_id, limit).await?;
+    Ok(HttpResponse::Ok().json(roots))
+}
+
+/// The concept with its ancestors up to `up` levels and descendants down to `down` levels as
+/// nodes and direct parent to child edges, enough to draw a hierarchy browser in one call.
+#[utoipa::path(
+    tag = "concepts",
+    summary = "Get a concept's hierarchy tree",
+    description = "The concept with its ancestors up to `up` levels and descendants down to `down` levels, as \
+        nodes and the direct parent to child edges between them. At most 500 descendants are included, nearest \
+        first; `truncated` tells when more were left out.",
+    params(("id" = i32, Path, description = "Concept id", example = 201826), HierarchyParameters),
+    responses(
+        (status = 200, description = "Hierarchy tree", body = HierarchyTree),
+        (status = 404, description = "Concept not found"),
+    )
+)]
+#[get("/api/concepts/{id}/hierarchy")]
+async fn get_concept_hierarchy(
+    path: web::Path<i32>,
+    parameters: Query<HierarchyParameters>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    let levels = |requested: Option<i32>| {
+        requested
+            .unwrap_or(DEFAULT_HIERARCHY_LEVELS)
+            .clamp(0, MAX_HIERARCHY_LEVELS)
+    };
+    let (up, down) = (levels(parameters.up), levels(parameters.down));
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let Some(concept) = db::get_concepts_by_ids(&pg_client, &[id]).await?.pop() else {
+        return Err(PgError::NotFound.into());
+    };
+
+    let mut nodes = vec![HierarchyNode::from_concept(0, concept)];
+    if up > 0 {
+        let ancestors = db::get_ancestor_concepts(&pg_client, id).await?;
+        nodes.extend(
+            ancestors
+                .into_iter()
+                .filter(|ancestor| ancestor.min_levels_of_separation <= up)
+                .map(|ancestor| {
+                    HierarchyNode::from_hierarchy(-ancestor.min_levels_of_separation, ancestor)
+                }),
+        );
+    }
+    let mut truncated = false;
+    if down > 0 {
+        let (total, descendants) = db::get_descendant_concepts_page(
+            &pg_client,
+            id,
+            Some(down),
+            None,
+            0,
+            MAX_HIERARCHY_DESCENDANTS,
+        )
+        .await?;
+        truncated = total > descendants.len() as i64;
+        nodes.extend(descendants.into_iter().map(|descendant| {
+            HierarchyNode::from_hierarchy(descendant.min_levels_of_separation, descendant)
+        }));
+    }
+
+    let concept_ids: Vec<i32> = nodes.iter().map(|node| node.concept_id).collect();
+    let edges = db::get_hierarchy_edges(&pg_client, &concept_ids).await?;
+    Ok(HttpResponse::Ok().json(HierarchyTree {
+        concept_id: id,
+        nodes,
+        edges,
+        truncated,
+    }))
+}
@@ -0,0 +1,271 @@
+use crate::spelling;
+use fst::automaton::{Automaton, Str};
+use fst::{IntoStreamer, Map, MapBuilder, Streamer};
+use log::{info, warn};
+use memmap2::Mmap;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Read;
+use uuid::Uuid;
+
+/// Identifies the index file format; anything else is read as the older JSON export.
+const MAGIC: &[u8; 8] = b"HCIDX\0\0\x01";
+/// Magic followed by the length of the FST section.
+const HEADER_LEN: usize = 16;
+
+/// The bytes an index is read from: the mapped file, or memory for indexes converted on load.
+enum Backing {
+    Mapped(Mmap),
+    Memory(Vec<u8>),
+}
+
+impl Backing {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Backing::Mapped(mmap) => mmap,
+            Backing::Memory(bytes) => bytes,
+        }
+    }
+}
+
+/// The FST section of the backing bytes.
+struct FstBytes {
+    backing: Backing,
+    len: usize,
+}
+
+impl AsRef<[u8]> for FstBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.backing.bytes()[HEADER_LEN..HEADER_LEN + self.len]
+    }
+}
+
+/// Lowercase concept name to the ids of its points in the vector store.
+///
+/// The file written by `ingest` is a finite state transducer from names to offsets into a list
+/// of point ids, memory-mapped on load so startup does not parse it and the operating system
+/// only pages in the names that are looked up.
+pub struct ConceptIndex {
+    map: Map<FstBytes>,
+}
+
+impl fmt::Debug for ConceptIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConceptIndex")
+            .field("names", &self.len())
+            .finish()
+    }
+}
+
+impl ConceptIndex {
+    pub fn load(path: &str) -> Result<ConceptIndex, Box<dyn Error>> {
+        let file = File::open(path)?;
+        if !is_index_file(&file) {
+            warn!(
+                "{} is a JSON concept index, run `hecate-api convert-index` to load it instantly",
+                path
+            );
+            let builder = ConceptIndexBuilder::from_json(path)?;
+            return ConceptIndex::from_backing(Backing::Memory(builder.to_bytes()?));
+        }
+        // Safety: the index file is only ever replaced by renaming a new file over it, never
+        // modified in place, so the mapped bytes do not change underneath us.
+        let mmap = unsafe { Mmap::map(&file)? };
+        ConceptIndex::from_backing(Backing::Mapped(mmap))
+    }
+
+    fn from_backing(backing: Backing) -> Result<ConceptIndex, Box<dyn Error>> {
+        let bytes = backing.bytes();
+        if bytes.len() < HEADER_LEN || &bytes[..MAGIC.len()] != MAGIC {
+            return Err("Not a concept index file".into());
+        }
+        let len = u64::from_le_bytes(bytes[MAGIC.len()..HEADER_LEN].try_into()?) as usize;
+        if HEADER_LEN + len > bytes.len() {
+            return Err("Truncated concept index file".into());
+        }
+        let map = Map::new(FstBytes { backing, len })?;
+        Ok(ConceptIndex { map })
+    }
+
+    pub fn get(&self, name: &str) -> Option<Vec<Uuid>> {
+        let offset = self.map.get(name)? as usize;
+        let fst = self.map.as_fst().as_inner();
+        let postings = &fst.backing.bytes()[HEADER_LEN + fst.len..];
+        let count = u32::from_le_bytes(postings.get(offset..offset + 4)?.try_into().ok()?);
+        let ids = postings.get(offset + 4..offset + 4 + count as usize * 16)?;
+        Some(
+            ids.chunks_exact(16)
+                .map(|id| Uuid::from_bytes(id.try_into().unwrap()))
+                .collect(),
+        )
+    }
+
+    /// Up to `scan_limit` names starting with `prefix`, in lexicographic order. The FST walks
+    /// straight to the prefix, so this costs the names read rather than the size of the index.
+    pub fn names_with_prefix(&self, prefix: &str, scan_limit: usize) -> Vec<String> {
+        let matcher = Str::new(prefix).starts_with();
+        let mut stream = self.map.search(matcher).into_stream();
+        let mut names = Vec::new();
+        while names.len() < scan_limit
+            && let Some((name, _)) = stream.next()
+        {
+            names.push(String::from_utf8_lossy(name).into_owned());
+        }
+        names
+    }
+
+    /// Up to `limit` names starting with `prefix`, shortest first and in lexicographic order
+    /// within a length. Each length is its own walk of the FST that never goes deeper than it,
+    /// so short names are found however many longer ones sort before them.
+    pub fn shortest_names_with_prefix(&self, prefix: &str, limit: usize) -> Vec<String> {
+        let prefix = prefix.as_bytes();
+        let mut names = Vec::new();
+        let mut length = prefix.len();
+        while names.len() < limit {
+            let exact = PrefixWithLength {
+                prefix,
+                min: length,
+                max: length,
+            };
+            let mut stream = self.map.search(exact).into_stream();
+            while names.len() < limit
+                && let Some((name, _)) = stream.next()
+            {
+                names.push(String::from_utf8_lossy(name).into_owned());
+            }
+            length += 1;
+            let longer = PrefixWithLength {
+                prefix,
+                min: length,
+                max: usize::MAX,
+            };
+            if self.map.search(longer).into_stream().next().is_none() {
+                break;
+            }
+        }
+        names
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+/// Names starting with `prefix` that are `min` to `max` bytes long. Paths longer than `max`
+/// are never followed.
+struct PrefixWithLength<'a> {
+    prefix: &'a [u8],
+    min: usize,
+    max: usize,
+}
+
+impl Automaton for PrefixWithLength<'_> {
+    /// Bytes read so far, `None` once the name left the prefix or ran past `max`.
+    type State = Option<usize>;
+
+    fn start(&self) -> Option<usize> {
+        Some(0)
+    }
+
+    fn is_match(&self, state: &Option<usize>) -> bool {
+        state.is_some_and(|read| read >= self.min)
+    }
+
+    fn can_match(&self, state: &Option<usize>) -> bool {
+        state.is_some()
+    }
+
+    fn accept(&self, state: &Option<usize>, byte: u8) -> Option<usize> {
+        let read = (*state)?;
+        if read >= self.max
+            || self
+                .prefix
+                .get(read)
+                .is_some_and(|expected| *expected != byte)
+        {
+            return None;
+        }
+        Some(read + 1)
+    }
+}
+
+fn is_index_file(mut file: &File) -> bool {
+    let mut magic = [0; MAGIC.len()];
+    file.read_exact(&mut magic).is_ok() && &magic == MAGIC
+}
+
+/// Collects names and point ids during ingestion and writes them out as a `ConceptIndex`.
+#[derive(Default)]
+pub struct ConceptIndexBuilder {
+    names: BTreeMap<String, Vec<Uuid>>,
+}
+
+impl ConceptIndexBuilder {
+    /// Reads the JSON export written by earlier versions of `ingest`.
+    fn from_json(path: &str) -> Result<ConceptIndexBuilder, Box<dyn Error>> {
+        let legacy: HashMap<String, Vec<Uuid>> = serde_json::from_str(&fs::read_to_string(path)?)?;
+        Ok(ConceptIndexBuilder {
+            names: legacy.into_iter().collect(),
+        })
+    }
+
+    pub fn insert(&mut self, name: &str, id: Uuid) {
+        self.names.entry(name.to_string()).or_default().push(id);
+    }
+
+    pub fn name_count(&self) -> usize {
+        self.names.len()
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut postings = Vec::new();
+        let mut builder = MapBuilder::memory();
+        for (name, ids) in &self.names {
+            builder.insert(name, postings.len() as u64)?;
+            postings.extend((ids.len() as u32).to_le_bytes());
+            for id in ids {
+                postings.extend(id.as_bytes());
+            }
+        }
+        let fst = builder.into_inner()?;
+        let mut bytes = Vec::with_capacity(HEADER_LEN + fst.len() + postings.len());
+        bytes.extend(MAGIC);
+        bytes.extend((fst.len() as u64).to_le_bytes());
+        bytes.extend(fst);
+        bytes.extend(postings);
+        Ok(bytes)
+    }
+
+    /// Writes next to `path` and renames over it, so running APIs keep their mapping of the
+    /// previous file. The spelling dictionary of the names is written alongside.
+    pub fn write(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let temporary = format!("{}.{}.tmp", path, Uuid::new_v4().simple());
+        fs::write(&temporary, self.to_bytes()?)?;
+        fs::rename(&temporary, path)?;
+        let words = spelling::write_dictionary(path, self.names.keys().map(String::as_str))?;
+        info!("Wrote spelling dictionary with {} words", words);
+        Ok(())
+    }
+}
+
+/// Rewrites a JSON concept index at `path` in the memory-mapped format.
+pub fn convert(path: &str) -> Result<(), Box<dyn Error>> {
+    if is_index_file(&File::open(path)?) {
+        info!("{} is already a memory-mapped concept index", path);
+        return Ok(());
+    }
+    let builder = ConceptIndexBuilder::from_json(path)?;
+    builder.write(path)?;
+    info!(
+        "Converted concept index with {} names at {}",
+        builder.name_count(),
+        path
+    );
+    Ok(())
+}
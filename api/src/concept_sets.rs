@@ -0,0 +1,548 @@
+use crate::api::{analyze_with_state, resolved_concepts};
+use crate::auth::Identity;
+use crate::domain::{
+    Concept, ConceptSetChanges, ConceptSetVersion, ResolvedConcept, StoredConceptSet,
+};
+use crate::errors::PgError;
+use crate::expansions;
+use crate::profiles::ValidationProfile;
+use crate::validation::{self, ConceptSetAnalysis, Progress};
+use crate::{StateWrapper, db};
+use actix_web::web::{Data, Json, Query, ReqData};
+use actix_web::{Error, HttpResponse, delete, get, post, put, web};
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use utoipa::{IntoParams, ToSchema};
+
+/// Concept sets listed when the request does not ask for a limit.
+const DEFAULT_LIST_LIMIT: i64 = 100;
+
+#[derive(Deserialize, ToSchema)]
+struct CreateConceptSetRequest {
+    name: String,
+    description: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    /// ATLAS concept set JSON, as sent to the analyze endpoint.
+    concept_set: String,
+    /// The author when the request is not authenticated.
+    created_by: Option<String>,
+    /// Recorded with the version.
+    note: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct UpdateConceptSetRequest {
+    name: Option<String>,
+    description: Option<String>,
+    tags: Option<Vec<String>>,
+    /// ATLAS concept set JSON, stored as a new version when it resolves differently.
+    concept_set: Option<String>,
+    created_by: Option<String>,
+    note: Option<String>,
+    base_version: Option<i32>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ConceptSetListParameters {
+    tag: Option<String>,
+    /// Part of the name, case-insensitive.
+    name: Option<String>,
+    #[param(default = 100)]
+    limit: Option<i64>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct AnalyzeStoredRequest {
+    /// Defaults to the latest version.
+    version: Option<i32>,
+    #[serde(default)]
+    profile: ValidationProfile,
+}
+
+/// A stored concept set with the expression of its latest version.
+#[derive(Serialize, ToSchema)]
+struct ConceptSetDetail {
+    #[serde(flatten)]
+    concept_set: StoredConceptSet,
+    expression: Value,
+}
+
+/// The analysis of a stored concept set, with the version it ran on.
+#[derive(Serialize, ToSchema)]
+struct StoredConceptSetAnalysis<'a> {
+    concept_set_id: i64,
+    version: i32,
+    #[serde(flatten)]
+    analysis: ConceptSetAnalysis<'a>,
+}
+
+/// The resolution an impact report compares against.
+#[derive(Serialize, ToSchema)]
+struct PreviousResolution {
+    version: i32,
+    vocabulary_version: Option<String>,
+    resolved_at: DateTime<Utc>,
+    concept_count: usize,
+}
+
+/// What re-resolving a stored concept set against the current vocabulary changed.
+#[derive(Serialize, ToSchema)]
+struct ConceptSetImpact {
+    concept_set_id: i64,
+    version: i32,
+    vocabulary_version: Option<String>,
+    concept_count: usize,
+    /// None on the first impact report of a set, which only records the baseline.
+    previous: Option<PreviousResolution>,
+    added: Vec<ResolvedConcept>,
+    /// Concepts no longer resolved, other than the deprecated ones.
+    dropped: Vec<ResolvedConcept>,
+    /// Concepts of either resolution that became invalid since the previous one.
+    deprecated: Vec<Concept>,
+    /// Whether this resolution was kept as the baseline for the next report.
+    recorded: bool,
+    warnings: Vec<String>,
+}
+
+/// The expression as stored and its hash.
+fn stored_expression(concept_set: &str) -> Result<(Value, String), String> {
+    let expression = validation::parse_concept_set(concept_set)?;
+    let hash = expansions::expression_hash(&expression);
+    Ok((serde_json::to_value(&expression).unwrap_or_default(), hash))
+}
+
+/// Trimmed, without empty and repeated tags.
+fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = tags
+        .iter()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    normalized.sort();
+    normalized.dedup();
+    normalized
+}
+
+/// The authenticated caller, or the name given in the request when authentication is off.
+pub(crate) fn author(
+    identity: Option<ReqData<Identity>>,
+    created_by: Option<&str>,
+) -> Option<String> {
+    identity
+        .map(|identity| identity.into_inner().name)
+        .or_else(|| created_by.map(str::to_string))
+}
+
+async fn latest_version(
+    pg_client: &deadpool_postgres::Client,
+    concept_set: &StoredConceptSet,
+) -> Result<ConceptSetVersion, PgError> {
+    db::get_concept_set_versions(pg_client, concept_set.id, Some(concept_set.latest_version))
+        .await?
+        .pop()
+        .ok_or(PgError::NotFound)
+}
+
+#[utoipa::path(
+    tag = "stored concept sets",
+    summary = "List saved concept sets",
+    description = "Saved concept sets, most recently updated first, without their expressions.",
+    params(ConceptSetListParameters),
+    responses((status = 200, description = "Saved concept sets", body = Vec<StoredConceptSet>))
+)]
+#[get("/api/conceptsets")]
+async fn list_concept_sets(
+    parameters: Query<ConceptSetListParameters>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let concept_sets = db::get_concept_sets(
+        &pg_client,
+        parameters.tag.as_deref(),
+        parameters.name.as_deref(),
+        parameters.limit.unwrap_or(DEFAULT_LIST_LIMIT),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(concept_sets))
+}
+
+#[utoipa::path(
+    tag = "stored concept sets",
+    summary = "Save a concept set",
+    description = "Stores a named concept set with its expression as version 1. The author is the authenticated \
+        caller, or `created_by` when authentication is off.",
+    responses(
+        (status = 201, description = "The saved concept set", body = ConceptSetDetail),
+        (status = 400, description = "Missing name or unreadable concept set"),
+    )
+)]
+#[post("/api/conceptsets")]
+async fn create_concept_set(
+    request: Json<CreateConceptSetRequest>,
+    identity: Option<ReqData<Identity>>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Ok(
+            HttpResponse::BadRequest().json(serde_json::json!({ "error": "A name is required" }))
+        );
+    }
+    let (expression, hash) = match stored_expression(&request.concept_set) {
+        Ok(stored) => stored,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+        }
+    };
+    let author = author(identity, request.created_by.as_deref());
+    info!("Saving concept set {:?}", name);
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let concept_set = db::insert_concept_set(
+        &pg_client,
+        name,
+        request.description.as_deref(),
+        &normalize_tags(&request.tags),
+        author.as_deref(),
+        &expression,
+        &hash,
+        request.note.as_deref(),
+    )
+    .await?;
+    Ok(HttpResponse::Created().json(ConceptSetDetail {
+        concept_set,
+        expression,
+    }))
+}
+
+#[utoipa::path(
+    tag = "stored concept sets",
+    summary = "Get a saved concept set",
+    description = "The concept set with the expression of its latest version.",
+    params(("id" = i64, Path, description = "Concept set id")),
+    responses(
+        (status = 200, description = "The saved concept set", body = ConceptSetDetail),
+        (status = 404, description = "No such concept set"),
+    )
+)]
+#[get("/api/conceptsets/{id:\\d+}")]
+async fn get_concept_set(
+    path: web::Path<i64>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let concept_set = db::get_concept_set(&pg_client, path.into_inner()).await?;
+    let version = latest_version(&pg_client, &concept_set).await?;
+    Ok(HttpResponse::Ok().json(ConceptSetDetail {
+        concept_set,
+        expression: version.expression,
+    }))
+}
+
+/// Updates the metadata in place. A changed expression is stored as a new version, earlier
+/// versions stay as they were. With `base_version`, the update is refused with 409 when
+/// someone else saved a newer version meanwhile.
+#[utoipa::path(
+    tag = "stored concept sets",
+    summary = "Update a saved concept set",
+    description = "Updates name, description and tags in place. A `concept_set` that resolves differently from \
+        the latest version is stored as a new version; earlier versions never change. With `base_version`, the \
+        update is refused when a newer version was saved meanwhile.",
+    params(("id" = i64, Path, description = "Concept set id")),
+    responses(
+        (status = 200, description = "The updated concept set", body = ConceptSetDetail),
+        (status = 400, description = "Empty name or unreadable concept set"),
+        (status = 404, description = "No such concept set"),
+        (status = 409, description = "The latest version is newer than `base_version`"),
+    )
+)]
+#[put("/api/conceptsets/{id:\\d+}")]
+async fn update_concept_set(
+    path: web::Path<i64>,
+    request: Json<UpdateConceptSetRequest>,
+    identity: Option<ReqData<Identity>>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    let name = request.name.as_deref().map(str::trim);
+    if name.is_some_and(str::is_empty) {
+        return Ok(HttpResponse::BadRequest()
+            .json(serde_json::json!({ "error": "The name cannot be empty" })));
+    }
+    let expression = match request.concept_set.as_deref().map(stored_expression) {
+        Some(Ok(stored)) => Some(stored),
+        Some(Err(e)) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+        }
+        None => None,
+    };
+    let tags = request.tags.as_deref().map(normalize_tags);
+    let author = author(identity, request.created_by.as_deref());
+    let changes = ConceptSetChanges {
+        name,
+        description: request.description.as_deref(),
+        tags: tags.as_deref(),
+        expression: expression
+            .as_ref()
+            .map(|(expression, hash)| (expression, hash.as_str())),
+        author: author.as_deref(),
+        note: request.note.as_deref(),
+        base_version: request.base_version,
+    };
+    info!("Updating concept set {}", id);
+    let mut pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let concept_set = match db::update_concept_set(&mut pg_client, id, &changes).await {
+        Ok(concept_set) => concept_set,
+        Err(PgError::Conflict) => {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": "The concept set has a newer version than base_version, reload it and apply the changes again"
+            })));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let version = latest_version(&pg_client, &concept_set).await?;
+    Ok(HttpResponse::Ok().json(ConceptSetDetail {
+        concept_set,
+        expression: version.expression,
+    }))
+}
+
+/// Removes the concept set from listings and lookups. Its versions are kept for audit.
+#[utoipa::path(
+    tag = "stored concept sets",
+    summary = "Delete a saved concept set",
+    description = "Removes the concept set from listings and lookups; its versions are kept.",
+    params(("id" = i64, Path, description = "Concept set id")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 404, description = "No such concept set"),
+    )
+)]
+#[delete("/api/conceptsets/{id:\\d+}")]
+async fn delete_concept_set(
+    path: web::Path<i64>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    info!("Deleting concept set {}", id);
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    db::delete_concept_set(&pg_client, id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    tag = "stored concept sets",
+    summary = "List the versions of a saved concept set",
+    description = "Every version, newest first.",
+    params(("id" = i64, Path, description = "Concept set id")),
+    responses(
+        (status = 200, description = "The versions", body = Vec<ConceptSetVersion>),
+        (status = 404, description = "No such concept set"),
+    )
+)]
+#[get("/api/conceptsets/{id:\\d+}/versions")]
+async fn list_concept_set_versions(
+    path: web::Path<i64>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let versions = db::get_concept_set_versions(&pg_client, path.into_inner(), None).await?;
+    if versions.is_empty() {
+        return Err(PgError::NotFound.into());
+    }
+    Ok(HttpResponse::Ok().json(versions))
+}
+
+#[utoipa::path(
+    tag = "stored concept sets",
+    summary = "Get a version of a saved concept set",
+    params(
+        ("id" = i64, Path, description = "Concept set id"),
+        ("version" = i32, Path, description = "Version number, starting at 1"),
+    ),
+    responses(
+        (status = 200, description = "The version", body = ConceptSetVersion),
+        (status = 404, description = "No such concept set or version"),
+    )
+)]
+#[get("/api/conceptsets/{id:\\d+}/versions/{version}")]
+async fn get_concept_set_version(
+    path: web::Path<(i64, i32)>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let (id, version) = path.into_inner();
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let version = db::get_concept_set_versions(&pg_client, id, Some(version))
+        .await?
+        .pop()
+        .ok_or(PgError::NotFound)?;
+    Ok(HttpResponse::Ok().json(version))
+}
+
+/// Runs concept set analysis on a stored version, the latest unless `version` is given.
+#[utoipa::path(
+    tag = "stored concept sets",
+    summary = "Analyze a saved concept set",
+    description = "Runs concept set analysis on a saved version, the latest unless `version` is given. The \
+        response is that of the analyze endpoint plus `concept_set_id` and `version`.",
+    params(("id" = i64, Path, description = "Concept set id")),
+    request_body = Option<AnalyzeStoredRequest>,
+    responses(
+        (status = 200, description = "Analysis of the saved concept set", body = StoredConceptSetAnalysis),
+        (status = 404, description = "No such concept set or version"),
+    )
+)]
+#[post("/api/conceptsets/{id:\\d+}/analyze")]
+async fn analyze_stored_concept_set(
+    path: web::Path<i64>,
+    request: Option<Json<AnalyzeStoredRequest>>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    let (version, profile) = request
+        .map(|request| (request.version, request.profile))
+        .unwrap_or_default();
+    info!("Analyzing stored concept set {}", id);
+    let stored = {
+        let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+        let concept_set = db::get_concept_set(&pg_client, id).await?;
+        db::get_concept_set_versions(
+            &pg_client,
+            id,
+            Some(version.unwrap_or(concept_set.latest_version)),
+        )
+        .await?
+        .pop()
+        .ok_or(PgError::NotFound)?
+    };
+    let concept_set = serde_json::to_string(&stored.expression).unwrap_or_default();
+    let result = analyze_with_state(&state, &concept_set, profile, Progress::default()).await?;
+    Ok(HttpResponse::Ok().json(StoredConceptSetAnalysis {
+        concept_set_id: stored.concept_set_id,
+        version: stored.version,
+        analysis: result.analysis(),
+    }))
+}
+
+/// Re-resolves the latest version of a stored concept set and reports the concepts added,
+/// dropped and deprecated since the previous report, then records this resolution as the next
+/// baseline. Resolutions that ran into lookup failures are reported but not recorded.
+#[utoipa::path(
+    tag = "stored concept sets",
+    summary = "Vocabulary update impact on a saved concept set",
+    description = "Re-resolves the latest version of a saved concept set against the current vocabulary and \
+        lists the concepts `added`, `dropped` and `deprecated` since the previous impact report. The resolution \
+        is then recorded as the baseline for the next report; the first report only records it. Resolutions \
+        with `warnings` about failed lookups are not recorded.",
+    params(("id" = i64, Path, description = "Concept set id")),
+    responses(
+        (status = 200, description = "Changes since the previous resolution", body = ConceptSetImpact),
+        (status = 400, description = "The stored expression could not be parsed"),
+        (status = 404, description = "No such concept set"),
+    )
+)]
+#[post("/api/conceptsets/{id:\\d+}/impact")]
+async fn get_concept_set_impact(
+    path: web::Path<i64>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    info!("Checking vocabulary impact on concept set {}", id);
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let concept_set = db::get_concept_set(&pg_client, id).await?;
+    let stored = latest_version(&pg_client, &concept_set).await?;
+    let expression = match validation::parse_concept_set(&stored.expression.to_string()) {
+        Ok(expression) => expression,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+        }
+    };
+    let previous = db::get_latest_concept_set_resolution(&pg_client, id).await?;
+
+    let vocabulary_version = state.vocabulary_catalog.vocabulary_version.as_deref();
+    let mut result = validation::ValidationResult::new();
+    let resolved: BTreeSet<i32> =
+        expansions::resolve_concept_set(&expression, &pg_client, vocabulary_version, &mut result)
+            .await
+            .resolved_concept_ids()
+            .into_iter()
+            .collect();
+    let previous_ids: BTreeSet<i32> = previous
+        .as_ref()
+        .map(|previous| previous.concept_ids.iter().copied().collect())
+        .unwrap_or_default();
+    let previously_invalid: BTreeSet<i32> = previous
+        .as_ref()
+        .map(|previous| previous.invalid_concept_ids.iter().copied().collect())
+        .unwrap_or_default();
+
+    let checked: Vec<i32> = resolved.union(&previous_ids).copied().collect();
+    let invalid = db::get_invalid_concepts_by_ids(&pg_client, &checked).await?;
+    let invalid_ids: Vec<i32> = invalid
+        .iter()
+        .map(|concept| concept.concept_id)
+        .filter(|concept_id| resolved.contains(concept_id))
+        .collect();
+
+    let (added, dropped, deprecated) = match &previous {
+        Some(_) => {
+            let mut deprecated: Vec<Concept> = invalid
+                .into_iter()
+                .filter(|concept| !previously_invalid.contains(&concept.concept_id))
+                .collect();
+            deprecated.sort_by_key(|concept| concept.concept_id);
+            let deprecated_ids: BTreeSet<i32> = deprecated
+                .iter()
+                .map(|concept| concept.concept_id)
+                .collect();
+            let added: Vec<i32> = resolved.difference(&previous_ids).copied().collect();
+            let dropped: Vec<i32> = previous_ids
+                .difference(&resolved)
+                .filter(|concept_id| !deprecated_ids.contains(concept_id))
+                .copied()
+                .collect();
+            (
+                resolved_concepts(db::get_concepts_by_ids(&pg_client, &added).await?),
+                resolved_concepts(db::get_concepts_by_ids(&pg_client, &dropped).await?),
+                deprecated,
+            )
+        }
+        None => (Vec::new(), Vec::new(), Vec::new()),
+    };
+
+    let recorded = result.warnings.is_empty();
+    if recorded {
+        let concept_ids: Vec<i32> = resolved.iter().copied().collect();
+        db::insert_concept_set_resolution(
+            &pg_client,
+            id,
+            stored.version,
+            vocabulary_version,
+            &concept_ids,
+            &invalid_ids,
+        )
+        .await?;
+    }
+
+    Ok(HttpResponse::Ok().json(ConceptSetImpact {
+        concept_set_id: id,
+        version: stored.version,
+        vocabulary_version: vocabulary_version.map(str::to_string),
+        concept_count: resolved.len(),
+        previous: previous.map(|previous| PreviousResolution {
+            version: previous.version,
+            vocabulary_version: previous.vocabulary_version,
+            resolved_at: previous.resolved_at,
+            concept_count: previous.concept_ids.len(),
+        }),
+        added,
+        dropped,
+        deprecated,
+        recorded,
+        warnings: result.warnings,
+    }))
+}
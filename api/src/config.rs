@@ -1,19 +1,235 @@
 use confik::Configuration;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 #[derive(Default, Configuration, Clone)]
 pub struct Configs {
     pub server_addr: String,
     pub qdrant_uri: String,
+    /// Collection searched at startup, can be swapped at runtime through the admin API.
+    #[confik(default = DEFAULT_QDRANT_COLLECTION)]
+    pub qdrant_collection: String,
     pub vectordb_data_path: String,
+    pub qdrant_read: QdrantReadConfig,
+    /// Non-OMOP code systems searchable next to the standard vocabulary, one collection each.
+    #[confik(default = Vec::new())]
+    pub code_systems: Vec<CodeSystemConfig>,
+    /// Earlier vocabulary releases the current one can be compared against.
+    #[confik(default = Vec::new())]
+    pub vocabulary_snapshots: Vec<VocabularySnapshotConfig>,
     pub cors_origins: Vec<String>,
     #[confik(from = DbConfig)]
     pub pg: deadpool_postgres::Config,
+    /// Create and upgrade the Hecate-owned tables in the `hecate` schema on startup.
+    #[confik(default = true)]
+    pub run_migrations: bool,
+    /// Persist searches that returned nothing so they can be curated.
+    #[confik(default = false)]
+    pub capture_zero_result_queries: bool,
+    /// JSON file with score boosting rules applied during final ranking.
+    pub boosting_rules_path: Option<String>,
+    /// Milliseconds concept set recommendations may take before the partial result is
+    /// returned, unlimited when unset.
+    pub recommendation_budget_ms: Option<u64>,
+    pub demo: DemoConfig,
+    pub search: SearchConfig,
+    pub slo: SloConfig,
+    pub ingest: IngestConfig,
+    pub embedding: EmbeddingConfig,
+    pub telemetry: TelemetryConfig,
+    pub auth: AuthConfig,
+    pub rate_limit: RateLimitConfig,
+    pub cache: CacheConfig,
+    pub jobs: JobsConfig,
+    pub grpc: GrpcConfig,
+    pub idempotency: IdempotencyConfig,
+}
+
+impl Configs {
+    /// The configured rate limits, tightened to the demo quota on the demo instance.
+    pub fn rate_limit_in_effect(&self) -> RateLimitConfig {
+        if !self.demo.enabled {
+            return self.rate_limit.clone();
+        }
+        let demo = &self.demo;
+        RateLimitConfig {
+            enabled: true,
+            requests_per_minute: self
+                .rate_limit
+                .requests_per_minute
+                .min(demo.requests_per_minute),
+            burst: self.rate_limit.burst.min(demo.burst),
+            routes: self
+                .rate_limit
+                .routes
+                .iter()
+                .map(|route| RouteRateLimit {
+                    path: route.path.clone(),
+                    requests_per_minute: route.requests_per_minute.min(demo.requests_per_minute),
+                    burst: route.burst.min(demo.burst),
+                })
+                .collect(),
+            trust_forwarded_for: self.rate_limit.trust_forwarded_for,
+            forwarded_for_hops: self.rate_limit.forwarded_for_hops,
+        }
+    }
+
+    pub fn recommendation_budget(&self) -> Option<std::time::Duration> {
+        self.recommendation_budget_ms
+            .map(std::time::Duration::from_millis)
+    }
+}
+
+/// Where search candidates come from, in the order they are consulted.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CandidateSource {
+    /// Curated synonym overrides.
+    Override,
+    /// Exact concept name matches from the concept index.
+    Index,
+    /// Concept name or id lookups in the vocabulary tables.
+    Vocabulary,
+    /// Nearest neighbours of the query embedding.
+    Embedding,
+    /// Postgres full-text matches on concept names.
+    FullText,
+}
+
+impl confik::Configuration for CandidateSource {
+    type Builder = Option<Self>;
+}
+
+/// How the candidates of several sources are combined.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FusionStrategy {
+    /// Stop at the first source that produces candidates.
+    Cascade,
+    /// Consult every source and pool the candidates.
+    Union,
+    /// Consult every source and blend the full-text and embedding scores of each candidate by
+    /// `lexical_weight`.
+    Hybrid,
+}
+
+impl confik::Configuration for FusionStrategy {
+    type Builder = Option<Self>;
+}
+
+fn default_candidate_sources() -> Vec<CandidateSource> {
+    vec![
+        CandidateSource::Override,
+        CandidateSource::Index,
+        CandidateSource::Vocabulary,
+        CandidateSource::Embedding,
+    ]
+}
+
+/// Composition of the search pipeline, see `search::SearchPipeline`.
+#[derive(Debug, Configuration, Clone)]
+pub struct SearchConfig {
+    #[confik(default = default_candidate_sources())]
+    pub candidate_generators: Vec<CandidateSource>,
+    #[confik(default = FusionStrategy::Cascade)]
+    pub fusion: FusionStrategy,
+    /// Apply the boosting rules when ranking.
+    #[confik(default = true)]
+    pub boosting: bool,
+    /// Share of the full-text score in hybrid fusion, the embedding score makes up the rest.
+    #[confik(default = DEFAULT_LEXICAL_WEIGHT)]
+    pub lexical_weight: f64,
+    /// Least similarity of an embedding neighbour, unless a search passes `score_threshold`.
+    #[confik(default = DEFAULT_SCORE_THRESHOLD)]
+    pub score_threshold: f32,
+    /// Points requested from Qdrant for the query embedding, unless a search passes
+    /// `candidates`. More than any limit, to account for the filters applied afterwards.
+    #[confik(default = DEFAULT_EMBEDDING_CANDIDATES)]
+    pub embedding_candidates: u64,
+    /// Points requested from Qdrant as neighbours of exact matches, unless a search passes
+    /// `candidates`.
+    #[confik(default = DEFAULT_NEIGHBOUR_CANDIDATES)]
+    pub neighbour_candidates: u64,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            candidate_generators: default_candidate_sources(),
+            fusion: FusionStrategy::Cascade,
+            boosting: true,
+            lexical_weight: DEFAULT_LEXICAL_WEIGHT,
+            score_threshold: DEFAULT_SCORE_THRESHOLD,
+            embedding_candidates: DEFAULT_EMBEDDING_CANDIDATES,
+            neighbour_candidates: DEFAULT_NEIGHBOUR_CANDIDATES,
+        }
+    }
+}
+
+const DEFAULT_QDRANT_COLLECTION: &str = "meddra";
+const DEFAULT_LEXICAL_WEIGHT: f64 = 0.3;
+const DEFAULT_SCORE_THRESHOLD: f32 = 0.50;
+const DEFAULT_EMBEDDING_CANDIDATES: u64 = 250;
+const DEFAULT_NEIGHBOUR_CANDIDATES: u64 = 500;
+const DEFAULT_DEMO_MAX_LIMIT: u64 = 25;
+const DEFAULT_DEMO_MAX_CONCEPT_SET_ITEMS: usize = 50;
+const DEFAULT_DEMO_CACHE_MAX_AGE_SECS: u32 = 300;
+const DEFAULT_DEMO_REQUESTS_PER_MINUTE: u32 = 60;
+const DEFAULT_DEMO_BURST: u32 = 20;
+const DEFAULT_DEMO_WATERMARK: &str =
+    "Hecate public demo - results are not for clinical or production use";
+
+/// Settings for running the public demo instance: state-changing endpoints are disabled,
+/// limits are clamped, responses are cacheable and carry a watermark.
+#[derive(Debug, Configuration, Clone)]
+pub struct DemoConfig {
+    #[confik(default = false)]
+    pub enabled: bool,
+    #[confik(default = DEFAULT_DEMO_MAX_LIMIT)]
+    pub max_limit: u64,
+    #[confik(default = DEFAULT_DEMO_MAX_CONCEPT_SET_ITEMS)]
+    pub max_concept_set_items: usize,
+    #[confik(default = DEFAULT_DEMO_CACHE_MAX_AGE_SECS)]
+    pub cache_max_age_secs: u32,
+    /// Quota of each client, rate limiting is always on for the demo instance and no route gets
+    /// more than this.
+    #[confik(default = DEFAULT_DEMO_REQUESTS_PER_MINUTE)]
+    pub requests_per_minute: u32,
+    #[confik(default = DEFAULT_DEMO_BURST)]
+    pub burst: u32,
+    /// Sent as the `x-hecate-demo` header and the `watermark` field of JSON objects.
+    #[confik(default = DEFAULT_DEMO_WATERMARK)]
+    pub watermark: String,
+}
+
+impl Default for DemoConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_limit: DEFAULT_DEMO_MAX_LIMIT,
+            max_concept_set_items: DEFAULT_DEMO_MAX_CONCEPT_SET_ITEMS,
+            cache_max_age_secs: DEFAULT_DEMO_CACHE_MAX_AGE_SECS,
+            requests_per_minute: DEFAULT_DEMO_REQUESTS_PER_MINUTE,
+            burst: DEFAULT_DEMO_BURST,
+            watermark: DEFAULT_DEMO_WATERMARK.to_string(),
+        }
+    }
+}
+
+impl DemoConfig {
+    /// Clamps a requested result limit to what the demo instance allows.
+    pub fn clamp_limit(&self, requested: u64) -> u64 {
+        if self.enabled {
+            requested.min(self.max_limit)
+        } else {
+            requested
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(transparent)]
-struct DbConfig(deadpool_postgres::Config);
+pub struct DbConfig(deadpool_postgres::Config);
 
 impl From<DbConfig> for deadpool_postgres::Config {
     fn from(value: DbConfig) -> Self {
@@ -24,3 +240,490 @@ impl From<DbConfig> for deadpool_postgres::Config {
 impl confik::Configuration for DbConfig {
     type Builder = Option<Self>;
 }
+
+const DEFAULT_SLO_WINDOW: usize = 1000;
+const DEFAULT_SLO_BUDGET_MS: u64 = 1000;
+
+/// A p95 latency budget for one route, keyed by its pattern such as `/api/concepts/{id}`.
+#[derive(Debug, Configuration, Clone, Serialize)]
+pub struct LatencyBudget {
+    pub path: String,
+    pub p95_ms: u64,
+}
+
+fn default_latency_budgets() -> Vec<LatencyBudget> {
+    vec![
+        LatencyBudget {
+            path: "/api/search".to_string(),
+            p95_ms: 500,
+        },
+        LatencyBudget {
+            path: "/api/conceptsets/analyze".to_string(),
+            p95_ms: 10_000,
+        },
+        LatencyBudget {
+            path: "/api/conceptsets/review".to_string(),
+            p95_ms: 10_000,
+        },
+    ]
+}
+
+/// Latency objectives: percentiles are tracked over the last `window` requests per route and
+/// compared against the route's budget, or `default_budget_ms` for routes without one.
+#[derive(Debug, Configuration, Clone)]
+pub struct SloConfig {
+    #[confik(default = DEFAULT_SLO_WINDOW)]
+    pub window: usize,
+    #[confik(default = DEFAULT_SLO_BUDGET_MS)]
+    pub default_budget_ms: u64,
+    #[confik(default = default_latency_budgets())]
+    pub budgets: Vec<LatencyBudget>,
+    /// Skip concept set recommendations while search is over its budget.
+    #[confik(default = false)]
+    pub shed_recommendations: bool,
+}
+
+impl Default for SloConfig {
+    fn default() -> Self {
+        Self {
+            window: DEFAULT_SLO_WINDOW,
+            default_budget_ms: DEFAULT_SLO_BUDGET_MS,
+            budgets: default_latency_budgets(),
+            shed_recommendations: false,
+        }
+    }
+}
+
+impl SloConfig {
+    pub fn budget_ms(&self, path: &str) -> u64 {
+        self.budgets
+            .iter()
+            .find(|budget| budget.path == path)
+            .map_or(self.default_budget_ms, |budget| budget.p95_ms)
+    }
+}
+
+/// Read options for clustered Qdrant deployments, applied to every point lookup and query.
+#[derive(Debug, Default, Configuration, Clone)]
+pub struct QdrantReadConfig {
+    /// `all`, `majority`, `quorum` or a number of replicas that must agree, Qdrant's default of
+    /// a single replica when unset.
+    pub consistency: Option<String>,
+    /// Shard keys reads are restricted to, for collections using custom sharding.
+    #[confik(default = Vec::new())]
+    pub shard_keys: Vec<String>,
+}
+
+/// An auxiliary code system such as an institution-local lab catalog, kept in its own Qdrant
+/// collection and addressed by `id` in the `system` search parameter.
+#[derive(Debug, Configuration, Clone, Serialize)]
+pub struct CodeSystemConfig {
+    pub id: String,
+    pub name: String,
+    pub collection: String,
+}
+
+/// An earlier vocabulary release, e.g. the previous Athena download, loaded into its own schema
+/// with the `cdm` table layout and addressed by `name` in vocabulary diffs.
+#[derive(Debug, Configuration, Clone, Serialize)]
+pub struct VocabularySnapshotConfig {
+    pub name: String,
+    pub schema: String,
+}
+
+const DEFAULT_INGEST_BATCH_SIZE: usize = 256;
+
+/// Settings of `hecate-api ingest`, which builds the concept collection from the vocabulary
+/// tables.
+#[derive(Debug, Configuration, Clone)]
+pub struct IngestConfig {
+    /// Collection to write, `QDRANT_COLLECTION` when unset.
+    pub collection: Option<String>,
+    /// Vocabularies to ingest, all of them when empty.
+    #[confik(default = Vec::new())]
+    pub vocabulary_ids: Vec<String>,
+    /// Also embed the names in `concept_synonym`.
+    #[confik(default = true)]
+    pub include_synonyms: bool,
+    /// Names embedded per request and upserted per call.
+    #[confik(default = DEFAULT_INGEST_BATCH_SIZE)]
+    pub batch_size: usize,
+}
+
+impl Default for IngestConfig {
+    fn default() -> Self {
+        Self {
+            collection: None,
+            vocabulary_ids: Vec::new(),
+            include_synonyms: true,
+            batch_size: DEFAULT_INGEST_BATCH_SIZE,
+        }
+    }
+}
+
+/// The service that turns text into vectors, see `embeddings::EmbeddingProvider`.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmbeddingBackend {
+    /// The OpenAI embeddings API, or a compatible one at `url`.
+    Openai,
+    /// A local Ollama server.
+    Ollama,
+    /// HuggingFace text-embeddings-inference.
+    Tei,
+    /// Any service accepting `{"model", "inputs"}` and answering `{"embeddings"}`.
+    Http,
+    /// A sentence-transformer in `model_path`, run in-process.
+    Local,
+}
+
+impl confik::Configuration for EmbeddingBackend {
+    type Builder = Option<Self>;
+}
+
+const DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-3-large";
+const DEFAULT_EMBEDDING_DIMENSIONS: u32 = 1024;
+const DEFAULT_EMBEDDING_MAX_BATCH_SIZE: usize = 256;
+
+/// Queries must be embedded with the model and dimensions the collections were built with.
+#[derive(Debug, Configuration, Clone)]
+pub struct EmbeddingConfig {
+    #[confik(default = EmbeddingBackend::Openai)]
+    pub provider: EmbeddingBackend,
+    #[confik(default = DEFAULT_EMBEDDING_MODEL)]
+    pub model: String,
+    #[confik(default = DEFAULT_EMBEDDING_DIMENSIONS)]
+    pub dimensions: u32,
+    /// Base URL of the service, the provider's usual local address when unset.
+    pub url: Option<String>,
+    /// Sent as a bearer token, OpenAI falls back to `OPENAI_API_KEY`.
+    pub api_key: Option<String>,
+    /// Inputs per request, larger batches are split. TEI rejects more than 32 by default.
+    #[confik(default = DEFAULT_EMBEDDING_MAX_BATCH_SIZE)]
+    pub max_batch_size: usize,
+    /// Directory of the sentence-transformer the local provider loads, holding its
+    /// `config.json`, `tokenizer.json` and `model.safetensors`.
+    pub model_path: Option<String>,
+}
+
+impl Default for EmbeddingConfig {
+    fn default() -> Self {
+        Self {
+            provider: EmbeddingBackend::Openai,
+            model: DEFAULT_EMBEDDING_MODEL.to_string(),
+            dimensions: DEFAULT_EMBEDDING_DIMENSIONS,
+            url: None,
+            api_key: None,
+            max_batch_size: DEFAULT_EMBEDDING_MAX_BATCH_SIZE,
+            model_path: None,
+        }
+    }
+}
+
+const DEFAULT_TELEMETRY_SERVICE_NAME: &str = "hecate-api";
+const DEFAULT_TELEMETRY_EXPORT_INTERVAL_MS: u64 = 5000;
+
+/// OpenTelemetry export of request traces, see `telemetry`.
+#[derive(Debug, Configuration, Clone)]
+pub struct TelemetryConfig {
+    /// Base URL of an OTLP/HTTP collector such as `http://localhost:4318`, spans are only
+    /// recorded when set.
+    pub otlp_endpoint: Option<String>,
+    #[confik(default = DEFAULT_TELEMETRY_SERVICE_NAME)]
+    pub service_name: String,
+    /// How often finished spans are sent to the collector.
+    #[confik(default = DEFAULT_TELEMETRY_EXPORT_INTERVAL_MS)]
+    pub export_interval_ms: u64,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: DEFAULT_TELEMETRY_SERVICE_NAME.to_string(),
+            export_interval_ms: DEFAULT_TELEMETRY_EXPORT_INTERVAL_MS,
+        }
+    }
+}
+
+/// What an API key may do, see `auth::required_scope` for the routes each scope covers.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// Search, concept lookups and the vocabulary catalog.
+    Read,
+    /// Concept set analysis, review, expansion and export.
+    Validate,
+    /// The `/api/admin` endpoints. Grants every other scope as well.
+    Admin,
+}
+
+impl confik::Configuration for Scope {
+    type Builder = Option<Self>;
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Validate => "validate",
+            Scope::Admin => "admin",
+        }
+    }
+
+    pub fn parse(scope: &str) -> Option<Scope> {
+        match scope {
+            "read" => Some(Scope::Read),
+            "validate" => Some(Scope::Validate),
+            "admin" => Some(Scope::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// An API key defined in the configuration rather than in `hecate.api_key`, e.g. the first
+/// admin key of a new deployment.
+#[derive(Debug, Configuration, Clone)]
+pub struct ApiKeyConfig {
+    pub name: String,
+    pub key: String,
+    pub scopes: Vec<Scope>,
+}
+
+/// Requiring an API key or an OIDC token on every request except health, metrics and the API
+/// documentation.
+#[derive(Debug, Default, Configuration, Clone)]
+pub struct AuthConfig {
+    #[confik(default = false)]
+    pub enabled: bool,
+    #[confik(default = Vec::new())]
+    pub api_keys: Vec<ApiKeyConfig>,
+    pub oidc: OidcConfig,
+}
+
+const DEFAULT_OIDC_SCOPES_CLAIM: &str = "scope";
+
+fn default_oidc_scopes() -> Vec<Scope> {
+    vec![Scope::Read]
+}
+
+/// Bearer tokens issued by an OpenID Connect provider, accepted next to API keys when `issuer`
+/// is set.
+#[derive(Debug, Configuration, Clone)]
+pub struct OidcConfig {
+    /// Must equal the token's `iss` claim, e.g. `https://login.example.org/realms/ohdsi`.
+    pub issuer: Option<String>,
+    /// Must be among the token's `aud` claim when set.
+    pub audience: Option<String>,
+    /// Where the signing keys are published, discovered from the issuer's
+    /// `/.well-known/openid-configuration` when unset.
+    pub jwks_url: Option<String>,
+    /// Claim holding the token's scopes or roles, as a space-separated string or an array.
+    #[confik(default = DEFAULT_OIDC_SCOPES_CLAIM)]
+    pub scopes_claim: String,
+    /// Prefix of the claim values naming Hecate scopes, e.g. `hecate:` for `hecate:admin`.
+    #[confik(default = String::new())]
+    pub scope_prefix: String,
+    /// Scopes every valid token gets, whatever its claims say.
+    #[confik(default = default_oidc_scopes())]
+    pub default_scopes: Vec<Scope>,
+}
+
+impl Default for OidcConfig {
+    fn default() -> Self {
+        Self {
+            issuer: None,
+            audience: None,
+            jwks_url: None,
+            scopes_claim: DEFAULT_OIDC_SCOPES_CLAIM.to_string(),
+            scope_prefix: String::new(),
+            default_scopes: default_oidc_scopes(),
+        }
+    }
+}
+
+const DEFAULT_RATE_LIMIT_REQUESTS_PER_MINUTE: u32 = 600;
+const DEFAULT_RATE_LIMIT_BURST: u32 = 100;
+const DEFAULT_RATE_LIMIT_FORWARDED_FOR_HOPS: usize = 1;
+
+/// A tighter quota for the routes under `path`, such as the embedding-backed search.
+#[derive(Debug, Configuration, Clone, Serialize)]
+pub struct RouteRateLimit {
+    pub path: String,
+    pub requests_per_minute: u32,
+    pub burst: u32,
+}
+
+fn default_route_rate_limits() -> Vec<RouteRateLimit> {
+    vec![RouteRateLimit {
+        path: "/api/search".to_string(),
+        requests_per_minute: 120,
+        burst: 20,
+    }]
+}
+
+/// Token buckets per client, the API key or token holder when authenticated and the IP address
+/// otherwise. Each client may send `burst` requests at once, refilled at `requests_per_minute`.
+#[derive(Debug, Configuration, Clone)]
+pub struct RateLimitConfig {
+    #[confik(default = false)]
+    pub enabled: bool,
+    #[confik(default = DEFAULT_RATE_LIMIT_REQUESTS_PER_MINUTE)]
+    pub requests_per_minute: u32,
+    #[confik(default = DEFAULT_RATE_LIMIT_BURST)]
+    pub burst: u32,
+    /// Quotas used instead of the default for routes under their path, the longest match wins.
+    #[confik(default = default_route_rate_limits())]
+    pub routes: Vec<RouteRateLimit>,
+    /// Key anonymous clients by their `X-Forwarded-For` address instead of the peer address,
+    /// only safe behind a proxy that appends to it.
+    #[confik(default = false)]
+    pub trust_forwarded_for: bool,
+    /// Trusted proxies in front of the API. The client's address is the one the outermost of
+    /// them appended, this many entries from the right of `X-Forwarded-For`; the entries left
+    /// of it are whatever the client sent.
+    #[confik(default = DEFAULT_RATE_LIMIT_FORWARDED_FOR_HOPS)]
+    pub forwarded_for_hops: usize,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            requests_per_minute: DEFAULT_RATE_LIMIT_REQUESTS_PER_MINUTE,
+            burst: DEFAULT_RATE_LIMIT_BURST,
+            routes: default_route_rate_limits(),
+            trust_forwarded_for: false,
+            forwarded_for_hops: DEFAULT_RATE_LIMIT_FORWARDED_FOR_HOPS,
+        }
+    }
+}
+
+const DEFAULT_CACHE_CAPACITY: usize = 10_000;
+const DEFAULT_CACHE_TTL_SECS: u64 = 300;
+const DEFAULT_REDIS_TIMEOUT_MS: u64 = 100;
+const DEFAULT_REDIS_KEY_PREFIX: &str = "hecate:";
+const DEFAULT_RECOMMENDATION_CACHE_CAPACITY: usize = 1000;
+const DEFAULT_RECOMMENDATION_CACHE_TTL_SECS: u64 = 3600;
+
+/// The in-process cache of search results, keyed by normalized query and filters, optionally
+/// backed by Redis so replicas share search results, UMLS definitions and descendant lookups.
+#[derive(Debug, Configuration, Clone)]
+pub struct CacheConfig {
+    #[confik(default = true)]
+    pub enabled: bool,
+    /// Entries kept before the least frequently used ones are evicted.
+    #[confik(default = DEFAULT_CACHE_CAPACITY)]
+    pub capacity: usize,
+    #[confik(default = DEFAULT_CACHE_TTL_SECS)]
+    pub ttl_secs: u64,
+    /// `redis://[:password@]host[:port][/db]`, unset to keep every cache in-process.
+    pub redis_url: Option<String>,
+    /// Redis calls slower than this count as misses, so a struggling Redis cannot slow searches.
+    #[confik(default = DEFAULT_REDIS_TIMEOUT_MS)]
+    pub redis_timeout_ms: u64,
+    /// Prepended to every key, for Redis instances shared with other applications.
+    #[confik(default = DEFAULT_REDIS_KEY_PREFIX)]
+    pub redis_key_prefix: String,
+    /// Concept sets whose recommendations are kept in memory, see `RecommendationCache`.
+    #[confik(default = DEFAULT_RECOMMENDATION_CACHE_CAPACITY)]
+    pub recommendation_capacity: usize,
+    /// Seconds the recommendations of a concept set are reused for.
+    #[confik(default = DEFAULT_RECOMMENDATION_CACHE_TTL_SECS)]
+    pub recommendation_ttl_secs: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            capacity: DEFAULT_CACHE_CAPACITY,
+            ttl_secs: DEFAULT_CACHE_TTL_SECS,
+            redis_url: None,
+            redis_timeout_ms: DEFAULT_REDIS_TIMEOUT_MS,
+            redis_key_prefix: DEFAULT_REDIS_KEY_PREFIX.to_string(),
+            recommendation_capacity: DEFAULT_RECOMMENDATION_CACHE_CAPACITY,
+            recommendation_ttl_secs: DEFAULT_RECOMMENDATION_CACHE_TTL_SECS,
+        }
+    }
+}
+
+/// Large enough for the source code lists of mapping jobs, the largest bodies the API accepts.
+const DEFAULT_IDEMPOTENCY_MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
+/// Longer than any request runs before the server times it out.
+const DEFAULT_IDEMPOTENCY_CLAIM_TIMEOUT_SECS: u64 = 120;
+
+/// Requests carrying an `Idempotency-Key` header, see `idempotency::idempotency_guard`.
+#[derive(Debug, Configuration, Clone)]
+pub struct IdempotencyConfig {
+    /// Bodies read and hashed to recognize repeated requests, larger ones are refused with 413.
+    #[confik(default = DEFAULT_IDEMPOTENCY_MAX_BODY_BYTES)]
+    pub max_body_bytes: usize,
+    /// How long a key stays claimed by a request that has not responded. A retry after that
+    /// takes the key over instead of getting 409, in case the original request was lost.
+    #[confik(default = DEFAULT_IDEMPOTENCY_CLAIM_TIMEOUT_SECS)]
+    pub claim_timeout_secs: u64,
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        Self {
+            max_body_bytes: DEFAULT_IDEMPOTENCY_MAX_BODY_BYTES,
+            claim_timeout_secs: DEFAULT_IDEMPOTENCY_CLAIM_TIMEOUT_SECS,
+        }
+    }
+}
+
+const DEFAULT_JOB_CONCURRENCY: usize = 2;
+const DEFAULT_JOB_TIMEOUT_SECS: u64 = 3600;
+const DEFAULT_JOB_RETENTION_HOURS: i64 = 24;
+
+/// Background concept set validations, see `jobs`.
+#[derive(Debug, Configuration, Clone)]
+pub struct JobsConfig {
+    /// Jobs analyzed at the same time by one replica, later ones wait in the queue.
+    #[confik(default = DEFAULT_JOB_CONCURRENCY)]
+    pub max_concurrent: usize,
+    /// Seconds from submission after which a job that has not finished is failed, queueing
+    /// included.
+    #[confik(default = DEFAULT_JOB_TIMEOUT_SECS)]
+    pub timeout_secs: u64,
+    /// Hours finished jobs and their results are kept.
+    #[confik(default = DEFAULT_JOB_RETENTION_HOURS)]
+    pub retention_hours: i64,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: DEFAULT_JOB_CONCURRENCY,
+            timeout_secs: DEFAULT_JOB_TIMEOUT_SECS,
+            retention_hours: DEFAULT_JOB_RETENTION_HOURS,
+        }
+    }
+}
+
+const DEFAULT_GRPC_ADDR: &str = "0.0.0.0:50051";
+const DEFAULT_GRPC_THREADS: usize = 2;
+
+/// The optional gRPC server, see `grpc`.
+#[derive(Debug, Configuration, Clone)]
+pub struct GrpcConfig {
+    #[confik(default = false)]
+    pub enabled: bool,
+    #[confik(default = DEFAULT_GRPC_ADDR)]
+    pub addr: String,
+    /// Threads running gRPC requests, apart from the HTTP workers.
+    #[confik(default = DEFAULT_GRPC_THREADS)]
+    pub threads: usize,
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            addr: DEFAULT_GRPC_ADDR.to_string(),
+            threads: DEFAULT_GRPC_THREADS,
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use crate::domain::{SynonymOverride, ZeroResultQuery};
+use crate::errors::PgError;
+use crate::{StateWrapper, db};
+use actix_web::web::{Data, Json, Query};
+use actix_web::{Error, HttpResponse, delete, get, post, put, web};
+use deadpool_postgres::Pool;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::{IntoParams, ToSchema};
+
+/// Curated alias (lowercase) to concept ids, consulted by search before the concept index and
+/// vector search. Aliases come from curated zero-result queries or are added by hand.
+pub type SynonymOverrides = HashMap<String, Vec<i32>>;
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ZeroResultStatus {
+    Open,
+    Resolved,
+    Dismissed,
+}
+
+impl ZeroResultStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ZeroResultStatus::Open => "open",
+            ZeroResultStatus::Resolved => "resolved",
+            ZeroResultStatus::Dismissed => "dismissed",
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ZeroResultListParameters {
+    status: Option<ZeroResultStatus>,
+    #[param(default = 100)]
+    limit: Option<i64>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct OverrideRequest {
+    concept_ids: Vec<i32>,
+    created_by: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SynonymListParameters {
+    alias: Option<String>,
+    concept_id: Option<i32>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CreateSynonymRequest {
+    alias: String,
+    concept_id: i32,
+    created_by: Option<String>,
+    note: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct UpdateSynonymRequest {
+    alias: Option<String>,
+    concept_id: Option<i32>,
+    note: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct StatusRequest {
+    status: ZeroResultStatus,
+}
+
+/// The number of override concepts attached to a zero-result query.
+#[derive(Serialize, ToSchema)]
+struct InsertedOverrides {
+    inserted: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct CreatedSynonym {
+    id: i64,
+}
+
+pub async fn load_synonym_overrides(pg_pool: &Pool) -> Result<SynonymOverrides, PgError> {
+    let pg_client = pg_pool.get().await?;
+    let overrides = db::get_synonym_overrides(&pg_client).await?;
+    info!("{} synonym overrides loaded", overrides.len());
+    Ok(overrides)
+}
+
+/// Reloads the in-memory override table after it was changed through the admin API.
+pub async fn refresh_synonym_overrides(state: &StateWrapper) -> Result<(), PgError> {
+    let overrides = load_synonym_overrides(&state.pg_pool).await?;
+    *state.synonym_overrides.write().unwrap() = overrides;
+    state.search_cache.invalidate_all();
+    state.shared_cache.invalidate_searches().await;
+    Ok(())
+}
+
+/// Records a query that returned nothing, without holding up the response.
+pub fn capture_zero_result_query(state: &StateWrapper, query: &str, filters: serde_json::Value) {
+    if !state.config.capture_zero_result_queries || query.is_empty() {
+        return;
+    }
+    let pg_pool = state.pg_pool.clone();
+    let query = query.to_string();
+    actix_web::rt::spawn(async move {
+        let result = match pg_pool.get().await {
+            Ok(pg_client) => db::record_zero_result_query(&pg_client, &query, &filters).await,
+            Err(e) => Err(PgError::PoolError(e)),
+        };
+        if let Err(e) = result {
+            warn!("Could not record zero-result query {:?}: {}", query, e);
+        }
+    });
+}
+
+#[utoipa::path(
+    tag = "admin",
+    summary = "List zero-result queries",
+    description = "Captured searches that returned nothing, most frequent first, with the concepts attached to \
+        them as overrides.",
+    params(ZeroResultListParameters),
+    responses((status = 200, description = "Zero-result queries", body = Vec<ZeroResultQuery>))
+)]
+#[get("/api/admin/zero-results")]
+async fn list_zero_result_queries(
+    parameters: Query<ZeroResultListParameters>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let queries = db::get_zero_result_queries(
+        &pg_client,
+        parameters.status.map(|status| status.as_str()),
+        parameters.limit.unwrap_or(100),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(queries))
+}
+
+#[utoipa::path(
+    tag = "admin",
+    summary = "Resolve a zero-result query with concepts",
+    description = "Adds the query as a synonym override of each concept and marks it resolved, so the same \
+        search finds the concepts from now on.",
+    params(("id" = i64, Path, description = "Zero-result query id")),
+    responses(
+        (status = 200, description = "The number of overrides added", body = InsertedOverrides),
+        (status = 404, description = "No such zero-result query"),
+    )
+)]
+#[post("/api/admin/zero-results/{id}/overrides")]
+async fn add_zero_result_overrides(
+    path: web::Path<i64>,
+    request: Json<OverrideRequest>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    info!(
+        "Attaching {} override concepts to zero-result query {}",
+        request.concept_ids.len(),
+        id
+    );
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let inserted = db::add_zero_result_overrides(
+        &pg_client,
+        id,
+        &request.concept_ids,
+        request.created_by.as_deref(),
+    )
+    .await?;
+    db::set_zero_result_query_status(&pg_client, id, ZeroResultStatus::Resolved.as_str()).await?;
+    refresh_synonym_overrides(&state).await?;
+    Ok(HttpResponse::Ok().json(InsertedOverrides { inserted }))
+}
+
+#[utoipa::path(
+    tag = "admin",
+    summary = "Set the status of a zero-result query",
+    params(("id" = i64, Path, description = "Zero-result query id")),
+    responses(
+        (status = 204, description = "Status set"),
+        (status = 404, description = "No such zero-result query"),
+    )
+)]
+#[put("/api/admin/zero-results/{id}/status")]
+async fn set_zero_result_status(
+    path: web::Path<i64>,
+    request: Json<StatusRequest>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    db::set_zero_result_query_status(&pg_client, path.into_inner(), request.status.as_str())
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Checks that a curated alias points at a concept that actually exists.
+async fn unknown_concept(
+    pg_client: &deadpool_postgres::Client,
+    concept_id: i32,
+) -> Result<Option<HttpResponse>, PgError> {
+    if db::get_concepts_by_ids(pg_client, &[concept_id])
+        .await?
+        .is_empty()
+    {
+        return Ok(Some(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Concept {} does not exist in the vocabulary", concept_id)
+        }))));
+    }
+    Ok(None)
+}
+
+#[utoipa::path(
+    tag = "admin",
+    summary = "List synonym overrides",
+    description = "Curated aliases that search resolves to a concept before consulting the concept index and \
+        vector search.",
+    params(SynonymListParameters),
+    responses((status = 200, description = "Synonym overrides", body = Vec<SynonymOverride>))
+)]
+#[get("/api/admin/synonyms")]
+async fn list_synonyms(
+    parameters: Query<SynonymListParameters>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let synonyms = db::get_synonym_override_rows(
+        &pg_client,
+        parameters.alias.as_deref(),
+        parameters.concept_id,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(synonyms))
+}
+
+#[utoipa::path(
+    tag = "admin",
+    summary = "Add a synonym override",
+    responses(
+        (status = 201, description = "The id of the override", body = CreatedSynonym),
+        (status = 400, description = "Empty alias or unknown concept"),
+    )
+)]
+#[post("/api/admin/synonyms")]
+async fn create_synonym(
+    request: Json<CreateSynonymRequest>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let alias = request.alias.trim();
+    if alias.is_empty() {
+        return Ok(HttpResponse::BadRequest()
+            .json(serde_json::json!({ "error": "Alias cannot be empty" })));
+    }
+    info!(
+        "Adding synonym override {:?} -> {}",
+        alias, request.concept_id
+    );
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    if let Some(response) = unknown_concept(&pg_client, request.concept_id).await? {
+        return Ok(response);
+    }
+    let id = db::insert_synonym_override(
+        &pg_client,
+        alias,
+        request.concept_id,
+        request.created_by.as_deref(),
+        request.note.as_deref(),
+    )
+    .await?;
+    refresh_synonym_overrides(&state).await?;
+    Ok(HttpResponse::Created().json(CreatedSynonym { id }))
+}
+
+#[utoipa::path(
+    tag = "admin",
+    summary = "Update a synonym override",
+    description = "Changes the fields the request sets and keeps the others.",
+    params(("id" = i64, Path, description = "Synonym override id")),
+    responses(
+        (status = 204, description = "Updated"),
+        (status = 400, description = "Empty alias or unknown concept"),
+        (status = 404, description = "No such synonym override"),
+    )
+)]
+#[put("/api/admin/synonyms/{id}")]
+async fn update_synonym(
+    path: web::Path<i64>,
+    request: Json<UpdateSynonymRequest>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    let alias = request.alias.as_deref().map(str::trim);
+    if alias.is_some_and(str::is_empty) {
+        return Ok(HttpResponse::BadRequest()
+            .json(serde_json::json!({ "error": "Alias cannot be empty" })));
+    }
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    if let Some(concept_id) = request.concept_id
+        && let Some(response) = unknown_concept(&pg_client, concept_id).await?
+    {
+        return Ok(response);
+    }
+    db::update_synonym_override(
+        &pg_client,
+        id,
+        alias,
+        request.concept_id,
+        request.note.as_deref(),
+    )
+    .await?;
+    refresh_synonym_overrides(&state).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    tag = "admin",
+    summary = "Delete a synonym override",
+    params(("id" = i64, Path, description = "Synonym override id")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 404, description = "No such synonym override"),
+    )
+)]
+#[delete("/api/admin/synonyms/{id}")]
+async fn delete_synonym(
+    path: web::Path<i64>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    db::delete_synonym_override(&pg_client, path.into_inner()).await?;
+    refresh_synonym_overrides(&state).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -1,16 +1,133 @@
-use crate::domain::{Concept, RelatedConcept};
+use crate::domain::{
+    ApiKey, ArchivedConceptSet, ArchivedConceptSetVersion, ArchivedRecommendationFeedback,
+    ArchivedSynonymOverride, ArchivedZeroResultQuery, Concept, ConceptClass, ConceptClassSummary,
+    ConceptCount, ConceptSetChanges, ConceptSetResolution, ConceptSetVersion, ConceptSynonym,
+    Domain, HierarchyConcept, HierarchyEdge, HierarchyRoot, IdempotencyKey, IdempotencyRecord,
+    IngestName, LinkedConcept, MappingJob, MappingJobRow, MappingReview, MappingReviewChange,
+    MappingReviewComment, NewRecommendationFeedback, RecommendationFeedback, RelatedConcept,
+    ResolvedExpansion, StoredConceptSet, SynonymOverride, ValidationJob, Vocabulary,
+    VocabularyChange, ZeroResultQuery,
+};
 use crate::errors::PgError;
 use deadpool_postgres::Client;
+use futures::{Stream, StreamExt};
 use log::info;
+use std::collections::{HashMap, HashSet};
 use tokio_pg_mapper::FromTokioPostgresRow;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::types::ToSql;
+use tracing::instrument;
 
+/// Concept IDs bound as one array parameter of the batch lookups; larger inputs are queried
+/// in chunks so a single statement's result stays bounded.
+const CONCEPT_IDS_PER_QUERY: usize = 10_000;
+
+/// Hecate-owned tables live in the `hecate` schema, next to the read-only vocabulary in `cdm`.
+/// Migrations are applied in order and recorded so they only ever run once.
+const MIGRATIONS: &[(&str, &str)] = &[
+    (
+        "0001_zero_result_queries",
+        include_str!("../sql/migrations/0001_zero_result_queries.sql"),
+    ),
+    (
+        "0002_synonym_override_provenance",
+        include_str!("../sql/migrations/0002_synonym_override_provenance.sql"),
+    ),
+    (
+        "0003_idempotency_keys",
+        include_str!("../sql/migrations/0003_idempotency_keys.sql"),
+    ),
+    (
+        "0004_resolved_expansions",
+        include_str!("../sql/migrations/0004_resolved_expansions.sql"),
+    ),
+    (
+        "0005_api_keys",
+        include_str!("../sql/migrations/0005_api_keys.sql"),
+    ),
+    (
+        "0006_concept_sets",
+        include_str!("../sql/migrations/0006_concept_sets.sql"),
+    ),
+    (
+        "0007_validation_jobs",
+        include_str!("../sql/migrations/0007_validation_jobs.sql"),
+    ),
+    (
+        "0008_concept_set_resolutions",
+        include_str!("../sql/migrations/0008_concept_set_resolutions.sql"),
+    ),
+    (
+        "0009_recommendation_feedback",
+        include_str!("../sql/migrations/0009_recommendation_feedback.sql"),
+    ),
+    (
+        "0010_mapping_jobs",
+        include_str!("../sql/migrations/0010_mapping_jobs.sql"),
+    ),
+    (
+        "0011_mapping_reviews",
+        include_str!("../sql/migrations/0011_mapping_reviews.sql"),
+    ),
+    (
+        "0012_idempotency_key_owner",
+        include_str!("../sql/migrations/0012_idempotency_key_owner.sql"),
+    ),
+    (
+        "0013_vocabulary_diffs",
+        include_str!("../sql/migrations/0013_vocabulary_diffs.sql"),
+    ),
+    (
+        "0014_concept_set_uid",
+        include_str!("../sql/migrations/0014_concept_set_uid.sql"),
+    ),
+    (
+        "0015_mapping_job_rows",
+        include_str!("../sql/migrations/0015_mapping_job_rows.sql"),
+    ),
+    (
+        "0016_idempotency_key_claimed_at",
+        include_str!("../sql/migrations/0016_idempotency_key_claimed_at.sql"),
+    ),
+];
+
+pub async fn run_migrations(client: &mut Client) -> Result<(), PgError> {
+    client
+        .batch_execute(include_str!("../sql/create_schema_migrations.sql"))
+        .await?;
+    let applied: HashSet<String> = client
+        .query("SELECT version FROM hecate.schema_migrations", &[])
+        .await?
+        .iter()
+        .map(|row| row.get("version"))
+        .collect();
+
+    for (version, sql) in MIGRATIONS {
+        if applied.contains(*version) {
+            continue;
+        }
+        info!("Applying migration {}", version);
+        let transaction = client.transaction().await?;
+        transaction.batch_execute(sql).await?;
+        transaction
+            .execute(
+                "INSERT INTO hecate.schema_migrations (version) VALUES ($1)",
+                &[version],
+            )
+            .await?;
+        transaction.commit().await?;
+    }
+    Ok(())
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_concept_for_numeric_input"))]
 pub async fn get_concept_name_by_number(
     client: &Client,
     input: i32,
 ) -> Result<Vec<String>, PgError> {
     info!("Checking vocabulary for {}", &input.to_string());
     let stmt = include_str!("../sql/select_concept_for_numeric_input.sql");
-    let stmt = client.prepare(stmt).await?;
+    let stmt = client.prepare_cached(stmt).await?;
 
     let results = client
         .query(&stmt, &[&input, &input.to_string()])
@@ -22,10 +139,11 @@ pub async fn get_concept_name_by_number(
     Ok(results)
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_concept_by_id"))]
 pub async fn get_concept_by_id(client: &Client, input: i32) -> Result<Concept, PgError> {
     info!("Checking vocabulary for {}", &input.to_string());
     let stmt = include_str!("../sql/select_concept_by_id.sql");
-    let stmt = client.prepare(stmt).await?;
+    let stmt = client.prepare_cached(stmt).await?;
 
     let result = client
         .query(&stmt, &[&input])
@@ -38,16 +156,114 @@ pub async fn get_concept_by_id(client: &Client, input: i32) -> Result<Concept, P
     Ok(result)
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_concepts_by_ids"))]
+pub async fn get_concepts_by_ids(
+    client: &Client,
+    concept_ids: &[i32],
+) -> Result<Vec<Concept>, PgError> {
+    if concept_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    info!("Getting {} concepts by id", concept_ids.len());
+    let stmt = include_str!("../sql/select_concepts_by_ids.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&concept_ids])
+        .await?
+        .iter()
+        .map(|row| Concept::from_row(row.clone()).unwrap())
+        .collect::<Vec<Concept>>();
+
+    Ok(results)
+}
+
+/// The concepts among `concept_ids` that are no longer valid.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_invalid_concepts_by_ids"))]
+pub async fn get_invalid_concepts_by_ids(
+    client: &Client,
+    concept_ids: &[i32],
+) -> Result<Vec<Concept>, PgError> {
+    if concept_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let stmt = include_str!("../sql/select_invalid_concepts_by_ids.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&concept_ids])
+        .await?
+        .iter()
+        .map(|row| Concept::from_row(row.clone()).unwrap())
+        .collect::<Vec<Concept>>();
+
+    Ok(results)
+}
+
+/// The concepts with any of the given vocabulary and code pairs. Vocabulary IDs match
+/// case-insensitively, codes exactly.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_concepts_by_codes"))]
+pub async fn get_concepts_by_codes(
+    client: &Client,
+    codes: &[(String, String)],
+) -> Result<Vec<Concept>, PgError> {
+    if codes.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    info!("Getting {} concepts by code", codes.len());
+    let (vocabulary_ids, concept_codes): (Vec<&str>, Vec<&str>) = codes
+        .iter()
+        .map(|(vocabulary_id, code)| (vocabulary_id.as_str(), code.as_str()))
+        .unzip();
+    let stmt = include_str!("../sql/select_concepts_by_codes.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&vocabulary_ids, &concept_codes])
+        .await?
+        .iter()
+        .map(|row| Concept::from_row(row.clone()).unwrap())
+        .collect::<Vec<Concept>>();
+
+    Ok(results)
+}
+
+/// Every distinct concept name, and synonym when requested, with the concepts carrying it.
+pub async fn get_ingest_names(
+    client: &Client,
+    vocabulary_ids: &[String],
+    include_synonyms: bool,
+) -> Result<Vec<IngestName>, PgError> {
+    info!("Getting concept names to ingest");
+    let stmt = include_str!("../sql/select_ingest_names.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&vocabulary_ids, &include_synonyms])
+        .await?
+        .iter()
+        .map(|row| IngestName::from_row(row.clone()).unwrap())
+        .collect::<Vec<IngestName>>();
+
+    Ok(results)
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_related_concepts"))]
+/// The concept's relationships, only those with one of `relationship_ids` when given.
 pub async fn get_concept_relationships(
     client: &Client,
     input: i32,
+    relationship_ids: Option<&[String]>,
 ) -> Result<Vec<RelatedConcept>, PgError> {
     info!("Checking vocabulary for {}", &input.to_string());
     let stmt = include_str!("../sql/select_related_concepts.sql");
-    let stmt = client.prepare(stmt).await?;
+    let stmt = client.prepare_cached(stmt).await?;
 
     let results = client
-        .query(&stmt, &[&input])
+        .query(&stmt, &[&input, &relationship_ids])
         .await?
         .iter()
         .map(|row| RelatedConcept::from_row(row.clone()).unwrap())
@@ -56,13 +272,187 @@ pub async fn get_concept_relationships(
     Ok(results)
 }
 
+/// The concepts related to the concept through a valid `relationship_id` relationship, e.g. its
+/// standard concepts through `Maps to`.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_mapped_concepts"))]
+pub async fn get_mapped_concepts(
+    client: &Client,
+    concept_id: i32,
+    relationship_id: &str,
+) -> Result<Vec<Concept>, PgError> {
+    let stmt = include_str!("../sql/select_mapped_concepts.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&concept_id, &relationship_id])
+        .await?
+        .iter()
+        .map(|row| Concept::from_row(row.clone()).unwrap())
+        .collect::<Vec<Concept>>();
+
+    Ok(results)
+}
+
+/// The concept's synonyms, only those in one of `language_concept_ids` when given.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_concept_synonyms"))]
+pub async fn get_concept_synonyms(
+    client: &Client,
+    concept_id: i32,
+    language_concept_ids: Option<&[i32]>,
+) -> Result<Vec<ConceptSynonym>, PgError> {
+    let stmt = include_str!("../sql/select_concept_synonyms.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&concept_id, &language_concept_ids])
+        .await?
+        .iter()
+        .map(|row| ConceptSynonym::from_row(row.clone()).unwrap())
+        .collect::<Vec<ConceptSynonym>>();
+
+    Ok(results)
+}
+
+/// Every ancestor of the concept, nearest first.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_ancestor_concepts"))]
+pub async fn get_ancestor_concepts(
+    client: &Client,
+    concept_id: i32,
+) -> Result<Vec<HierarchyConcept>, PgError> {
+    let stmt = include_str!("../sql/select_ancestor_concepts.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&concept_id])
+        .await?
+        .iter()
+        .map(|row| HierarchyConcept::from_row(row.clone()).unwrap())
+        .collect::<Vec<HierarchyConcept>>();
+
+    Ok(results)
+}
+
+pub async fn count_descendant_concepts(
+    client: &Client,
+    concept_id: i32,
+    max_levels: Option<i32>,
+    domain_id: Option<&str>,
+) -> Result<i64, PgError> {
+    let stmt = include_str!("../sql/count_descendant_concepts.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let total: i64 = client
+        .query_one(&stmt, &[&concept_id, &max_levels, &domain_id])
+        .await?
+        .get("total");
+    Ok(total)
+}
+
+/// A page of the concept's descendants, nearest first, and how many there are in total, at most
+/// `max_levels` below it and in `domain_id` when given.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_descendant_concepts_page"))]
+pub async fn get_descendant_concepts_page(
+    client: &Client,
+    concept_id: i32,
+    max_levels: Option<i32>,
+    domain_id: Option<&str>,
+    offset: i64,
+    limit: i64,
+) -> Result<(i64, Vec<HierarchyConcept>), PgError> {
+    let total = count_descendant_concepts(client, concept_id, max_levels, domain_id).await?;
+    if offset >= total {
+        return Ok((total, Vec::new()));
+    }
+
+    let stmt = include_str!("../sql/select_descendant_concepts_page.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let results = client
+        .query(
+            &stmt,
+            &[&concept_id, &max_levels, &domain_id, &offset, &limit],
+        )
+        .await?
+        .iter()
+        .map(|row| HierarchyConcept::from_row(row.clone()).unwrap())
+        .collect::<Vec<HierarchyConcept>>();
+
+    Ok((total, results))
+}
+
+/// The descendants in the order of `get_descendant_concepts_page`, row by row as Postgres sends
+/// them, all of them past `offset` when `limit` is `None`. The stream holds on to the
+/// connection until it is dropped.
+pub async fn stream_descendant_concepts(
+    client: Client,
+    concept_id: i32,
+    max_levels: Option<i32>,
+    domain_id: Option<String>,
+    offset: i64,
+    limit: Option<i64>,
+) -> Result<impl Stream<Item = Result<HierarchyConcept, PgError>> + 'static, PgError> {
+    let stmt = include_str!("../sql/select_descendant_concepts_page.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let params: [&(dyn ToSql + Sync); 5] = [&concept_id, &max_levels, &domain_id, &offset, &limit];
+    let rows = client.query_raw(&stmt, params).await?;
+    Ok(rows.map(move |row| {
+        // Keeps the connection out of the pool while rows are still coming in
+        let _ = &client;
+        Ok(HierarchyConcept::from_row(row?).unwrap())
+    }))
+}
+
+/// The direct parent to child steps between the given concepts.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_hierarchy_edges"))]
+pub async fn get_hierarchy_edges(
+    client: &Client,
+    concept_ids: &[i32],
+) -> Result<Vec<HierarchyEdge>, PgError> {
+    if concept_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let stmt = include_str!("../sql/select_hierarchy_edges.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&concept_ids])
+        .await?
+        .iter()
+        .map(|row| HierarchyEdge::from_row(row.clone()).unwrap())
+        .collect::<Vec<HierarchyEdge>>();
+
+    Ok(results)
+}
+
+/// The valid concepts the given concepts point to through any of `relationship_ids`.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_linked_concepts"))]
+pub async fn get_linked_concepts(
+    client: &Client,
+    concept_ids: &[i32],
+    relationship_ids: &[&str],
+) -> Result<Vec<LinkedConcept>, PgError> {
+    if concept_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let stmt = include_str!("../sql/select_linked_concepts.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&concept_ids, &relationship_ids])
+        .await?
+        .iter()
+        .map(|row| LinkedConcept::from_row(row.clone()).unwrap())
+        .collect::<Vec<LinkedConcept>>();
+
+    Ok(results)
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_phoebe_concepts"))]
 pub async fn get_concept_phoebe(
     client: &Client,
     input: i32,
 ) -> Result<Vec<RelatedConcept>, PgError> {
     info!("Checking vocabulary for {}", &input.to_string());
     let stmt = include_str!("../sql/select_phoebe_concepts.sql");
-    let stmt = client.prepare(stmt).await?;
+    let stmt = client.prepare_cached(stmt).await?;
 
     let results = client
         .query(&stmt, &[&input])
@@ -74,13 +464,42 @@ pub async fn get_concept_phoebe(
     Ok(results)
 }
 
+/// The valid concepts PHOEBE recommends for any of `concept_ids`, each with the concept it was
+/// recommended for.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_batch_phoebe_concepts"))]
+pub async fn get_batch_phoebe_concepts(
+    client: &Client,
+    concept_ids: &[i32],
+) -> Result<Vec<(i32, Concept)>, PgError> {
+    if concept_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let stmt = include_str!("../sql/select_batch_phoebe_concepts.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&concept_ids])
+        .await?
+        .iter()
+        .map(|row| {
+            (
+                row.get("source_concept_id"),
+                Concept::from_row(row.clone()).unwrap(),
+            )
+        })
+        .collect();
+
+    Ok(results)
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_concept_for_non_numeric_input"))]
 pub async fn get_concept_name_by_string(
     client: &Client,
     input: String,
 ) -> Result<Vec<String>, PgError> {
     info!("Checking vocabulary for {}", &input.to_string());
     let stmt = include_str!("../sql/select_concept_for_non_numeric_input.sql");
-    let stmt = client.prepare(stmt).await?;
+    let stmt = client.prepare_cached(stmt).await?;
 
     let results = client
         .query(&stmt, &[&input])
@@ -92,13 +511,59 @@ pub async fn get_concept_name_by_string(
     Ok(results)
 }
 
+/// Concept names matching the words of the input, with their normalized full-text rank.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_concept_names_full_text"))]
+pub async fn get_concept_names_full_text(
+    client: &Client,
+    input: &str,
+    limit: i64,
+) -> Result<Vec<(String, f32)>, PgError> {
+    info!("Full-text search for {}", input);
+    let stmt = include_str!("../sql/select_concept_names_full_text.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&input, &limit])
+        .await?
+        .iter()
+        .map(|row| (row.get("concept_name"), row.get("rank")))
+        .collect::<Vec<(String, f32)>>();
+
+    Ok(results)
+}
+
+/// Ancestors of `concept_ids` whose whole descendant tree, themselves included, lies within
+/// `resolved`, so the ancestor with `includeDescendants` adds nothing outside it.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_covering_ancestors"))]
+pub async fn get_covering_ancestors(
+    client: &Client,
+    concept_ids: &[i32],
+    resolved: &[i32],
+) -> Result<Vec<i32>, PgError> {
+    if concept_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let stmt = include_str!("../sql/select_covering_ancestors.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&concept_ids, &resolved])
+        .await?
+        .iter()
+        .map(|row| row.get("concept_id"))
+        .collect::<Vec<i32>>();
+
+    Ok(results)
+}
+
+#[allow(dead_code)]
 pub async fn get_descendant_concepts(
     client: &Client,
     concept_id: i32,
 ) -> Result<Vec<i32>, PgError> {
     info!("Getting descendant concepts for {}", &concept_id);
     let stmt = include_str!("../sql/select_descendant_concepts.sql");
-    let stmt = client.prepare(stmt).await?;
+    let stmt = client.prepare_cached(stmt).await?;
 
     let results = client
         .query(&stmt, &[&concept_id])
@@ -110,12 +575,11 @@ pub async fn get_descendant_concepts(
     Ok(results)
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_batch_descendant_concepts"))]
 pub async fn get_batch_descendant_concepts(
     client: &Client,
     concept_ids: &[i32],
-) -> Result<std::collections::HashMap<i32, Vec<i32>>, PgError> {
-    use std::collections::HashMap;
-
+) -> Result<HashMap<i32, Vec<i32>>, PgError> {
     if concept_ids.is_empty() {
         return Ok(HashMap::new());
     }
@@ -124,100 +588,1579 @@ pub async fn get_batch_descendant_concepts(
         "Getting descendant concepts for {} concepts",
         concept_ids.len()
     );
+    let stmt = include_str!("../sql/select_batch_descendant_concepts.sql");
+    let stmt = client.prepare_cached(stmt).await?;
 
-    // Build the SQL query with IN clause
-    let placeholders: Vec<String> = (1..=concept_ids.len()).map(|i| format!("${}", i)).collect();
-    let sql = format!(
-        "SELECT ancestor_concept_id, descendant_concept_id as concept_id
-         FROM cdm.concept_ancestor 
-         WHERE ancestor_concept_id IN ({})
-           AND min_levels_of_separation > 0",
-        placeholders.join(", ")
-    );
-
-    let stmt = client.prepare(&sql).await?;
-
-    // Convert concept_ids to references for the query
-    let params: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> = concept_ids
+    // Every requested concept gets an entry, also those without descendants
+    let mut result: HashMap<i32, Vec<i32>> = concept_ids
         .iter()
-        .map(|id| id as &(dyn tokio_postgres::types::ToSql + Sync))
+        .map(|&concept_id| (concept_id, Vec::new()))
         .collect();
+    for chunk in concept_ids.chunks(CONCEPT_IDS_PER_QUERY) {
+        for row in client.query(&stmt, &[&chunk]).await? {
+            result
+                .entry(row.get("ancestor_concept_id"))
+                .or_default()
+                .push(row.get("concept_id"));
+        }
+    }
 
-    let rows = client.query(&stmt, &params).await?;
-
-    // Group results by ancestor concept ID
-    let mut result: HashMap<i32, Vec<i32>> = HashMap::new();
+    Ok(result)
+}
 
-    // Initialize empty vectors for all requested concept IDs
-    for &concept_id in concept_ids {
-        result.insert(concept_id, Vec::new());
+/// Like `get_batch_descendant_concepts`, but only descendants at most `max_levels` steps below
+/// their ancestor.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_descendant_concepts_within_levels"))]
+pub async fn get_batch_descendant_concepts_within_levels(
+    client: &Client,
+    concept_ids: &[i32],
+    max_levels: i32,
+) -> Result<HashMap<i32, Vec<i32>>, PgError> {
+    if concept_ids.is_empty() {
+        return Ok(HashMap::new());
     }
+    let stmt = include_str!("../sql/select_descendant_concepts_within_levels.sql");
+    let stmt = client.prepare_cached(stmt).await?;
 
-    // Populate with actual descendants
-    for row in rows {
-        let ancestor_id: i32 = row.get("ancestor_concept_id");
-        let descendant_id: i32 = row.get("concept_id");
-
+    let mut result: HashMap<i32, Vec<i32>> = concept_ids
+        .iter()
+        .map(|&concept_id| (concept_id, Vec::new()))
+        .collect();
+    for row in client.query(&stmt, &[&concept_ids, &max_levels]).await? {
         result
-            .entry(ancestor_id)
-            .or_insert_with(Vec::new)
-            .push(descendant_id);
+            .entry(row.get("ancestor_concept_id"))
+            .or_default()
+            .push(row.get("concept_id"));
     }
 
     Ok(result)
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_batch_mapped_concepts"))]
 pub async fn get_batch_mapped_concepts(
     client: &Client,
     concept_ids: &[i32],
-) -> Result<std::collections::HashMap<i32, Vec<i32>>, PgError> {
-    use std::collections::HashMap;
-
+) -> Result<HashMap<i32, Vec<i32>>, PgError> {
     if concept_ids.is_empty() {
         return Ok(HashMap::new());
     }
 
     info!("Getting mapped concepts for {} concepts", concept_ids.len());
+    let stmt = include_str!("../sql/select_batch_mapped_concepts.sql");
+    let stmt = client.prepare_cached(stmt).await?;
 
-    // Build the SQL query with IN clause
-    let placeholders: Vec<String> = (1..=concept_ids.len()).map(|i| format!("${}", i)).collect();
-    let sql = format!(
-        "SELECT cr.concept_id_2 as source_concept_id, cr.concept_id_1 as mapped_concept_id
-         FROM cdm.concept_relationship cr
-         WHERE cr.concept_id_2 IN ({})
-           AND cr.relationship_id = 'Maps to' 
-           AND cr.invalid_reason IS NULL",
-        placeholders.join(", ")
-    );
+    // Every requested concept gets an entry, also those without mapped concepts
+    let mut result: HashMap<i32, Vec<i32>> = concept_ids
+        .iter()
+        .map(|&concept_id| (concept_id, Vec::new()))
+        .collect();
+    for chunk in concept_ids.chunks(CONCEPT_IDS_PER_QUERY) {
+        for row in client.query(&stmt, &[&chunk]).await? {
+            result
+                .entry(row.get("source_concept_id"))
+                .or_default()
+                .push(row.get("mapped_concept_id"));
+        }
+    }
+
+    Ok(result)
+}
 
-    let stmt = client.prepare(&sql).await?;
+/// Like `get_concept_relationships`, for many concepts at once, keyed by concept ID.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_batch_related_concepts"))]
+pub async fn get_batch_concept_relationships(
+    client: &Client,
+    concept_ids: &[i32],
+    relationship_ids: Option<&[String]>,
+) -> Result<HashMap<i32, Vec<RelatedConcept>>, PgError> {
+    let stmt = include_str!("../sql/select_batch_related_concepts.sql");
+    let stmt = client.prepare_cached(stmt).await?;
 
-    // Convert concept_ids to references for the query
-    let params: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> = concept_ids
+    let mut result: HashMap<i32, Vec<RelatedConcept>> = concept_ids
         .iter()
-        .map(|id| id as &(dyn tokio_postgres::types::ToSql + Sync))
+        .map(|&concept_id| (concept_id, Vec::new()))
         .collect();
+    for chunk in concept_ids.chunks(CONCEPT_IDS_PER_QUERY) {
+        for row in client.query(&stmt, &[&chunk, &relationship_ids]).await? {
+            result
+                .entry(row.get("source_concept_id"))
+                .or_default()
+                .push(RelatedConcept::from_row(row).unwrap());
+        }
+    }
 
-    let rows = client.query(&stmt, &params).await?;
+    Ok(result)
+}
 
-    // Group results by source concept ID
-    let mut result: HashMap<i32, Vec<i32>> = HashMap::new();
+/// Like `get_mapped_concepts`, for many concepts at once, keyed by concept ID.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_batch_concepts_by_relationship"))]
+pub async fn get_batch_concepts_by_relationship(
+    client: &Client,
+    concept_ids: &[i32],
+    relationship_id: &str,
+) -> Result<HashMap<i32, Vec<Concept>>, PgError> {
+    let stmt = include_str!("../sql/select_batch_concepts_by_relationship.sql");
+    let stmt = client.prepare_cached(stmt).await?;
 
-    // Initialize empty vectors for all requested concept IDs
-    for &concept_id in concept_ids {
-        result.insert(concept_id, Vec::new());
+    let mut result: HashMap<i32, Vec<Concept>> = concept_ids
+        .iter()
+        .map(|&concept_id| (concept_id, Vec::new()))
+        .collect();
+    for chunk in concept_ids.chunks(CONCEPT_IDS_PER_QUERY) {
+        for row in client.query(&stmt, &[&chunk, &relationship_id]).await? {
+            result
+                .entry(row.get("source_concept_id"))
+                .or_default()
+                .push(Concept::from_row(row).unwrap());
+        }
     }
 
-    // Populate with actual mapped concepts
-    for row in rows {
-        let source_id: i32 = row.get("source_concept_id");
-        let mapped_id: i32 = row.get("mapped_concept_id");
+    Ok(result)
+}
 
-        result
-            .entry(source_id)
-            .or_insert_with(Vec::new)
-            .push(mapped_id);
+/// Like `get_concept_synonyms`, for many concepts at once, keyed by concept ID.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_batch_concept_synonyms"))]
+pub async fn get_batch_concept_synonyms(
+    client: &Client,
+    concept_ids: &[i32],
+    language_concept_ids: Option<&[i32]>,
+) -> Result<HashMap<i32, Vec<ConceptSynonym>>, PgError> {
+    let stmt = include_str!("../sql/select_batch_concept_synonyms.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let mut result: HashMap<i32, Vec<ConceptSynonym>> = concept_ids
+        .iter()
+        .map(|&concept_id| (concept_id, Vec::new()))
+        .collect();
+    for chunk in concept_ids.chunks(CONCEPT_IDS_PER_QUERY) {
+        for row in client
+            .query(&stmt, &[&chunk, &language_concept_ids])
+            .await?
+        {
+            result
+                .entry(row.get("concept_id"))
+                .or_default()
+                .push(ConceptSynonym::from_row(row).unwrap());
+        }
+    }
+
+    Ok(result)
+}
+
+/// Like `get_ancestor_concepts`, for many concepts at once, keyed by concept ID.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_batch_ancestor_concepts"))]
+pub async fn get_batch_ancestor_concepts(
+    client: &Client,
+    concept_ids: &[i32],
+) -> Result<HashMap<i32, Vec<HierarchyConcept>>, PgError> {
+    let stmt = include_str!("../sql/select_batch_ancestor_concepts.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let mut result: HashMap<i32, Vec<HierarchyConcept>> = concept_ids
+        .iter()
+        .map(|&concept_id| (concept_id, Vec::new()))
+        .collect();
+    for chunk in concept_ids.chunks(CONCEPT_IDS_PER_QUERY) {
+        for row in client.query(&stmt, &[&chunk]).await? {
+            result
+                .entry(row.get("source_concept_id"))
+                .or_default()
+                .push(HierarchyConcept::from_row(row).unwrap());
+        }
+    }
+
+    Ok(result)
+}
+
+/// Like `get_descendant_concepts_page`, the same page for many concepts at once, keyed by
+/// concept ID.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_batch_descendant_concepts_page"))]
+pub async fn get_batch_descendant_concepts_page(
+    client: &Client,
+    concept_ids: &[i32],
+    max_levels: Option<i32>,
+    domain_id: Option<&str>,
+    offset: i64,
+    limit: i64,
+) -> Result<HashMap<i32, (i64, Vec<HierarchyConcept>)>, PgError> {
+    let count = include_str!("../sql/count_batch_descendant_concepts.sql");
+    let count = client.prepare_cached(count).await?;
+    let stmt = include_str!("../sql/select_batch_descendant_concepts_page.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let mut result: HashMap<i32, (i64, Vec<HierarchyConcept>)> = concept_ids
+        .iter()
+        .map(|&concept_id| (concept_id, (0, Vec::new())))
+        .collect();
+    for chunk in concept_ids.chunks(CONCEPT_IDS_PER_QUERY) {
+        for row in client
+            .query(&count, &[&chunk, &max_levels, &domain_id])
+            .await?
+        {
+            result.entry(row.get("ancestor_concept_id")).or_default().0 = row.get("total");
+        }
+        for row in client
+            .query(&stmt, &[&chunk, &max_levels, &domain_id, &offset, &limit])
+            .await?
+        {
+            result
+                .entry(row.get("ancestor_concept_id"))
+                .or_default()
+                .1
+                .push(HierarchyConcept::from_row(row).unwrap());
+        }
     }
 
     Ok(result)
 }
+
+/// Which of `candidate_ids` the concept descends from and which it maps to, the links through
+/// which descendant and mapped expansion would reach it.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_concept_links_among"))]
+pub async fn get_concept_links_among(
+    client: &Client,
+    concept_id: i32,
+    candidate_ids: &[i32],
+) -> Result<(Vec<i32>, Vec<i32>), PgError> {
+    let stmt = include_str!("../sql/select_concept_links_among.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let mut ancestors = Vec::new();
+    let mut maps_to = Vec::new();
+    for row in client.query(&stmt, &[&concept_id, &candidate_ids]).await? {
+        let link: &str = row.get("link");
+        if link == "mapped" {
+            maps_to.push(row.get("concept_id"));
+        } else {
+            ancestors.push(row.get("concept_id"));
+        }
+    }
+
+    Ok((ancestors, maps_to))
+}
+
+/// Which of `candidate_ids` are ancestors or descendants of any of `concept_ids`.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_hierarchy_related_among"))]
+pub async fn get_hierarchy_related_among(
+    client: &Client,
+    concept_ids: &[i32],
+    candidate_ids: &[i32],
+) -> Result<HashSet<i32>, PgError> {
+    if concept_ids.is_empty() || candidate_ids.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let stmt = include_str!("../sql/select_hierarchy_related_among.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let mut related = HashSet::new();
+    for chunk in concept_ids.chunks(CONCEPT_IDS_PER_QUERY) {
+        for row in client.query(&stmt, &[&chunk, &candidate_ids]).await? {
+            related.insert(row.get("concept_id"));
+        }
+    }
+
+    Ok(related)
+}
+
+pub async fn record_zero_result_query(
+    client: &Client,
+    query: &str,
+    filters: &serde_json::Value,
+) -> Result<(), PgError> {
+    let stmt = include_str!("../sql/upsert_zero_result_query.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    client
+        .execute(&stmt, &[&query.to_lowercase(), &query, filters])
+        .await?;
+    Ok(())
+}
+
+pub async fn get_zero_result_queries(
+    client: &Client,
+    status: Option<&str>,
+    limit: i64,
+) -> Result<Vec<ZeroResultQuery>, PgError> {
+    let stmt = include_str!("../sql/select_zero_result_queries.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&status, &limit])
+        .await?
+        .iter()
+        .map(|row| ZeroResultQuery::from_row(row.clone()).unwrap())
+        .collect::<Vec<ZeroResultQuery>>();
+
+    Ok(results)
+}
+
+pub async fn add_zero_result_overrides(
+    client: &Client,
+    zero_result_query_id: i64,
+    concept_ids: &[i32],
+    created_by: Option<&str>,
+) -> Result<u64, PgError> {
+    let stmt = include_str!("../sql/insert_zero_result_overrides.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let inserted = client
+        .execute(&stmt, &[&zero_result_query_id, &concept_ids, &created_by])
+        .await?;
+    Ok(inserted)
+}
+
+pub async fn set_zero_result_query_status(
+    client: &Client,
+    zero_result_query_id: i64,
+    status: &str,
+) -> Result<(), PgError> {
+    let stmt = include_str!("../sql/update_zero_result_query_status.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let updated = client
+        .execute(&stmt, &[&zero_result_query_id, &status])
+        .await?;
+    if updated == 0 {
+        return Err(PgError::NotFound);
+    }
+    Ok(())
+}
+
+pub async fn get_synonym_overrides(client: &Client) -> Result<HashMap<String, Vec<i32>>, PgError> {
+    let stmt = include_str!("../sql/select_synonym_overrides.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let mut overrides: HashMap<String, Vec<i32>> = HashMap::new();
+    for row in client.query(&stmt, &[]).await? {
+        overrides
+            .entry(row.get("alias_lower"))
+            .or_default()
+            .push(row.get("concept_id"));
+    }
+
+    Ok(overrides)
+}
+
+pub async fn get_synonym_override_rows(
+    client: &Client,
+    alias: Option<&str>,
+    concept_id: Option<i32>,
+) -> Result<Vec<SynonymOverride>, PgError> {
+    let stmt = include_str!("../sql/select_synonym_override_rows.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&alias, &concept_id])
+        .await?
+        .iter()
+        .map(|row| SynonymOverride::from_row(row.clone()).unwrap())
+        .collect::<Vec<SynonymOverride>>();
+
+    Ok(results)
+}
+
+pub async fn insert_synonym_override(
+    client: &Client,
+    alias: &str,
+    concept_id: i32,
+    created_by: Option<&str>,
+    note: Option<&str>,
+) -> Result<i64, PgError> {
+    let stmt = include_str!("../sql/insert_synonym_override.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    match client
+        .query_one(&stmt, &[&alias, &concept_id, &created_by, &note])
+        .await
+    {
+        Ok(row) => Ok(row.get("id")),
+        Err(e) if e.code() == Some(&SqlState::UNIQUE_VIOLATION) => {
+            Err(duplicate_synonym_override(client, None, Some(alias), Some(concept_id)).await)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn update_synonym_override(
+    client: &Client,
+    id: i64,
+    alias: Option<&str>,
+    concept_id: Option<i32>,
+    note: Option<&str>,
+) -> Result<(), PgError> {
+    let stmt = include_str!("../sql/update_synonym_override.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let updated = match client
+        .execute(&stmt, &[&id, &alias, &concept_id, &note])
+        .await
+    {
+        Ok(updated) => updated,
+        Err(e) if e.code() == Some(&SqlState::UNIQUE_VIOLATION) => {
+            return Err(duplicate_synonym_override(client, Some(id), alias, concept_id).await);
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if updated == 0 {
+        return Err(PgError::NotFound);
+    }
+    Ok(())
+}
+
+/// The override already holding the alias and concept an insert or update of `id` ran into.
+async fn duplicate_synonym_override(
+    client: &Client,
+    id: Option<i64>,
+    alias: Option<&str>,
+    concept_id: Option<i32>,
+) -> PgError {
+    let stmt = include_str!("../sql/select_duplicate_synonym_override.sql");
+    let stmt = match client.prepare_cached(stmt).await {
+        Ok(stmt) => stmt,
+        Err(e) => return e.into(),
+    };
+    match client.query_opt(&stmt, &[&id, &alias, &concept_id]).await {
+        Ok(Some(row)) => PgError::Duplicate { id: row.get("id") },
+        // Deleted again in the meantime.
+        Ok(None) => PgError::Conflict,
+        Err(e) => e.into(),
+    }
+}
+
+pub async fn delete_synonym_override(client: &Client, id: i64) -> Result<(), PgError> {
+    let deleted = client
+        .execute("DELETE FROM hecate.synonym_override WHERE id = $1", &[&id])
+        .await?;
+    if deleted == 0 {
+        return Err(PgError::NotFound);
+    }
+    Ok(())
+}
+
+pub async fn get_archived_synonym_overrides(
+    client: &Client,
+) -> Result<Vec<ArchivedSynonymOverride>, PgError> {
+    let stmt = include_str!("../sql/select_archived_synonym_overrides.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[])
+        .await?
+        .iter()
+        .map(|row| ArchivedSynonymOverride::from_row(row.clone()).unwrap())
+        .collect::<Vec<ArchivedSynonymOverride>>();
+
+    Ok(results)
+}
+
+pub async fn get_archived_zero_result_queries(
+    client: &Client,
+) -> Result<Vec<ArchivedZeroResultQuery>, PgError> {
+    let stmt = include_str!("../sql/select_archived_zero_result_queries.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[])
+        .await?
+        .iter()
+        .map(|row| ArchivedZeroResultQuery::from_row(row.clone()).unwrap())
+        .collect::<Vec<ArchivedZeroResultQuery>>();
+
+    Ok(results)
+}
+
+pub async fn get_archived_concept_sets(
+    client: &Client,
+) -> Result<Vec<ArchivedConceptSet>, PgError> {
+    let stmt = include_str!("../sql/select_archived_concept_sets.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[])
+        .await?
+        .iter()
+        .map(|row| ArchivedConceptSet::from_row(row.clone()).unwrap())
+        .collect::<Vec<ArchivedConceptSet>>();
+
+    Ok(results)
+}
+
+pub async fn get_archived_concept_set_versions(
+    client: &Client,
+) -> Result<Vec<ArchivedConceptSetVersion>, PgError> {
+    let stmt = include_str!("../sql/select_archived_concept_set_versions.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[])
+        .await?
+        .iter()
+        .map(|row| ArchivedConceptSetVersion::from_row(row.clone()).unwrap())
+        .collect::<Vec<ArchivedConceptSetVersion>>();
+
+    Ok(results)
+}
+
+pub async fn get_archived_recommendation_feedback(
+    client: &Client,
+) -> Result<Vec<ArchivedRecommendationFeedback>, PgError> {
+    let stmt = include_str!("../sql/select_archived_recommendation_feedback.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[])
+        .await?
+        .iter()
+        .map(|row| ArchivedRecommendationFeedback::from_row(row.clone()).unwrap())
+        .collect::<Vec<ArchivedRecommendationFeedback>>();
+
+    Ok(results)
+}
+
+/// Writes archived curation data in one transaction, merging with what is there or, with
+/// `replace`, replacing it. Queries go first so overrides can be linked to them. Concept sets
+/// are left untouched when `concept_sets` is `None`, as for archives from before they were
+/// carried; an archived concept set replaces the target's copy of it with all its versions.
+/// Recommendation feedback goes last so it can be linked to the imported concept sets, and
+/// feedback the target already has is not added twice.
+pub async fn import_archived_curation(
+    client: &mut Client,
+    zero_result_queries: &[ArchivedZeroResultQuery],
+    synonym_overrides: &[ArchivedSynonymOverride],
+    concept_sets: Option<(&[ArchivedConceptSet], &[ArchivedConceptSetVersion])>,
+    recommendation_feedback: Option<&[ArchivedRecommendationFeedback]>,
+    replace: bool,
+) -> Result<(), PgError> {
+    let transaction = client.transaction().await?;
+    if replace {
+        transaction
+            .batch_execute(
+                "DELETE FROM hecate.synonym_override; DELETE FROM hecate.zero_result_query;",
+            )
+            .await?;
+    }
+
+    let stmt = transaction
+        .prepare_cached(include_str!("../sql/upsert_archived_zero_result_query.sql"))
+        .await?;
+    for query in zero_result_queries {
+        transaction
+            .execute(
+                &stmt,
+                &[
+                    &query.query,
+                    &query.filters,
+                    &query.occurrences,
+                    &query.status,
+                    &query.first_seen,
+                    &query.last_seen,
+                ],
+            )
+            .await?;
+    }
+
+    let stmt = transaction
+        .prepare_cached(include_str!("../sql/upsert_archived_synonym_override.sql"))
+        .await?;
+    for synonym in synonym_overrides {
+        transaction
+            .execute(
+                &stmt,
+                &[
+                    &synonym.alias,
+                    &synonym.concept_id,
+                    &synonym.source,
+                    &synonym.created_by,
+                    &synonym.note,
+                    &synonym.zero_result_query.as_deref().map(str::to_lowercase),
+                    &synonym.created_at,
+                    &synonym.updated_at,
+                ],
+            )
+            .await?;
+    }
+
+    if let Some((concept_sets, versions)) = concept_sets {
+        if replace {
+            transaction
+                .batch_execute(
+                    "DELETE FROM hecate.concept_set_resolution; \
+                     DELETE FROM hecate.concept_set_version; \
+                     DELETE FROM hecate.concept_set;",
+                )
+                .await?;
+        }
+
+        let stmt = transaction
+            .prepare_cached(include_str!("../sql/upsert_archived_concept_set.sql"))
+            .await?;
+        for concept_set in concept_sets {
+            let id: i64 = transaction
+                .query_one(
+                    &stmt,
+                    &[
+                        &concept_set.uid,
+                        &concept_set.name,
+                        &concept_set.description,
+                        &concept_set.tags,
+                        &concept_set.created_by,
+                        &concept_set.latest_version,
+                        &concept_set.created_at,
+                        &concept_set.updated_at,
+                        &concept_set.deleted_at,
+                    ],
+                )
+                .await?
+                .get("id");
+            transaction
+                .execute(
+                    "DELETE FROM hecate.concept_set_version WHERE concept_set_id = $1",
+                    &[&id],
+                )
+                .await?;
+        }
+
+        let stmt = transaction
+            .prepare_cached(include_str!(
+                "../sql/insert_archived_concept_set_version.sql"
+            ))
+            .await?;
+        for version in versions {
+            transaction
+                .execute(
+                    &stmt,
+                    &[
+                        &version.concept_set_uid,
+                        &version.version,
+                        &version.expression,
+                        &version.expression_hash,
+                        &version.created_by,
+                        &version.note,
+                        &version.created_at,
+                    ],
+                )
+                .await?;
+        }
+    }
+
+    if let Some(recommendation_feedback) = recommendation_feedback {
+        if replace {
+            transaction
+                .batch_execute("DELETE FROM hecate.recommendation_feedback;")
+                .await?;
+        }
+
+        let stmt = transaction
+            .prepare_cached(include_str!(
+                "../sql/insert_archived_recommendation_feedback.sql"
+            ))
+            .await?;
+        for feedback in recommendation_feedback {
+            transaction
+                .execute(
+                    &stmt,
+                    &[
+                        &feedback.concept_set_uid,
+                        &feedback.concept_set_id,
+                        &feedback.concept_id,
+                        &feedback.source_concept_id,
+                        &feedback.source_text,
+                        &feedback.similarity_score,
+                        &feedback.verdict,
+                        &feedback.vocabulary_version,
+                        &feedback.created_by,
+                        &feedback.created_at,
+                    ],
+                )
+                .await?;
+        }
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+pub async fn get_vocabularies(client: &Client) -> Result<Vec<Vocabulary>, PgError> {
+    let stmt = include_str!("../sql/select_vocabularies.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[])
+        .await?
+        .iter()
+        .map(|row| Vocabulary::from_row(row.clone()).unwrap())
+        .collect::<Vec<Vocabulary>>();
+
+    Ok(results)
+}
+
+pub async fn get_domains(client: &Client) -> Result<Vec<Domain>, PgError> {
+    let stmt = include_str!("../sql/select_domains.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[])
+        .await?
+        .iter()
+        .map(|row| Domain::from_row(row.clone()).unwrap())
+        .collect::<Vec<Domain>>();
+
+    Ok(results)
+}
+
+pub async fn get_concept_classes(client: &Client) -> Result<Vec<ConceptClass>, PgError> {
+    let stmt = include_str!("../sql/select_concept_classes.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[])
+        .await?
+        .iter()
+        .map(|row| ConceptClass::from_row(row.clone()).unwrap())
+        .collect::<Vec<ConceptClass>>();
+
+    Ok(results)
+}
+
+/// Concept counts per vocabulary, domain and concept class, in one scan of `cdm.concept`.
+pub async fn get_concept_counts(client: &Client) -> Result<Vec<ConceptCount>, PgError> {
+    let stmt = include_str!("../sql/select_concept_counts.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[])
+        .await?
+        .iter()
+        .map(|row| ConceptCount::from_row(row.clone()).unwrap())
+        .collect::<Vec<ConceptCount>>();
+
+    Ok(results)
+}
+
+/// The vocabularies of the release in `schema`, a plain identifier checked by the caller.
+pub async fn get_snapshot_vocabularies(
+    client: &Client,
+    schema: &str,
+) -> Result<Vec<Vocabulary>, PgError> {
+    let stmt = include_str!("../sql/select_snapshot_vocabularies.sql").replace("{schema}", schema);
+    let stmt = client.prepare_cached(&stmt).await?;
+
+    let results = client
+        .query(&stmt, &[])
+        .await?
+        .iter()
+        .map(|row| Vocabulary::from_row(row.clone()).unwrap())
+        .collect::<Vec<Vocabulary>>();
+
+    Ok(results)
+}
+
+/// The ID of the stored diff between two releases, identified by their schema and version.
+/// When there is none yet, runs `queries`, which take the new diff ID as `$1`, to store it, in
+/// one transaction and replacing the diffs between earlier versions of the same schemas.
+/// Requests for the same diff wait for the first one to finish storing it.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "insert_vocabulary_diff"))]
+pub async fn materialize_vocabulary_diff(
+    client: &mut Client,
+    (from_schema, from_version): (&str, &str),
+    (to_schema, to_version): (&str, &str),
+    queries: &[String],
+) -> Result<i64, PgError> {
+    let transaction = client.transaction().await?;
+    transaction
+        .execute(
+            "SELECT pg_advisory_xact_lock(hashtext('hecate.vocabulary_diff:' || $1 || ':' || $2))",
+            &[&from_schema, &to_schema],
+        )
+        .await?;
+    let stmt = transaction
+        .prepare_cached(include_str!("../sql/select_vocabulary_diff.sql"))
+        .await?;
+    if let Some(row) = transaction
+        .query_opt(
+            &stmt,
+            &[&from_schema, &from_version, &to_schema, &to_version],
+        )
+        .await?
+    {
+        transaction.commit().await?;
+        return Ok(row.get("id"));
+    }
+
+    info!(
+        "Storing the vocabulary diff between {} and {}",
+        from_schema, to_schema
+    );
+    transaction
+        .execute(
+            "DELETE FROM hecate.vocabulary_diff WHERE from_schema = $1 AND to_schema = $2",
+            &[&from_schema, &to_schema],
+        )
+        .await?;
+    let stmt = transaction
+        .prepare_cached(include_str!("../sql/insert_vocabulary_diff.sql"))
+        .await?;
+    let id: i64 = transaction
+        .query_one(
+            &stmt,
+            &[&from_schema, &from_version, &to_schema, &to_version],
+        )
+        .await?
+        .get("id");
+    for query in queries {
+        transaction.execute(query.as_str(), &[&id]).await?;
+    }
+    transaction.commit().await?;
+
+    Ok(id)
+}
+
+/// A page of the `change` rows of a stored vocabulary diff, ordered by concept ID, and how many
+/// there are in total.
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_vocabulary_diff_changes"))]
+pub async fn get_vocabulary_changes(
+    client: &Client,
+    diff_id: i64,
+    change: &str,
+    vocabulary_ids: Option<&[String]>,
+    offset: i64,
+    limit: i64,
+) -> Result<(i64, Vec<VocabularyChange>), PgError> {
+    let count = include_str!("../sql/count_vocabulary_diff_changes.sql");
+    let count = client.prepare_cached(count).await?;
+    let total: i64 = client
+        .query_one(&count, &[&diff_id, &change, &vocabulary_ids])
+        .await?
+        .get("total");
+    if offset >= total {
+        return Ok((total, Vec::new()));
+    }
+
+    let stmt = include_str!("../sql/select_vocabulary_diff_changes.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let results = client
+        .query(
+            &stmt,
+            &[&diff_id, &change, &vocabulary_ids, &offset, &limit],
+        )
+        .await?
+        .iter()
+        .map(|row| VocabularyChange::from_row(row.clone()).unwrap())
+        .collect::<Vec<VocabularyChange>>();
+
+    Ok((total, results))
+}
+
+/// Claims an idempotency key of `owner`, the authenticated identity or an empty string for
+/// anonymous requests. Returns `None` when the key is new and the request should be processed,
+/// or the stored record when the key was used before. A claim of the same request still without
+/// a response after `claim_timeout_secs` is taken over, its request is assumed lost. Keys expire
+/// after 24 hours.
+pub async fn claim_idempotency_key(
+    client: &Client,
+    key: &IdempotencyKey<'_>,
+    request_hash: &str,
+    claim_timeout_secs: u64,
+) -> Result<Option<IdempotencyRecord>, PgError> {
+    client
+        .execute(
+            "DELETE FROM hecate.idempotency_key WHERE created_at < now() - interval '24 hours'",
+            &[],
+        )
+        .await?;
+    let stmt = include_str!("../sql/insert_idempotency_key.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let inserted = client
+        .execute(
+            &stmt,
+            &[
+                &key.key,
+                &key.owner,
+                &key.method,
+                &key.path,
+                &request_hash,
+                &(claim_timeout_secs as f64),
+            ],
+        )
+        .await?;
+    if inserted == 1 {
+        return Ok(None);
+    }
+
+    let stmt = include_str!("../sql/select_idempotency_key.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let record = client
+        .query_opt(&stmt, &[&key.key, &key.owner, &key.method, &key.path])
+        .await?
+        .map(|row| IdempotencyRecord::from_row(row).unwrap());
+    Ok(record)
+}
+
+pub async fn store_idempotent_response(
+    client: &Client,
+    key: &IdempotencyKey<'_>,
+    status_code: i32,
+    content_type: Option<&str>,
+    response_body: &[u8],
+) -> Result<(), PgError> {
+    let stmt = include_str!("../sql/update_idempotency_key_response.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    client
+        .execute(
+            &stmt,
+            &[
+                &key.key,
+                &key.owner,
+                &key.method,
+                &key.path,
+                &status_code,
+                &content_type,
+                &response_body,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Releases a key whose request failed, so the client can retry with it.
+pub async fn release_idempotency_key(
+    client: &Client,
+    key: &IdempotencyKey<'_>,
+) -> Result<(), PgError> {
+    client
+        .execute(
+            "DELETE FROM hecate.idempotency_key
+             WHERE key = $1 AND owner = $2 AND method = $3 AND path = $4",
+            &[&key.key, &key.owner, &key.method, &key.path],
+        )
+        .await?;
+    Ok(())
+}
+
+pub async fn get_concept_classes_by_vocabulary(
+    client: &Client,
+    vocabulary_id: &str,
+    samples: i64,
+) -> Result<Vec<ConceptClassSummary>, PgError> {
+    let stmt = include_str!("../sql/select_concept_classes_by_vocabulary.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&vocabulary_id, &samples])
+        .await?
+        .iter()
+        .map(|row| ConceptClassSummary::from_row(row.clone()).unwrap())
+        .collect::<Vec<ConceptClassSummary>>();
+
+    Ok(results)
+}
+
+pub async fn get_vocabulary_roots(
+    client: &Client,
+    vocabulary_id: &str,
+    limit: i64,
+) -> Result<Vec<HierarchyRoot>, PgError> {
+    let stmt = include_str!("../sql/select_vocabulary_roots.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&vocabulary_id, &limit])
+        .await?
+        .iter()
+        .map(|row| HierarchyRoot::from_row(row.clone()).unwrap())
+        .collect::<Vec<HierarchyRoot>>();
+
+    Ok(results)
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql", db.statement = "select_resolved_expansion"))]
+pub async fn get_resolved_expansion(
+    client: &Client,
+    expression_hash: &str,
+    vocabulary_version: &str,
+) -> Result<Option<ResolvedExpansion>, PgError> {
+    let stmt = include_str!("../sql/select_resolved_expansion.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let row = client
+        .query_opt(&stmt, &[&expression_hash, &vocabulary_version])
+        .await?;
+    Ok(row.map(|row| ResolvedExpansion::from_row(row).unwrap()))
+}
+
+/// Fetches a cached expansion and counts the hit.
+pub async fn use_resolved_expansion(
+    client: &Client,
+    expression_hash: &str,
+    vocabulary_version: &str,
+) -> Result<Option<serde_json::Value>, PgError> {
+    let stmt = include_str!("../sql/update_resolved_expansion_hit.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let row = client
+        .query_opt(&stmt, &[&expression_hash, &vocabulary_version])
+        .await?;
+    Ok(row.map(|row| row.get("expansion")))
+}
+
+pub async fn insert_resolved_expansion(
+    client: &Client,
+    expression_hash: &str,
+    vocabulary_version: &str,
+    expression: &serde_json::Value,
+    expansion: &serde_json::Value,
+    resolved_count: i32,
+) -> Result<(), PgError> {
+    let stmt = include_str!("../sql/insert_resolved_expansion.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    client
+        .execute(
+            &stmt,
+            &[
+                &expression_hash,
+                &vocabulary_version,
+                expression,
+                expansion,
+                &resolved_count,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+pub async fn insert_api_key(
+    client: &Client,
+    name: &str,
+    key_hash: &str,
+    scopes: &[&str],
+) -> Result<ApiKey, PgError> {
+    let stmt = include_str!("../sql/insert_api_key.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let row = client
+        .query_one(&stmt, &[&name, &key_hash, &scopes])
+        .await?;
+    Ok(ApiKey::from_row(row).unwrap())
+}
+
+pub async fn get_api_keys(client: &Client) -> Result<Vec<ApiKey>, PgError> {
+    let stmt = include_str!("../sql/select_api_keys.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[])
+        .await?
+        .iter()
+        .map(|row| ApiKey::from_row(row.clone()).unwrap())
+        .collect::<Vec<ApiKey>>();
+
+    Ok(results)
+}
+
+/// Looks up an active key by its hash and records that it was used.
+pub async fn use_api_key(client: &Client, key_hash: &str) -> Result<Option<ApiKey>, PgError> {
+    let stmt = include_str!("../sql/update_api_key_last_used.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let row = client.query_opt(&stmt, &[&key_hash]).await?;
+    Ok(row.map(|row| ApiKey::from_row(row).unwrap()))
+}
+
+pub async fn revoke_api_key(client: &Client, id: i64) -> Result<(), PgError> {
+    let revoked = client
+        .execute(
+            "UPDATE hecate.api_key SET revoked_at = now() WHERE id = $1 AND revoked_at IS NULL",
+            &[&id],
+        )
+        .await?;
+    if revoked == 0 {
+        return Err(PgError::NotFound);
+    }
+    Ok(())
+}
+
+/// Stores a concept set with its expression as version 1.
+#[allow(clippy::too_many_arguments)]
+pub async fn insert_concept_set(
+    client: &Client,
+    name: &str,
+    description: Option<&str>,
+    tags: &[String],
+    author: Option<&str>,
+    expression: &serde_json::Value,
+    expression_hash: &str,
+    note: Option<&str>,
+) -> Result<StoredConceptSet, PgError> {
+    let stmt = include_str!("../sql/insert_concept_set.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let row = client
+        .query_one(
+            &stmt,
+            &[
+                &name,
+                &description,
+                &tags,
+                &author,
+                &expression,
+                &expression_hash,
+                &note,
+            ],
+        )
+        .await?;
+    Ok(StoredConceptSet::from_row(row).unwrap())
+}
+
+pub async fn get_concept_sets(
+    client: &Client,
+    tag: Option<&str>,
+    name: Option<&str>,
+    limit: i64,
+) -> Result<Vec<StoredConceptSet>, PgError> {
+    let stmt = include_str!("../sql/select_concept_sets.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&tag, &name, &limit])
+        .await?
+        .iter()
+        .map(|row| StoredConceptSet::from_row(row.clone()).unwrap())
+        .collect::<Vec<StoredConceptSet>>();
+
+    Ok(results)
+}
+
+pub async fn get_concept_set(client: &Client, id: i64) -> Result<StoredConceptSet, PgError> {
+    let stmt = include_str!("../sql/select_concept_set.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let row = client
+        .query_opt(&stmt, &[&id])
+        .await?
+        .ok_or(PgError::NotFound)?;
+    Ok(StoredConceptSet::from_row(row).unwrap())
+}
+
+/// The versions of a concept set, newest first, or only `version` when given.
+pub async fn get_concept_set_versions(
+    client: &Client,
+    id: i64,
+    version: Option<i32>,
+) -> Result<Vec<ConceptSetVersion>, PgError> {
+    let stmt = include_str!("../sql/select_concept_set_versions.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&id, &version])
+        .await?
+        .iter()
+        .map(|row| ConceptSetVersion::from_row(row.clone()).unwrap())
+        .collect::<Vec<ConceptSetVersion>>();
+
+    Ok(results)
+}
+
+/// Applies `changes` in one transaction, writing the next version when the expression changed.
+pub async fn update_concept_set(
+    client: &mut Client,
+    id: i64,
+    changes: &ConceptSetChanges<'_>,
+) -> Result<StoredConceptSet, PgError> {
+    let transaction = client.transaction().await?;
+    let latest = transaction
+        .query_opt(
+            "SELECT s.latest_version, v.expression_hash
+             FROM hecate.concept_set AS s
+                      JOIN hecate.concept_set_version AS v
+                           ON v.concept_set_id = s.id AND v.version = s.latest_version
+             WHERE s.id = $1
+               AND s.deleted_at IS NULL
+             FOR UPDATE OF s",
+            &[&id],
+        )
+        .await?
+        .ok_or(PgError::NotFound)?;
+    let mut version: i32 = latest.get("latest_version");
+    let latest_hash: String = latest.get("expression_hash");
+    if changes
+        .base_version
+        .is_some_and(|base_version| base_version != version)
+    {
+        return Err(PgError::Conflict);
+    }
+
+    if let Some((expression, expression_hash)) = changes.expression
+        && expression_hash != latest_hash
+    {
+        version += 1;
+        let stmt = transaction
+            .prepare_cached(include_str!("../sql/insert_concept_set_version.sql"))
+            .await?;
+        transaction
+            .execute(
+                &stmt,
+                &[
+                    &id,
+                    &version,
+                    &expression,
+                    &expression_hash,
+                    &changes.author,
+                    &changes.note,
+                ],
+            )
+            .await?;
+    }
+
+    let stmt = transaction
+        .prepare_cached(include_str!("../sql/update_concept_set.sql"))
+        .await?;
+    let row = transaction
+        .query_one(
+            &stmt,
+            &[
+                &id,
+                &changes.name,
+                &changes.description,
+                &changes.tags,
+                &version,
+            ],
+        )
+        .await?;
+    transaction.commit().await?;
+    Ok(StoredConceptSet::from_row(row).unwrap())
+}
+
+/// Hides a concept set from listings. Its versions are kept.
+pub async fn delete_concept_set(client: &Client, id: i64) -> Result<(), PgError> {
+    let deleted = client
+        .execute(
+            "UPDATE hecate.concept_set SET deleted_at = now() WHERE id = $1 AND deleted_at IS NULL",
+            &[&id],
+        )
+        .await?;
+    if deleted == 0 {
+        return Err(PgError::NotFound);
+    }
+    Ok(())
+}
+
+/// The resolution recorded last for a concept set, if any.
+pub async fn get_latest_concept_set_resolution(
+    client: &Client,
+    concept_set_id: i64,
+) -> Result<Option<ConceptSetResolution>, PgError> {
+    let stmt = include_str!("../sql/select_latest_concept_set_resolution.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let row = client.query_opt(&stmt, &[&concept_set_id]).await?;
+    Ok(row.map(|row| ConceptSetResolution::from_row(row).unwrap()))
+}
+
+pub async fn insert_concept_set_resolution(
+    client: &Client,
+    concept_set_id: i64,
+    version: i32,
+    vocabulary_version: Option<&str>,
+    concept_ids: &[i32],
+    invalid_concept_ids: &[i32],
+) -> Result<ConceptSetResolution, PgError> {
+    let stmt = include_str!("../sql/insert_concept_set_resolution.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let row = client
+        .query_one(
+            &stmt,
+            &[
+                &concept_set_id,
+                &version,
+                &vocabulary_version,
+                &concept_ids,
+                &invalid_concept_ids,
+            ],
+        )
+        .await?;
+    Ok(ConceptSetResolution::from_row(row).unwrap())
+}
+
+pub async fn insert_validation_job(
+    client: &Client,
+    id: uuid::Uuid,
+    profile: &str,
+    created_by: Option<&str>,
+) -> Result<ValidationJob, PgError> {
+    let stmt = include_str!("../sql/insert_validation_job.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let row = client
+        .query_one(&stmt, &[&id, &profile, &created_by])
+        .await?;
+    Ok(ValidationJob::from_row(row).unwrap())
+}
+
+pub async fn get_validation_job(client: &Client, id: uuid::Uuid) -> Result<ValidationJob, PgError> {
+    let stmt = include_str!("../sql/select_validation_job.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let row = client
+        .query_opt(&stmt, &[&id])
+        .await?
+        .ok_or(PgError::NotFound)?;
+    Ok(ValidationJob::from_row(row).unwrap())
+}
+
+/// Records the stage a job reached, unless it already finished.
+pub async fn update_validation_job_stage(
+    client: &Client,
+    id: uuid::Uuid,
+    stage: &str,
+    progress: f64,
+) -> Result<(), PgError> {
+    let stmt = include_str!("../sql/update_validation_job_stage.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    client.execute(&stmt, &[&id, &stage, &progress]).await?;
+    Ok(())
+}
+
+pub async fn finish_validation_job(
+    client: &Client,
+    id: uuid::Uuid,
+    status: &str,
+    result: Option<&serde_json::Value>,
+    error: Option<&str>,
+) -> Result<(), PgError> {
+    let stmt = include_str!("../sql/update_validation_job_finished.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    client
+        .execute(&stmt, &[&id, &status, &result, &error])
+        .await?;
+    Ok(())
+}
+
+/// Removes jobs submitted more than `retention_hours` ago.
+pub async fn delete_expired_validation_jobs(
+    client: &Client,
+    retention_hours: i64,
+) -> Result<u64, PgError> {
+    let deleted = client
+        .execute(
+            "DELETE FROM hecate.validation_job WHERE created_at < now() - make_interval(hours => $1::int)",
+            &[&(retention_hours as i32)],
+        )
+        .await?;
+    Ok(deleted)
+}
+
+pub async fn insert_mapping_job(
+    client: &Client,
+    id: uuid::Uuid,
+    source_codes: i32,
+    warnings: &[String],
+    created_by: Option<&str>,
+) -> Result<MappingJob, PgError> {
+    let stmt = include_str!("../sql/insert_mapping_job.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let row = client
+        .query_one(&stmt, &[&id, &source_codes, &warnings, &created_by])
+        .await?;
+    Ok(MappingJob::from_row(row).unwrap())
+}
+
+pub async fn get_mapping_job(client: &Client, id: uuid::Uuid) -> Result<MappingJob, PgError> {
+    let stmt = include_str!("../sql/select_mapping_job.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let row = client
+        .query_opt(&stmt, &[&id])
+        .await?
+        .ok_or(PgError::NotFound)?;
+    Ok(MappingJob::from_row(row).unwrap())
+}
+
+/// Stores the mapped source codes of a chunk of a mapping job in one statement.
+pub async fn insert_mapping_job_rows(
+    client: &Client,
+    job_id: uuid::Uuid,
+    rows: &[MappingJobRow],
+) -> Result<(), PgError> {
+    let stmt = include_str!("../sql/insert_mapping_job_rows.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let row_numbers: Vec<i32> = rows.iter().map(|row| row.row_number).collect();
+    let source_codes: Vec<&str> = rows.iter().map(|row| row.source_code.as_str()).collect();
+    let source_names: Vec<&str> = rows.iter().map(|row| row.source_name.as_str()).collect();
+    let frequencies: Vec<Option<i64>> = rows.iter().map(|row| row.frequency).collect();
+    let candidates: Vec<&serde_json::Value> = rows.iter().map(|row| &row.candidates).collect();
+    let errors: Vec<Option<&str>> = rows.iter().map(|row| row.error.as_deref()).collect();
+    client
+        .execute(
+            &stmt,
+            &[
+                &job_id,
+                &row_numbers,
+                &source_codes,
+                &source_names,
+                &frequencies,
+                &candidates,
+                &errors,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// The rows of a mapping job in order, only those with the review `status` and assigned to
+/// `assigned_to` when given.
+pub async fn get_mapping_job_rows(
+    client: &Client,
+    job_id: uuid::Uuid,
+    status: Option<&str>,
+    assigned_to: Option<&str>,
+) -> Result<Vec<MappingJobRow>, PgError> {
+    let stmt = include_str!("../sql/select_mapping_job_rows.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let rows = client
+        .query(&stmt, &[&job_id, &status, &assigned_to])
+        .await?
+        .into_iter()
+        .map(|row| MappingJobRow::from_row(row).unwrap())
+        .collect();
+    Ok(rows)
+}
+
+/// Records how many source codes a job mapped so far, unless it already finished.
+pub async fn update_mapping_job_progress(
+    client: &Client,
+    id: uuid::Uuid,
+    progress: f64,
+    mapped_codes: i32,
+) -> Result<(), PgError> {
+    let stmt = include_str!("../sql/update_mapping_job_progress.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    client
+        .execute(&stmt, &[&id, &progress, &mapped_codes])
+        .await?;
+    Ok(())
+}
+
+/// Records the outcome of a mapping job with how many of its source codes had no candidates
+/// and how many failed.
+pub async fn finish_mapping_job(
+    client: &Client,
+    id: uuid::Uuid,
+    status: &str,
+    (unmatched_codes, failed_codes): (i32, i32),
+    error: Option<&str>,
+) -> Result<(), PgError> {
+    let stmt = include_str!("../sql/update_mapping_job_finished.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    client
+        .execute(
+            &stmt,
+            &[&id, &status, &unmatched_codes, &failed_codes, &error],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Removes mapping jobs submitted more than `retention_hours` ago, unless their review started.
+pub async fn delete_expired_mapping_jobs(
+    client: &Client,
+    retention_hours: i64,
+) -> Result<u64, PgError> {
+    let deleted = client
+        .execute(
+            "DELETE FROM hecate.mapping_job job
+             WHERE created_at < now() - make_interval(hours => $1::int)
+               AND NOT EXISTS (SELECT 1 FROM hecate.mapping_review WHERE job_id = job.id)
+               AND NOT EXISTS (SELECT 1 FROM hecate.mapping_review_comment WHERE job_id = job.id)",
+            &[&(retention_hours as i32)],
+        )
+        .await?;
+    Ok(deleted)
+}
+
+/// Removes a mapping job with its review state and comments.
+pub async fn delete_mapping_job(client: &Client, id: uuid::Uuid) -> Result<(), PgError> {
+    let deleted = client
+        .execute("DELETE FROM hecate.mapping_job WHERE id = $1", &[&id])
+        .await?;
+    if deleted == 0 {
+        return Err(PgError::NotFound);
+    }
+    Ok(())
+}
+
+pub async fn upsert_mapping_review(
+    client: &Client,
+    review: &MappingReviewChange<'_>,
+) -> Result<MappingReview, PgError> {
+    let stmt = include_str!("../sql/upsert_mapping_review.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let row = client
+        .query_one(
+            &stmt,
+            &[
+                &review.job_id,
+                &review.row_number,
+                &review.status,
+                &review.concept_id,
+                &review.assigned_to,
+                &review.updated_by,
+            ],
+        )
+        .await?;
+    Ok(MappingReview::from_row(row).unwrap())
+}
+
+/// The review state of the rows of a mapping job that were reviewed or assigned.
+pub async fn get_mapping_reviews(
+    client: &Client,
+    job_id: uuid::Uuid,
+) -> Result<Vec<MappingReview>, PgError> {
+    let stmt = include_str!("../sql/select_mapping_reviews.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let reviews = client
+        .query(&stmt, &[&job_id])
+        .await?
+        .into_iter()
+        .map(|row| MappingReview::from_row(row).unwrap())
+        .collect();
+    Ok(reviews)
+}
+
+pub async fn insert_mapping_review_comment(
+    client: &Client,
+    job_id: uuid::Uuid,
+    row_number: i32,
+    body: &str,
+    created_by: Option<&str>,
+) -> Result<MappingReviewComment, PgError> {
+    let stmt = include_str!("../sql/insert_mapping_review_comment.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let row = client
+        .query_one(&stmt, &[&job_id, &row_number, &body, &created_by])
+        .await?;
+    Ok(MappingReviewComment::from_row(row).unwrap())
+}
+
+pub async fn get_mapping_review_comments(
+    client: &Client,
+    job_id: uuid::Uuid,
+    row_number: i32,
+) -> Result<Vec<MappingReviewComment>, PgError> {
+    let stmt = include_str!("../sql/select_mapping_review_comments.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let comments = client
+        .query(&stmt, &[&job_id, &row_number])
+        .await?
+        .into_iter()
+        .map(|row| MappingReviewComment::from_row(row).unwrap())
+        .collect();
+    Ok(comments)
+}
+
+pub async fn insert_recommendation_feedback(
+    client: &Client,
+    feedback: &NewRecommendationFeedback<'_>,
+) -> Result<RecommendationFeedback, PgError> {
+    let stmt = include_str!("../sql/insert_recommendation_feedback.sql");
+    let stmt = client.prepare_cached(stmt).await?;
+    let row = client
+        .query_one(
+            &stmt,
+            &[
+                &feedback.concept_set_id,
+                &feedback.concept_id,
+                &feedback.source_concept_id,
+                &feedback.source_text,
+                &feedback.similarity_score,
+                &feedback.verdict,
+                &feedback.vocabulary_version,
+                &feedback.created_by,
+            ],
+        )
+        .await?;
+    Ok(RecommendationFeedback::from_row(row).unwrap())
+}
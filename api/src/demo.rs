@@ -0,0 +1,114 @@
+use crate::StateWrapper;
+use actix_web::body::{BoxBody, MessageBody, to_bytes};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::http::header::{CACHE_CONTROL, CONTENT_TYPE, HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::web::{Bytes, Data};
+use actix_web::{Error, HttpResponse};
+use log::info;
+use serde_json::Value;
+
+pub const DEMO_HEADER: HeaderName = HeaderName::from_static("x-hecate-demo");
+
+/// POST endpoints that only compute a response and never change server state, so they stay
+/// available on the demo instance.
+const READ_ONLY_POST_PATHS: &[&str] = &[
+    "/api/concepts/code",
+    "/api/conceptsets/analyze",
+    "/api/conceptsets/analyze/stream",
+    "/api/conceptsets/import",
+    "/api/conceptsets/export/sql",
+    "/api/conceptsets/export/xlsx",
+    "/api/conceptsets/review",
+    "/api/conceptsets/resolve",
+    "/api/conceptsets/compare",
+    "/api/conceptsets/optimize",
+    "/api/expand",
+    "/api/recommendations",
+    "/api/negative-controls",
+    "/api/graphql",
+    "/api/search/batch",
+    "/fhir/ValueSet/$expand",
+    "/fhir/ConceptMap/$translate",
+    "/fhir/ValueSet/$validate-code",
+];
+
+/// Adds the watermark to JSON object bodies, so it survives copying the response without its
+/// headers. Arrays, streams and other bodies only get the header.
+async fn watermark_json(
+    res: ServiceResponse<BoxBody>,
+    watermark: &str,
+) -> ServiceResponse<BoxBody> {
+    let is_json = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json {
+        return res;
+    }
+    let (http_req, http_res) = res.into_parts();
+    let (http_res, body) = http_res.into_parts();
+    let Ok(body) = to_bytes(body).await else {
+        return ServiceResponse::new(http_req, HttpResponse::InternalServerError().finish());
+    };
+    let body = match serde_json::from_slice::<Value>(&body) {
+        Ok(Value::Object(mut object)) => {
+            object.insert("watermark".to_string(), Value::from(watermark));
+            serde_json::to_vec(&object).map(Bytes::from).unwrap_or(body)
+        }
+        _ => body,
+    };
+    ServiceResponse::new(http_req, http_res.set_body(BoxBody::new(body)))
+}
+
+fn is_state_changing(method: &Method, path: &str) -> bool {
+    if path.starts_with("/api/admin") {
+        return true;
+    }
+    match *method {
+        Method::GET | Method::HEAD | Method::OPTIONS => false,
+        Method::POST => !READ_ONLY_POST_PATHS.contains(&path),
+        _ => true,
+    }
+}
+
+/// Middleware enforcing demo mode: rejects state-changing requests, marks read responses as
+/// cacheable and watermarks every response. The stricter demo rate limits are applied by
+/// `rate_limit::limit_requests`, see `Configs::rate_limit_in_effect`.
+pub async fn demo_mode_guard(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let demo = match req.app_data::<Data<StateWrapper>>() {
+        Some(state) if state.config.demo.enabled => state.config.demo.clone(),
+        _ => return next.call(req).await.map(|res| res.map_into_boxed_body()),
+    };
+    let watermark = HeaderValue::from_str(&demo.watermark)
+        .unwrap_or_else(|_| HeaderValue::from_static("Hecate public demo"));
+
+    if is_state_changing(req.method(), req.path()) {
+        info!("Rejecting {} {} in demo mode", req.method(), req.path());
+        let response = HttpResponse::Forbidden()
+            .insert_header((DEMO_HEADER, watermark))
+            .json(serde_json::json!({
+                "error": "This endpoint is disabled on the demo instance",
+                "watermark": demo.watermark
+            }));
+        return Ok(req.into_response(response));
+    }
+
+    let is_read = req.method() == Method::GET;
+    let mut res =
+        watermark_json(next.call(req).await?.map_into_boxed_body(), &demo.watermark).await;
+    let headers = res.headers_mut();
+    headers.insert(DEMO_HEADER, watermark);
+    if is_read && res.status().is_success() {
+        let cache_control = format!("public, max-age={}", demo.cache_max_age_secs);
+        if let Ok(value) = HeaderValue::from_str(&cache_control) {
+            res.headers_mut().insert(CACHE_CONTROL, value);
+        }
+    }
+    Ok(res)
+}
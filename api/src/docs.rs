@@ -0,0 +1,199 @@
+use crate::{
+    api, auth, autocomplete, boosting, catalog, code_systems, concept_codes, concept_graph,
+    concept_sets, curation, debug, expand, expansions, fhir, graphql, jobs, mapping_export,
+    mapping_reviews, metrics, negative_controls, optimize, promotion, recommendations, review, slo,
+    snapshot, source_mapping, vocabulary_diff,
+};
+use actix_web::http::header;
+use actix_web::{Error, HttpResponse, get};
+use serde::Serialize;
+use std::sync::LazyLock;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
+
+/// The body of a rejected request.
+#[derive(Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+/// The specification, generated from the `#[utoipa::path]` annotations of the handlers and the
+/// schemas of their request and response types, so it always matches the running build.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Hecate API",
+        description = "Semantic search engine for the OHDSI vocabulary. Rejected requests answer with an \
+            `ErrorResponse` body.",
+        version = "1.0.0",
+        contact(name = "Hecate API", url = "https://hecate.pantheon-hds.com")
+    ),
+    servers(
+        (url = "https://hecate.pantheon-hds.com", description = "Production server"),
+        (url = "http://localhost:8080", description = "Local development server (main API)"),
+        (url = "http://localhost:8081", description = "Local development server (autocomplete)")
+    ),
+    // Keys are only required when the instance runs with AUTH__ENABLED=true
+    security((), ("apiKey" = []), ("bearerAuth" = [])),
+    modifiers(&SecuritySchemes),
+    paths(
+        api::search,
+        api::search_batch,
+        api::get_concept_by_id,
+        api::get_concept_relationships,
+        api::get_concept_mappings,
+        api::get_concept_synonyms,
+        api::get_concept_ancestors,
+        api::get_concept_descendants,
+        api::get_concept_phoebe,
+        api::get_concept_definition,
+        api::analyze_concept_set,
+        api::analyze_concept_set_stream,
+        api::get_validation_profiles,
+        api::import_concept_sets,
+        api::export_codeset_sql,
+        api::export_concept_set_xlsx,
+        api::resolve_concept_set,
+        api::compare_concept_sets,
+        auth::list_api_keys,
+        auth::create_api_key,
+        auth::revoke_api_key,
+        autocomplete::autocomplete,
+        boosting::get_boosting_rules,
+        boosting::reload_boosting_rules,
+        catalog::list_vocabularies,
+        catalog::list_domains,
+        catalog::list_concept_classes,
+        catalog::get_version,
+        code_systems::list_systems,
+        code_systems::ingest_code_system,
+        code_systems::get_mapping_candidates,
+        concept_codes::get_concepts_by_code,
+        concept_codes::get_concepts_by_codes,
+        concept_graph::get_concept_classes,
+        concept_graph::get_vocabulary_roots,
+        concept_graph::get_concept_hierarchy,
+        concept_sets::list_concept_sets,
+        concept_sets::create_concept_set,
+        concept_sets::get_concept_set,
+        concept_sets::update_concept_set,
+        concept_sets::delete_concept_set,
+        concept_sets::list_concept_set_versions,
+        concept_sets::get_concept_set_version,
+        concept_sets::analyze_stored_concept_set,
+        concept_sets::get_concept_set_impact,
+        curation::list_zero_result_queries,
+        curation::add_zero_result_overrides,
+        curation::set_zero_result_status,
+        curation::list_synonyms,
+        curation::create_synonym,
+        curation::update_synonym,
+        curation::delete_synonym,
+        debug::export_search_bundle,
+        expand::expand_concepts,
+        expansions::get_expansion,
+        fhir::lookup_code,
+        fhir::expand_value_set,
+        fhir::validate_code,
+        fhir::translate_code,
+        graphql::graphql,
+        jobs::create_validation_job,
+        jobs::get_validation_job,
+        source_mapping::create_mapping_job,
+        source_mapping::get_mapping_job,
+        source_mapping::get_mapping_job_results,
+        source_mapping::delete_mapping_job,
+        mapping_export::export_mappings,
+        mapping_reviews::review_mapping_row,
+        mapping_reviews::list_mapping_row_comments,
+        mapping_reviews::comment_on_mapping_row,
+        metrics::get_metrics,
+        slo::get_slo_status,
+        slo::get_health,
+        slo::get_readiness,
+        snapshot::get_index_snapshot,
+        snapshot::swap_index_snapshot,
+        snapshot::reindex,
+        negative_controls::suggest_negative_controls,
+        optimize::optimize_concept_set,
+        review::review_concept_set,
+        recommendations::recommend_concepts,
+        recommendations::record_feedback,
+        promotion::export_state,
+        promotion::import_state,
+        vocabulary_diff::get_vocabulary_diff,
+    ),
+    components(schemas(ErrorResponse)),
+    tags(
+        (name = "search", description = "Concept search and autocomplete"),
+        (name = "concepts", description = "Concepts, their relationships and hierarchy"),
+        (name = "vocabularies", description = "The loaded vocabularies and their releases"),
+        (name = "concept sets", description = "Analysis, resolution and export of concept sets"),
+        (name = "stored concept sets", description = "Concept sets saved in Hecate, with their versions"),
+        (name = "jobs", description = "Background concept set analysis"),
+        (name = "mapping", description = "Source code mapping jobs and their review"),
+        (name = "code systems", description = "Auxiliary code systems searched next to the OMOP vocabulary"),
+        (name = "fhir", description = "FHIR terminology operations"),
+        (name = "graphql", description = "GraphQL queries over concepts"),
+        (name = "operations", description = "Health, readiness and metrics"),
+        (name = "admin", description = "Administration, requires the admin scope"),
+    )
+)]
+pub struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "apiKey",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-API-Key"))),
+        );
+        components.add_security_scheme(
+            "bearerAuth",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some(
+                        "An API key, or a JWT from the configured OpenID Connect issuer",
+                    ))
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// The specification as YAML, rendered once.
+static OPENAPI_YAML: LazyLock<String> = LazyLock::new(|| {
+    ApiDoc::openapi()
+        .to_yaml()
+        .expect("The OpenAPI specification renders as YAML")
+});
+
+pub fn openapi_yaml() -> &'static str {
+    &OPENAPI_YAML
+}
+
+/// Swagger UI at `/api/docs/` with the JSON specification at `/api/openapi.json`. The UI's
+/// assets are bundled into the binary, so the documentation works without internet access.
+pub fn swagger_ui() -> SwaggerUi {
+    SwaggerUi::new("/api/docs/{_:.*}").url("/api/openapi.json", ApiDoc::openapi())
+}
+
+/// The specification as YAML, for tools that prefer it over `/api/openapi.json`.
+#[get("/api/openapi.yaml")]
+async fn get_openapi_spec() -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok()
+        .content_type("application/yaml")
+        .body(OPENAPI_YAML.as_str()))
+}
+
+/// Swagger UI is served below `/api/docs/`, where its relative asset paths resolve.
+#[get("/api/docs")]
+async fn get_swagger_ui() -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::PermanentRedirect()
+        .insert_header((header::LOCATION, "/api/docs/"))
+        .finish())
+}
@@ -1,16 +1,29 @@
-use chrono::NaiveDate;
+use crate::debug::SearchExplanation;
+use async_graphql::SimpleObject;
+use chrono::{DateTime, NaiveDate, Utc};
+use log::warn;
 use qdrant_client::qdrant::{RetrievedPoint, ScoredPoint};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
 use tokio_pg_mapper_derive::PostgresMapper;
+use utoipa::ToSchema;
+use uuid::Uuid;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject, ToSchema)]
 pub struct SearchResponse {
+    /// Always the OMOP vocabulary, matches from auxiliary code systems are `CodeSystemMatch`es.
+    #[serde(default = "omop_system")]
+    pub system: String,
     pub concept_name: String,
     pub concept_name_lower: String,
     pub score: Option<f64>,
     pub concepts: Vec<Concept>,
 }
 
+fn omop_system() -> String {
+    crate::code_systems::OMOP_SYSTEM.to_string()
+}
+
 impl SearchResponse {
     pub(crate) fn append_concepts(&mut self, additional_concepts: &mut Vec<Concept>) {
         self.concepts.append(additional_concepts)
@@ -20,23 +33,171 @@ impl SearchResponse {
 impl From<ScoredPoint> for SearchResponse {
     fn from(item: ScoredPoint) -> Self {
         let payload = serde_json::to_string(&item.payload).unwrap();
-        let res: Result<SearchResponse, _> = serde_json::from_str(&payload);
-        if res.is_ok() {
-            let mut concept: SearchResponse = res.unwrap();
-            concept.score = Some(item.score as f64);
-            concept
-        } else {
-            dbg!("{?}", item.payload);
-            SearchResponse {
-                concept_name: "String".parse().unwrap(),
-                concept_name_lower: "String".parse().unwrap(),
-                score: Some(0f64),
-                concepts: Vec::new(),
+        match serde_json::from_str::<SearchResponse>(&payload) {
+            Ok(mut concept) => {
+                concept.score = Some(item.score as f64);
+                concept
+            }
+            Err(e) => {
+                warn!(
+                    "Point {:?} has a payload that is not a search result: {}",
+                    item.id, e
+                );
+                SearchResponse {
+                    system: omop_system(),
+                    concept_name: "String".parse().unwrap(),
+                    concept_name_lower: "String".parse().unwrap(),
+                    score: Some(0f64),
+                    concepts: Vec::new(),
+                }
             }
         }
     }
 }
 
+/// A concept in the resolved set returned by `/api/conceptsets/resolve`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ResolvedConcept {
+    pub concept_id: i32,
+    pub concept_name: String,
+    pub vocabulary_id: String,
+    pub domain_id: String,
+}
+
+/// Envelope returned by `/api/search` when the client asks for `envelope=true`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchResults {
+    pub results: Vec<SearchResponse>,
+    /// Results across all pages.
+    pub total: u64,
+    pub has_more: bool,
+    /// Pass as `cursor` to fetch the next page.
+    pub next_cursor: Option<String>,
+    /// A respelling of a query without results, e.g. "azithromycin" for "azitromycin".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggested_query: Option<String>,
+    /// Counts across all pages per filter value.
+    pub facets: SearchFacets,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diagnostics: Option<SearchDiagnostics>,
+    /// The trace of the search, with `debug=true`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub debug: Option<SearchExplanation>,
+}
+
+/// Explains why a search came back empty and what the caller could relax.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, ToSchema)]
+pub struct SearchDiagnostics {
+    pub override_hit: bool,
+    pub index_hit: bool,
+    pub vocabulary_hit: bool,
+    pub embedding_attempted: bool,
+    pub embedding_succeeded: Option<bool>,
+    pub candidates_retrieved: usize,
+    pub score_threshold: f32,
+    pub best_below_threshold_score: Option<f32>,
+    pub rejected_by_filter: BTreeMap<String, usize>,
+    pub suggested_relaxations: Vec<Relaxation>,
+    /// Filled in while candidates are filtered, moved to the cached search afterwards.
+    #[serde(skip)]
+    pub facets: FacetCounter,
+}
+
+/// Result counts per filter value, for rendering filter facets. Each facet counts the concepts
+/// all other filters let through, so the values a filter left out still show what selecting
+/// them would return.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, ToSchema)]
+pub struct SearchFacets {
+    pub vocabulary_id: BTreeMap<String, usize>,
+    pub domain_id: BTreeMap<String, usize>,
+    pub concept_class_id: BTreeMap<String, usize>,
+    /// `S` and `C`, and `""` for non-standard concepts as the `standard_concept` filter takes it.
+    pub standard_concept: BTreeMap<String, usize>,
+}
+
+/// Builds `SearchFacets` from the candidates of every generator, counting each concept once
+/// per facet even when several generators found it.
+#[derive(Clone, Debug, Default)]
+pub struct FacetCounter {
+    seen: [HashSet<i32>; 4],
+    facets: SearchFacets,
+}
+
+impl FacetCounter {
+    /// Counts the concept in the facet named after its filter parameter.
+    pub fn record(&mut self, facet: &str, concept: &Concept) {
+        let (index, counts, value) = match facet {
+            "vocabulary_id" => (
+                0,
+                &mut self.facets.vocabulary_id,
+                concept.vocabulary_id.as_str(),
+            ),
+            "domain_id" => (1, &mut self.facets.domain_id, concept.domain_id.as_str()),
+            "concept_class_id" => (
+                2,
+                &mut self.facets.concept_class_id,
+                concept.concept_class_id.as_str(),
+            ),
+            "standard_concept" => (
+                3,
+                &mut self.facets.standard_concept,
+                concept.standard_concept.as_deref().unwrap_or(""),
+            ),
+            _ => return,
+        };
+        if self.seen[index].insert(concept.concept_id) {
+            *counts.entry(value.to_string()).or_default() += 1;
+        }
+    }
+
+    pub fn take(&mut self) -> SearchFacets {
+        self.seen = Default::default();
+        std::mem::take(&mut self.facets)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Relaxation {
+    LowerThreshold { score_threshold: f32 },
+    RemoveFilter { filter: String, rejected: usize },
+}
+
+impl SearchDiagnostics {
+    pub fn new(score_threshold: f32) -> Self {
+        Self {
+            score_threshold,
+            ..Default::default()
+        }
+    }
+
+    pub fn record_rejection(&mut self, filter: &str) {
+        *self
+            .rejected_by_filter
+            .entry(filter.to_string())
+            .or_default() += 1;
+    }
+
+    /// Derive the suggested relaxations from what was observed during the search.
+    pub fn suggest_relaxations(&mut self) {
+        self.suggested_relaxations.clear();
+        if let Some(best) = self.best_below_threshold_score
+            && best < self.score_threshold
+        {
+            // Round down so the suggested threshold actually admits the best candidate
+            let score_threshold = (best * 100.0).floor() / 100.0;
+            self.suggested_relaxations
+                .push(Relaxation::LowerThreshold { score_threshold });
+        }
+        for (filter, rejected) in &self.rejected_by_filter {
+            self.suggested_relaxations.push(Relaxation::RemoveFilter {
+                filter: filter.clone(),
+                rejected: *rejected,
+            });
+        }
+    }
+}
+
 impl From<RetrievedPoint> for SearchResponse {
     fn from(item: RetrievedPoint) -> Self {
         let payload = serde_json::to_string(&item.payload).unwrap();
@@ -46,8 +207,11 @@ impl From<RetrievedPoint> for SearchResponse {
     }
 }
 
-#[derive(Debug, Deserialize, PostgresMapper, Serialize)]
+/// A vocabulary concept. Serializes with the API's snake_case names by default; concept set
+/// expressions use the ATLAS profile through `#[serde(with = "atlas_concept")]`.
+#[derive(Clone, Debug, Deserialize, PostgresMapper, Serialize, SimpleObject, ToSchema)]
 #[pg_mapper(table = "concept")]
+#[graphql(complex)]
 pub struct Concept {
     pub concept_id: i32,
     pub concept_name: String,
@@ -61,7 +225,104 @@ pub struct Concept {
     pub valid_end_date: Option<NaiveDate>,
 }
 
-#[derive(Debug, Deserialize, PostgresMapper, Serialize)]
+/// The ATLAS serde profile of `Concept`: uppercase field names and the display captions ATLAS
+/// shows next to the standard and invalid flags.
+pub mod atlas_concept {
+    use super::Concept;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use utoipa::ToSchema;
+
+    #[derive(Deserialize, Serialize, ToSchema)]
+    #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+    pub struct AtlasConcept {
+        pub concept_id: i32,
+        pub concept_name: String,
+        pub vocabulary_id: String,
+        pub domain_id: String,
+        pub concept_class_id: String,
+        pub standard_concept: Option<String>,
+        #[serde(default)]
+        pub standard_concept_caption: Option<String>,
+        pub invalid_reason: Option<String>,
+        #[serde(default)]
+        pub invalid_reason_caption: Option<String>,
+        #[serde(default)]
+        pub concept_code: Option<String>,
+    }
+
+    fn standard_concept_caption(standard_concept: Option<&str>) -> &'static str {
+        match standard_concept {
+            Some("S") => "Standard",
+            Some("C") => "Classification",
+            _ => "Non-Standard",
+        }
+    }
+
+    fn invalid_reason_caption(invalid_reason: Option<&str>) -> &'static str {
+        match invalid_reason {
+            None | Some("") | Some("V") => "Valid",
+            Some(_) => "Invalid",
+        }
+    }
+
+    impl From<AtlasConcept> for Concept {
+        fn from(concept: AtlasConcept) -> Self {
+            Concept {
+                concept_id: concept.concept_id,
+                concept_name: concept.concept_name,
+                domain_id: concept.domain_id,
+                vocabulary_id: concept.vocabulary_id,
+                concept_class_id: concept.concept_class_id,
+                standard_concept: concept.standard_concept,
+                concept_code: concept.concept_code.unwrap_or_default(),
+                invalid_reason: concept.invalid_reason,
+                valid_start_date: None,
+                valid_end_date: None,
+            }
+        }
+    }
+
+    impl From<&Concept> for AtlasConcept {
+        fn from(concept: &Concept) -> Self {
+            AtlasConcept {
+                concept_id: concept.concept_id,
+                concept_name: concept.concept_name.clone(),
+                vocabulary_id: concept.vocabulary_id.clone(),
+                domain_id: concept.domain_id.clone(),
+                concept_class_id: concept.concept_class_id.clone(),
+                standard_concept: concept.standard_concept.clone(),
+                standard_concept_caption: Some(
+                    standard_concept_caption(concept.standard_concept.as_deref()).to_string(),
+                ),
+                invalid_reason: concept.invalid_reason.clone(),
+                invalid_reason_caption: Some(
+                    invalid_reason_caption(concept.invalid_reason.as_deref()).to_string(),
+                ),
+                concept_code: (!concept.concept_code.is_empty())
+                    .then(|| concept.concept_code.clone()),
+            }
+        }
+    }
+
+    pub fn serialize<S: Serializer>(concept: &Concept, serializer: S) -> Result<S::Ok, S::Error> {
+        AtlasConcept::from(concept).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Concept, D::Error> {
+        AtlasConcept::deserialize(deserializer).map(Concept::from)
+    }
+}
+
+/// A name to embed as one point of the concept collection, with the concepts it names.
+#[derive(Debug, PostgresMapper)]
+#[pg_mapper(table = "ingest_name")]
+pub struct IngestName {
+    pub concept_name: String,
+    pub concept_name_lower: String,
+    pub concept_ids: Vec<i32>,
+}
+
+#[derive(Clone, Debug, Deserialize, PostgresMapper, Serialize, SimpleObject, ToSchema)]
 #[pg_mapper(table = "related_concept_dto)")]
 pub struct RelatedConcept {
     pub relationship_id: String,
@@ -69,3 +330,457 @@ pub struct RelatedConcept {
     pub concept_name: String,
     pub vocabulary_id: String,
 }
+
+/// A valid concept another concept points to through `relationship_id`, e.g. its replacement
+/// or the standard concept it maps to.
+#[derive(Debug, Clone, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "linked_concept")]
+pub struct LinkedConcept {
+    pub source_concept_id: i32,
+    pub relationship_id: String,
+    pub concept_id: i32,
+    pub concept_name: String,
+    pub vocabulary_id: String,
+    pub domain_id: String,
+    pub standard_concept: Option<String>,
+}
+
+#[derive(Debug, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "zero_result_query")]
+pub struct ZeroResultQuery {
+    pub id: i64,
+    pub query: String,
+    pub query_lower: String,
+    pub filters: Option<serde_json::Value>,
+    pub occurrences: i32,
+    pub status: String,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub override_concept_ids: Vec<i32>,
+}
+
+#[derive(Debug, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "synonym_override")]
+pub struct SynonymOverride {
+    pub id: i64,
+    pub alias: String,
+    pub alias_lower: String,
+    pub concept_id: i32,
+    pub concept_name: Option<String>,
+    pub source: String,
+    pub created_by: Option<String>,
+    pub note: Option<String>,
+    pub zero_result_query_id: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A synonym override as carried between instances, linked to its zero-result query by the
+/// query text since ids differ between databases.
+#[derive(Debug, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "synonym_override")]
+pub struct ArchivedSynonymOverride {
+    pub alias: String,
+    pub concept_id: i32,
+    pub source: String,
+    pub created_by: Option<String>,
+    pub note: Option<String>,
+    pub zero_result_query: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "zero_result_query")]
+pub struct ArchivedZeroResultQuery {
+    pub query: String,
+    pub filters: Option<serde_json::Value>,
+    pub occurrences: i32,
+    pub status: String,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+/// A stored concept set as carried between instances, identified by its `uid` since ids differ
+/// between databases.
+#[derive(Debug, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "concept_set")]
+pub struct ArchivedConceptSet {
+    pub uid: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub created_by: Option<String>,
+    pub latest_version: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+/// A version of an archived concept set, linked to it by the set's `uid`.
+#[derive(Debug, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "concept_set_version")]
+pub struct ArchivedConceptSetVersion {
+    pub concept_set_uid: Uuid,
+    pub version: i32,
+    pub expression: serde_json::Value,
+    pub expression_hash: String,
+    pub created_by: Option<String>,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A recommendation verdict as carried between instances. Feedback on a stored concept set
+/// refers to it by its `uid`, feedback on an ATLAS concept set by the ATLAS `concept_set_id`.
+#[derive(Debug, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "recommendation_feedback")]
+pub struct ArchivedRecommendationFeedback {
+    pub concept_set_uid: Option<Uuid>,
+    pub concept_set_id: Option<i64>,
+    pub concept_id: i32,
+    pub source_concept_id: Option<i32>,
+    pub source_text: Option<String>,
+    pub similarity_score: Option<f32>,
+    pub verdict: String,
+    pub vocabulary_version: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "vocabulary")]
+pub struct Vocabulary {
+    pub vocabulary_id: String,
+    pub vocabulary_name: String,
+    pub vocabulary_version: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "domain")]
+pub struct Domain {
+    pub domain_id: String,
+    pub domain_name: String,
+}
+
+#[derive(Debug, Clone, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "concept_class")]
+pub struct ConceptClass {
+    pub concept_class_id: String,
+    pub concept_class_name: String,
+}
+
+/// How many concepts share a vocabulary, domain and concept class.
+#[derive(Debug, PostgresMapper)]
+#[pg_mapper(table = "concept_count")]
+pub struct ConceptCount {
+    pub vocabulary_id: String,
+    pub domain_id: String,
+    pub concept_class_id: String,
+    pub concept_count: i64,
+}
+
+/// What an idempotency key is scoped to: the same key sent by another client, or to another
+/// route, is a different request.
+pub struct IdempotencyKey<'a> {
+    pub key: &'a str,
+    /// The authenticated identity, empty for anonymous requests.
+    pub owner: &'a str,
+    pub method: &'a str,
+    pub path: &'a str,
+}
+
+#[derive(Debug, PostgresMapper)]
+#[pg_mapper(table = "idempotency_key")]
+pub struct IdempotencyRecord {
+    pub request_hash: String,
+    pub status_code: Option<i32>,
+    pub content_type: Option<String>,
+    pub response_body: Option<Vec<u8>>,
+}
+
+/// A concept set expansion shared by everyone resolving the same expression against the same
+/// vocabulary release.
+#[derive(Debug, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "resolved_expansion")]
+pub struct ResolvedExpansion {
+    pub expression_hash: String,
+    pub vocabulary_version: String,
+    pub expression: serde_json::Value,
+    pub expansion: serde_json::Value,
+    pub resolved_count: i32,
+    pub hits: i32,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "concept_class_summary")]
+pub struct ConceptClassSummary {
+    pub concept_class_id: String,
+    pub concept_count: i64,
+    pub standard_count: i64,
+    /// The `concept_id` and `concept_name` of a few concepts of the class.
+    pub sample_concepts: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "hierarchy_root")]
+pub struct HierarchyRoot {
+    pub concept_id: i32,
+    pub concept_name: String,
+    pub domain_id: String,
+    pub concept_class_id: String,
+    pub standard_concept: Option<String>,
+    pub descendant_count: i64,
+}
+
+/// An alternative name of a concept from `concept_synonym`, with the language it is in.
+#[derive(Clone, Debug, Deserialize, PostgresMapper, Serialize, SimpleObject, ToSchema)]
+#[pg_mapper(table = "concept_synonym")]
+pub struct ConceptSynonym {
+    pub concept_synonym_name: String,
+    pub language_concept_id: i32,
+    pub language_name: Option<String>,
+}
+
+/// A concept that is new, deprecated or mapped differently in one vocabulary release compared to
+/// an earlier one, with its standard concepts before and after when they changed.
+#[derive(Debug, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "vocabulary_change")]
+pub struct VocabularyChange {
+    pub concept_id: i32,
+    pub concept_name: String,
+    pub domain_id: String,
+    pub vocabulary_id: String,
+    pub concept_class_id: String,
+    pub standard_concept: Option<String>,
+    pub concept_code: String,
+    pub invalid_reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_maps_to: Option<Vec<i32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maps_to: Option<Vec<i32>>,
+}
+
+/// A concept above or below another one in `concept_ancestor`, with the number of steps between
+/// them along the shortest and the longest path.
+#[derive(Clone, Debug, Deserialize, PostgresMapper, Serialize, SimpleObject, ToSchema)]
+#[pg_mapper(table = "hierarchy_concept")]
+pub struct HierarchyConcept {
+    pub concept_id: i32,
+    pub concept_name: String,
+    pub domain_id: String,
+    pub vocabulary_id: String,
+    pub concept_class_id: String,
+    pub standard_concept: Option<String>,
+    pub concept_code: String,
+    pub invalid_reason: Option<String>,
+    pub min_levels_of_separation: i32,
+    pub max_levels_of_separation: i32,
+}
+
+/// A direct parent to child step in `concept_ancestor`.
+#[derive(Debug, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "hierarchy_edge")]
+pub struct HierarchyEdge {
+    pub parent_concept_id: i32,
+    pub child_concept_id: i32,
+}
+
+/// A named concept set stored in `hecate.concept_set`. Its expressions are kept per version in
+/// `hecate.concept_set_version` and never changed once written.
+#[derive(Debug, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "concept_set")]
+pub struct StoredConceptSet {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub created_by: Option<String>,
+    pub latest_version: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "concept_set_version")]
+pub struct ConceptSetVersion {
+    pub concept_set_id: i64,
+    pub version: i32,
+    pub expression: serde_json::Value,
+    pub expression_hash: String,
+    pub created_by: Option<String>,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The concept IDs a stored concept set resolved to at some point, kept to report what a
+/// vocabulary update changed.
+#[derive(Debug, Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "concept_set_resolution")]
+pub struct ConceptSetResolution {
+    pub id: i64,
+    pub concept_set_id: i64,
+    pub version: i32,
+    pub vocabulary_version: Option<String>,
+    pub concept_ids: Vec<i32>,
+    /// The resolved concepts that were already invalid at the time.
+    pub invalid_concept_ids: Vec<i32>,
+    pub resolved_at: DateTime<Utc>,
+}
+
+/// Changes to a stored concept set. A new version is only written when the expression hash
+/// differs from the latest one.
+pub struct ConceptSetChanges<'a> {
+    pub name: Option<&'a str>,
+    pub description: Option<&'a str>,
+    pub tags: Option<&'a [String]>,
+    pub expression: Option<(&'a serde_json::Value, &'a str)>,
+    pub author: Option<&'a str>,
+    pub note: Option<&'a str>,
+    /// The version the changes were made against; a newer latest version is a conflict.
+    pub base_version: Option<i32>,
+}
+
+/// A concept set analysis run in the background, see `jobs`.
+#[derive(Debug, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "validation_job")]
+pub struct ValidationJob {
+    pub id: Uuid,
+    /// `queued`, `running`, `succeeded` or `failed`.
+    pub status: String,
+    pub stage: Option<String>,
+    /// Share of the analysis done, from 0 to 1.
+    pub progress: f64,
+    pub profile: String,
+    /// The analysis, as answered by `/api/conceptsets/analyze`, once the job succeeded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// A bulk mapping of source codes run in the background, see `source_mapping`. The candidates
+/// are stored per source code in `MappingJobRow`s and fetched separately.
+#[derive(Debug, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "mapping_job")]
+pub struct MappingJob {
+    pub id: Uuid,
+    /// `queued`, `running`, `succeeded` or `failed`.
+    pub status: String,
+    /// Share of the source codes mapped, from 0 to 1.
+    pub progress: f64,
+    pub source_codes: i32,
+    pub mapped_codes: i32,
+    /// Source codes without a single candidate.
+    pub unmatched_codes: i32,
+    /// Source codes whose search failed, each row records why.
+    pub failed_codes: i32,
+    /// Lines of the upload that were skipped.
+    pub warnings: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// One source code of a mapping job with its candidates as JSON, or the error its search failed
+/// with.
+#[derive(Debug, Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "mapping_job_row")]
+pub struct MappingJobRow {
+    pub row_number: i32,
+    pub source_code: String,
+    pub source_name: String,
+    pub frequency: Option<i64>,
+    pub candidates: serde_json::Value,
+    pub error: Option<String>,
+}
+
+/// The review state of one row of a mapping job, see `mapping_reviews`. Rows without one are
+/// unreviewed and unassigned.
+#[derive(Clone, Debug, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "mapping_review")]
+pub struct MappingReview {
+    pub job_id: Uuid,
+    /// The row of the job's results, from 1.
+    pub row_number: i32,
+    /// `unreviewed`, `approved` or `flagged`.
+    pub status: String,
+    /// The concept the source code maps to, set when it was approved.
+    pub concept_id: Option<i32>,
+    pub assigned_to: Option<String>,
+    pub updated_by: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A change to the review of a row. Fields left `None` keep their value, an empty
+/// `assigned_to` unassigns the row.
+pub struct MappingReviewChange<'a> {
+    pub job_id: Uuid,
+    pub row_number: i32,
+    pub status: Option<&'a str>,
+    pub concept_id: Option<i32>,
+    pub assigned_to: Option<&'a str>,
+    pub updated_by: Option<&'a str>,
+}
+
+#[derive(Debug, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "mapping_review_comment")]
+pub struct MappingReviewComment {
+    pub id: i64,
+    pub job_id: Uuid,
+    pub row_number: i32,
+    pub body: String,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A key stored in `hecate.api_key`. Only its hash is kept, the key itself is shown once when
+/// it is created.
+#[derive(Debug, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "api_key")]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// A reviewer's decision on a recommended concept, stored in `hecate.recommendation_feedback`
+/// to audit reviews and to evaluate the ranking of recommendations against.
+#[derive(Debug, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "recommendation_feedback")]
+pub struct RecommendationFeedback {
+    pub id: i64,
+    /// The stored concept set, or the ATLAS one, the concept was recommended for.
+    pub concept_set_id: Option<i64>,
+    pub concept_id: i32,
+    pub source_concept_id: Option<i32>,
+    pub source_text: Option<String>,
+    pub similarity_score: Option<f32>,
+    /// `accepted` or `rejected`.
+    pub verdict: String,
+    pub vocabulary_version: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+pub struct NewRecommendationFeedback<'a> {
+    pub concept_set_id: Option<i64>,
+    pub concept_id: i32,
+    pub source_concept_id: Option<i32>,
+    pub source_text: Option<&'a str>,
+    pub similarity_score: Option<f32>,
+    pub verdict: &'a str,
+    pub vocabulary_version: Option<&'a str>,
+    pub created_by: Option<&'a str>,
+}
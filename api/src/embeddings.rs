@@ -1,19 +1,494 @@
+use crate::config::{EmbeddingBackend, EmbeddingConfig};
+use crate::errors::EmbeddingError;
 use async_openai::Client;
-use async_openai::types::{CreateEmbeddingRequestArgs, Embedding};
-use log::info;
+use async_openai::config::OpenAIConfig;
+use async_openai::types::CreateEmbeddingRequestArgs;
+use async_trait::async_trait;
+use candle_core::{Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config as BertConfig, DTYPE};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
 use std::error::Error;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokenizers::{PaddingParams, Tokenizer, TruncationParams};
+use tracing::instrument;
+use utoipa::ToSchema;
 
-pub async fn fetch_embeddings(input: String) -> Result<Embedding, Box<dyn Error>> {
-    info!("Fetching embedding from OpenAI for {:?}", &input);
-    let client = Client::new();
+const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+const DEFAULT_TEI_URL: &str = "http://localhost:8080";
 
-    let request = CreateEmbeddingRequestArgs::default()
-        .model("text-embedding-3-large")
-        .input(input.to_string())
-        .dimensions(1024u32)
-        .build()?;
+/// Vectors in input order, with the model the service says produced them when it says so.
+pub struct Embeddings {
+    pub model: Option<String>,
+    pub vectors: Vec<Vec<f32>>,
+}
+
+/// A service turning text into vectors. Implementations only transport; checking the result
+/// against the collections is up to `Embedder`.
+#[async_trait(?Send)]
+pub trait EmbeddingProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn embed(
+        &self,
+        inputs: Vec<String>,
+        model: &str,
+        dimensions: u32,
+    ) -> Result<Embeddings, Box<dyn Error>>;
+}
+
+/// The OpenAI embeddings API, or any API compatible with it.
+pub struct OpenAiProvider {
+    client: Client<OpenAIConfig>,
+}
+
+#[async_trait(?Send)]
+impl EmbeddingProvider for OpenAiProvider {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    async fn embed(
+        &self,
+        inputs: Vec<String>,
+        model: &str,
+        dimensions: u32,
+    ) -> Result<Embeddings, Box<dyn Error>> {
+        let request = CreateEmbeddingRequestArgs::default()
+            .model(model)
+            .input(inputs)
+            .dimensions(dimensions)
+            .build()?;
+        let mut response = self.client.embeddings().create(request).await?;
+        response.data.sort_by_key(|embedding| embedding.index);
+        Ok(Embeddings {
+            model: Some(response.model),
+            vectors: response
+                .data
+                .into_iter()
+                .map(|embedding| embedding.embedding)
+                .collect(),
+        })
+    }
+}
+
+/// Ollama's `/api/embed`.
+pub struct OllamaProvider {
+    client: reqwest::Client,
+    url: String,
+    api_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OllamaResponse {
+    model: Option<String>,
+    embeddings: Vec<Vec<f32>>,
+}
+
+#[async_trait(?Send)]
+impl EmbeddingProvider for OllamaProvider {
+    fn name(&self) -> &'static str {
+        "ollama"
+    }
+
+    async fn embed(
+        &self,
+        inputs: Vec<String>,
+        model: &str,
+        _dimensions: u32,
+    ) -> Result<Embeddings, Box<dyn Error>> {
+        let request = self
+            .client
+            .post(format!("{}/api/embed", self.url))
+            .json(&serde_json::json!({ "model": model, "input": inputs }));
+        let response: OllamaResponse = with_bearer(request, self.api_key.as_deref())
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(Embeddings {
+            model: response.model,
+            vectors: response.embeddings,
+        })
+    }
+}
+
+/// HuggingFace text-embeddings-inference, which serves the single model it was started with.
+pub struct TeiProvider {
+    client: reqwest::Client,
+    url: String,
+    api_key: Option<String>,
+}
+
+#[async_trait(?Send)]
+impl EmbeddingProvider for TeiProvider {
+    fn name(&self) -> &'static str {
+        "tei"
+    }
+
+    async fn embed(
+        &self,
+        inputs: Vec<String>,
+        _model: &str,
+        _dimensions: u32,
+    ) -> Result<Embeddings, Box<dyn Error>> {
+        let request = self
+            .client
+            .post(format!("{}/embed", self.url))
+            .json(&serde_json::json!({ "inputs": inputs }));
+        let vectors: Vec<Vec<f32>> = with_bearer(request, self.api_key.as_deref())
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(Embeddings {
+            model: None,
+            vectors,
+        })
+    }
+}
+
+/// A site's own service: receives `{"model", "inputs", "dimensions"}` at `url` and answers
+/// `{"embeddings": [[...]], "model": "..."}`, the model being optional.
+pub struct HttpProvider {
+    client: reqwest::Client,
+    url: String,
+    api_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct HttpResponse {
+    #[serde(default)]
+    model: Option<String>,
+    embeddings: Vec<Vec<f32>>,
+}
+
+#[async_trait(?Send)]
+impl EmbeddingProvider for HttpProvider {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    async fn embed(
+        &self,
+        inputs: Vec<String>,
+        model: &str,
+        dimensions: u32,
+    ) -> Result<Embeddings, Box<dyn Error>> {
+        let request = self.client.post(&self.url).json(&serde_json::json!({
+            "model": model,
+            "inputs": inputs,
+            "dimensions": dimensions,
+        }));
+        let response: HttpResponse = with_bearer(request, self.api_key.as_deref())
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(Embeddings {
+            model: response.model,
+            vectors: response.embeddings,
+        })
+    }
+}
+
+/// A sentence-transformer run in-process with candle, for sites that cannot reach an embedding
+/// service. Inference runs on the blocking pool so it does not stall the workers.
+pub struct LocalProvider {
+    model: Arc<LocalModel>,
+}
+
+struct LocalModel {
+    bert: BertModel,
+    tokenizer: Tokenizer,
+}
+
+impl LocalModel {
+    /// Loads a BERT-style sentence-transformer such as `all-MiniLM-L6-v2` from its directory.
+    fn load(path: &Path) -> Result<LocalModel, Box<dyn Error + Send + Sync>> {
+        let config: BertConfig =
+            serde_json::from_str(&std::fs::read_to_string(path.join("config.json"))?)?;
+        let mut tokenizer = Tokenizer::from_file(path.join("tokenizer.json"))?;
+        tokenizer.with_padding(Some(PaddingParams::default()));
+        tokenizer.with_truncation(Some(TruncationParams {
+            max_length: config.max_position_embeddings,
+            ..Default::default()
+        }))?;
+        // Safety: the weights are only read, and must not change while the API runs.
+        let weights = unsafe {
+            VarBuilder::from_mmaped_safetensors(
+                &[path.join("model.safetensors")],
+                DTYPE,
+                &Device::Cpu,
+            )?
+        };
+        Ok(LocalModel {
+            bert: BertModel::load(weights, &config)?,
+            tokenizer,
+        })
+    }
+
+    /// Mean of the token embeddings, padding left out, scaled to unit length as
+    /// sentence-transformers does.
+    fn embed(&self, inputs: Vec<String>) -> Result<Vec<Vec<f32>>, Box<dyn Error + Send + Sync>> {
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let encodings = self.tokenizer.encode_batch(inputs, true)?;
+        let device = &self.bert.device;
+        let ids = encodings
+            .iter()
+            .map(|encoding| Tensor::new(encoding.get_ids(), device))
+            .collect::<Result<Vec<_>, _>>()?;
+        let masks = encodings
+            .iter()
+            .map(|encoding| Tensor::new(encoding.get_attention_mask(), device))
+            .collect::<Result<Vec<_>, _>>()?;
+        let ids = Tensor::stack(&ids, 0)?;
+        let mask = Tensor::stack(&masks, 0)?;
+        let hidden = self.bert.forward(&ids, &ids.zeros_like()?, Some(&mask))?;
+        let mask = mask.to_dtype(DTYPE)?.unsqueeze(2)?;
+        let pooled = hidden
+            .broadcast_mul(&mask)?
+            .sum(1)?
+            .broadcast_div(&mask.sum(1)?)?;
+        let norms = pooled.sqr()?.sum_keepdim(1)?.sqrt()?;
+        Ok(pooled.broadcast_div(&norms)?.to_vec2()?)
+    }
+}
+
+#[async_trait(?Send)]
+impl EmbeddingProvider for LocalProvider {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn embed(
+        &self,
+        inputs: Vec<String>,
+        _model: &str,
+        _dimensions: u32,
+    ) -> Result<Embeddings, Box<dyn Error>> {
+        let model = self.model.clone();
+        let vectors = actix_web::rt::task::spawn_blocking(move || model.embed(inputs))
+            .await?
+            .map_err(|error| error as Box<dyn Error>)?;
+        Ok(Embeddings {
+            model: None,
+            vectors,
+        })
+    }
+}
+
+fn with_bearer(request: reqwest::RequestBuilder, api_key: Option<&str>) -> reqwest::RequestBuilder {
+    match api_key {
+        Some(api_key) => request.bearer_auth(api_key),
+        None => request,
+    }
+}
+
+/// Whether the embedder still produces the vectors the collections were built with. Flipped to
+/// degraded by the first mismatching response and back once a response checks out again.
+#[derive(Debug, Default)]
+struct EmbeddingHealth {
+    degraded: AtomicBool,
+    mismatches: AtomicU64,
+    last_mismatch: Mutex<Option<(DateTime<Utc>, EmbeddingError)>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct EmbeddingStatus {
+    pub provider: &'static str,
+    pub model: String,
+    pub dimensions: u32,
+    pub degraded: bool,
+    pub mismatches: u64,
+    pub last_mismatch_at: Option<DateTime<Utc>>,
+    pub last_mismatch: Option<EmbeddingError>,
+}
+
+/// The configured provider together with the model and dimensions every response is checked
+/// against before any of its vectors reach Qdrant.
+pub struct Embedder {
+    provider: Box<dyn EmbeddingProvider>,
+    model: String,
+    dimensions: u32,
+    max_batch_size: usize,
+    health: EmbeddingHealth,
+}
+
+impl Embedder {
+    pub fn from_config(config: &EmbeddingConfig) -> Result<Embedder, String> {
+        let url = config
+            .url
+            .as_deref()
+            .map(|url| url.trim_end_matches('/').to_string());
+        let api_key = config.api_key.clone();
+        let provider: Box<dyn EmbeddingProvider> = match config.provider {
+            EmbeddingBackend::Openai => {
+                let mut openai_config = OpenAIConfig::new();
+                if let Some(url) = url {
+                    openai_config = openai_config.with_api_base(url);
+                }
+                if let Some(api_key) = api_key {
+                    openai_config = openai_config.with_api_key(api_key);
+                }
+                Box::new(OpenAiProvider {
+                    client: Client::with_config(openai_config),
+                })
+            }
+            EmbeddingBackend::Ollama => Box::new(OllamaProvider {
+                client: reqwest::Client::new(),
+                url: url.unwrap_or_else(|| DEFAULT_OLLAMA_URL.to_string()),
+                api_key,
+            }),
+            EmbeddingBackend::Tei => Box::new(TeiProvider {
+                client: reqwest::Client::new(),
+                url: url.unwrap_or_else(|| DEFAULT_TEI_URL.to_string()),
+                api_key,
+            }),
+            EmbeddingBackend::Http => Box::new(HttpProvider {
+                client: reqwest::Client::new(),
+                url: url.ok_or("EMBEDDING__URL is required for the http embedding provider")?,
+                api_key,
+            }),
+            EmbeddingBackend::Local => {
+                let path = config
+                    .model_path
+                    .as_deref()
+                    .ok_or("EMBEDDING__MODEL_PATH is required for the local embedding provider")?;
+                let model = LocalModel::load(Path::new(path)).map_err(|error| {
+                    format!("Could not load the embedding model in {}: {}", path, error)
+                })?;
+                Box::new(LocalProvider {
+                    model: Arc::new(model),
+                })
+            }
+        };
+        info!(
+            "Embedding with {} via {}, {} dimensions",
+            config.model,
+            provider.name(),
+            config.dimensions
+        );
+        Ok(Embedder {
+            provider,
+            model: config.model.clone(),
+            dimensions: config.dimensions,
+            max_batch_size: config.max_batch_size.max(1),
+            health: EmbeddingHealth::default(),
+        })
+    }
+
+    pub fn dimensions(&self) -> u32 {
+        self.dimensions
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.health.degraded.load(Ordering::Relaxed)
+    }
+
+    pub fn mismatches(&self) -> u64 {
+        self.health.mismatches.load(Ordering::Relaxed)
+    }
+
+    pub fn status(&self) -> EmbeddingStatus {
+        let last_mismatch = self.health.last_mismatch.lock().unwrap().clone();
+        EmbeddingStatus {
+            provider: self.provider.name(),
+            model: self.model.clone(),
+            dimensions: self.dimensions,
+            degraded: self.is_degraded(),
+            mismatches: self.mismatches(),
+            last_mismatch_at: last_mismatch.as_ref().map(|(at, _)| *at),
+            last_mismatch: last_mismatch.map(|(_, mismatch)| mismatch),
+        }
+    }
+
+    pub async fn embed(&self, input: String) -> Result<Vec<f32>, Box<dyn Error>> {
+        info!(
+            "Fetching embedding from {} for {:?}",
+            self.provider.name(),
+            &input
+        );
+        let mut vectors = self.embed_checked(vec![input]).await?;
+        vectors
+            .pop()
+            .ok_or_else(|| "Empty embedding response".into())
+    }
+
+    /// Embeds several inputs in as few requests as the provider allows, returned in input order.
+    pub async fn embed_batch(&self, inputs: Vec<String>) -> Result<Vec<Vec<f32>>, Box<dyn Error>> {
+        info!(
+            "Fetching {} embeddings from {}",
+            inputs.len(),
+            self.provider.name()
+        );
+        let mut vectors = Vec::with_capacity(inputs.len());
+        for chunk in inputs.chunks(self.max_batch_size) {
+            vectors.extend(self.embed_checked(chunk.to_vec()).await?);
+        }
+        Ok(vectors)
+    }
+
+    #[instrument(
+        skip_all,
+        fields(provider = self.provider.name(), model = %self.model, inputs = inputs.len())
+    )]
+    async fn embed_checked(&self, inputs: Vec<String>) -> Result<Vec<Vec<f32>>, Box<dyn Error>> {
+        let expected = inputs.len();
+        let embeddings = self
+            .provider
+            .embed(inputs, &self.model, self.dimensions)
+            .await?;
+        if embeddings.vectors.len() != expected {
+            return Err(format!(
+                "Embedding service returned {} vectors for {} inputs",
+                embeddings.vectors.len(),
+                expected
+            )
+            .into());
+        }
+        self.check(embeddings.model.as_deref(), &embeddings.vectors)?;
+        Ok(embeddings.vectors)
+    }
 
-    let response = client.embeddings().create(request).await?;
-    let embedding = response.data[0].clone();
-    Ok(embedding)
+    fn check(&self, model: Option<&str>, vectors: &[Vec<f32>]) -> Result<(), EmbeddingError> {
+        let mismatch = match model {
+            Some(model) if !model.starts_with(&self.model) => Some(EmbeddingError::ModelMismatch {
+                expected: self.model.clone(),
+                actual: model.to_string(),
+            }),
+            _ => vectors
+                .iter()
+                .find(|vector| vector.len() != self.dimensions as usize)
+                .map(|vector| EmbeddingError::DimensionMismatch {
+                    expected: self.dimensions as usize,
+                    actual: vector.len(),
+                }),
+        };
+        let health = &self.health;
+        match mismatch {
+            Some(mismatch) => {
+                if !health.degraded.swap(true, Ordering::Relaxed) {
+                    error!("Embedding service degraded: {}", mismatch);
+                }
+                health.mismatches.fetch_add(1, Ordering::Relaxed);
+                *health.last_mismatch.lock().unwrap() = Some((Utc::now(), mismatch.clone()));
+                Err(mismatch)
+            }
+            None => {
+                if health.degraded.swap(false, Ordering::Relaxed) {
+                    info!("Embedding service recovered, responses match the collections again");
+                }
+                Ok(())
+            }
+        }
+    }
 }
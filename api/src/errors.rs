@@ -1,13 +1,26 @@
+use actix_web::http::StatusCode;
 use actix_web::{HttpResponse, ResponseError};
 use deadpool_postgres::PoolError;
 use derive_more::{Display, Error, From};
+use qdrant_client::QdrantError;
+use serde::Serialize;
 use tokio_pg_mapper::Error as PGMError;
 use tokio_postgres::error::Error as PGError;
+use utoipa::ToSchema;
 
 #[derive(Debug, Display, Error, From)]
 #[allow(dead_code)]
 pub enum PgError {
     NotFound,
+    /// The row changed since the caller read it.
+    Conflict,
+    /// A unique constraint rejected the row, `id` is the row that already holds the values.
+    #[from(ignore)]
+    #[display("Duplicate of {id}")]
+    Duplicate {
+        #[error(not(source))]
+        id: i64,
+    },
     PGError(PGError),
     PGMError(PGMError),
     PoolError(PoolError),
@@ -17,6 +30,11 @@ impl ResponseError for PgError {
     fn error_response(&self) -> HttpResponse {
         match *self {
             PgError::NotFound => HttpResponse::NotFound().finish(),
+            PgError::Conflict => HttpResponse::Conflict().finish(),
+            PgError::Duplicate { id } => HttpResponse::Conflict().json(serde_json::json!({
+                "error": "A row with these values already exists",
+                "id": id
+            })),
             PgError::PoolError(ref err) => {
                 HttpResponse::InternalServerError().body(err.to_string())
             }
@@ -24,3 +42,166 @@ impl ResponseError for PgError {
         }
     }
 }
+
+/// Errors caused by the request itself rather than by the backing stores.
+#[derive(Debug, Display, Error)]
+pub enum ApiError {
+    /// A filter value that does not exist in the loaded vocabulary.
+    #[display("Unknown {parameter} value(s): {}", invalid.join(", "))]
+    InvalidFilter {
+        parameter: &'static str,
+        #[error(not(source))]
+        invalid: Vec<String>,
+        #[error(not(source))]
+        valid: Vec<String>,
+    },
+    /// A numeric parameter outside the values it accepts.
+    #[display("{parameter} must be between {min} and {max}")]
+    OutOfRange {
+        parameter: &'static str,
+        #[error(not(source))]
+        min: f64,
+        #[error(not(source))]
+        max: f64,
+    },
+}
+
+impl ResponseError for ApiError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            ApiError::InvalidFilter {
+                parameter,
+                invalid,
+                valid,
+            } => HttpResponse::BadRequest().json(serde_json::json!({
+                "error": self.to_string(),
+                "parameter": parameter,
+                "invalid": invalid,
+                "valid": valid,
+            })),
+            ApiError::OutOfRange {
+                parameter,
+                min,
+                max,
+            } => HttpResponse::BadRequest().json(serde_json::json!({
+                "error": self.to_string(),
+                "parameter": parameter,
+                "min": min,
+                "max": max,
+            })),
+        }
+    }
+}
+
+/// Errors of the FHIR facade, answered as OperationOutcome resources rather than the JSON
+/// errors of the rest of the API.
+#[derive(Debug, Display, Error)]
+pub enum FhirError {
+    /// A missing or malformed parameter.
+    #[display("{_0}")]
+    Invalid(#[error(not(source))] String),
+    /// A code that is not in the code system.
+    #[display("{_0}")]
+    CodeInvalid(#[error(not(source))] String),
+    #[display("{_0}")]
+    NotSupported(#[error(not(source))] String),
+    #[display("{_0}")]
+    NotFound(#[error(not(source))] String),
+    /// Resolution ran into lookup failures, an expansion would be partial.
+    #[display("{_0}")]
+    Incomplete(#[error(not(source))] String),
+    #[display("The terminology could not be read")]
+    Database(PgError),
+}
+
+impl From<PgError> for FhirError {
+    fn from(value: PgError) -> Self {
+        match value {
+            PgError::NotFound => FhirError::NotFound("Resource not found".to_string()),
+            e => FhirError::Database(e),
+        }
+    }
+}
+
+impl ResponseError for FhirError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            FhirError::Invalid(_) | FhirError::CodeInvalid(_) | FhirError::NotSupported(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            FhirError::NotFound(_) => StatusCode::NOT_FOUND,
+            FhirError::Incomplete(_) | FhirError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let code = match self {
+            FhirError::Invalid(_) => "invalid",
+            FhirError::CodeInvalid(_) => "code-invalid",
+            FhirError::NotSupported(_) => "not-supported",
+            FhirError::NotFound(_) => "not-found",
+            FhirError::Incomplete(_) => "incomplete",
+            FhirError::Database(_) => "exception",
+        };
+        HttpResponse::build(self.status_code())
+            .content_type("application/fhir+json")
+            .json(serde_json::json!({
+                "resourceType": "OperationOutcome",
+                "issue": [{
+                    "severity": "error",
+                    "code": code,
+                    "diagnostics": self.to_string(),
+                }],
+            }))
+    }
+}
+
+/// The embedding service answered, but not with vectors the collections were built from.
+/// Querying Qdrant with them would return plausible looking but meaningless rankings.
+#[derive(Debug, Clone, Display, Error, Serialize, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EmbeddingError {
+    #[display("Embedding has {actual} dimensions, the collections expect {expected}")]
+    DimensionMismatch { expected: usize, actual: usize },
+    #[display("Embedding was produced by {actual}, the collections were built with {expected}")]
+    ModelMismatch {
+        #[error(not(source))]
+        expected: String,
+        #[error(not(source))]
+        actual: String,
+    },
+}
+
+impl ResponseError for EmbeddingError {
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": self.to_string(),
+            "embedding": self,
+        }))
+    }
+}
+
+#[derive(Debug, Display, Error, From)]
+pub enum SearchError {
+    Pg(PgError),
+    Embedding(EmbeddingError),
+    Qdrant(QdrantError),
+}
+
+impl From<PoolError> for SearchError {
+    fn from(value: PoolError) -> Self {
+        SearchError::Pg(PgError::PoolError(value))
+    }
+}
+
+impl ResponseError for SearchError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SearchError::Pg(err) => err.error_response(),
+            SearchError::Embedding(err) => err.error_response(),
+            SearchError::Qdrant(err) => HttpResponse::BadGateway().json(serde_json::json!({
+                "error": format!("Could not query the vector store: {}", err)
+            })),
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use crate::StateWrapper;
+use crate::db;
+use crate::errors::PgError;
+use actix_web::web::{Data, Json};
+use actix_web::{Error, HttpResponse, post};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use utoipa::ToSchema;
+
+/// Upper bound on concepts per request, the lookups bind one parameter per concept.
+const MAX_EXPAND_CONCEPTS: usize = 5000;
+
+#[derive(Deserialize, ToSchema)]
+struct ExpandItem {
+    concept_id: i32,
+    #[serde(default)]
+    descendants: bool,
+    #[serde(default)]
+    mapped: bool,
+    /// Limits descendants to this many levels of separation, all levels when left out.
+    #[schema(minimum = 1)]
+    levels: Option<i32>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct ExpandRequest {
+    concepts: Vec<ExpandItem>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ExpandResponse {
+    /// Requested concept id to its descendants, for items with `descendants` set.
+    descendants: BTreeMap<i32, Vec<i32>>,
+    /// Requested concept id to the concepts mapped to it, for items with `mapped` set.
+    mapped: BTreeMap<i32, Vec<i32>>,
+    /// Every requested concept plus everything it expanded to.
+    resolved: BTreeSet<i32>,
+}
+
+/// Resolves descendants and mapped concepts for a list of concept ids, the same expansion
+/// concept set analysis uses, for R and Python pipelines that would otherwise re-implement the
+/// SQL.
+#[utoipa::path(
+    tag = "concept sets",
+    summary = "Expand concepts",
+    description = "Resolves descendants and mapped concepts for a list of concept ids, using the same lookups as \
+        concept set analysis. At most 5000 concepts per request.",
+    responses(
+        (status = 200, description = "Expansion maps keyed by requested concept id, plus every resolved concept id",
+            body = ExpandResponse),
+        (status = 400, description = "Too many concepts or an invalid level limit"),
+    )
+)]
+#[post("/api/expand")]
+async fn expand_concepts(
+    request: Json<ExpandRequest>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let request = request.into_inner();
+    if request.concepts.len() > MAX_EXPAND_CONCEPTS {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("At most {} concepts can be expanded per request", MAX_EXPAND_CONCEPTS)
+        })));
+    }
+    if let Some(item) = request
+        .concepts
+        .iter()
+        .find(|item| item.levels.is_some_and(|levels| levels < 1))
+    {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("levels must be at least 1 for concept {}", item.concept_id)
+        })));
+    }
+    info!("Expanding {} concepts", request.concepts.len());
+
+    // Descendant lookups are batched per level limit, unlimited ones in a single query
+    let mut by_levels: BTreeMap<Option<i32>, Vec<i32>> = BTreeMap::new();
+    for item in request.concepts.iter().filter(|item| item.descendants) {
+        by_levels
+            .entry(item.levels)
+            .or_default()
+            .push(item.concept_id);
+    }
+    let mapped_ids: Vec<i32> = request
+        .concepts
+        .iter()
+        .filter(|item| item.mapped)
+        .map(|item| item.concept_id)
+        .collect();
+
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let mut descendants: BTreeMap<i32, Vec<i32>> = BTreeMap::new();
+    for (levels, concept_ids) in by_levels {
+        let found = match levels {
+            None => {
+                state
+                    .shared_cache
+                    .get_batch_descendant_concepts(&pg_client, &concept_ids)
+                    .await?
+            }
+            Some(levels) => {
+                db::get_batch_descendant_concepts_within_levels(&pg_client, &concept_ids, levels)
+                    .await?
+            }
+        };
+        descendants.extend(found);
+    }
+    let mut mapped: BTreeMap<i32, Vec<i32>> =
+        db::get_batch_mapped_concepts(&pg_client, &mapped_ids)
+            .await?
+            .into_iter()
+            .collect();
+
+    for ids in descendants.values_mut().chain(mapped.values_mut()) {
+        ids.sort_unstable();
+        ids.dedup();
+    }
+    let resolved: BTreeSet<i32> = request
+        .concepts
+        .iter()
+        .map(|item| item.concept_id)
+        .chain(descendants.values().flatten().copied())
+        .chain(mapped.values().flatten().copied())
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ExpandResponse {
+        descendants,
+        mapped,
+        resolved,
+    }))
+}
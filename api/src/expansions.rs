@@ -0,0 +1,149 @@
+use crate::StateWrapper;
+use crate::db;
+use crate::domain::ResolvedExpansion;
+use crate::errors::PgError;
+use crate::validation::{self, ConceptGatheringResult, ConceptSetExpression, ValidationResult};
+use actix_web::web::{Data, Query};
+use actix_web::{Error, HttpResponse, get, web};
+use deadpool_postgres::Client;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::{IntoParams, ToSchema};
+
+/// What resolution depends on: names and captions in the expression do not change the result.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+struct CanonicalItem {
+    concept_id: i32,
+    is_excluded: bool,
+    include_descendants: bool,
+    include_mapped: bool,
+}
+
+fn canonical_items(expression: &ConceptSetExpression) -> Vec<CanonicalItem> {
+    let mut items: Vec<CanonicalItem> = expression
+        .items
+        .iter()
+        .map(|item| CanonicalItem {
+            concept_id: item.concept.concept_id,
+            is_excluded: item.is_excluded,
+            include_descendants: item.include_descendants,
+            include_mapped: item.include_mapped,
+        })
+        .collect();
+    items.sort();
+    items.dedup();
+    items
+}
+
+/// Content address of an expression: the sha256 of its canonical items, so the same set
+/// authored by different users, in any item order, shares one hash.
+pub fn expression_hash(expression: &ConceptSetExpression) -> String {
+    let canonical = serde_json::to_vec(&canonical_items(expression)).unwrap_or_default();
+    Sha256::digest(&canonical)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Resolves the expression through the shared expansion store, computing and storing it on a
+/// miss. Without a known vocabulary version nothing is cached; expansions that ran into lookup
+/// failures are returned but not stored.
+pub async fn resolve_concept_set(
+    expression: &ConceptSetExpression,
+    pg_client: &Client,
+    vocabulary_version: Option<&str>,
+    result: &mut ValidationResult,
+) -> ConceptGatheringResult {
+    let Some(vocabulary_version) = vocabulary_version else {
+        return validation::expand_concept_set(expression, pg_client, result).await;
+    };
+    let hash = expression_hash(expression);
+    result.expansion_hash = Some(hash.clone());
+
+    match db::use_resolved_expansion(pg_client, &hash, vocabulary_version).await {
+        Ok(Some(expansion)) => match serde_json::from_value(expansion) {
+            Ok(expansion) => {
+                info!("Using cached expansion {}", hash);
+                return expansion;
+            }
+            Err(e) => warn!("Ignoring unreadable cached expansion {}: {}", hash, e),
+        },
+        Ok(None) => {}
+        Err(e) => warn!("Could not read cached expansion {}: {}", hash, e),
+    }
+
+    let warnings_before = result.warnings.len();
+    let expansion = validation::expand_concept_set(expression, pg_client, result).await;
+    if result.warnings.len() > warnings_before {
+        return expansion;
+    }
+    let stored = db::insert_resolved_expansion(
+        pg_client,
+        &hash,
+        vocabulary_version,
+        &serde_json::to_value(canonical_items(expression)).unwrap_or_default(),
+        &serde_json::to_value(&expansion).unwrap_or_default(),
+        expansion.resolved_concept_ids().len() as i32,
+    )
+    .await;
+    if let Err(e) = stored {
+        warn!("Could not store expansion {}: {}", hash, e);
+    }
+    expansion
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ExpansionParameters {
+    /// Defaults to the vocabulary version currently loaded.
+    vocabulary_version: Option<String>,
+}
+
+/// A stored expansion with the concept ids it resolves to.
+#[derive(Serialize, ToSchema)]
+struct StoredExpansion {
+    expansion: ResolvedExpansion,
+    resolved_concept_ids: Vec<i32>,
+}
+
+/// A stored expansion by expression hash, as returned in `expansion_hash` by concept set
+/// analysis and review.
+#[utoipa::path(
+    tag = "concept sets",
+    summary = "Get a stored concept set expansion",
+    description = "Concept set analysis and review store each resolved expansion under the hash of its \
+        expression (`expansion_hash` in their responses) and the vocabulary version, so identical sets are \
+        resolved once. Returns the stored expansion and the resolved concept ids.",
+    params(("hash" = String, Path, description = "Expression hash"), ExpansionParameters),
+    responses(
+        (status = 200, description = "The stored expansion", body = StoredExpansion),
+        (status = 404, description = "No expansion stored for this hash and vocabulary version"),
+    )
+)]
+#[get("/api/expansions/{hash}")]
+async fn get_expansion(
+    path: web::Path<String>,
+    parameters: Query<ExpansionParameters>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let hash = path.into_inner();
+    let Some(vocabulary_version) = parameters
+        .into_inner()
+        .vocabulary_version
+        .or_else(|| state.vocabulary_catalog.vocabulary_version.clone())
+    else {
+        return Err(PgError::NotFound.into());
+    };
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let expansion = db::get_resolved_expansion(&pg_client, &hash, &vocabulary_version)
+        .await?
+        .ok_or(PgError::NotFound)?;
+    let resolved = serde_json::from_value::<ConceptGatheringResult>(expansion.expansion.clone())
+        .map(|expansion| expansion.resolved_concept_ids())
+        .unwrap_or_default();
+    Ok(HttpResponse::Ok().json(StoredExpansion {
+        expansion,
+        resolved_concept_ids: resolved,
+    }))
+}
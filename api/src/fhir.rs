@@ -0,0 +1,798 @@
+use crate::StateWrapper;
+use crate::concept_codes::lookup_codes;
+use crate::db;
+use crate::domain::Concept;
+use crate::errors::{FhirError, PgError};
+use crate::expansions;
+use crate::validation::{self, ConceptSetExpression, ConceptSetItem};
+use actix_web::web::{Bytes, Data, Query};
+use actix_web::{HttpResponse, get, post};
+use chrono::Utc;
+use deadpool_postgres::Client;
+use log::info;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use utoipa::IntoParams;
+
+const FHIR_JSON: &str = "application/fhir+json";
+
+/// Codes of vocabularies without a FHIR system URL are OMOP concept IDs in this system.
+const OMOP_CONCEPT_SYSTEM: &str = "https://fhir-terminology.ohdsi.org";
+
+/// Canonical FHIR code system URLs and the OMOP vocabularies they name.
+const CODE_SYSTEMS: &[(&str, &str)] = &[
+    ("http://snomed.info/sct", "SNOMED"),
+    ("http://loinc.org", "LOINC"),
+    ("http://www.nlm.nih.gov/research/umls/rxnorm", "RxNorm"),
+    ("http://hl7.org/fhir/sid/icd-10-cm", "ICD10CM"),
+    ("http://hl7.org/fhir/sid/icd-10", "ICD10"),
+    ("http://hl7.org/fhir/sid/icd-9-cm", "ICD9CM"),
+    ("http://www.ama-assn.org/go/cpt", "CPT4"),
+    ("http://hl7.org/fhir/sid/ndc", "NDC"),
+    ("http://hl7.org/fhir/sid/cvx", "CVX"),
+    ("http://unitsofmeasure.org", "UCUM"),
+    ("http://www.whocc.no/atc", "ATC"),
+];
+
+/// Codes listed per expansion page when the request does not give a `count`.
+const DEFAULT_EXPANSION_COUNT: usize = 1000;
+const MAX_EXPANSION_COUNT: usize = 10_000;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct LookupParameters {
+    #[param(required = true, example = "http://snomed.info/sct")]
+    system: Option<String>,
+    #[param(required = true, example = "44054006")]
+    code: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ExpandParameters {
+    #[param(default = 0)]
+    offset: Option<usize>,
+    #[param(default = 1000, maximum = 10000)]
+    count: Option<usize>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CodingParameters {
+    #[param(example = "http://hl7.org/fhir/sid/icd-10-cm")]
+    system: Option<String>,
+    #[param(example = "E11.9")]
+    code: Option<String>,
+    /// `$translate` only: the code system to translate to.
+    targetsystem: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ValueSet {
+    compose: Option<ValueSetCompose>,
+}
+
+#[derive(Deserialize)]
+struct ValueSetCompose {
+    #[serde(default)]
+    include: Vec<ComposeSet>,
+    #[serde(default)]
+    exclude: Vec<ComposeSet>,
+}
+
+#[derive(Deserialize)]
+struct ComposeSet {
+    system: Option<String>,
+    #[serde(default)]
+    concept: Vec<ComposeConcept>,
+    #[serde(default)]
+    filter: Vec<ComposeFilter>,
+}
+
+#[derive(Deserialize)]
+struct ComposeConcept {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct ComposeFilter {
+    property: String,
+    op: String,
+    value: String,
+}
+
+/// The vocabulary a FHIR system URL stands for.
+fn vocabulary_id(system: &str) -> Option<&'static str> {
+    CODE_SYSTEMS
+        .iter()
+        .find(|(url, _)| *url == system.trim_end_matches('/'))
+        .map(|(_, vocabulary_id)| *vocabulary_id)
+}
+
+/// The system and code a concept is listed with in expansions.
+fn coding(concept: &Concept) -> (&'static str, String) {
+    CODE_SYSTEMS
+        .iter()
+        .find(|(_, vocabulary_id)| *vocabulary_id == concept.vocabulary_id)
+        .map_or(
+            (OMOP_CONCEPT_SYSTEM, concept.concept_id.to_string()),
+            |(url, _)| (*url, concept.concept_code.clone()),
+        )
+}
+
+/// Of the concepts carrying a code, the valid one, as codes can be reused after deprecation.
+fn coded_concept(concepts: &[Concept]) -> Option<&Concept> {
+    concepts
+        .iter()
+        .find(|concept| concept.invalid_reason.is_none())
+        .or_else(|| concepts.first())
+}
+
+/// A named parameter of a Parameters resource.
+fn parameter<'a>(body: &'a Value, name: &str) -> Option<&'a Value> {
+    body["parameter"]
+        .as_array()?
+        .iter()
+        .find(|parameter| parameter["name"] == name)
+}
+
+fn is_omop_system(system: &str) -> bool {
+    system.trim_end_matches('/') == OMOP_CONCEPT_SYSTEM
+}
+
+fn property(code: &str, value: Value) -> Value {
+    json!({
+        "name": "property",
+        "part": [{ "name": "code", "valueCode": code }, value],
+    })
+}
+
+/// The `$lookup` output: display and version, synonyms as designations and the OMOP attributes
+/// as properties, `maps-to` naming the standard concepts the code maps to.
+fn lookup_parameters(
+    concept: &Concept,
+    vocabulary_name: &str,
+    vocabulary_version: Option<&str>,
+    synonyms: &[String],
+    maps_to: &[i32],
+) -> Value {
+    let mut parameter = vec![json!({ "name": "name", "valueString": vocabulary_name })];
+    if let Some(version) = vocabulary_version {
+        parameter.push(json!({ "name": "version", "valueString": version }));
+    }
+    parameter.push(json!({ "name": "display", "valueString": concept.concept_name }));
+    parameter.extend(synonyms.iter().map(|synonym| {
+        json!({
+            "name": "designation",
+            "part": [{ "name": "value", "valueString": synonym }],
+        })
+    }));
+    parameter.push(property(
+        "concept-id",
+        json!({ "name": "value", "valueInteger": concept.concept_id }),
+    ));
+    parameter.push(property(
+        "domain",
+        json!({ "name": "value", "valueString": concept.domain_id }),
+    ));
+    parameter.push(property(
+        "concept-class",
+        json!({ "name": "value", "valueString": concept.concept_class_id }),
+    ));
+    parameter.push(property(
+        "standard",
+        json!({ "name": "value", "valueBoolean": concept.standard_concept.as_deref() == Some("S") }),
+    ));
+    parameter.push(property(
+        "inactive",
+        json!({ "name": "value", "valueBoolean": concept.invalid_reason.is_some() }),
+    ));
+    parameter.extend(maps_to.iter().map(|concept_id| {
+        property(
+            "maps-to",
+            json!({ "name": "value", "valueInteger": concept_id }),
+        )
+    }));
+    json!({ "resourceType": "Parameters", "parameter": parameter })
+}
+
+/// FHIR `CodeSystem/$lookup` over the concept-by-code lookup, so terminology clients can resolve
+/// codes of the code systems in `CODE_SYSTEMS` against the OMOP vocabulary.
+#[utoipa::path(
+    tag = "fhir",
+    summary = "FHIR CodeSystem $lookup",
+    description = "Looks up a code of a FHIR code system in the matching OMOP vocabulary and answers with a FHIR \
+        Parameters resource holding the `display`, synonyms as `designation` and the `concept-id`, `domain`, \
+        `concept-class`, `standard`, `inactive` and `maps-to` properties. Supported systems are SNOMED CT, LOINC, \
+        RxNorm, ICD-10-CM, ICD-10, ICD-9-CM, CPT, NDC, CVX, UCUM and ATC by their canonical URLs. Errors are \
+        OperationOutcome resources.",
+    params(LookupParameters),
+    responses(
+        (status = 200, description = "FHIR Parameters resource", body = Value, content_type = "application/fhir+json"),
+        (status = 400, description = "Missing system or code"),
+        (status = 404, description = "Unknown code system or code"),
+    )
+)]
+#[get("/fhir/CodeSystem/$lookup")]
+async fn lookup_code(
+    parameters: Query<LookupParameters>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, FhirError> {
+    let (Some(system), Some(code)) = (parameters.system.as_deref(), parameters.code.as_deref())
+    else {
+        return Err(FhirError::Invalid(
+            "Both system and code are required".to_string(),
+        ));
+    };
+    let Some(vocabulary_id) = vocabulary_id(system) else {
+        return Err(FhirError::NotSupported(format!(
+            "Unknown code system {}",
+            system
+        )));
+    };
+    info!("FHIR lookup of {} in {}", code, vocabulary_id);
+
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let lookup = lookup_codes(
+        vec![(vocabulary_id.to_string(), code.to_string())],
+        &pg_client,
+    )
+    .await?
+    .pop()
+    .ok_or(PgError::NotFound)?;
+    let Some(concept) = coded_concept(&lookup.concepts) else {
+        return Err(FhirError::NotFound(format!(
+            "Code {} not found in {}",
+            code, system
+        )));
+    };
+
+    let synonyms: Vec<String> = db::get_concept_synonyms(&pg_client, concept.concept_id, None)
+        .await?
+        .into_iter()
+        .map(|synonym| synonym.concept_synonym_name)
+        .filter(|synonym| *synonym != concept.concept_name)
+        .collect();
+    let maps_to: Vec<i32> = lookup
+        .maps_to
+        .iter()
+        .filter(|linked| linked.source_concept_id == concept.concept_id)
+        .map(|linked| linked.concept_id)
+        .collect();
+    let vocabulary = state
+        .vocabulary_catalog
+        .vocabularies
+        .iter()
+        .find(|vocabulary| vocabulary.vocabulary_id == concept.vocabulary_id);
+
+    Ok(HttpResponse::Ok()
+        .content_type(FHIR_JSON)
+        .json(lookup_parameters(
+            concept,
+            vocabulary.map_or(vocabulary_id, |vocabulary| &vocabulary.vocabulary_name),
+            vocabulary.and_then(|vocabulary| vocabulary.vocabulary_version.as_deref()),
+            &synonyms,
+            &maps_to,
+        )))
+}
+
+/// A code of a compose element and how its descendants take part.
+struct ComposeCode<'a> {
+    system: &'a str,
+    code: &'a str,
+    include_descendants: bool,
+    /// `descendent-of`: the descendants without the code itself.
+    exclude_self: bool,
+    is_excluded: bool,
+}
+
+fn compose_codes<'a>(
+    sets: &'a [ComposeSet],
+    is_excluded: bool,
+    codes: &mut Vec<ComposeCode<'a>>,
+) -> Result<(), FhirError> {
+    for set in sets {
+        let Some(system) = set.system.as_deref() else {
+            return Err(FhirError::NotSupported(
+                "Compose elements need a system, value set references are not supported"
+                    .to_string(),
+            ));
+        };
+        codes.extend(set.concept.iter().map(|concept| ComposeCode {
+            system,
+            code: &concept.code,
+            include_descendants: false,
+            exclude_self: false,
+            is_excluded,
+        }));
+        for filter in &set.filter {
+            let (include_descendants, exclude_self) =
+                match (filter.property.as_str(), filter.op.as_str()) {
+                    ("concept", "=") => (false, false),
+                    ("concept", "is-a") => (true, false),
+                    ("concept", "descendent-of") if !is_excluded => (true, true),
+                    _ => {
+                        return Err(FhirError::NotSupported(format!(
+                            "Unsupported filter {} {} {}",
+                            filter.property, filter.op, filter.value
+                        )));
+                    }
+                };
+            codes.push(ComposeCode {
+                system,
+                code: &filter.value,
+                include_descendants,
+                exclude_self,
+                is_excluded,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Translates the compose element of a ValueSet into a concept set expression: listed codes
+/// become plain items, `is-a` filters items with descendants, exclusions excluded items.
+async fn compose_expression(
+    compose: &ValueSetCompose,
+    pg_client: &Client,
+) -> Result<ConceptSetExpression, FhirError> {
+    let mut codes = Vec::new();
+    compose_codes(&compose.include, false, &mut codes)?;
+    compose_codes(&compose.exclude, true, &mut codes)?;
+
+    let mut requested = Vec::new();
+    let mut concept_ids = Vec::new();
+    for code in &codes {
+        if is_omop_system(code.system) {
+            match code.code.parse::<i32>() {
+                Ok(concept_id) => concept_ids.push(concept_id),
+                Err(_) => {
+                    return Err(FhirError::CodeInvalid(format!(
+                        "{} is not an OMOP concept ID",
+                        code.code
+                    )));
+                }
+            }
+        } else {
+            let Some(vocabulary_id) = vocabulary_id(code.system) else {
+                return Err(FhirError::NotSupported(format!(
+                    "Unknown code system {}",
+                    code.system
+                )));
+            };
+            requested.push((vocabulary_id.to_string(), code.code.to_string()));
+        }
+    }
+    let lookups = lookup_codes(requested, pg_client).await?;
+    let by_id = db::get_concepts_by_ids(pg_client, &concept_ids).await?;
+
+    let mut items = Vec::new();
+    let mut unknown = Vec::new();
+    let mut lookups = lookups.iter();
+    for code in &codes {
+        let concept = if is_omop_system(code.system) {
+            by_id
+                .iter()
+                .find(|concept| concept.concept_id.to_string() == code.code)
+        } else {
+            lookups
+                .next()
+                .and_then(|lookup| coded_concept(&lookup.concepts))
+        };
+        let Some(concept) = concept else {
+            unknown.push(format!("{}|{}", code.system, code.code));
+            continue;
+        };
+        items.push(ConceptSetItem {
+            concept: concept.clone(),
+            is_excluded: code.is_excluded,
+            include_descendants: code.include_descendants,
+            include_mapped: false,
+        });
+        if code.exclude_self {
+            items.push(ConceptSetItem {
+                concept: concept.clone(),
+                is_excluded: true,
+                include_descendants: false,
+                include_mapped: false,
+            });
+        }
+    }
+    if !unknown.is_empty() {
+        return Err(FhirError::CodeInvalid(format!(
+            "Unknown codes: {}",
+            unknown.join(", ")
+        )));
+    }
+    Ok(ConceptSetExpression { items })
+}
+
+/// The expression of a stored concept set, at its latest version.
+async fn stored_expression(
+    id: i64,
+    pg_client: &Client,
+) -> Result<(String, ConceptSetExpression), FhirError> {
+    let concept_set = db::get_concept_set(pg_client, id).await?;
+    let version = db::get_concept_set_versions(pg_client, id, Some(concept_set.latest_version))
+        .await?
+        .pop()
+        .ok_or(PgError::NotFound)?;
+    let expression = validation::parse_concept_set(&version.expression.to_string())
+        .map_err(FhirError::Invalid)?;
+    Ok((concept_set.name, expression))
+}
+
+/// The value set a request names: a ValueSet resource, or a Parameters resource with a
+/// `valueSet` resource or the `conceptSetId` of a stored concept set. Returns the identity
+/// of stored sets along with the expression.
+async fn requested_value_set(
+    body: &Value,
+    pg_client: &Client,
+) -> Result<(Option<(i64, String)>, ConceptSetExpression), FhirError> {
+    let value_set = match body["resourceType"].as_str() {
+        Some("ValueSet") => body.clone(),
+        Some("Parameters") => {
+            if let Some(id) = parameter(body, "conceptSetId") {
+                let Some(id) = id["valueInteger"]
+                    .as_i64()
+                    .or_else(|| id["valueString"].as_str()?.parse().ok())
+                else {
+                    return Err(FhirError::Invalid(
+                        "conceptSetId must be an integer".to_string(),
+                    ));
+                };
+                let (name, expression) = stored_expression(id, pg_client).await?;
+                return Ok((Some((id, name)), expression));
+            }
+            match parameter(body, "valueSet") {
+                Some(value_set) => value_set["resource"].clone(),
+                None => {
+                    return Err(FhirError::Invalid(
+                        "Either valueSet or conceptSetId is required".to_string(),
+                    ));
+                }
+            }
+        }
+        _ => {
+            return Err(FhirError::Invalid(
+                "Expected a ValueSet or Parameters resource".to_string(),
+            ));
+        }
+    };
+    let Some(compose) = serde_json::from_value::<ValueSet>(value_set)
+        .map_err(|e| FhirError::Invalid(e.to_string()))?
+        .compose
+    else {
+        return Err(FhirError::Invalid(
+            "The value set has no compose element".to_string(),
+        ));
+    };
+    Ok((None, compose_expression(&compose, pg_client).await?))
+}
+
+/// FHIR `ValueSet/$expand`: resolves the value set with the descendant and mapped expansion of
+/// concept set analysis and lists a page of the resulting codes.
+#[utoipa::path(
+    tag = "fhir",
+    summary = "FHIR ValueSet $expand",
+    description = "Expands a value set with the descendant and mapped resolution of concept set analysis. The \
+        body is a ValueSet resource, or a Parameters resource with a `valueSet` resource or the `conceptSetId` of \
+        a saved concept set. Compose elements may list codes and use the `concept` filters `=`, `is-a` and, in \
+        includes, `descendent-of`. Codes of vocabularies without a FHIR system URL are OMOP concept IDs in the \
+        `https://fhir-terminology.ohdsi.org` system. Errors are OperationOutcome resources.",
+    params(ExpandParameters),
+    request_body(
+        content(
+            (Value = "application/fhir+json", example = json!({
+                "resourceType": "ValueSet",
+                "compose": {"include": [{
+                    "system": "http://snomed.info/sct",
+                    "filter": [{"property": "concept", "op": "is-a", "value": "44054006"}]
+                }]}
+            })),
+            (Value = "application/json"),
+        )
+    ),
+    responses(
+        (status = 200, description = "ValueSet resource with an expansion", body = Value,
+            content_type = "application/fhir+json"),
+        (status = 400, description = "Unsupported compose element or unknown code"),
+        (status = 404, description = "No such concept set"),
+        (status = 500, description = "Resolution failed, the expansion would be incomplete"),
+    )
+)]
+#[post("/fhir/ValueSet/$expand")]
+async fn expand_value_set(
+    body: Bytes,
+    parameters: Query<ExpandParameters>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, FhirError> {
+    let offset = parameters.offset.unwrap_or(0);
+    let count = parameters
+        .count
+        .unwrap_or(DEFAULT_EXPANSION_COUNT)
+        .min(MAX_EXPANSION_COUNT);
+    // FHIR clients send application/fhir+json, which the Json extractor refuses.
+    let body: Value =
+        serde_json::from_slice(&body).map_err(|e| FhirError::Invalid(e.to_string()))?;
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let (stored, expression) = requested_value_set(&body, &pg_client).await?;
+    info!("FHIR expansion of {} items", expression.items.len());
+
+    let vocabulary_version = state.vocabulary_catalog.vocabulary_version.as_deref();
+    let mut result = validation::ValidationResult::new();
+    let resolved =
+        expansions::resolve_concept_set(&expression, &pg_client, vocabulary_version, &mut result)
+            .await
+            .resolved_concept_ids();
+    if !result.warnings.is_empty() {
+        return Err(FhirError::Incomplete(result.warnings.join("; ")));
+    }
+
+    let page: Vec<i32> = resolved.iter().skip(offset).take(count).copied().collect();
+    let mut concepts = db::get_concepts_by_ids(&pg_client, &page).await?;
+    concepts.sort_by_key(|concept| concept.concept_id);
+    let contains: Vec<Value> = concepts
+        .iter()
+        .map(|concept| {
+            let (system, code) = coding(concept);
+            let mut contains = json!({
+                "system": system,
+                "code": code,
+                "display": concept.concept_name,
+            });
+            if concept.invalid_reason.is_some() {
+                contains["inactive"] = json!(true);
+            }
+            contains
+        })
+        .collect();
+
+    let mut expansion = json!({
+        "timestamp": Utc::now().to_rfc3339(),
+        "total": resolved.len(),
+        "offset": offset,
+        "contains": contains,
+    });
+    if let Some(version) = vocabulary_version {
+        expansion["parameter"] = json!([{ "name": "version", "valueString": version }]);
+    }
+    let mut value_set = json!({
+        "resourceType": "ValueSet",
+        "status": "active",
+        "expansion": expansion,
+    });
+    if let Some((id, name)) = stored {
+        value_set["id"] = json!(id.to_string());
+        value_set["name"] = json!(name);
+    }
+    Ok(HttpResponse::Ok().content_type(FHIR_JSON).json(value_set))
+}
+
+/// The code an operation is about: the `system` and `code` query parameters, or the `system`,
+/// `code` or `coding` parameters of a Parameters body.
+fn requested_coding<'a>(
+    parameters: &'a CodingParameters,
+    body: &'a Value,
+) -> Result<(&'a str, &'a str), FhirError> {
+    let coding = parameter(body, "coding").map(|coding| &coding["valueCoding"]);
+    let system = parameters
+        .system
+        .as_deref()
+        .or_else(|| parameter(body, "system")?["valueUri"].as_str())
+        .or_else(|| coding?["system"].as_str());
+    let code = parameters
+        .code
+        .as_deref()
+        .or_else(|| parameter(body, "code")?["valueCode"].as_str())
+        .or_else(|| coding?["code"].as_str());
+    match (system, code) {
+        (Some(system), Some(code)) => Ok((system, code)),
+        _ => Err(FhirError::Invalid(
+            "Both system and code are required".to_string(),
+        )),
+    }
+}
+
+/// The concept a system and code stand for, the valid one when the code was reused.
+async fn coded_concept_for(
+    system: &str,
+    code: &str,
+    pg_client: &Client,
+) -> Result<Option<Concept>, FhirError> {
+    if is_omop_system(system) {
+        let Ok(concept_id) = code.parse::<i32>() else {
+            return Ok(None);
+        };
+        return Ok(db::get_concepts_by_ids(pg_client, &[concept_id])
+            .await?
+            .pop());
+    }
+    let Some(vocabulary_id) = vocabulary_id(system) else {
+        return Err(FhirError::NotSupported(format!(
+            "Unknown code system {}",
+            system
+        )));
+    };
+    let lookup = lookup_codes(
+        vec![(vocabulary_id.to_string(), code.to_string())],
+        pg_client,
+    )
+    .await?
+    .pop()
+    .ok_or(PgError::NotFound)?;
+    Ok(coded_concept(&lookup.concepts).cloned())
+}
+
+/// FHIR `ValueSet/$validate-code`: whether a code is in a value set, given like for `$expand`.
+/// The code comes from the `system` and `code` query parameters or the `system`, `code` or
+/// `coding` parameters, and is checked against the items instead of expanding the set.
+#[utoipa::path(
+    tag = "fhir",
+    summary = "FHIR ValueSet $validate-code",
+    description = "Checks whether a code is in a value set, given as for `$expand`, without expanding it. The code \
+        is checked against the items with the same descendant, mapped and exclusion rules as concept set \
+        resolution. It is taken from the `system` and `code` query parameters or from the `system`, `code` or \
+        `coding` parameters of a Parameters body. The answer is a Parameters resource with `result`, `display` \
+        and, when the code is not in the set, a `message`.",
+    params(CodingParameters),
+    request_body(
+        content(
+            (Value = "application/fhir+json", example = json!({
+                "resourceType": "Parameters",
+                "parameter": [
+                    {"name": "conceptSetId", "valueInteger": 12},
+                    {"name": "coding", "valueCoding": {"system": "http://snomed.info/sct", "code": "44054006"}}
+                ]
+            })),
+            (Value = "application/json"),
+        )
+    ),
+    responses(
+        (status = 200, description = "Parameters resource with the result", body = Value,
+            content_type = "application/fhir+json"),
+        (status = 400, description = "Missing code, unsupported compose element or code system"),
+        (status = 404, description = "No such concept set"),
+    )
+)]
+#[post("/fhir/ValueSet/$validate-code")]
+async fn validate_code(
+    body: Bytes,
+    parameters: Query<CodingParameters>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, FhirError> {
+    let body: Value =
+        serde_json::from_slice(&body).map_err(|e| FhirError::Invalid(e.to_string()))?;
+    let (system, code) = requested_coding(&parameters, &body)?;
+
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let (_, expression) = requested_value_set(&body, &pg_client).await?;
+    info!("FHIR validation of {} in {}", code, system);
+
+    let mut parameter = Vec::new();
+    match coded_concept_for(system, code, &pg_client).await? {
+        None => {
+            parameter.push(json!({ "name": "result", "valueBoolean": false }));
+            parameter.push(json!({
+                "name": "message",
+                "valueString": format!("Code {} not found in {}", code, system),
+            }));
+        }
+        Some(concept) => {
+            let membership =
+                validation::concept_membership(&expression, concept.concept_id, &pg_client).await?;
+            parameter.push(json!({ "name": "result", "valueBoolean": membership.included }));
+            parameter.push(json!({ "name": "display", "valueString": concept.concept_name }));
+            let message = match (membership.included_by, membership.excluded_by) {
+                (Some(_), Some(excluded_by)) => Some(format!(
+                    "Code {} is excluded from the value set by concept {}",
+                    code, excluded_by
+                )),
+                (None, _) => Some(format!("Code {} is not in the value set", code)),
+                (Some(_), None) => None,
+            };
+            if let Some(message) = message {
+                parameter.push(json!({ "name": "message", "valueString": message }));
+            }
+        }
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(FHIR_JSON)
+        .json(json!({ "resourceType": "Parameters", "parameter": parameter })))
+}
+
+/// FHIR `ConceptMap/$translate` over the `Maps to` relationships: the standard concepts a source
+/// code maps to, optionally only those of `targetsystem`. A standard code maps to itself, which
+/// is reported as `equal`, other mappings as `equivalent`.
+#[utoipa::path(
+    tag = "fhir",
+    summary = "FHIR ConceptMap $translate",
+    description = "Translates a source code to the standard concepts it maps to through `Maps to`. The code is \
+        taken from the `system` and `code` query parameters or from the `system`, `code` or `coding` parameters \
+        of a Parameters body, which may be omitted. `targetsystem` keeps only targets of one code system. Each \
+        `match` carries the target coding and an `equivalence`, `equal` when a standard code maps to itself and \
+        `equivalent` otherwise.",
+    params(CodingParameters),
+    request_body(content((Option<Value> = "application/fhir+json"), (Option<Value> = "application/json"))),
+    responses(
+        (status = 200, description = "Parameters resource with `result` and the matches", body = Value,
+            content_type = "application/fhir+json"),
+        (status = 400, description = "Missing code or unknown code system"),
+        (status = 404, description = "Unknown code"),
+    )
+)]
+#[post("/fhir/ConceptMap/$translate")]
+async fn translate_code(
+    body: Bytes,
+    parameters: Query<CodingParameters>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, FhirError> {
+    // The code can come in the query alone, leaving the body empty.
+    let body: Value = if body.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&body).map_err(|e| FhirError::Invalid(e.to_string()))?
+    };
+    let (system, code) = requested_coding(&parameters, &body)?;
+    let target_system = parameters
+        .targetsystem
+        .as_deref()
+        .or_else(|| parameter(&body, "targetsystem")?["valueUri"].as_str())
+        .map(|system| system.trim_end_matches('/'));
+    info!("FHIR translation of {} in {}", code, system);
+
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let Some(source) = coded_concept_for(system, code, &pg_client).await? else {
+        return Err(FhirError::NotFound(format!(
+            "Code {} not found in {}",
+            code, system
+        )));
+    };
+    let target_ids: Vec<i32> =
+        db::get_linked_concepts(&pg_client, &[source.concept_id], &["Maps to"])
+            .await?
+            .into_iter()
+            .map(|linked| linked.concept_id)
+            .collect();
+    let mut targets = db::get_concepts_by_ids(&pg_client, &target_ids).await?;
+    targets.sort_by_key(|target| target.concept_id);
+
+    let matches: Vec<Value> = targets
+        .iter()
+        .map(|target| (coding(target), target))
+        .filter(|((url, _), _)| target_system.is_none_or(|system| system == *url))
+        .map(|((url, target_code), target)| {
+            let equivalence = if target.concept_id == source.concept_id {
+                "equal"
+            } else {
+                "equivalent"
+            };
+            json!({
+                "name": "match",
+                "part": [
+                    { "name": "equivalence", "valueCode": equivalence },
+                    {
+                        "name": "concept",
+                        "valueCoding": {
+                            "system": url,
+                            "code": target_code,
+                            "display": target.concept_name,
+                        },
+                    },
+                ],
+            })
+        })
+        .collect();
+
+    let mut parameter = vec![json!({ "name": "result", "valueBoolean": !matches.is_empty() })];
+    if matches.is_empty() {
+        parameter.push(json!({
+            "name": "message",
+            "valueString": format!("Code {} has no standard mapping", code),
+        }));
+    }
+    parameter.extend(matches);
+    Ok(HttpResponse::Ok()
+        .content_type(FHIR_JSON)
+        .json(json!({ "resourceType": "Parameters", "parameter": parameter })))
+}
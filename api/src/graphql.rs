@@ -0,0 +1,413 @@
+use crate::StateWrapper;
+use crate::db;
+use crate::domain::{
+    Concept, ConceptSynonym, HierarchyConcept, RelatedConcept, SearchDiagnostics, SearchResponse,
+};
+use crate::errors::PgError;
+use crate::search::SearchFilters;
+use actix_web::web::{Data, Json};
+use actix_web::{Error, HttpResponse, post};
+use async_graphql::dataloader::{DataLoader, Loader};
+use async_graphql::{
+    ComplexObject, Context, EmptyMutation, EmptySubscription, Object, Schema, SimpleObject,
+};
+use deadpool_postgres::{Client, Pool};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// Nesting allowed in a query, so a request cannot walk the whole concept graph.
+const MAX_QUERY_DEPTH: usize = 8;
+/// Weight allowed in a query, where every field counts once and list fields count once per item
+/// they can return, so wide pages of nested lists are refused before they run.
+const MAX_QUERY_COMPLEXITY: usize = 10_000;
+const MAX_CONCEPT_IDS: usize = 1000;
+const MAX_DESCENDANTS: i64 = 1000;
+const MAX_SEARCH_RESULTS: u64 = 100;
+
+pub type HecateSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+pub fn build_schema() -> HecateSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_QUERY_DEPTH)
+        .limit_complexity(MAX_QUERY_COMPLEXITY)
+        .finish()
+}
+
+/// A pooled connection for one root field. The state is added to every request by `graphql`.
+async fn pg_client(ctx: &Context<'_>) -> async_graphql::Result<Client> {
+    let state = ctx.data::<Data<StateWrapper>>()?;
+    Ok(state.pg_pool.get().await.map_err(PgError::PoolError)?)
+}
+
+/// Loads the nested fields of `Concept` for all concepts of a query at once, so a list of
+/// concepts takes one connection and one query per field instead of one per concept.
+pub struct ConceptLoader {
+    pg_pool: Pool,
+}
+
+impl ConceptLoader {
+    async fn pg_client(&self) -> Result<Client, Arc<PgError>> {
+        Ok(self.pg_pool.get().await.map_err(PgError::PoolError)?)
+    }
+}
+
+/// The concept IDs of `keys` grouped by the arguments they were requested with, each group loaded
+/// with one batch query.
+fn group_keys<K, A>(keys: &[K], split: impl Fn(&K) -> (i32, A)) -> HashMap<A, Vec<i32>>
+where
+    A: Eq + Hash,
+{
+    let mut groups: HashMap<A, Vec<i32>> = HashMap::new();
+    for key in keys {
+        let (concept_id, arguments) = split(key);
+        groups.entry(arguments).or_default().push(concept_id);
+    }
+    groups
+}
+
+#[derive(Clone, Eq, Hash, PartialEq)]
+struct RelationshipsKey(i32, Option<Vec<String>>);
+
+impl Loader<RelationshipsKey> for ConceptLoader {
+    type Value = Vec<RelatedConcept>;
+    type Error = Arc<PgError>;
+
+    async fn load(
+        &self,
+        keys: &[RelationshipsKey],
+    ) -> Result<HashMap<RelationshipsKey, Self::Value>, Self::Error> {
+        let pg_client = self.pg_client().await?;
+        let mut result = HashMap::new();
+        for (relationship_ids, concept_ids) in group_keys(keys, |key| (key.0, key.1.clone())) {
+            let relationships = db::get_batch_concept_relationships(
+                &pg_client,
+                &concept_ids,
+                relationship_ids.as_deref(),
+            )
+            .await?;
+            result.extend(relationships.into_iter().map(|(concept_id, concepts)| {
+                (
+                    RelationshipsKey(concept_id, relationship_ids.clone()),
+                    concepts,
+                )
+            }));
+        }
+        Ok(result)
+    }
+}
+
+#[derive(Clone, Eq, Hash, PartialEq)]
+struct AncestorsKey(i32);
+
+impl Loader<AncestorsKey> for ConceptLoader {
+    type Value = Vec<HierarchyConcept>;
+    type Error = Arc<PgError>;
+
+    async fn load(
+        &self,
+        keys: &[AncestorsKey],
+    ) -> Result<HashMap<AncestorsKey, Self::Value>, Self::Error> {
+        let pg_client = self.pg_client().await?;
+        let concept_ids: Vec<i32> = keys.iter().map(|key| key.0).collect();
+        let ancestors = db::get_batch_ancestor_concepts(&pg_client, &concept_ids).await?;
+        Ok(ancestors
+            .into_iter()
+            .map(|(concept_id, concepts)| (AncestorsKey(concept_id), concepts))
+            .collect())
+    }
+}
+
+#[derive(Clone, Eq, Hash, PartialEq)]
+struct DescendantsKey {
+    concept_id: i32,
+    max_levels: Option<i32>,
+    domain_id: Option<String>,
+    offset: i64,
+    limit: i64,
+}
+
+impl Loader<DescendantsKey> for ConceptLoader {
+    type Value = DescendantPage;
+    type Error = Arc<PgError>;
+
+    async fn load(
+        &self,
+        keys: &[DescendantsKey],
+    ) -> Result<HashMap<DescendantsKey, Self::Value>, Self::Error> {
+        let pg_client = self.pg_client().await?;
+        let mut result = HashMap::new();
+        let groups = group_keys(keys, |key| {
+            let arguments = (key.max_levels, key.domain_id.clone(), key.offset, key.limit);
+            (key.concept_id, arguments)
+        });
+        for ((max_levels, domain_id, offset, limit), concept_ids) in groups {
+            let pages = db::get_batch_descendant_concepts_page(
+                &pg_client,
+                &concept_ids,
+                max_levels,
+                domain_id.as_deref(),
+                offset,
+                limit,
+            )
+            .await?;
+            result.extend(pages.into_iter().map(|(concept_id, (total, concepts))| {
+                let key = DescendantsKey {
+                    concept_id,
+                    max_levels,
+                    domain_id: domain_id.clone(),
+                    offset,
+                    limit,
+                };
+                (key, DescendantPage { total, concepts })
+            }));
+        }
+        Ok(result)
+    }
+}
+
+#[derive(Clone, Eq, Hash, PartialEq)]
+struct SynonymsKey(i32, Option<Vec<i32>>);
+
+impl Loader<SynonymsKey> for ConceptLoader {
+    type Value = Vec<ConceptSynonym>;
+    type Error = Arc<PgError>;
+
+    async fn load(
+        &self,
+        keys: &[SynonymsKey],
+    ) -> Result<HashMap<SynonymsKey, Self::Value>, Self::Error> {
+        let pg_client = self.pg_client().await?;
+        let mut result = HashMap::new();
+        for (language_concept_ids, concept_ids) in group_keys(keys, |key| (key.0, key.1.clone())) {
+            let synonyms = db::get_batch_concept_synonyms(
+                &pg_client,
+                &concept_ids,
+                language_concept_ids.as_deref(),
+            )
+            .await?;
+            result.extend(synonyms.into_iter().map(|(concept_id, synonyms)| {
+                (
+                    SynonymsKey(concept_id, language_concept_ids.clone()),
+                    synonyms,
+                )
+            }));
+        }
+        Ok(result)
+    }
+}
+
+/// A concept and the relationship, `Maps to` or `Mapped from`, to follow from it.
+#[derive(Clone, Eq, Hash, PartialEq)]
+struct MappedKey(i32, &'static str);
+
+impl Loader<MappedKey> for ConceptLoader {
+    type Value = Vec<Concept>;
+    type Error = Arc<PgError>;
+
+    async fn load(
+        &self,
+        keys: &[MappedKey],
+    ) -> Result<HashMap<MappedKey, Self::Value>, Self::Error> {
+        let pg_client = self.pg_client().await?;
+        let mut result = HashMap::new();
+        for (relationship_id, concept_ids) in group_keys(keys, |key| (key.0, key.1)) {
+            let mapped =
+                db::get_batch_concepts_by_relationship(&pg_client, &concept_ids, relationship_id)
+                    .await?;
+            result.extend(
+                mapped.into_iter().map(|(concept_id, concepts)| {
+                    (MappedKey(concept_id, relationship_id), concepts)
+                }),
+            );
+        }
+        Ok(result)
+    }
+}
+
+/// Loads `key` through the request's `ConceptLoader`, added to every request by `graphql`.
+async fn load<K>(
+    ctx: &Context<'_>,
+    key: K,
+) -> async_graphql::Result<<ConceptLoader as Loader<K>>::Value>
+where
+    K: Send + Sync + Hash + Eq + Clone + 'static,
+    ConceptLoader: Loader<K, Error = Arc<PgError>>,
+    <ConceptLoader as Loader<K>>::Value: Default,
+{
+    let loader = ctx.data::<DataLoader<ConceptLoader>>()?;
+    Ok(loader.load_one(key).await?.unwrap_or_default())
+}
+
+/// A page of descendants and how many there are in total.
+#[derive(Clone, Default, SimpleObject)]
+struct DescendantPage {
+    total: i64,
+    concepts: Vec<HierarchyConcept>,
+}
+
+#[ComplexObject]
+impl Concept {
+    /// Related concepts, optionally only through the given relationships.
+    async fn relationships(
+        &self,
+        ctx: &Context<'_>,
+        relationship_id: Option<Vec<String>>,
+    ) -> async_graphql::Result<Vec<RelatedConcept>> {
+        load(ctx, RelationshipsKey(self.concept_id, relationship_id)).await
+    }
+
+    async fn ancestors(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<HierarchyConcept>> {
+        load(ctx, AncestorsKey(self.concept_id)).await
+    }
+
+    #[graphql(complexity = "limit.clamp(0, MAX_DESCENDANTS) as usize * child_complexity")]
+    async fn descendants(
+        &self,
+        ctx: &Context<'_>,
+        max_levels: Option<i32>,
+        domain_id: Option<String>,
+        #[graphql(default = 0)] offset: i64,
+        #[graphql(default = 100)] limit: i64,
+    ) -> async_graphql::Result<DescendantPage> {
+        let key = DescendantsKey {
+            concept_id: self.concept_id,
+            max_levels,
+            domain_id,
+            offset: offset.max(0),
+            limit: limit.clamp(0, MAX_DESCENDANTS),
+        };
+        load(ctx, key).await
+    }
+
+    async fn synonyms(
+        &self,
+        ctx: &Context<'_>,
+        language_concept_id: Option<Vec<i32>>,
+    ) -> async_graphql::Result<Vec<ConceptSynonym>> {
+        load(ctx, SynonymsKey(self.concept_id, language_concept_id)).await
+    }
+
+    /// The standard concepts this concept maps to.
+    async fn maps_to(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Concept>> {
+        load(ctx, MappedKey(self.concept_id, "Maps to")).await
+    }
+
+    /// The source concepts that map to this concept.
+    async fn mapped_from(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Concept>> {
+        load(ctx, MappedKey(self.concept_id, "Mapped from")).await
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn concept(
+        &self,
+        ctx: &Context<'_>,
+        concept_id: i32,
+    ) -> async_graphql::Result<Option<Concept>> {
+        let pg_client = pg_client(ctx).await?;
+        Ok(db::get_concepts_by_ids(&pg_client, &[concept_id])
+            .await?
+            .pop())
+    }
+
+    #[graphql(complexity = "concept_ids.len() * child_complexity")]
+    async fn concepts(
+        &self,
+        ctx: &Context<'_>,
+        concept_ids: Vec<i32>,
+    ) -> async_graphql::Result<Vec<Concept>> {
+        if concept_ids.len() > MAX_CONCEPT_IDS {
+            return Err(format!("At most {} concept IDs can be requested", MAX_CONCEPT_IDS).into());
+        }
+        let pg_client = pg_client(ctx).await?;
+        let mut concepts = db::get_concepts_by_ids(&pg_client, &concept_ids).await?;
+        concepts.sort_by_key(|concept| {
+            concept_ids
+                .iter()
+                .position(|concept_id| *concept_id == concept.concept_id)
+        });
+        Ok(concepts)
+    }
+
+    /// Concept search with the filters of `/api/search`, grouped by concept name.
+    #[allow(clippy::too_many_arguments)]
+    #[graphql(complexity = "limit.min(MAX_SEARCH_RESULTS) as usize * child_complexity")]
+    async fn search(
+        &self,
+        ctx: &Context<'_>,
+        query: String,
+        vocabulary_id: Option<Vec<String>>,
+        domain_id: Option<Vec<String>>,
+        concept_class_id: Option<Vec<String>>,
+        standard_concept: Option<String>,
+        #[graphql(default = 20)] limit: u64,
+    ) -> async_graphql::Result<Vec<SearchResponse>> {
+        let state = ctx.data::<Data<StateWrapper>>()?;
+        state
+            .vocabulary_catalog
+            .validate_vocabulary_ids(vocabulary_id.as_deref())?;
+        state
+            .vocabulary_catalog
+            .validate_domain_ids(domain_id.as_deref())?;
+        let filters = SearchFilters {
+            vocabulary_id,
+            standard_concept,
+            domain_id,
+            concept_class_id,
+            ..Default::default()
+        };
+        let limit = state.config.demo.clamp_limit(limit.min(MAX_SEARCH_RESULTS));
+        // The search pipeline is not Send, as resolvers must be, so it runs as a local task of
+        // the worker handling the request.
+        let state = state.clone();
+        let results = actix_web::rt::spawn(async move {
+            let mut diagnostics = SearchDiagnostics::new(state.config.search.score_threshold);
+            state
+                .search_pipeline
+                .run(&state, &query, filters, limit, &mut diagnostics)
+                .await
+        })
+        .await??;
+        Ok(results)
+    }
+}
+
+/// GraphQL queries over concepts, their relationships, hierarchy, synonyms and mappings, and
+/// search, for clients that want nested data in one round trip.
+#[utoipa::path(
+    tag = "graphql",
+    summary = "GraphQL queries",
+    description = "Read-only GraphQL over concepts. The root fields are `concept(conceptId)`, \
+        `concepts(conceptIds)` and `search(query, vocabularyId, domainId, conceptClassId, standardConcept, \
+        limit)`. A concept nests `relationships(relationshipId)`, `ancestors`, `descendants(maxLevels, domainId, \
+        offset, limit)`, `synonyms(languageConceptId)`, `mapsTo` and `mappedFrom`. Queries may nest at most 8 \
+        levels. Errors are reported in the `errors` member of a 200 response, as GraphQL does.",
+    request_body(
+        description = "A GraphQL request with `query` and optionally `operationName` and `variables`",
+        content = serde_json::Value,
+        example = json!({
+            "query": "{ concept(conceptId: 201826) { conceptName ancestors { conceptName minLevelsOfSeparation } \
+                synonyms { conceptSynonymName } mapsTo { conceptId } } }"
+        })
+    ),
+    responses((status = 200, description = "GraphQL response with `data` and `errors`", body = serde_json::Value))
+)]
+#[post("/api/graphql")]
+async fn graphql(
+    request: Json<async_graphql::Request>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let loader = ConceptLoader {
+        pg_pool: state.pg_pool.clone(),
+    };
+    let request = request
+        .into_inner()
+        .data(state.clone())
+        .data(DataLoader::new(loader, actix_web::rt::spawn));
+    let response = state.graphql_schema.execute(request).await;
+    Ok(HttpResponse::Ok().json(response))
+}
@@ -0,0 +1,358 @@
+use crate::StateWrapper;
+use crate::api::{analyze_with_state, cached_search, demo_size_error};
+use crate::auth::{self, API_KEY_HEADER, Identity};
+use crate::config::Scope;
+use crate::db;
+use crate::domain::{Concept, SearchResponse};
+use crate::profiles::ValidationProfile;
+use crate::rate_limit;
+use crate::search::SearchFilters;
+use crate::validation::Progress;
+use actix_web::rt::System;
+use actix_web::web::Data;
+use futures::future::LocalBoxFuture;
+use futures::{Stream, StreamExt};
+use log::{error, info};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::{mpsc, oneshot};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+
+pub mod proto {
+    tonic::include_proto!("hecate.v1");
+}
+
+use proto::hecate_server::{Hecate, HecateServer};
+
+const DEFAULT_SEARCH_LIMIT: u64 = 100;
+const MAX_SEARCH_LIMIT: u64 = 1000;
+const MAX_CONCEPT_IDS: usize = 10_000;
+/// Searches of one stream in flight at once. Replies still come back in request order.
+const STREAM_SEARCHES_IN_FLIGHT: usize = 8;
+
+type LocalJob = Box<dyn FnOnce(Data<StateWrapper>) -> LocalBoxFuture<'static, ()> + Send>;
+
+/// Threads running gRPC requests. Tonic handlers have to be Send and search and analysis are
+/// not, so handlers hand the work to one of these threads and wait for the answer.
+struct LocalWorkers {
+    senders: Vec<mpsc::UnboundedSender<LocalJob>>,
+    next: AtomicUsize,
+}
+
+impl LocalWorkers {
+    fn start(state: Data<StateWrapper>, threads: usize) -> Self {
+        let senders = (0..threads.max(1))
+            .map(|index| {
+                let (sender, mut receiver) = mpsc::unbounded_channel::<LocalJob>();
+                let state = state.clone();
+                std::thread::Builder::new()
+                    .name(format!("grpc-worker-{}", index))
+                    .spawn(move || {
+                        System::new().block_on(async move {
+                            while let Some(job) = receiver.recv().await {
+                                actix_web::rt::spawn(job(state.clone()));
+                            }
+                        })
+                    })
+                    .expect("Could not start a gRPC worker thread");
+                sender
+            })
+            .collect();
+        Self {
+            senders,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    async fn run<T, F, Fut>(&self, work: F) -> Result<T, Status>
+    where
+        T: Send + 'static,
+        F: FnOnce(Data<StateWrapper>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<T, Status>> + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        let job: LocalJob = Box::new(move |state| {
+            Box::pin(async move {
+                let _ = sender.send(work(state).await);
+            })
+        });
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.senders.len();
+        self.senders[index]
+            .send(job)
+            .map_err(|_| Status::unavailable("The gRPC workers stopped"))?;
+        receiver
+            .await
+            .map_err(|_| Status::internal("The request was dropped"))?
+    }
+}
+
+struct HecateService {
+    state: Data<StateWrapper>,
+    workers: Arc<LocalWorkers>,
+}
+
+/// Takes a token from the caller's bucket for the REST route `path` stands in for.
+fn limit_rate(state: &StateWrapper, client: &str, path: &str) -> Result<(), Status> {
+    if !state.rate_limiter.enabled() {
+        return Ok(());
+    }
+    state.rate_limiter.acquire(client, path).map_err(|wait| {
+        Status::resource_exhausted(format!(
+            "Too many requests, retry in {}s",
+            rate_limit::retry_after_secs(wait)
+        ))
+    })
+}
+
+impl HecateService {
+    /// The API key or OIDC token check and the rate limit of the HTTP API, with the key sent as
+    /// `x-api-key` or `authorization: Bearer` metadata and `path` the REST route whose quota
+    /// applies. Returns the caller's rate limit bucket, for calls doing more work later.
+    async fn authorize<T>(
+        &self,
+        request: &Request<T>,
+        scope: Scope,
+        path: &str,
+    ) -> Result<String, Status> {
+        let identity = self.identify(request, scope).await?;
+        let address = request
+            .remote_addr()
+            .map(|address| address.ip().to_string());
+        let client = rate_limit::client_key_of(identity.as_ref(), address);
+        limit_rate(&self.state, &client, path)?;
+        Ok(client)
+    }
+
+    async fn identify<T>(
+        &self,
+        request: &Request<T>,
+        scope: Scope,
+    ) -> Result<Option<Identity>, Status> {
+        if !self.state.config.auth.enabled {
+            return Ok(None);
+        }
+        let metadata = request.metadata();
+        let key = metadata
+            .get(API_KEY_HEADER.as_str())
+            .and_then(|key| key.to_str().ok())
+            .or_else(|| {
+                metadata
+                    .get("authorization")?
+                    .to_str()
+                    .ok()?
+                    .strip_prefix("Bearer ")
+            })
+            .map(str::trim)
+            .ok_or_else(|| Status::unauthenticated("An API key or bearer token is required"))?;
+        let identity = auth::identify(&self.state, key)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(Status::unauthenticated)?;
+        if !identity.has_scope(scope) {
+            return Err(Status::permission_denied(format!(
+                "This call requires the {} scope",
+                scope.as_str()
+            )));
+        }
+        Ok(Some(identity))
+    }
+}
+
+fn concept_message(concept: Concept) -> proto::Concept {
+    proto::Concept {
+        concept_id: concept.concept_id,
+        concept_name: concept.concept_name,
+        domain_id: concept.domain_id,
+        vocabulary_id: concept.vocabulary_id,
+        concept_class_id: concept.concept_class_id,
+        standard_concept: concept.standard_concept,
+        concept_code: concept.concept_code,
+        invalid_reason: concept.invalid_reason,
+    }
+}
+
+fn search_result_message(result: SearchResponse) -> proto::SearchResult {
+    proto::SearchResult {
+        concept_name: result.concept_name,
+        score: result.score,
+        concepts: result.concepts.into_iter().map(concept_message).collect(),
+    }
+}
+
+/// Runs a search with the filters of `/api/search` through its cache, on a worker thread.
+async fn search(
+    state: Data<StateWrapper>,
+    request: proto::SearchRequest,
+) -> Result<proto::SearchReply, Status> {
+    let as_filter = |values: Vec<String>| (!values.is_empty()).then_some(values);
+    let filters = SearchFilters {
+        vocabulary_id: as_filter(request.vocabulary_id),
+        standard_concept: request.standard_concept,
+        domain_id: as_filter(request.domain_id),
+        concept_class_id: as_filter(request.concept_class_id),
+        ..Default::default()
+    };
+    state
+        .vocabulary_catalog
+        .validate_vocabulary_ids(filters.vocabulary_id.as_deref())
+        .map_err(|e| Status::invalid_argument(e.to_string()))?;
+    state
+        .vocabulary_catalog
+        .validate_domain_ids(filters.domain_id.as_deref())
+        .map_err(|e| Status::invalid_argument(e.to_string()))?;
+    let limit = state.config.demo.clamp_limit(
+        request
+            .limit
+            .unwrap_or(DEFAULT_SEARCH_LIMIT)
+            .min(MAX_SEARCH_LIMIT),
+    );
+    let cached = cached_search(&state, &request.query, filters)
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?;
+    Ok(proto::SearchReply {
+        query: request.query,
+        results: cached
+            .results
+            .iter()
+            .take(limit as usize)
+            .cloned()
+            .map(search_result_message)
+            .collect(),
+    })
+}
+
+type SearchReplyStream = Pin<Box<dyn Stream<Item = Result<proto::SearchReply, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Hecate for HecateService {
+    async fn search(
+        &self,
+        request: Request<proto::SearchRequest>,
+    ) -> Result<Response<proto::SearchReply>, Status> {
+        self.authorize(&request, Scope::Read, "/api/search").await?;
+        let request = request.into_inner();
+        let reply = self
+            .workers
+            .run(move |state| search(state, request))
+            .await?;
+        Ok(Response::new(reply))
+    }
+
+    type SearchStreamStream = SearchReplyStream;
+
+    async fn search_stream(
+        &self,
+        request: Request<Streaming<proto::SearchRequest>>,
+    ) -> Result<Response<Self::SearchStreamStream>, Status> {
+        // Opening the stream takes a token and every search on it another, like a search each
+        let client = self.authorize(&request, Scope::Read, "/api/search").await?;
+        let state = self.state.clone();
+        let workers = self.workers.clone();
+        let replies = request
+            .into_inner()
+            .map(move |request| {
+                let workers = workers.clone();
+                let limited = limit_rate(&state, &client, "/api/search");
+                async move {
+                    let request = request?;
+                    limited?;
+                    workers.run(move |state| search(state, request)).await
+                }
+            })
+            .buffered(STREAM_SEARCHES_IN_FLIGHT);
+        Ok(Response::new(Box::pin(replies)))
+    }
+
+    async fn get_concepts(
+        &self,
+        request: Request<proto::GetConceptsRequest>,
+    ) -> Result<Response<proto::GetConceptsReply>, Status> {
+        self.authorize(&request, Scope::Read, "/api/concepts")
+            .await?;
+        let concept_ids = request.into_inner().concept_ids;
+        if concept_ids.len() > MAX_CONCEPT_IDS {
+            return Err(Status::invalid_argument(format!(
+                "At most {} concept IDs can be requested",
+                MAX_CONCEPT_IDS
+            )));
+        }
+        let pg_client = self
+            .state
+            .pg_pool
+            .get()
+            .await
+            .map_err(|e| Status::unavailable(e.to_string()))?;
+        let concepts = db::get_concepts_by_ids(&pg_client, &concept_ids)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(proto::GetConceptsReply {
+            concepts: concepts.into_iter().map(concept_message).collect(),
+        }))
+    }
+
+    async fn validate_concept_set(
+        &self,
+        request: Request<proto::ValidateConceptSetRequest>,
+    ) -> Result<Response<proto::ValidateConceptSetReply>, Status> {
+        self.authorize(&request, Scope::Validate, "/api/conceptsets/analyze")
+            .await?;
+        let request = request.into_inner();
+        if let Some(error) = demo_size_error(&self.state, &request.concept_set) {
+            return Err(Status::invalid_argument(error));
+        }
+        let profile = match request.profile {
+            Some(profile) => serde_json::from_value::<ValidationProfile>(profile.into())
+                .map_err(|_| Status::invalid_argument("Unknown validation profile"))?,
+            None => ValidationProfile::default(),
+        };
+        let reply = self
+            .workers
+            .run(move |state| async move {
+                let result =
+                    analyze_with_state(&state, &request.concept_set, profile, Progress::default())
+                        .await
+                        .map_err(|e| Status::internal(e.to_string()))?;
+                Ok(proto::ValidateConceptSetReply {
+                    valid: result.valid,
+                    errors: result.errors.clone(),
+                    warnings: result.warnings.clone(),
+                    result_json: result.to_json().to_string(),
+                })
+            })
+            .await?;
+        Ok(Response::new(reply))
+    }
+}
+
+/// Starts the gRPC server next to the HTTP one when `GRPC__ENABLED` is set.
+pub fn serve(state: Data<StateWrapper>) {
+    let config = &state.config.grpc;
+    if !config.enabled {
+        return;
+    }
+    let addr: SocketAddr = match config.addr.parse() {
+        Ok(addr) => addr,
+        Err(e) => {
+            error!("Not starting gRPC, invalid address {}: {}", config.addr, e);
+            return;
+        }
+    };
+    let service = HecateService {
+        workers: Arc::new(LocalWorkers::start(state.clone(), config.threads)),
+        state: state.clone(),
+    };
+    info!("Starting gRPC server on {}", addr);
+    actix_web::rt::spawn(async move {
+        if let Err(e) = Server::builder()
+            .add_service(HecateServer::new(service))
+            .serve(addr)
+            .await
+        {
+            error!("gRPC server stopped: {}", e);
+        }
+    });
+}
@@ -0,0 +1,247 @@
+use crate::StateWrapper;
+use crate::auth::Identity;
+use crate::db;
+use crate::domain::IdempotencyKey;
+use crate::errors::PgError;
+use actix_web::body::{BoxBody, MessageBody, to_bytes};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{CONTENT_TYPE, HeaderName, HeaderValue};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::web::{self, Data};
+use actix_web::{Error, HttpMessage, HttpResponse};
+use deadpool_postgres::Pool;
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+
+pub const IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("idempotency-key");
+pub const IDEMPOTENT_REPLAYED_HEADER: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+const MAX_KEY_LENGTH: usize = 255;
+
+fn request_hash(body: &[u8]) -> String {
+    Sha256::digest(body)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// A key claimed for a request being processed. Unless the response was stored, dropping it
+/// releases the key, so a retry is not refused with 409 after the handler failed, panicked or
+/// was dropped when the client disconnected.
+struct Claim {
+    pool: Pool,
+    key: String,
+    owner: String,
+    method: String,
+    path: String,
+    settled: bool,
+}
+
+impl Claim {
+    fn key(&self) -> IdempotencyKey<'_> {
+        IdempotencyKey {
+            key: &self.key,
+            owner: &self.owner,
+            method: &self.method,
+            path: &self.path,
+        }
+    }
+
+    /// Releases the key before the response goes out, so an immediate retry can claim it.
+    async fn release(mut self) {
+        self.settled = true;
+        release(&self.pool, &self.key()).await;
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        if self.settled {
+            return;
+        }
+        let pool = self.pool.clone();
+        let key = std::mem::take(&mut self.key);
+        let owner = std::mem::take(&mut self.owner);
+        let method = std::mem::take(&mut self.method);
+        let path = std::mem::take(&mut self.path);
+        actix_web::rt::spawn(async move {
+            let key = IdempotencyKey {
+                key: &key,
+                owner: &owner,
+                method: &method,
+                path: &path,
+            };
+            release(&pool, &key).await;
+        });
+    }
+}
+
+async fn release(pool: &Pool, key: &IdempotencyKey<'_>) {
+    let released = match pool.get().await {
+        Ok(pg_client) => db::release_idempotency_key(&pg_client, key).await,
+        Err(e) => Err(PgError::PoolError(e)),
+    };
+    if let Err(e) = released {
+        warn!("Could not release idempotency key {:?}: {}", key.key, e);
+    }
+}
+
+fn error_response(req: ServiceRequest, status: StatusCode, error: &str) -> ServiceResponse {
+    let response = HttpResponse::build(status).json(serde_json::json!({ "error": error }));
+    req.into_response(response)
+}
+
+/// Middleware making state-changing requests that carry an `Idempotency-Key` header safe to
+/// retry: the first response is stored and replayed for repeats of the same request, so a
+/// client retrying after a timeout does not create duplicates.
+pub async fn idempotency_guard(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let key = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(key) if !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) => {
+            key.to_str().unwrap_or_default().to_string()
+        }
+        _ => return next.call(req).await.map(|res| res.map_into_boxed_body()),
+    };
+    if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+        return Ok(error_response(
+            req,
+            StatusCode::BAD_REQUEST,
+            "Idempotency-Key must be between 1 and 255 visible ASCII characters",
+        ));
+    }
+    let Some(state) = req.app_data::<Data<StateWrapper>>().cloned() else {
+        return next.call(req).await.map(|res| res.map_into_boxed_body());
+    };
+
+    // The body is hashed so a key reused for a different request is caught, then handed back
+    // to the handler
+    let max_body_bytes = state.config.idempotency.max_body_bytes;
+    let Ok(body) = req
+        .extract::<web::Payload>()
+        .await?
+        .to_bytes_limited(max_body_bytes)
+        .await
+    else {
+        return Ok(error_response(
+            req,
+            StatusCode::PAYLOAD_TOO_LARGE,
+            &format!(
+                "Requests with an Idempotency-Key are limited to {} bytes",
+                max_body_bytes
+            ),
+        ));
+    };
+    let body = body?;
+    let hash = request_hash(&body);
+    req.set_payload(Payload::from(body));
+
+    let owner = req
+        .extensions()
+        .get::<Identity>()
+        .map(|identity| identity.name.clone())
+        .unwrap_or_default();
+    let mut claim = Claim {
+        pool: state.pg_pool.clone(),
+        key,
+        owner,
+        method: req.method().to_string(),
+        path: req.path().to_string(),
+        // Nothing to release until the key is claimed
+        settled: true,
+    };
+    let key = claim.key();
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let claimed = db::claim_idempotency_key(
+        &pg_client,
+        &key,
+        &hash,
+        state.config.idempotency.claim_timeout_secs,
+    )
+    .await?;
+    // The connection is not held while the handler runs, it may need connections of its own
+    drop(pg_client);
+
+    if let Some(record) = claimed {
+        if record.request_hash != hash {
+            return Ok(error_response(
+                req,
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Idempotency-Key was already used for a different request",
+            ));
+        }
+        let Some(status_code) = record.status_code else {
+            return Ok(error_response(
+                req,
+                StatusCode::CONFLICT,
+                "A request with this Idempotency-Key is still being processed",
+            ));
+        };
+        info!("Replaying response for idempotency key {:?}", key.key);
+        let mut response =
+            HttpResponse::build(StatusCode::from_u16(status_code as u16).unwrap_or(StatusCode::OK));
+        response.insert_header((IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true")));
+        if let Some(content_type) = record.content_type {
+            response.insert_header((CONTENT_TYPE, content_type));
+        }
+        return Ok(req.into_response(response.body(record.response_body.unwrap_or_default())));
+    }
+    claim.settled = false;
+
+    let res = next.call(req).await?;
+    let status = res.status();
+    // Rejected and failed requests are not replayed, the client can fix the request or retry
+    // it with the same key
+    if status.is_client_error() || status.is_server_error() {
+        claim.release().await;
+        return Ok(res.map_into_boxed_body());
+    }
+
+    let content_type = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let (http_req, http_res) = res.into_parts();
+    let (http_res, body) = http_res.into_parts();
+    let body = match to_bytes(body).await {
+        Ok(body) => body,
+        Err(_) => {
+            claim.release().await;
+            return Ok(ServiceResponse::new(
+                http_req,
+                HttpResponse::InternalServerError().finish(),
+            ));
+        }
+    };
+    let stored = match state.pg_pool.get().await {
+        Ok(pg_client) => {
+            db::store_idempotent_response(
+                &pg_client,
+                &claim.key(),
+                status.as_u16() as i32,
+                content_type.as_deref(),
+                &body,
+            )
+            .await
+        }
+        Err(e) => Err(PgError::PoolError(e)),
+    };
+    match stored {
+        Ok(()) => claim.settled = true,
+        // The key is released, a retry runs the request again rather than waiting for it
+        Err(e) => {
+            warn!(
+                "Could not store response for idempotency key {:?}: {}",
+                claim.key, e
+            );
+            claim.release().await;
+        }
+    }
+    Ok(ServiceResponse::new(
+        http_req,
+        http_res.set_body(BoxBody::new(body)),
+    ))
+}
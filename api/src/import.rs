@@ -0,0 +1,331 @@
+use crate::db;
+use crate::domain::Concept;
+use crate::errors::PgError;
+use crate::validation::{ConceptSetExpression, ConceptSetItem, ConceptSetWithMetadata};
+use deadpool_postgres::Client;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use utoipa::ToSchema;
+
+/// The CSV layouts produced by the R tooling around ATLAS.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CsvLayout {
+    /// One concept set per file, as written by Capr.
+    Capr,
+    /// Several concept sets per file keyed by `conceptSetId`/`conceptSetName`, as in the
+    /// Phenotype Library.
+    PhenotypeLibrary,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportResult {
+    pub layout: CsvLayout,
+    pub concept_sets: Vec<ConceptSetWithMetadata>,
+    pub warnings: Vec<String>,
+}
+
+/// Rows read from the CSV, ready to be turned into concept sets.
+pub struct ParsedCsv {
+    layout: CsvLayout,
+    rows: Vec<CsvRow>,
+    warnings: Vec<String>,
+}
+
+/// A single parsed CSV row, before concept metadata has been filled in from the vocabulary.
+#[derive(Debug, Default)]
+struct CsvRow {
+    concept_set_id: Option<i32>,
+    concept_set_name: Option<String>,
+    concept_id: i32,
+    concept_name: Option<String>,
+    vocabulary_id: Option<String>,
+    domain_id: Option<String>,
+    concept_class_id: Option<String>,
+    standard_concept: Option<String>,
+    concept_code: Option<String>,
+    invalid_reason: Option<String>,
+    is_excluded: bool,
+    include_descendants: bool,
+    include_mapped: bool,
+}
+
+/// Normalizes a header so that `conceptId`, `concept_id` and `CONCEPT_ID` all compare equal.
+fn normalize_header(header: &str) -> String {
+    header
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+fn canonical_column(header: &str) -> Option<&'static str> {
+    match normalize_header(header).as_str() {
+        "conceptsetid" | "codesetid" => Some("concept_set_id"),
+        "conceptsetname" | "codesetname" => Some("concept_set_name"),
+        "conceptid" => Some("concept_id"),
+        "conceptname" => Some("concept_name"),
+        "vocabularyid" => Some("vocabulary_id"),
+        "domainid" => Some("domain_id"),
+        "conceptclassid" => Some("concept_class_id"),
+        "standardconcept" => Some("standard_concept"),
+        "conceptcode" => Some("concept_code"),
+        "invalidreason" => Some("invalid_reason"),
+        "isexcluded" | "excluded" | "exclude" => Some("is_excluded"),
+        "includedescendants" | "descendants" => Some("include_descendants"),
+        "includemapped" | "mapped" => Some("include_mapped"),
+        _ => None,
+    }
+}
+
+fn parse_flag(value: &str) -> Result<bool, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "t" | "1" | "yes" | "y" => Ok(true),
+        "false" | "f" | "0" | "no" | "n" | "" | "na" => Ok(false),
+        other => Err(format!("'{}' is not a boolean", other)),
+    }
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim();
+    if value.is_empty() || value.eq_ignore_ascii_case("na") {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+fn detect_layout(columns: &[Option<&'static str>]) -> CsvLayout {
+    if columns
+        .iter()
+        .any(|c| matches!(c, Some("concept_set_id") | Some("concept_set_name")))
+    {
+        CsvLayout::PhenotypeLibrary
+    } else {
+        CsvLayout::Capr
+    }
+}
+
+/// Reads a Capr or Phenotype Library concept set CSV. The layout is detected from the header
+/// unless the caller specifies it.
+pub fn parse_csv(content: &str, requested_layout: Option<CsvLayout>) -> Result<ParsedCsv, String> {
+    let mut warnings = Vec::new();
+    // Both comma and tab separated exports are common, sniff the header line
+    let header_line = content.lines().next().unwrap_or_default();
+    let delimiter = if header_line.contains('\t') {
+        b'\t'
+    } else {
+        b','
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(content.as_bytes());
+
+    let columns: Vec<Option<&'static str>> = reader
+        .headers()
+        .map_err(|e| format!("Could not read CSV header: {}", e))?
+        .iter()
+        .map(canonical_column)
+        .collect();
+
+    if !columns.contains(&Some("concept_id")) {
+        return Err("CSV is missing a conceptId column".to_string());
+    }
+
+    let layout = requested_layout.unwrap_or_else(|| detect_layout(&columns));
+    let mut rows = Vec::new();
+
+    for (index, record) in reader.records().enumerate() {
+        // Header is line 1
+        let line = index + 2;
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                warnings.push(format!("Skipping line {}: {}", line, e));
+                continue;
+            }
+        };
+
+        let mut row = CsvRow::default();
+        let mut row_error: Option<String> = None;
+        for (column, value) in columns.iter().zip(record.iter()) {
+            let parsed = match column {
+                Some("concept_id") => value
+                    .parse::<i32>()
+                    .map(|id| row.concept_id = id)
+                    .map_err(|_| format!("'{}' is not a concept id", value)),
+                Some("concept_set_id") => {
+                    row.concept_set_id = value.parse::<i32>().ok();
+                    Ok(())
+                }
+                Some("concept_set_name") => {
+                    row.concept_set_name = non_empty(value);
+                    Ok(())
+                }
+                Some("concept_name") => {
+                    row.concept_name = non_empty(value);
+                    Ok(())
+                }
+                Some("vocabulary_id") => {
+                    row.vocabulary_id = non_empty(value);
+                    Ok(())
+                }
+                Some("domain_id") => {
+                    row.domain_id = non_empty(value);
+                    Ok(())
+                }
+                Some("concept_class_id") => {
+                    row.concept_class_id = non_empty(value);
+                    Ok(())
+                }
+                Some("standard_concept") => {
+                    row.standard_concept = non_empty(value);
+                    Ok(())
+                }
+                Some("concept_code") => {
+                    row.concept_code = non_empty(value);
+                    Ok(())
+                }
+                Some("invalid_reason") => {
+                    row.invalid_reason = non_empty(value);
+                    Ok(())
+                }
+                Some("is_excluded") => parse_flag(value).map(|flag| row.is_excluded = flag),
+                Some("include_descendants") => {
+                    parse_flag(value).map(|flag| row.include_descendants = flag)
+                }
+                Some("include_mapped") => parse_flag(value).map(|flag| row.include_mapped = flag),
+                _ => Ok(()),
+            };
+            if let Err(e) = parsed {
+                row_error = Some(e);
+                break;
+            }
+        }
+
+        match row_error {
+            Some(e) => warnings.push(format!("Skipping line {}: {}", line, e)),
+            None => rows.push(row),
+        }
+    }
+
+    info!(
+        "Parsed {} concept set rows from {:?} CSV",
+        rows.len(),
+        layout
+    );
+    Ok(ParsedCsv {
+        layout,
+        rows,
+        warnings,
+    })
+}
+
+/// Turns parsed rows into ATLAS-shaped concept sets, filling in any concept metadata the file
+/// does not carry from the vocabulary tables.
+pub async fn build_concept_sets(
+    parsed: ParsedCsv,
+    pg_client: &Client,
+) -> Result<ImportResult, PgError> {
+    let ParsedCsv {
+        layout,
+        rows,
+        mut warnings,
+    } = parsed;
+
+    let mut concept_ids: Vec<i32> = rows.iter().map(|row| row.concept_id).collect();
+    concept_ids.sort();
+    concept_ids.dedup();
+    let vocabulary: HashMap<i32, Concept> = db::get_concepts_by_ids(pg_client, &concept_ids)
+        .await?
+        .into_iter()
+        .map(|concept| (concept.concept_id, concept))
+        .collect();
+
+    // Keep the concept sets in the order they first appear in the file
+    let mut order: Vec<(Option<i32>, Option<String>)> = Vec::new();
+    let mut grouped: BTreeMap<usize, Vec<ConceptSetItem>> = BTreeMap::new();
+
+    for row in rows {
+        let key = match layout {
+            CsvLayout::Capr => (None, None),
+            CsvLayout::PhenotypeLibrary => (row.concept_set_id, row.concept_set_name.clone()),
+        };
+        let position = match order.iter().position(|k| k.0 == key.0 && k.1 == key.1) {
+            Some(position) => position,
+            None => {
+                order.push(key);
+                order.len() - 1
+            }
+        };
+
+        let known = vocabulary.get(&row.concept_id);
+        if known.is_none() && row.concept_name.is_none() {
+            warnings.push(format!(
+                "Concept {} was not found in the vocabulary and the file does not describe it",
+                row.concept_id
+            ));
+            continue;
+        }
+
+        let concept = Concept {
+            concept_id: row.concept_id,
+            concept_name: row
+                .concept_name
+                .or_else(|| known.map(|c| c.concept_name.clone()))
+                .unwrap_or_default(),
+            vocabulary_id: row
+                .vocabulary_id
+                .or_else(|| known.map(|c| c.vocabulary_id.clone()))
+                .unwrap_or_default(),
+            domain_id: row
+                .domain_id
+                .or_else(|| known.map(|c| c.domain_id.clone()))
+                .unwrap_or_default(),
+            concept_class_id: row
+                .concept_class_id
+                .or_else(|| known.map(|c| c.concept_class_id.clone()))
+                .unwrap_or_default(),
+            standard_concept: row
+                .standard_concept
+                .or_else(|| known.and_then(|c| c.standard_concept.clone())),
+            invalid_reason: row
+                .invalid_reason
+                .or_else(|| known.and_then(|c| c.invalid_reason.clone())),
+            concept_code: row
+                .concept_code
+                .or_else(|| known.map(|c| c.concept_code.clone()))
+                .unwrap_or_default(),
+            valid_start_date: known.and_then(|c| c.valid_start_date),
+            valid_end_date: known.and_then(|c| c.valid_end_date),
+        };
+
+        grouped.entry(position).or_default().push(ConceptSetItem {
+            concept,
+            is_excluded: row.is_excluded,
+            include_descendants: row.include_descendants,
+            include_mapped: row.include_mapped,
+        });
+    }
+
+    let concept_sets = grouped
+        .into_iter()
+        .map(|(position, items)| {
+            let (id, name) = order[position].clone();
+            ConceptSetWithMetadata {
+                id,
+                name,
+                expression: ConceptSetExpression { items },
+            }
+        })
+        .collect();
+
+    Ok(ImportResult {
+        layout,
+        concept_sets,
+        warnings,
+    })
+}
@@ -0,0 +1,145 @@
+use crate::concept_index::ConceptIndexBuilder;
+use crate::config::Configs;
+use crate::db;
+use crate::domain::{Concept, IngestName};
+use crate::embeddings::Embedder;
+use crate::errors::EmbeddingError;
+use crate::qdrant::{ensure_collection, stable_point_id};
+use actix_web::rt::time::sleep;
+use log::{info, warn};
+use qdrant_client::qdrant::{PointStruct, UpsertPointsBuilder};
+use qdrant_client::{Payload, Qdrant};
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::{Duration, Instant};
+use tokio_postgres::NoTls;
+
+/// Attempts per embedding batch before the ingest gives up; rate limits are common on long runs.
+const EMBEDDING_ATTEMPTS: u32 = 4;
+
+/// Builds the concept collection the API searches from `cdm.concept` and `cdm.concept_synonym`:
+/// one point per distinct lowercase name, carrying every concept with that name, plus the concept
+/// index file at `VECTORDB_DATA_PATH`. Point ids are derived from the name, so running it again
+/// after a vocabulary update refreshes the collection in place.
+pub async fn run(config: &Configs) -> Result<(), Box<dyn Error>> {
+    let collection = config
+        .ingest
+        .collection
+        .clone()
+        .unwrap_or_else(|| config.qdrant_collection.clone());
+    let batch_size = config.ingest.batch_size.max(1);
+    info!(
+        "Ingesting vocabularies {:?} into {} (synonyms: {})",
+        config.ingest.vocabulary_ids, collection, config.ingest.include_synonyms
+    );
+
+    let pg_pool = config.pg.create_pool(None, NoTls)?;
+    let pg_client = pg_pool.get().await?;
+    let qdrant_client = Qdrant::from_url(&config.qdrant_uri).build()?;
+    let embedder = Embedder::from_config(&config.embedding)?;
+    if ensure_collection(
+        &qdrant_client,
+        &collection,
+        embedder.dimensions(),
+        &[
+            "concept_name_lower",
+            "concepts[].vocabulary_id",
+            "concepts[].domain_id",
+            "concepts[].concept_class_id",
+            "concepts[].standard_concept",
+        ],
+    )
+    .await?
+    {
+        info!("Created collection {}", collection);
+    }
+
+    let names = db::get_ingest_names(
+        &pg_client,
+        &config.ingest.vocabulary_ids,
+        config.ingest.include_synonyms,
+    )
+    .await?;
+    info!("{} distinct names to ingest", names.len());
+
+    let mut concept_index = ConceptIndexBuilder::default();
+    let started = Instant::now();
+    for (batch_number, batch) in names.chunks(batch_size).enumerate() {
+        let concept_ids: Vec<i32> = batch
+            .iter()
+            .flat_map(|name| name.concept_ids.iter().copied())
+            .collect();
+        let concepts: HashMap<i32, Concept> = db::get_concepts_by_ids(&pg_client, &concept_ids)
+            .await?
+            .into_iter()
+            .map(|concept| (concept.concept_id, concept))
+            .collect();
+        let vectors = embed_with_retry(batch, &embedder).await?;
+
+        let mut points = Vec::with_capacity(batch.len());
+        for (name, vector) in batch.iter().zip(vectors) {
+            let id = stable_point_id(&name.concept_name_lower);
+            let payload = serde_json::json!({
+                "concept_name": name.concept_name,
+                "concept_name_lower": name.concept_name_lower,
+                "concepts": name
+                    .concept_ids
+                    .iter()
+                    .filter_map(|concept_id| concepts.get(concept_id))
+                    .collect::<Vec<_>>(),
+            });
+            points.push(PointStruct::new(
+                id.to_string(),
+                vector,
+                Payload::try_from(payload)?,
+            ));
+            concept_index.insert(&name.concept_name_lower, id);
+        }
+        qdrant_client
+            .upsert_points(UpsertPointsBuilder::new(collection.as_str(), points).wait(true))
+            .await?;
+
+        let done = ((batch_number + 1) * batch_size).min(names.len());
+        info!(
+            "Ingested {}/{} names in {:.0}s",
+            done,
+            names.len(),
+            started.elapsed().as_secs_f64()
+        );
+    }
+
+    concept_index.write(&config.vectordb_data_path)?;
+    info!(
+        "Wrote concept index with {} names to {}",
+        concept_index.name_count(),
+        config.vectordb_data_path
+    );
+    Ok(())
+}
+
+/// Embeds a batch, backing off on transient failures. Mismatching embeddings are not retried,
+/// they would poison the collection.
+async fn embed_with_retry(
+    batch: &[IngestName],
+    embedder: &Embedder,
+) -> Result<Vec<Vec<f32>>, Box<dyn Error>> {
+    let inputs: Vec<String> = batch.iter().map(|name| name.concept_name.clone()).collect();
+    let mut attempt = 1;
+    loop {
+        match embedder.embed_batch(inputs.clone()).await {
+            Ok(vectors) => return Ok(vectors),
+            Err(e) if attempt < EMBEDDING_ATTEMPTS && !e.is::<EmbeddingError>() => {
+                let backoff = Duration::from_secs(2u64.pow(attempt));
+                warn!(
+                    "Embedding attempt {} failed, retrying in {}s: {}",
+                    attempt,
+                    backoff.as_secs(),
+                    e
+                );
+                sleep(backoff).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
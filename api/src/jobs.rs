@@ -0,0 +1,166 @@
+use crate::api::analyze_with_state;
+use crate::auth::Identity;
+use crate::domain::ValidationJob;
+use crate::errors::PgError;
+use crate::profiles::ValidationProfile;
+use crate::validation::{self, Progress};
+use crate::{StateWrapper, db};
+use actix_web::rt::time::timeout;
+use actix_web::web::{Data, Json, ReqData};
+use actix_web::{Error, HttpResponse, get, post, web};
+use chrono::Utc;
+use log::{info, warn};
+use serde::Deserialize;
+use std::time::Duration;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(Deserialize, ToSchema)]
+struct ValidationJobRequest {
+    /// ATLAS concept set JSON.
+    concept_set: String,
+    #[serde(default)]
+    profile: ValidationProfile,
+}
+
+/// Analyzes the concept set once a slot is free and records every stage it reaches, so
+/// analyses too large for one HTTP request can be polled instead.
+async fn run_job(
+    state: Data<StateWrapper>,
+    id: Uuid,
+    concept_set: String,
+    profile: ValidationProfile,
+) {
+    let budget = Duration::from_secs(state.config.jobs.timeout_secs);
+    let (progress, mut stages) = Progress::channel();
+    let analysis = async {
+        let _slot = state.job_slots.acquire().await;
+        let mut result = analyze_with_state(&state, &concept_set, profile, progress).await?;
+        // Ends the stage updates once the analysis is done
+        result.progress = Progress::default();
+        Ok::<_, Error>(result)
+    };
+    let record_stages = async {
+        while let Some(stage) = stages.recv().await {
+            let Ok(pg_client) = state.pg_pool.get().await else {
+                continue;
+            };
+            if let Err(e) =
+                db::update_validation_job_stage(&pg_client, id, stage.as_str(), stage.fraction())
+                    .await
+            {
+                warn!(
+                    "Could not record the progress of validation job {}: {}",
+                    id, e
+                );
+            }
+        }
+    };
+    let (outcome, _) = futures::join!(timeout(budget, analysis), record_stages);
+
+    let (status, result, error) = match outcome {
+        Ok(Ok(result)) => ("succeeded", Some(result.to_json()), None),
+        Ok(Err(e)) => ("failed", None, Some(e.to_string())),
+        Err(_) => (
+            "failed",
+            None,
+            Some(format!(
+                "The job did not finish within {}s",
+                budget.as_secs()
+            )),
+        ),
+    };
+    info!("Validation job {} {}", id, status);
+    let finished = match state.pg_pool.get().await {
+        Ok(pg_client) => {
+            db::finish_validation_job(&pg_client, id, status, result.as_ref(), error.as_deref())
+                .await
+        }
+        Err(e) => Err(PgError::PoolError(e)),
+    };
+    if let Err(e) = finished {
+        warn!(
+            "Could not record the outcome of validation job {}: {}",
+            id, e
+        );
+    }
+}
+
+/// Queues a concept set analysis and answers `202 Accepted` with the job to poll.
+#[utoipa::path(
+    tag = "jobs",
+    summary = "Queue a concept set analysis",
+    description = "Runs the concept set analysis in the background for sets too large to analyze within one \
+        request. Poll the job at the `Location` header until its status is `succeeded` or `failed`.",
+    responses(
+        (status = 202, description = "The queued job", body = ValidationJob,
+            headers(("Location" = String, description = "The job to poll"))),
+        (status = 400, description = "The concept set could not be parsed"),
+    )
+)]
+#[post("/api/validate/jobs")]
+async fn create_validation_job(
+    request: Json<ValidationJobRequest>,
+    identity: Option<ReqData<Identity>>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    if let Err(e) = validation::parse_concept_set(&request.concept_set) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+    }
+    let request = request.into_inner();
+    let created_by = identity.map(|identity| identity.into_inner().name);
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    match db::delete_expired_validation_jobs(&pg_client, state.config.jobs.retention_hours).await {
+        Ok(0) => {}
+        Ok(deleted) => info!("Removed {} expired validation jobs", deleted),
+        Err(e) => warn!("Could not remove expired validation jobs: {}", e),
+    }
+    let id = Uuid::new_v4();
+    let job = db::insert_validation_job(
+        &pg_client,
+        id,
+        request.profile.as_str(),
+        created_by.as_deref(),
+    )
+    .await?;
+    info!("Queued validation job {}", id);
+    actix_web::rt::spawn(run_job(
+        state.clone(),
+        id,
+        request.concept_set,
+        request.profile,
+    ));
+    Ok(HttpResponse::Accepted()
+        .insert_header(("Location", format!("/api/validate/jobs/{}", id)))
+        .json(job))
+}
+
+/// The job's status and stage, with the analysis result once it succeeded. Jobs left unfinished
+/// past the timeout, e.g. because their replica restarted, are reported as failed.
+#[utoipa::path(
+    tag = "jobs",
+    summary = "Get a validation job",
+    description = "The job's status and progress, with the analysis result once it succeeded. Jobs are kept for \
+        `JOBS__RETENTION_HOURS`.",
+    params(("id" = Uuid, Path, description = "Job id")),
+    responses(
+        (status = 200, description = "The job", body = ValidationJob),
+        (status = 404, description = "No such job"),
+    )
+)]
+#[get("/api/validate/jobs/{id}")]
+async fn get_validation_job(
+    path: web::Path<Uuid>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let mut job: ValidationJob = db::get_validation_job(&pg_client, path.into_inner()).await?;
+    let deadline = job.created_at
+        + chrono::Duration::seconds(state.config.jobs.timeout_secs as i64)
+        + chrono::Duration::minutes(1);
+    if job.finished_at.is_none() && Utc::now() > deadline {
+        job.status = "failed".to_string();
+        job.error = Some("The job was interrupted before it finished".to_string());
+    }
+    Ok(HttpResponse::Ok().json(job))
+}
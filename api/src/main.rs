@@ -1,59 +1,176 @@
 mod api;
+mod auth;
+mod autocomplete;
+mod boosting;
+mod catalog;
+mod code_systems;
+mod codesets;
+mod concept_codes;
 mod concept_graph;
+mod concept_index;
+mod concept_sets;
 mod config;
+mod curation;
 mod db;
+mod debug;
+mod demo;
+mod docs;
 mod domain;
 mod embeddings;
 mod errors;
+mod expand;
+mod expansions;
+mod fhir;
+mod graphql;
+mod grpc;
+mod idempotency;
+mod import;
+mod ingest;
+mod jobs;
+mod mapping_export;
+mod mapping_reviews;
+mod metrics;
+mod ndjson;
+mod negative_controls;
+mod oidc;
+mod optimize;
+mod profiles;
+mod promotion;
 mod qdrant;
+mod query_operators;
+mod rate_limit;
+mod recommendations;
+mod review;
+mod search;
+mod shared_cache;
+mod slo;
+mod snapshot;
+mod source_mapping;
+mod spelling;
+mod tabular;
+mod telemetry;
 mod umls;
 mod utils;
 mod validation;
+mod vocabulary_diff;
+mod workbook;
 
 use crate::api::{
-    analyze_concept_set, get_concept_by_id, get_concept_definition, get_concept_phoebe,
-    get_concept_relationships, search,
+    analyze_concept_set, analyze_concept_set_stream, compare_concept_sets, export_codeset_sql,
+    get_concept_ancestors, get_concept_by_id, get_concept_definition, get_concept_descendants,
+    get_concept_mappings, get_concept_phoebe, get_concept_relationships, get_concept_synonyms,
+    get_validation_profiles, import_concept_sets, resolve_concept_set,
 };
-use crate::config::Configs;
+use crate::boosting::BoostingRules;
+use crate::catalog::VocabularyCatalog;
+use crate::config::{CacheConfig, Configs};
+use crate::curation::SynonymOverrides;
+use crate::embeddings::Embedder;
+use crate::metrics::Metrics;
+use crate::oidc::OidcVerifier;
+use crate::qdrant::ReadOptions;
+use crate::rate_limit::RateLimiter;
+use crate::search::{CachedSearch, SearchPipeline};
+use crate::shared_cache::SharedCache;
+use crate::slo::LatencyTracker;
+use crate::snapshot::IndexSnapshot;
+use crate::validation::RecommendationCache;
 use actix_cors::Cors;
+use actix_web::middleware::from_fn;
 use actix_web::web::Data;
 use actix_web::{App, HttpServer};
 use confik::{Configuration, EnvSource};
 use deadpool_postgres::Pool;
 use dotenvy::dotenv;
-use log::{LevelFilter, info};
+use log::{LevelFilter, info, warn};
+use moka::sync::Cache;
 use qdrant_client::Qdrant;
-use std::collections::HashMap;
 use std::error::Error;
-use std::fs;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::Semaphore;
 use tokio_postgres::NoTls;
-use uuid::Uuid;
 
 struct StateWrapper {
-    concept_index: HashMap<String, Vec<Uuid>>,
+    index_snapshot: RwLock<Arc<IndexSnapshot>>,
     pg_pool: Pool,
     qdrant_client: Qdrant,
+    qdrant_read_options: ReadOptions,
+    synonym_overrides: RwLock<SynonymOverrides>,
+    boosting_rules: RwLock<Arc<BoostingRules>>,
+    vocabulary_catalog: VocabularyCatalog,
+    search_pipeline: SearchPipeline,
+    search_cache: Cache<String, Arc<CachedSearch>>,
+    recommendation_cache: RecommendationCache,
+    shared_cache: SharedCache,
+    embedder: Embedder,
+    latency: LatencyTracker,
+    metrics: Metrics,
+    oidc: Option<OidcVerifier>,
+    rate_limiter: RateLimiter,
+    /// Validation jobs allowed to run at once, see `jobs`.
+    job_slots: Semaphore,
+    graphql_schema: graphql::HecateSchema,
+    config: Configs,
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    env_logger::builder().filter_level(LevelFilter::Info).init();
+    env_logger::builder()
+        .filter_level(LevelFilter::Info)
+        // Spans are exported by `telemetry`, not logged
+        .filter_module("tracing::span", LevelFilter::Warn)
+        .init();
     info!("Starting Hecate API!");
     info!("Init env");
     dotenv().ok();
 
+    // Prints the specification served at /api/openapi.yaml, no configuration needed
+    if std::env::args().nth(1).as_deref() == Some("openapi") {
+        print!("{}", docs::openapi_yaml());
+        return Ok(());
+    }
+
     info!("Load configs");
     let config = Configs::builder()
         .override_with(EnvSource::new())
         .try_build()
         .unwrap();
 
+    if std::env::args().nth(1).as_deref() == Some("ingest") {
+        return ingest::run(&config)
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()));
+    }
+    if std::env::args().nth(1).as_deref() == Some("convert-index") {
+        return concept_index::convert(&config.vectordb_data_path)
+            .map_err(|e| std::io::Error::other(e.to_string()));
+    }
+
+    telemetry::init(&config.telemetry);
+    auth::log_configuration(&config.auth);
     let state = create_state(&config).await.unwrap();
+    grpc::serve(state.clone());
+    shared_cache::watch_search_generation(state.clone());
 
     HttpServer::new(move || {
         let mut cors = Cors::default()
-            .allowed_methods(vec!["GET", "POST", "OPTIONS"])
-            .allowed_headers(vec!["Content-Type", "Authorization"])
+            .allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
+            .allowed_headers(vec![
+                "Content-Type",
+                "Authorization",
+                "Idempotency-Key",
+                "X-API-Key",
+                "traceparent",
+            ])
+            .expose_headers(vec![
+                "X-Total-Count",
+                "X-Next-Cursor",
+                "X-Vocab-Version",
+                "X-Expansion-Hash",
+                "X-Suggested-Query",
+                "X-Skipped-Rows",
+            ])
             .max_age(3600);
 
         for origin in &config.cors_origins {
@@ -61,13 +178,102 @@ async fn main() -> std::io::Result<()> {
         }
 
         App::new()
+            .wrap(from_fn(idempotency::idempotency_guard))
+            .wrap(from_fn(demo::demo_mode_guard))
+            .wrap(from_fn(slo::track_latency))
+            .wrap(from_fn(rate_limit::limit_requests))
+            .wrap(from_fn(auth::require_auth))
+            .wrap(from_fn(catalog::vocabulary_version_header))
+            .wrap(from_fn(telemetry::trace_requests))
             .wrap(cors)
-            .service(search)
+            .service(api::search)
+            .service(api::search_batch)
+            .service(autocomplete::autocomplete)
             .service(get_concept_by_id)
             .service(get_concept_relationships)
+            .service(get_concept_ancestors)
+            .service(get_concept_descendants)
+            .service(get_concept_synonyms)
+            .service(get_concept_mappings)
             .service(get_concept_definition)
             .service(get_concept_phoebe)
             .service(analyze_concept_set)
+            .service(analyze_concept_set_stream)
+            .service(get_validation_profiles)
+            .service(import_concept_sets)
+            .service(export_codeset_sql)
+            .service(api::export_concept_set_xlsx)
+            .service(resolve_concept_set)
+            .service(compare_concept_sets)
+            .service(review::review_concept_set)
+            .service(recommendations::recommend_concepts)
+            .service(recommendations::record_feedback)
+            .service(negative_controls::suggest_negative_controls)
+            .service(optimize::optimize_concept_set)
+            .service(concept_sets::list_concept_sets)
+            .service(concept_sets::create_concept_set)
+            .service(concept_sets::get_concept_set)
+            .service(concept_sets::update_concept_set)
+            .service(concept_sets::delete_concept_set)
+            .service(concept_sets::list_concept_set_versions)
+            .service(concept_sets::get_concept_set_version)
+            .service(concept_sets::analyze_stored_concept_set)
+            .service(concept_sets::get_concept_set_impact)
+            .service(jobs::create_validation_job)
+            .service(jobs::get_validation_job)
+            .service(source_mapping::create_mapping_job)
+            .service(source_mapping::get_mapping_job)
+            .service(source_mapping::get_mapping_job_results)
+            .service(source_mapping::delete_mapping_job)
+            .service(mapping_reviews::review_mapping_row)
+            .service(mapping_reviews::list_mapping_row_comments)
+            .service(mapping_reviews::comment_on_mapping_row)
+            .service(mapping_export::export_mappings)
+            .service(expand::expand_concepts)
+            .service(expansions::get_expansion)
+            .service(curation::list_zero_result_queries)
+            .service(curation::add_zero_result_overrides)
+            .service(curation::set_zero_result_status)
+            .service(curation::list_synonyms)
+            .service(curation::create_synonym)
+            .service(curation::update_synonym)
+            .service(curation::delete_synonym)
+            .service(catalog::list_vocabularies)
+            .service(catalog::list_domains)
+            .service(catalog::list_concept_classes)
+            .service(catalog::get_version)
+            .service(vocabulary_diff::get_vocabulary_diff)
+            .service(concept_codes::get_concepts_by_code)
+            .service(concept_codes::get_concepts_by_codes)
+            .service(fhir::lookup_code)
+            .service(fhir::expand_value_set)
+            .service(fhir::validate_code)
+            .service(fhir::translate_code)
+            .service(graphql::graphql)
+            .service(concept_graph::get_concept_classes)
+            .service(concept_graph::get_vocabulary_roots)
+            .service(concept_graph::get_concept_hierarchy)
+            .service(snapshot::get_index_snapshot)
+            .service(snapshot::swap_index_snapshot)
+            .service(snapshot::reindex)
+            .service(boosting::get_boosting_rules)
+            .service(boosting::reload_boosting_rules)
+            .service(debug::export_search_bundle)
+            .service(promotion::export_state)
+            .service(promotion::import_state)
+            .service(slo::get_slo_status)
+            .service(metrics::get_metrics)
+            .service(slo::get_health)
+            .service(slo::get_readiness)
+            .service(docs::get_openapi_spec)
+            .service(docs::get_swagger_ui)
+            .service(docs::swagger_ui())
+            .service(code_systems::list_systems)
+            .service(code_systems::ingest_code_system)
+            .service(code_systems::get_mapping_candidates)
+            .service(auth::list_api_keys)
+            .service(auth::create_api_key)
+            .service(auth::revoke_api_key)
             .app_data(state.clone())
     })
     .bind(config.server_addr.clone())?
@@ -75,6 +281,29 @@ async fn main() -> std::io::Result<()> {
     .await
 }
 
+/// Complete search results, keyed by normalized query and filters. A capacity of 0 stores
+/// nothing, which is how `CACHE__ENABLED=false` turns the cache off.
+fn search_cache(config: &CacheConfig) -> Cache<String, Arc<CachedSearch>> {
+    let capacity = if config.enabled { config.capacity } else { 0 };
+    Cache::builder()
+        .max_capacity(capacity as u64)
+        .time_to_live(Duration::from_secs(config.ttl_secs))
+        .build()
+}
+
+/// The cache of concept set recommendations, sized and timed on its own.
+fn recommendation_cache(config: &CacheConfig) -> RecommendationCache {
+    let capacity = if config.enabled {
+        config.recommendation_capacity
+    } else {
+        0
+    };
+    Cache::builder()
+        .max_capacity(capacity as u64)
+        .time_to_live(Duration::from_secs(config.recommendation_ttl_secs))
+        .build()
+}
+
 async fn create_state(config: &Configs) -> Result<Data<StateWrapper>, Box<dyn Error>> {
     info!("Initializing Postgres pool");
     let pg_pool = config.pg.create_pool(None, NoTls)?;
@@ -85,33 +314,73 @@ async fn create_state(config: &Configs) -> Result<Data<StateWrapper>, Box<dyn Er
         .await
         .expect("Postgres test query failed");
 
+    if config.run_migrations {
+        info!("Applying Hecate schema migrations");
+        db::run_migrations(&mut pg_pool.get().await?).await?;
+    }
+
     info!("Initializing Qdrant client");
     let qdrant_client = Qdrant::from_url(&config.qdrant_uri).build()?;
+    let qdrant_read_options = ReadOptions::from_config(&config.qdrant_read)?;
     qdrant_client
         .health_check()
         .await
         .expect("Qdrant health check failed");
 
-    let concept_index = load_concept_index(&config.vectordb_data_path)?;
+    // The concept index is loaded once the server is up, see `snapshot::load_in_background`
+    let index_snapshot =
+        IndexSnapshot::loading(&config.qdrant_collection, &config.vectordb_data_path);
+
+    let synonym_overrides = curation::load_synonym_overrides(&pg_pool)
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Could not load synonym overrides: {}", e);
+            SynonymOverrides::new()
+        });
+
+    let vocabulary_catalog = VocabularyCatalog::load(&pg_pool).await.unwrap_or_else(|e| {
+        warn!(
+            "Could not load vocabulary catalog, filters will not be validated: {}",
+            e
+        );
+        VocabularyCatalog::default()
+    });
+
+    let shared_cache = SharedCache::from_config(
+        &config.cache,
+        vocabulary_catalog.vocabulary_version.as_deref(),
+    )?;
+
+    let embedder = Embedder::from_config(&config.embedding)?;
+
+    let boosting_rules = BoostingRules::load(config.boosting_rules_path.as_deref())?;
+
+    if config.demo.enabled {
+        info!("Demo mode enabled, state-changing endpoints are disabled");
+    }
 
     let state = Data::new(StateWrapper {
-        concept_index,
+        index_snapshot: RwLock::new(Arc::new(index_snapshot)),
         pg_pool,
         qdrant_client,
+        qdrant_read_options,
+        synonym_overrides: RwLock::new(synonym_overrides),
+        boosting_rules: RwLock::new(Arc::new(boosting_rules)),
+        vocabulary_catalog,
+        search_pipeline: SearchPipeline::from_config(&config.search),
+        search_cache: search_cache(&config.cache),
+        recommendation_cache: recommendation_cache(&config.cache),
+        shared_cache,
+        embedder,
+        latency: LatencyTracker::new(config.slo.clone()),
+        metrics: Metrics::default(),
+        oidc: OidcVerifier::from_config(&config.auth.oidc),
+        rate_limiter: RateLimiter::new(config.rate_limit_in_effect()),
+        job_slots: Semaphore::new(config.jobs.max_concurrent.max(1)),
+        graphql_schema: graphql::build_schema(),
+        config: config.clone(),
     });
     info!("App data loaded");
+    snapshot::load_in_background(state.clone());
     Ok(state)
 }
-
-fn load_concept_index(
-    vectordb_data_path: &str,
-) -> Result<HashMap<String, Vec<Uuid>>, Box<dyn Error>> {
-    info!(
-        "Load all concept-vector_ids map from file: {}",
-        vectordb_data_path
-    );
-    let bytes = fs::read_to_string(vectordb_data_path)?;
-    let value_ids_map: HashMap<String, Vec<Uuid>> = serde_json::from_str(&bytes)?;
-    info!("{} concept-vector_ids loaded", value_ids_map.len());
-    Ok(value_ids_map)
-}
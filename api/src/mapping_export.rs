@@ -0,0 +1,412 @@
+use crate::domain::Concept;
+use crate::errors::{ApiError, PgError};
+use crate::mapping_reviews::ReviewStatus;
+use crate::source_mapping::MAX_SOURCE_CODES;
+use crate::tabular::{self, Format};
+use crate::{StateWrapper, db};
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::web::{Data, Query};
+use actix_web::{Error, HttpRequest, HttpResponse, get, web};
+use chrono::NaiveDate;
+use futures::stream;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+/// Column widths of the CDM tables the export is loaded into.
+const MAX_VOCABULARY_ID_LENGTH: usize = 20;
+const MAX_CODE_LENGTH: usize = 50;
+const MAX_NAME_LENGTH: usize = 255;
+/// Concept ids from two billion on are reserved for site-specific concepts.
+const MIN_CONCEPT_ID_START: i32 = 2_000_000_000;
+const DEFAULT_CONCEPT_ID_START: i32 = 2_000_000_001;
+/// Leaves room for a concept id per row of the largest job.
+const MAX_CONCEPT_ID_START: i32 = i32::MAX - MAX_SOURCE_CODES as i32;
+const DEFAULT_CONCEPT_CLASS_ID: &str = "Undefined";
+const VALID_START_DATE: NaiveDate = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+const VALID_END_DATE: NaiveDate = NaiveDate::from_ymd_opt(2099, 12, 31).unwrap();
+/// The approved rows left out of an export, by row number.
+const SKIPPED_ROWS_HEADER: &str = "X-Skipped-Rows";
+
+const SOURCE_TO_CONCEPT_MAP_COLUMNS: &[&str] = &[
+    "source_code",
+    "source_concept_id",
+    "source_vocabulary_id",
+    "source_code_description",
+    "target_concept_id",
+    "target_vocabulary_id",
+    "valid_start_date",
+    "valid_end_date",
+    "invalid_reason",
+];
+
+const CONCEPT_COLUMNS: &[&str] = &[
+    "concept_id",
+    "concept_name",
+    "domain_id",
+    "vocabulary_id",
+    "concept_class_id",
+    "standard_concept",
+    "concept_code",
+    "valid_start_date",
+    "valid_end_date",
+    "invalid_reason",
+];
+
+const CONCEPT_RELATIONSHIP_COLUMNS: &[&str] = &[
+    "concept_id_1",
+    "concept_id_2",
+    "relationship_id",
+    "valid_start_date",
+    "valid_end_date",
+    "invalid_reason",
+];
+
+/// The CDM table to export to. `source_to_concept_map` is what most ETLs read, the `concept`
+/// and `concept_relationship` staging tables add the source codes to the vocabulary instead.
+#[derive(Clone, Copy, Debug, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum Table {
+    #[default]
+    SourceToConceptMap,
+    Concept,
+    ConceptRelationship,
+}
+
+impl Table {
+    fn as_str(self) -> &'static str {
+        match self {
+            Table::SourceToConceptMap => "source_to_concept_map",
+            Table::Concept => "concept",
+            Table::ConceptRelationship => "concept_relationship",
+        }
+    }
+
+    fn columns(self) -> &'static [&'static str] {
+        match self {
+            Table::SourceToConceptMap => SOURCE_TO_CONCEPT_MAP_COLUMNS,
+            Table::Concept => CONCEPT_COLUMNS,
+            Table::ConceptRelationship => CONCEPT_RELATIONSHIP_COLUMNS,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ExportParameters {
+    /// The vocabulary the source codes are loaded as, e.g. `LOCAL_LAB`.
+    #[param(max_length = 20)]
+    source_vocabulary_id: String,
+    #[serde(default)]
+    #[param(inline)]
+    table: Table,
+    /// `csv`, `tsv` or `sql` for INSERT statements.
+    #[param(default = "csv")]
+    format: Option<String>,
+    /// The first concept id given to the source codes of the staging tables.
+    #[param(default = 2000000001, minimum = 2000000000)]
+    concept_id_start: Option<i32>,
+    /// The concept class of the source codes in the staging `concept` table.
+    #[param(default = "Undefined")]
+    concept_class_id: Option<String>,
+}
+
+/// An approved row with the concept it maps to.
+struct ApprovedMapping {
+    row_number: i32,
+    source_code: String,
+    source_name: String,
+    target: Concept,
+}
+
+#[derive(Serialize)]
+struct SourceToConceptMapRow {
+    source_code: String,
+    source_concept_id: i32,
+    source_vocabulary_id: String,
+    source_code_description: String,
+    target_concept_id: i32,
+    target_vocabulary_id: String,
+    valid_start_date: NaiveDate,
+    valid_end_date: NaiveDate,
+    invalid_reason: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ConceptRow {
+    concept_id: i32,
+    concept_name: String,
+    domain_id: String,
+    vocabulary_id: String,
+    concept_class_id: String,
+    standard_concept: Option<String>,
+    concept_code: String,
+    valid_start_date: NaiveDate,
+    valid_end_date: NaiveDate,
+    invalid_reason: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ConceptRelationshipRow {
+    concept_id_1: i32,
+    concept_id_2: i32,
+    relationship_id: &'static str,
+    valid_start_date: NaiveDate,
+    valid_end_date: NaiveDate,
+    invalid_reason: Option<String>,
+}
+
+fn truncated(text: &str, length: usize) -> String {
+    text.chars().take(length).collect()
+}
+
+/// A value of an INSERT statement, the way PostgreSQL and most other databases read it.
+fn sql_literal(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => "NULL".to_string(),
+        serde_json::Value::Number(number) => number.to_string(),
+        serde_json::Value::String(text) => format!("'{}'", text.replace('\'', "''")),
+        other => format!("'{}'", other.to_string().replace('\'', "''")),
+    }
+}
+
+/// One INSERT statement per row, in the order of `columns`.
+fn insert_statements<T: Serialize>(table: Table, rows: &[T]) -> String {
+    let columns = table.columns();
+    let mut script = String::new();
+    for row in rows {
+        let row = serde_json::to_value(row).unwrap_or_default();
+        let values: Vec<String> = columns
+            .iter()
+            .map(|column| sql_literal(&row[*column]))
+            .collect();
+        script.push_str(&format!(
+            "INSERT INTO {} ({}) VALUES ({});\n",
+            table.as_str(),
+            columns.join(", "),
+            values.join(", ")
+        ));
+    }
+    script
+}
+
+fn respond<T: Serialize + 'static>(
+    table: Table,
+    format: Option<Format>,
+    rows: Vec<T>,
+    skipped: &[i32],
+) -> HttpResponse {
+    let extension = match format {
+        Some(Format::Csv) => "csv",
+        Some(Format::Tsv) => "tsv",
+        None => "sql",
+    };
+    let mut response = HttpResponse::Ok();
+    response.insert_header(ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![DispositionParam::Filename(format!(
+            "{}.{}",
+            table.as_str(),
+            extension
+        ))],
+    });
+    if !skipped.is_empty() {
+        let skipped: Vec<String> = skipped.iter().map(i32::to_string).collect();
+        response.insert_header((SKIPPED_ROWS_HEADER, skipped.join(",")));
+    }
+    match format {
+        Some(format) => tabular::respond(
+            response,
+            format,
+            table.columns(),
+            stream::iter(rows.into_iter().map(Ok::<_, Infallible>)),
+        ),
+        None => response
+            .content_type("application/sql; charset=utf-8")
+            .body(insert_statements(table, &rows)),
+    }
+}
+
+/// The approved rows of a mapping job as a CDM table, ready to load into a CDM build: the
+/// `source_to_concept_map`, or the `concept` and `concept_relationship` staging tables that
+/// add the source codes as non-standard concepts mapping to their targets. Rows without a
+/// source code, or with one longer than the CDM allows, cannot be loaded, and rows approved
+/// with a target that is not a valid standard concept, e.g. since a vocabulary update, must not
+/// be. Both are left out and their row numbers listed in the `X-Skipped-Rows` header.
+#[utoipa::path(
+    tag = "mapping",
+    summary = "Export the approved mappings of a job",
+    description = "The approved rows as a CDM `source_to_concept_map`, or as the `concept` and \
+        `concept_relationship` staging tables that add the source codes as non-standard concepts. Rows without a \
+        source code or with one longer than 50 characters, and rows whose target is no longer a valid standard \
+        concept, are left out and listed in the `X-Skipped-Rows` header.",
+    params(("id" = Uuid, Path, description = "Job id"), ExportParameters),
+    responses(
+        (status = 200, description = "The table, as an attachment",
+            headers(("X-Skipped-Rows" = String, description = "The comma-separated row numbers of approved rows \
+                left out of the export, absent when none are")),
+            content(
+                (String = "text/csv"),
+                (String = "text/tab-separated-values"),
+                (String = "application/sql"),
+            )
+        ),
+        (status = 400, description = "A missing or too long source_vocabulary_id, or a concept_id_start out of range"),
+        (status = 404, description = "No such job"),
+        (status = 409, description = "The job has not succeeded (yet)"),
+    )
+)]
+#[get("/api/mapping/jobs/{id}/export")]
+async fn export_mappings(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    parameters: Query<ExportParameters>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    let parameters = parameters.into_inner();
+    let source_vocabulary_id = parameters.source_vocabulary_id.trim().to_string();
+    if source_vocabulary_id.is_empty() || source_vocabulary_id.len() > MAX_VOCABULARY_ID_LENGTH {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!(
+                "source_vocabulary_id needs 1 to {} characters",
+                MAX_VOCABULARY_ID_LENGTH
+            )
+        })));
+    }
+    let format = match parameters.format.as_deref() {
+        Some(format) if format.eq_ignore_ascii_case("sql") => None,
+        _ => Some(tabular::requested(&req).unwrap_or(Format::Csv)),
+    };
+    let concept_id_start = parameters
+        .concept_id_start
+        .unwrap_or(DEFAULT_CONCEPT_ID_START);
+    if !(MIN_CONCEPT_ID_START..=MAX_CONCEPT_ID_START).contains(&concept_id_start) {
+        return Err(ApiError::OutOfRange {
+            parameter: "concept_id_start",
+            min: MIN_CONCEPT_ID_START as f64,
+            max: MAX_CONCEPT_ID_START as f64,
+        }
+        .into());
+    }
+
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    if db::get_mapping_job(&pg_client, id).await?.status != "succeeded" {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "The job has no results, poll it until it succeeded"
+        })));
+    }
+    let rows =
+        db::get_mapping_job_rows(&pg_client, id, Some(ReviewStatus::Approved.as_str()), None)
+            .await?;
+    let approved: HashMap<i32, i32> = db::get_mapping_reviews(&pg_client, id)
+        .await?
+        .into_iter()
+        .filter(|review| review.status == ReviewStatus::Approved.as_str())
+        .filter_map(|review| Some((review.row_number, review.concept_id?)))
+        .collect();
+    let concept_ids: Vec<i32> = approved.values().copied().collect();
+    let targets: HashMap<i32, Concept> = db::get_concepts_by_ids(&pg_client, &concept_ids)
+        .await?
+        .into_iter()
+        .map(|concept| (concept.concept_id, concept))
+        .collect();
+    let mut mappings = Vec::new();
+    let mut skipped = Vec::new();
+    for code in rows {
+        let Some(concept_id) = approved.get(&code.row_number) else {
+            continue;
+        };
+        let loadable =
+            !code.source_code.is_empty() && code.source_code.chars().count() <= MAX_CODE_LENGTH;
+        match targets.get(concept_id) {
+            Some(target)
+                if loadable
+                    && target.standard_concept.as_deref() == Some("S")
+                    && target.invalid_reason.is_none() =>
+            {
+                mappings.push(ApprovedMapping {
+                    row_number: code.row_number,
+                    source_code: code.source_code,
+                    source_name: code.source_name,
+                    target: target.clone(),
+                });
+            }
+            _ => skipped.push(code.row_number),
+        }
+    }
+    info!(
+        "Exporting {} of {} approved mappings of job {} as {}, skipping rows {:?}",
+        mappings.len(),
+        approved.len(),
+        id,
+        parameters.table.as_str(),
+        skipped
+    );
+
+    // Staging concepts keep the row's id, so exports of a job agree with each other
+    let source_concept_id = |mapping: &ApprovedMapping| concept_id_start + mapping.row_number - 1;
+    Ok(match parameters.table {
+        Table::SourceToConceptMap => {
+            let rows: Vec<SourceToConceptMapRow> = mappings
+                .into_iter()
+                .map(|mapping| SourceToConceptMapRow {
+                    source_code: mapping.source_code,
+                    source_concept_id: 0,
+                    source_vocabulary_id: source_vocabulary_id.clone(),
+                    source_code_description: truncated(&mapping.source_name, MAX_NAME_LENGTH),
+                    target_concept_id: mapping.target.concept_id,
+                    target_vocabulary_id: mapping.target.vocabulary_id,
+                    valid_start_date: VALID_START_DATE,
+                    valid_end_date: VALID_END_DATE,
+                    invalid_reason: None,
+                })
+                .collect();
+            respond(parameters.table, format, rows, &skipped)
+        }
+        Table::Concept => {
+            let concept_class_id = parameters
+                .concept_class_id
+                .unwrap_or_else(|| DEFAULT_CONCEPT_CLASS_ID.to_string());
+            let rows: Vec<ConceptRow> = mappings
+                .iter()
+                .map(|mapping| ConceptRow {
+                    concept_id: source_concept_id(mapping),
+                    concept_name: truncated(&mapping.source_name, MAX_NAME_LENGTH),
+                    domain_id: mapping.target.domain_id.clone(),
+                    vocabulary_id: source_vocabulary_id.clone(),
+                    concept_class_id: concept_class_id.clone(),
+                    standard_concept: None,
+                    concept_code: mapping.source_code.clone(),
+                    valid_start_date: VALID_START_DATE,
+                    valid_end_date: VALID_END_DATE,
+                    invalid_reason: None,
+                })
+                .collect();
+            respond(parameters.table, format, rows, &skipped)
+        }
+        Table::ConceptRelationship => {
+            let rows: Vec<ConceptRelationshipRow> = mappings
+                .iter()
+                .flat_map(|mapping| {
+                    let (source, target) = (source_concept_id(mapping), mapping.target.concept_id);
+                    [(source, target, "Maps to"), (target, source, "Mapped from")]
+                })
+                .map(
+                    |(concept_id_1, concept_id_2, relationship_id)| ConceptRelationshipRow {
+                        concept_id_1,
+                        concept_id_2,
+                        relationship_id,
+                        valid_start_date: VALID_START_DATE,
+                        valid_end_date: VALID_END_DATE,
+                        invalid_reason: None,
+                    },
+                )
+                .collect();
+            respond(parameters.table, format, rows, &skipped)
+        }
+    })
+}
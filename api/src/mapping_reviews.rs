@@ -0,0 +1,217 @@
+use crate::auth::Identity;
+use crate::concept_sets::author;
+use crate::domain::{MappingReview, MappingReviewChange, MappingReviewComment};
+use crate::errors::PgError;
+use crate::{StateWrapper, db};
+use actix_web::web::{Data, Json, ReqData};
+use actix_web::{Error, HttpResponse, get, post, put, web};
+use log::info;
+use serde::Deserialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Comments are notes between reviewers, not documents.
+const MAX_COMMENT_LENGTH: usize = 4000;
+
+/// Where the review of a row of a mapping job stands.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewStatus {
+    Unreviewed,
+    Approved,
+    Flagged,
+}
+
+impl ReviewStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReviewStatus::Unreviewed => "unreviewed",
+            ReviewStatus::Approved => "approved",
+            ReviewStatus::Flagged => "flagged",
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+struct ReviewRequest {
+    status: Option<ReviewStatus>,
+    /// The concept the source code maps to, required to approve a row. Only valid standard
+    /// concepts can be mapped to.
+    concept_id: Option<i32>,
+    /// The reviewer the row is assigned to, an empty string unassigns it.
+    assigned_to: Option<String>,
+    /// The reviewer when the request is not authenticated.
+    updated_by: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CommentRequest {
+    #[schema(max_length = 4000)]
+    body: String,
+    /// The author when the request is not authenticated.
+    created_by: Option<String>,
+}
+
+/// Refuses rows of jobs that have no results yet, and rows past the end of the results.
+async fn unreviewable_row(
+    pg_client: &deadpool_postgres::Client,
+    job_id: Uuid,
+    row_number: i32,
+) -> Result<Option<HttpResponse>, PgError> {
+    let job = db::get_mapping_job(pg_client, job_id).await?;
+    if job.status != "succeeded" {
+        return Ok(Some(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Only the rows of succeeded jobs can be reviewed"
+        }))));
+    }
+    if !(1..=job.source_codes).contains(&row_number) {
+        return Ok(Some(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("The job has rows 1 to {}", job.source_codes)
+        }))));
+    }
+    Ok(None)
+}
+
+/// Sets the status, mapped concept or assignee of a row of a mapping job, keeping the fields
+/// the request leaves out.
+#[utoipa::path(
+    tag = "mapping",
+    summary = "Review a row of a mapping job",
+    description = "Sets the review status, mapped concept or assignee of a source code. Fields left out keep \
+        their value.",
+    params(
+        ("id" = Uuid, Path, description = "Job id"),
+        ("row" = i32, Path, description = "Row number of the results, from 1", minimum = 1),
+    ),
+    responses(
+        (status = 200, description = "The review state of the row", body = MappingReview),
+        (status = 400, description = "Approving without a concept, or an unknown, invalid or non-standard concept"),
+        (status = 404, description = "No such job or row"),
+        (status = 409, description = "The job has not succeeded"),
+    )
+)]
+#[put("/api/mapping/jobs/{id}/rows/{row}/review")]
+async fn review_mapping_row(
+    path: web::Path<(Uuid, i32)>,
+    request: Json<ReviewRequest>,
+    identity: Option<ReqData<Identity>>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let (job_id, row_number) = path.into_inner();
+    let request = request.into_inner();
+    if request.status == Some(ReviewStatus::Approved) && request.concept_id.is_none() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Approving a row needs the concept_id it maps to"
+        })));
+    }
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    if let Some(response) = unreviewable_row(&pg_client, job_id, row_number).await? {
+        return Ok(response);
+    }
+    if let Some(concept_id) = request.concept_id {
+        let error = match db::get_concepts_by_ids(&pg_client, &[concept_id])
+            .await?
+            .pop()
+        {
+            None => Some(format!(
+                "Concept {} does not exist in the vocabulary",
+                concept_id
+            )),
+            Some(concept) if concept.invalid_reason.is_some() => Some(format!(
+                "Concept {} is invalid and cannot be mapped to",
+                concept_id
+            )),
+            Some(concept) if concept.standard_concept.as_deref() != Some("S") => Some(format!(
+                "Concept {} is not a standard concept and cannot be mapped to",
+                concept_id
+            )),
+            Some(_) => None,
+        };
+        if let Some(error) = error {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": error })));
+        }
+    }
+    let updated_by = author(identity, request.updated_by.as_deref());
+    info!(
+        "Reviewing row {} of mapping job {}: {:?}",
+        row_number, job_id, request.status
+    );
+    let review = db::upsert_mapping_review(
+        &pg_client,
+        &MappingReviewChange {
+            job_id,
+            row_number,
+            status: request.status.map(ReviewStatus::as_str),
+            concept_id: request.concept_id,
+            assigned_to: request.assigned_to.as_deref().map(str::trim),
+            updated_by: updated_by.as_deref(),
+        },
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(review))
+}
+
+#[utoipa::path(
+    tag = "mapping",
+    summary = "List the comments on a row of a mapping job",
+    params(
+        ("id" = Uuid, Path, description = "Job id"),
+        ("row" = i32, Path, description = "Row number of the results, from 1"),
+    ),
+    responses((status = 200, description = "The comments, oldest first", body = Vec<MappingReviewComment>))
+)]
+#[get("/api/mapping/jobs/{id}/rows/{row}/comments")]
+async fn list_mapping_row_comments(
+    path: web::Path<(Uuid, i32)>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let (job_id, row_number) = path.into_inner();
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let comments = db::get_mapping_review_comments(&pg_client, job_id, row_number).await?;
+    Ok(HttpResponse::Ok().json(comments))
+}
+
+/// Adds a note to a row of a mapping job, e.g. why it was flagged.
+#[utoipa::path(
+    tag = "mapping",
+    summary = "Comment on a row of a mapping job",
+    params(
+        ("id" = Uuid, Path, description = "Job id"),
+        ("row" = i32, Path, description = "Row number of the results, from 1", minimum = 1),
+    ),
+    responses(
+        (status = 201, description = "The comment", body = MappingReviewComment),
+        (status = 400, description = "An empty or too long comment"),
+        (status = 404, description = "No such job or row"),
+        (status = 409, description = "The job has not succeeded"),
+    )
+)]
+#[post("/api/mapping/jobs/{id}/rows/{row}/comments")]
+async fn comment_on_mapping_row(
+    path: web::Path<(Uuid, i32)>,
+    request: Json<CommentRequest>,
+    identity: Option<ReqData<Identity>>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let (job_id, row_number) = path.into_inner();
+    let body = request.body.trim();
+    if body.is_empty() || body.chars().count() > MAX_COMMENT_LENGTH {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Comments need 1 to {} characters", MAX_COMMENT_LENGTH)
+        })));
+    }
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    if let Some(response) = unreviewable_row(&pg_client, job_id, row_number).await? {
+        return Ok(response);
+    }
+    let created_by = author(identity, request.created_by.as_deref());
+    let comment = db::insert_mapping_review_comment(
+        &pg_client,
+        job_id,
+        row_number,
+        body,
+        created_by.as_deref(),
+    )
+    .await?;
+    Ok(HttpResponse::Created().json(comment))
+}
@@ -0,0 +1,236 @@
+use crate::StateWrapper;
+use actix_web::web::Data;
+use actix_web::{Error, HttpResponse, routes};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Histogram bucket bounds in seconds, from a cached index lookup to a slow embedding call.
+const BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Observations per bucket of `BUCKETS`, not cumulative.
+    counts: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.counts[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+
+    fn render(&self, body: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(self.counts) {
+            cumulative += count;
+            let _ = writeln!(
+                body,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, labels, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            body,
+            "{}_bucket{{{},le=\"+Inf\"}} {}",
+            name, labels, self.count
+        );
+        let _ = writeln!(body, "{}_sum{{{}}} {}", name, labels, self.sum);
+        let _ = writeln!(body, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+/// Lookups of an in-memory cache that found an entry and that did not.
+#[derive(Debug, Default)]
+pub struct CacheStats {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CacheStats {
+    /// Counts a lookup and passes its result through.
+    pub fn record<V>(&self, lookup: Option<V>) -> Option<V> {
+        let counter = if lookup.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        lookup
+    }
+
+    fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+/// Request counts and latency histograms per route, timings of the Postgres, Qdrant and
+/// embedding calls made while searching, and the use of the in-memory caches.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<(String, u16), u64>>,
+    request_durations: Mutex<BTreeMap<String, Histogram>>,
+    dependency_durations: Mutex<BTreeMap<(&'static str, String), Histogram>>,
+    pub search_cache: CacheStats,
+    pub recommendation_cache: CacheStats,
+}
+
+impl Metrics {
+    pub fn record_request(&self, endpoint: &str, status: u16, elapsed: Duration) {
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((endpoint.to_string(), status))
+            .or_default() += 1;
+        self.request_durations
+            .lock()
+            .unwrap()
+            .entry(endpoint.to_string())
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Records a call to a backing service; `operation` is the search stage that made it.
+    pub fn record_dependency(&self, dependency: &'static str, operation: &str, elapsed: Duration) {
+        self.dependency_durations
+            .lock()
+            .unwrap()
+            .entry((dependency, operation.to_string()))
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    fn render(&self, body: &mut String) {
+        let _ = writeln!(
+            body,
+            "# HELP hecate_http_requests_total Requests per route and status."
+        );
+        let _ = writeln!(body, "# TYPE hecate_http_requests_total counter");
+        for ((endpoint, status), count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(
+                body,
+                "hecate_http_requests_total{{endpoint=\"{}\",status=\"{}\"}} {}",
+                endpoint, status, count
+            );
+        }
+
+        let _ = writeln!(
+            body,
+            "# HELP hecate_http_request_duration_seconds Request latency per route."
+        );
+        let _ = writeln!(
+            body,
+            "# TYPE hecate_http_request_duration_seconds histogram"
+        );
+        for (endpoint, histogram) in self.request_durations.lock().unwrap().iter() {
+            histogram.render(
+                body,
+                "hecate_http_request_duration_seconds",
+                &format!("endpoint=\"{}\"", endpoint),
+            );
+        }
+
+        let _ = writeln!(
+            body,
+            "# HELP hecate_dependency_duration_seconds Postgres, Qdrant and embedding calls per search stage."
+        );
+        let _ = writeln!(body, "# TYPE hecate_dependency_duration_seconds histogram");
+        for ((dependency, operation), histogram) in self.dependency_durations.lock().unwrap().iter()
+        {
+            histogram.render(
+                body,
+                "hecate_dependency_duration_seconds",
+                &format!("dependency=\"{}\",operation=\"{}\"", dependency, operation),
+            );
+        }
+    }
+}
+
+fn render_cache(state: &StateWrapper, body: &mut String) {
+    let (cache, cache_stats) = (&state.search_cache, &state.metrics.search_cache);
+    let (recommendations, recommendation_stats) = (
+        &state.recommendation_cache,
+        &state.metrics.recommendation_cache,
+    );
+    for (name, kind, help, value) in [
+        (
+            "hecate_search_cache_hits_total",
+            "counter",
+            "Searches answered from the search cache.",
+            cache_stats.hits(),
+        ),
+        (
+            "hecate_search_cache_misses_total",
+            "counter",
+            "Searches that had to run the search pipeline.",
+            cache_stats.misses(),
+        ),
+        (
+            "hecate_search_cache_entries",
+            "gauge",
+            "Results currently held in the search cache.",
+            cache.entry_count(),
+        ),
+        (
+            "hecate_recommendation_cache_hits_total",
+            "counter",
+            "Concept set recommendations answered from the recommendation cache.",
+            recommendation_stats.hits(),
+        ),
+        (
+            "hecate_recommendation_cache_misses_total",
+            "counter",
+            "Concept set recommendations that had to query Qdrant.",
+            recommendation_stats.misses(),
+        ),
+        (
+            "hecate_recommendation_cache_entries",
+            "gauge",
+            "Concept sets whose recommendations are held in the recommendation cache.",
+            recommendations.entry_count(),
+        ),
+    ] {
+        let _ = writeln!(body, "# HELP {} {}", name, help);
+        let _ = writeln!(body, "# TYPE {} {}", name, kind);
+        let _ = writeln!(body, "{} {}", name, value);
+    }
+}
+
+/// Everything in the Prometheus text format: request counts and histograms, dependency timings,
+/// the SLO percentiles and the embedding health.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "operations",
+    summary = "Prometheus metrics",
+    description = "Request counts and latency histograms per route, Postgres, Qdrant and embedding call \
+        durations, cache statistics, SLO percentiles and embedding health in the Prometheus text format. \
+        Also served at `/api/metrics`.",
+    responses((status = 200, description = "Metrics", body = String, content_type = "text/plain"))
+)]
+#[routes]
+#[get("/metrics")]
+#[get("/api/metrics")]
+async fn get_metrics(state: Data<StateWrapper>) -> Result<HttpResponse, Error> {
+    let mut body = String::new();
+    state.metrics.render(&mut body);
+    render_cache(&state, &mut body);
+    crate::slo::render_metrics(&state, &mut body);
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body))
+}
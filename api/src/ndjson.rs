@@ -0,0 +1,40 @@
+use actix_web::http::header::{ACCEPT, CONTENT_TYPE};
+use actix_web::web::Bytes;
+use actix_web::{HttpRequest, HttpResponse, HttpResponseBuilder};
+use futures::{Stream, StreamExt};
+use log::warn;
+use serde::Serialize;
+use std::error::Error;
+
+pub const NDJSON: &str = "application/x-ndjson";
+
+/// Whether the client asked for newline-delimited JSON instead of a JSON document.
+pub fn accepted(req: &HttpRequest) -> bool {
+    req.headers()
+        .get_all(ACCEPT)
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| {
+            value
+                .split(',')
+                .any(|media| media.trim().starts_with(NDJSON))
+        })
+}
+
+/// Streams the rows one JSON object per line as they are produced. A row that fails ends the
+/// response early, the client sees a truncated body rather than a status code.
+pub fn respond<T, E, S>(mut response: HttpResponseBuilder, rows: S) -> HttpResponse
+where
+    T: Serialize,
+    E: Error + 'static,
+    S: Stream<Item = Result<T, E>> + 'static,
+{
+    let lines = rows.map(|row| {
+        let row = row.inspect_err(|e| warn!("Ending NDJSON response early: {}", e))?;
+        let mut line = serde_json::to_vec(&row).unwrap_or_default();
+        line.push(b'\n');
+        Ok::<_, E>(Bytes::from(line))
+    });
+    response
+        .insert_header((CONTENT_TYPE, NDJSON))
+        .streaming(lines)
+}
@@ -0,0 +1,214 @@
+use crate::StateWrapper;
+use crate::db;
+use crate::domain::{Concept, SearchResponse};
+use crate::errors::{ApiError, PgError};
+use crate::qdrant::WithReadOptions;
+use crate::recommendations::seed_expression;
+use crate::search::{SearchFilters, payload_filter};
+use crate::snapshot;
+use crate::validation::{self, ConceptSetItem};
+use actix_web::web::{Data, Json};
+use actix_web::{Error, HttpResponse, post};
+use log::{info, warn};
+use qdrant_client::qdrant::{QueryPointsBuilder, RecommendInputBuilder, RecommendStrategy};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use utoipa::ToSchema;
+
+const DEFAULT_NEGATIVE_CONTROLS: u64 = 50;
+const MAX_NEGATIVE_CONTROLS: u64 = 500;
+/// Points requested per control asked for. Many candidates are dropped as relatives of the
+/// concept set or for their other concepts, the margin keeps the list full.
+const CANDIDATES_PER_CONTROL: u64 = 4;
+/// Items of the concept set sent to Qdrant as negative examples, like the recommendations.
+const MAX_EXAMPLES: usize = 50;
+
+#[derive(Deserialize, ToSchema)]
+struct NegativeControlRequest {
+    /// The outcome or exposure concept set, as ATLAS concept set JSON.
+    concept_set: Option<String>,
+    #[serde(default)]
+    #[schema(example = json!([201826]))]
+    concept_ids: Vec<i32>,
+    #[schema(minimum = 1, maximum = 500, default = 50)]
+    limit: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct NegativeControl {
+    #[serde(flatten)]
+    concept: Concept,
+    /// Qdrant's score with the concept set as the only, negative, examples: the higher, the
+    /// further the concept is from its nearest item.
+    score: f32,
+}
+
+#[derive(Serialize, ToSchema)]
+struct NegativeControls {
+    negative_controls: Vec<NegativeControl>,
+    /// The domains of the included items, the only ones controls are suggested from.
+    domains: Vec<String>,
+}
+
+/// Candidate negative controls for population-level estimation: standard concepts of the
+/// domains of the outcome or exposure concept set, as far from its items as the embeddings
+/// place them, that are neither ancestors nor descendants of them. Picking the controls is
+/// still up to the researcher, these are the concepts worth looking at first.
+#[utoipa::path(
+    tag = "concept sets",
+    summary = "Suggest negative control concepts",
+    description = "Candidate negative controls for an outcome or exposure concept set, passed as \
+        `concept_set` or `concept_ids`. Standard concepts of the domains of its included items, furthest \
+        from them in the embedding space first, that are neither ancestors nor descendants of an included \
+        item.",
+    responses(
+        (status = 200, description = "Candidate negative controls, furthest from the concept set first",
+            body = NegativeControls),
+        (status = 400, description = "Neither or both of concept_set and concept_ids were passed, the concept \
+            set could not be parsed or has no included items, or the limit is out of range"),
+        (status = 502, description = "The vector store could not be queried"),
+    )
+)]
+#[post("/api/negative-controls")]
+async fn suggest_negative_controls(
+    request: Json<NegativeControlRequest>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let request = request.into_inner();
+    let limit = request.limit.unwrap_or(DEFAULT_NEGATIVE_CONTROLS);
+    if !(1..=MAX_NEGATIVE_CONTROLS).contains(&limit) {
+        return Err(ApiError::OutOfRange {
+            parameter: "limit",
+            min: 1.0,
+            max: MAX_NEGATIVE_CONTROLS as f64,
+        }
+        .into());
+    }
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let expression = match seed_expression(
+        &pg_client,
+        request.concept_set.as_deref(),
+        &request.concept_ids,
+    )
+    .await?
+    {
+        Ok(expression) => expression,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+        }
+    };
+    let included: Vec<&ConceptSetItem> = expression
+        .items
+        .iter()
+        .filter(|item| !item.is_excluded)
+        .collect();
+    if included.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "There are no included concepts to find controls for"
+        })));
+    }
+    let domains: BTreeSet<String> = included
+        .iter()
+        .map(|item| item.concept.domain_id.clone())
+        .collect();
+    info!(
+        "Suggesting {} negative controls for {} concepts in {:?}",
+        limit,
+        included.len(),
+        domains
+    );
+
+    let snapshot = snapshot::current(&state);
+    let examples = validation::item_point_ids(
+        &included[..included.len().min(MAX_EXAMPLES)],
+        &snapshot,
+        &state.qdrant_client,
+        &state.qdrant_read_options,
+    )
+    .await;
+    if examples.is_empty() {
+        return Ok(HttpResponse::Ok().json(NegativeControls {
+            negative_controls: Vec::new(),
+            domains: domains.into_iter().collect(),
+        }));
+    }
+
+    // Without positive examples, the best score strategy ranks points by how far they are from
+    // the nearest negative one
+    let mut recommend = RecommendInputBuilder::default().strategy(RecommendStrategy::BestScore);
+    for example in examples {
+        recommend = recommend.add_negative(example);
+    }
+    let filters = SearchFilters {
+        domain_id: Some(domains.iter().cloned().collect()),
+        standard_concept: Some("S".to_string()),
+        ..SearchFilters::default()
+    };
+    let mut query = QueryPointsBuilder::new(snapshot.collection.as_str())
+        .query(recommend.build())
+        .limit(limit * CANDIDATES_PER_CONTROL)
+        .with_payload(true)
+        .with_read_options(&state.qdrant_read_options);
+    if let Some(filter) = payload_filter(&filters, &state.vocabulary_catalog) {
+        query = query.filter(filter);
+    }
+    let points = match state.qdrant_client.query(query).await {
+        Ok(response) => response.result,
+        Err(e) => {
+            warn!("Could not query negative control candidates: {}", e);
+            return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                "error": format!("Could not query the vector store: {}", e)
+            })));
+        }
+    };
+
+    let in_set: HashSet<i32> = expression
+        .items
+        .iter()
+        .map(|item| item.concept.concept_id)
+        .collect();
+    let mut seen = HashSet::new();
+    let candidates: Vec<(Concept, f32)> = points
+        .into_iter()
+        .flat_map(|point| {
+            let score = point.score;
+            SearchResponse::from(point)
+                .concepts
+                .into_iter()
+                .map(move |concept| (concept, score))
+        })
+        .filter(|(concept, _)| {
+            domains.contains(&concept.domain_id)
+                && concept.standard_concept.as_deref() == Some("S")
+                && !in_set.contains(&concept.concept_id)
+                && seen.insert(concept.concept_id)
+        })
+        .collect();
+
+    let included_ids: Vec<i32> = included
+        .iter()
+        .map(|item| item.concept.concept_id)
+        .collect();
+    let candidate_ids: Vec<i32> = candidates
+        .iter()
+        .map(|(concept, _)| concept.concept_id)
+        .collect();
+    let related =
+        db::get_hierarchy_related_among(&pg_client, &included_ids, &candidate_ids).await?;
+    info!(
+        "Dropping {} of {} negative control candidates related to the concept set",
+        related.len(),
+        candidates.len()
+    );
+    let negative_controls: Vec<NegativeControl> = candidates
+        .into_iter()
+        .filter(|(concept, _)| !related.contains(&concept.concept_id))
+        .take(limit as usize)
+        .map(|(concept, score)| NegativeControl { concept, score })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(NegativeControls {
+        negative_controls,
+        domains: domains.into_iter().collect(),
+    }))
+}
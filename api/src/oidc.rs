@@ -0,0 +1,325 @@
+use crate::auth::Identity;
+use crate::config::{OidcConfig, Scope};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::Utc;
+use derive_more::Display;
+use log::{info, warn};
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Clock skew tolerated on `exp` and `nbf`, in seconds.
+const LEEWAY_SECS: i64 = 60;
+/// Signing keys are fetched again after this long, so rotated keys are picked up.
+const JWKS_MAX_AGE: Duration = Duration::from_secs(3600);
+/// A token signed with an unknown key triggers a refresh at most this often, so forged key ids
+/// cannot make every request call out to the provider.
+const JWKS_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+/// Requests to the provider give up after this long, a hanging provider must not hold up
+/// every request waiting for its keys.
+const JWKS_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Display)]
+pub enum TokenError {
+    #[display("Malformed token")]
+    Malformed,
+    #[display("Unsupported signing algorithm {_0}")]
+    UnsupportedAlgorithm(String),
+    #[display("Token was signed with an unknown key")]
+    UnknownKey,
+    #[display("Invalid token signature")]
+    InvalidSignature,
+    #[display("Token was issued by {_0}")]
+    WrongIssuer(String),
+    #[display("Token is not meant for this API")]
+    WrongAudience,
+    #[display("Token has expired")]
+    Expired,
+    #[display("Token is not valid yet")]
+    NotYetValid,
+    #[display("Could not fetch the issuer's signing keys: {_0}")]
+    Jwks(String),
+}
+
+#[derive(Debug, Deserialize)]
+struct Header {
+    alg: String,
+    kid: Option<String>,
+}
+
+/// A public key from the issuer's JWKS, see RFC 7517.
+#[derive(Debug, Clone, Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+    crv: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Discovery {
+    jwks_uri: String,
+}
+
+#[derive(Debug, Default)]
+struct KeyCache {
+    keys: Vec<Jwk>,
+    fetched_at: Option<Instant>,
+    /// The last fetch, successful or not, which bounds how often the provider is called.
+    attempted_at: Option<Instant>,
+}
+
+/// Validates bearer tokens from the configured OpenID Connect issuer and turns their claims
+/// into an `Identity`.
+pub struct OidcVerifier {
+    config: OidcConfig,
+    issuer: String,
+    client: reqwest::Client,
+    keys: Mutex<KeyCache>,
+    /// Held while fetching, so concurrent requests wait for one fetch instead of each
+    /// starting their own.
+    refreshing: tokio::sync::Mutex<()>,
+}
+
+fn decode(segment: &str) -> Result<Vec<u8>, TokenError> {
+    URL_SAFE_NO_PAD
+        .decode(segment)
+        .map_err(|_| TokenError::Malformed)
+}
+
+/// Whether a bearer credential is a JWT rather than an API key.
+pub fn looks_like_jwt(token: &str) -> bool {
+    token.split('.').count() == 3 && token.starts_with("eyJ")
+}
+
+impl OidcVerifier {
+    pub fn from_config(config: &OidcConfig) -> Option<OidcVerifier> {
+        let issuer = config.issuer.clone()?;
+        info!("Accepting bearer tokens issued by {}", issuer);
+        Some(OidcVerifier {
+            config: config.clone(),
+            issuer,
+            client: reqwest::Client::builder()
+                .timeout(JWKS_FETCH_TIMEOUT)
+                .build()
+                .expect("Could not create the OIDC HTTP client"),
+            keys: Mutex::new(KeyCache::default()),
+            refreshing: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    pub async fn verify(&self, token: &str) -> Result<Identity, TokenError> {
+        let mut segments = token.split('.');
+        let (Some(header), Some(payload), Some(signature), None) = (
+            segments.next(),
+            segments.next(),
+            segments.next(),
+            segments.next(),
+        ) else {
+            return Err(TokenError::Malformed);
+        };
+        let header: Header =
+            serde_json::from_slice(&decode(header)?).map_err(|_| TokenError::Malformed)?;
+        let signed = &token[..header_and_payload_len(token)];
+        let signature = decode(signature)?;
+        let key = self.find_key(header.kid.as_deref()).await?;
+        verify_signature(&header.alg, &key, signed.as_bytes(), &signature)?;
+
+        let claims: Value =
+            serde_json::from_slice(&decode(payload)?).map_err(|_| TokenError::Malformed)?;
+        self.check_claims(&claims)?;
+        Ok(self.identity(&claims))
+    }
+
+    fn check_claims(&self, claims: &Value) -> Result<(), TokenError> {
+        let issuer = claims["iss"].as_str().unwrap_or_default();
+        if issuer.trim_end_matches('/') != self.issuer.trim_end_matches('/') {
+            return Err(TokenError::WrongIssuer(issuer.to_string()));
+        }
+        if let Some(audience) = &self.config.audience {
+            let matches = match &claims["aud"] {
+                Value::String(aud) => aud == audience,
+                Value::Array(auds) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+                _ => false,
+            };
+            if !matches {
+                return Err(TokenError::WrongAudience);
+            }
+        }
+        let now = Utc::now().timestamp();
+        match claims["exp"].as_i64() {
+            Some(exp) if exp + LEEWAY_SECS >= now => {}
+            _ => return Err(TokenError::Expired),
+        }
+        if let Some(nbf) = claims["nbf"].as_i64()
+            && nbf - LEEWAY_SECS > now
+        {
+            return Err(TokenError::NotYetValid);
+        }
+        Ok(())
+    }
+
+    fn identity(&self, claims: &Value) -> Identity {
+        let name = ["preferred_username", "email", "sub"]
+            .iter()
+            .find_map(|claim| claims[*claim].as_str())
+            .unwrap_or("unknown")
+            .to_string();
+        let values: Vec<&str> = match &claims[self.config.scopes_claim.as_str()] {
+            Value::String(scopes) => scopes.split_whitespace().collect(),
+            Value::Array(scopes) => scopes.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        let mut scopes = self.config.default_scopes.clone();
+        for value in values {
+            if let Some(scope) = value
+                .strip_prefix(self.config.scope_prefix.as_str())
+                .and_then(Scope::parse)
+                && !scopes.contains(&scope)
+            {
+                scopes.push(scope);
+            }
+        }
+        Identity { name, scopes }
+    }
+
+    /// The key with the given id, fetching the key set when it is stale or the id is new.
+    async fn find_key(&self, kid: Option<&str>) -> Result<Jwk, TokenError> {
+        if let Some(key) = self.cached_key(kid) {
+            return Ok(key);
+        }
+        let _refreshing = self.refreshing.lock().await;
+        let refresh = {
+            let mut cache = self.keys.lock().unwrap();
+            let refresh = cache
+                .attempted_at
+                .is_none_or(|attempted_at| attempted_at.elapsed() >= JWKS_MIN_REFRESH_INTERVAL);
+            if refresh {
+                cache.attempted_at = Some(Instant::now());
+            }
+            refresh
+        };
+        if refresh {
+            let keys = self.fetch_keys().await.map_err(|e| {
+                warn!("Could not fetch signing keys of {}: {}", self.issuer, e);
+                TokenError::Jwks(e.to_string())
+            })?;
+            let mut cache = self.keys.lock().unwrap();
+            cache.keys = keys;
+            cache.fetched_at = Some(Instant::now());
+        }
+        // Whatever the last fetch, maybe the one just awaited, left in the cache
+        let cache = self.keys.lock().unwrap();
+        select_key(&cache.keys, kid).ok_or(TokenError::UnknownKey)
+    }
+
+    fn cached_key(&self, kid: Option<&str>) -> Option<Jwk> {
+        let cache = self.keys.lock().unwrap();
+        cache
+            .fetched_at
+            .filter(|fetched_at| fetched_at.elapsed() < JWKS_MAX_AGE)
+            .and_then(|_| select_key(&cache.keys, kid))
+    }
+
+    async fn fetch_keys(&self) -> Result<Vec<Jwk>, reqwest::Error> {
+        let jwks_url = match &self.config.jwks_url {
+            Some(jwks_url) => jwks_url.clone(),
+            None => {
+                let discovery_url = format!(
+                    "{}/.well-known/openid-configuration",
+                    self.issuer.trim_end_matches('/')
+                );
+                let discovery: Discovery = self
+                    .client
+                    .get(discovery_url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                discovery.jwks_uri
+            }
+        };
+        let key_set: JwkSet = self
+            .client
+            .get(&jwks_url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        info!(
+            "Loaded {} signing keys from {}",
+            key_set.keys.len(),
+            jwks_url
+        );
+        Ok(key_set.keys)
+    }
+}
+
+fn header_and_payload_len(token: &str) -> usize {
+    token.rfind('.').unwrap_or(token.len())
+}
+
+fn select_key(keys: &[Jwk], kid: Option<&str>) -> Option<Jwk> {
+    match kid {
+        Some(kid) => keys.iter().find(|key| key.kid.as_deref() == Some(kid)),
+        // Providers with a single key may leave out the id
+        None if keys.len() == 1 => keys.first(),
+        None => None,
+    }
+    .cloned()
+}
+
+fn verify_signature(
+    alg: &str,
+    key: &Jwk,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<(), TokenError> {
+    let component = |value: &Option<String>| -> Result<Vec<u8>, TokenError> {
+        decode(value.as_deref().ok_or(TokenError::UnknownKey)?)
+    };
+    let verified = match (alg, key.kty.as_str()) {
+        ("RS256" | "RS384" | "RS512" | "PS256" | "PS384" | "PS512", "RSA") => {
+            let parameters = match alg {
+                "RS256" => &signature::RSA_PKCS1_2048_8192_SHA256,
+                "RS384" => &signature::RSA_PKCS1_2048_8192_SHA384,
+                "RS512" => &signature::RSA_PKCS1_2048_8192_SHA512,
+                "PS256" => &signature::RSA_PSS_2048_8192_SHA256,
+                "PS384" => &signature::RSA_PSS_2048_8192_SHA384,
+                _ => &signature::RSA_PSS_2048_8192_SHA512,
+            };
+            RsaPublicKeyComponents {
+                n: component(&key.n)?,
+                e: component(&key.e)?,
+            }
+            .verify(parameters, message, signature)
+        }
+        ("ES256" | "ES384", "EC") => {
+            let algorithm = match (alg, key.crv.as_deref()) {
+                ("ES256", Some("P-256")) => &signature::ECDSA_P256_SHA256_FIXED,
+                ("ES384", Some("P-384")) => &signature::ECDSA_P384_SHA384_FIXED,
+                _ => return Err(TokenError::UnsupportedAlgorithm(alg.to_string())),
+            };
+            // Uncompressed point encoding: 0x04 || x || y
+            let mut point = vec![0x04];
+            point.extend(component(&key.x)?);
+            point.extend(component(&key.y)?);
+            UnparsedPublicKey::new(algorithm, point).verify(message, signature)
+        }
+        _ => return Err(TokenError::UnsupportedAlgorithm(alg.to_string())),
+    };
+    verified.map_err(|_| TokenError::InvalidSignature)
+}
@@ -0,0 +1,296 @@
+use crate::StateWrapper;
+use crate::db;
+use crate::errors::PgError;
+use crate::validation::{self, ConceptSetExpression, ConceptSetItem, describe_item};
+use actix_web::web::{Data, Json};
+use actix_web::{Error, HttpResponse, post};
+use deadpool_postgres::Client;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use utoipa::ToSchema;
+
+/// Explicit items an ancestor has to replace before it is added; swapping a single item for
+/// its ancestor is not simpler.
+const MIN_COLLAPSED_ITEMS: usize = 2;
+
+#[derive(Deserialize, ToSchema)]
+struct ConceptSetOptimizeRequest {
+    /// The concept set expression, or a concept set with its expression, as JSON text.
+    concept_set: String,
+}
+
+/// A logically equivalent, smaller expression and what was done to get there.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OptimizedConceptSet {
+    pub expression: ConceptSetExpression,
+    pub original_item_count: usize,
+    pub optimized_item_count: usize,
+    pub resolved_concept_count: usize,
+    /// Items removed or replaced, and why, in the order it was done.
+    pub changes: Vec<String>,
+}
+
+/// An item with the concepts it adds, or removes when excluded.
+struct Entry {
+    item: ConceptSetItem,
+    concepts: HashSet<i32>,
+    kept: bool,
+}
+
+/// Simplifies an expression without changing what it resolves to: explicit items whose
+/// ancestor's whole tree is in the resolved set are replaced by the ancestor with
+/// `includeDescendants`, then items that other items already cover are removed, and so are
+/// exclusions that remove nothing another exclusion does not.
+pub async fn optimize(
+    expression: ConceptSetExpression,
+    pg_client: &Client,
+) -> Result<OptimizedConceptSet, PgError> {
+    let original_item_count = expression.items.len();
+    let items: Vec<&ConceptSetItem> = expression.items.iter().collect();
+    let concepts = validation::item_concepts(&items, pg_client).await?;
+    let mut entries: Vec<Entry> = expression
+        .items
+        .into_iter()
+        .zip(concepts)
+        .map(|(item, concepts)| Entry {
+            item,
+            concepts,
+            kept: true,
+        })
+        .collect();
+
+    let included: HashSet<i32> = entries
+        .iter()
+        .filter(|entry| !entry.item.is_excluded)
+        .flat_map(|entry| entry.concepts.iter().copied())
+        .collect();
+    let excluded: HashSet<i32> = entries
+        .iter()
+        .filter(|entry| entry.item.is_excluded)
+        .flat_map(|entry| entry.concepts.iter().copied())
+        .collect();
+    let mut resolved: Vec<i32> = included.difference(&excluded).copied().collect();
+    resolved.sort();
+
+    let mut changes = Vec::new();
+    collapse_into_ancestors(&mut entries, &resolved, pg_client, &mut changes).await?;
+    drop_covered(&mut entries, false, None, &mut changes);
+    drop_covered(&mut entries, true, Some(&included), &mut changes);
+
+    let items: Vec<ConceptSetItem> = entries
+        .into_iter()
+        .filter(|entry| entry.kept)
+        .map(|entry| entry.item)
+        .collect();
+    Ok(OptimizedConceptSet {
+        original_item_count,
+        optimized_item_count: items.len(),
+        resolved_concept_count: resolved.len(),
+        expression: ConceptSetExpression { items },
+        changes,
+    })
+}
+
+/// Adds the highest ancestors whose descendants all resolve anyway, largest replacement first,
+/// and drops the explicit items they cover. Items with `includeMapped` stay, the ancestor does
+/// not bring their mapped concepts.
+async fn collapse_into_ancestors(
+    entries: &mut Vec<Entry>,
+    resolved: &[i32],
+    pg_client: &Client,
+    changes: &mut Vec<String>,
+) -> Result<(), PgError> {
+    let collapsible =
+        |entry: &Entry| entry.kept && !entry.item.is_excluded && !entry.item.include_mapped;
+    let concept_ids: Vec<i32> = entries
+        .iter()
+        .filter(|entry| collapsible(entry))
+        .map(|entry| entry.item.concept.concept_id)
+        .collect();
+    if concept_ids.len() < MIN_COLLAPSED_ITEMS {
+        return Ok(());
+    }
+    let candidates = db::get_covering_ancestors(pg_client, &concept_ids, resolved).await?;
+    let descendants_map = db::get_batch_descendant_concepts(pg_client, &candidates).await?;
+    let trees: HashMap<i32, HashSet<i32>> = candidates
+        .iter()
+        .map(|&ancestor| {
+            let mut tree: HashSet<i32> = descendants_map
+                .get(&ancestor)
+                .into_iter()
+                .flatten()
+                .copied()
+                .collect();
+            tree.insert(ancestor);
+            (ancestor, tree)
+        })
+        .collect();
+    // An ancestor inside another candidate's tree never covers more than that candidate
+    let mut highest: Vec<i32> = candidates
+        .iter()
+        .copied()
+        .filter(|ancestor| {
+            !trees
+                .iter()
+                .any(|(other, tree)| other != ancestor && tree.contains(ancestor))
+        })
+        .collect();
+    let mut ancestor_concepts: HashMap<i32, _> = db::get_concepts_by_ids(pg_client, &highest)
+        .await?
+        .into_iter()
+        .map(|concept| (concept.concept_id, concept))
+        .collect();
+
+    loop {
+        let best = highest
+            .iter()
+            .map(|ancestor| {
+                let tree = &trees[ancestor];
+                let covered: Vec<usize> = entries
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, entry)| collapsible(entry) && entry.concepts.is_subset(tree))
+                    .map(|(index, _)| index)
+                    .collect();
+                (*ancestor, covered)
+            })
+            .max_by_key(|(ancestor, covered)| (covered.len(), Reverse(*ancestor)));
+        let Some((ancestor, covered)) = best else {
+            break;
+        };
+        if covered.len() < MIN_COLLAPSED_ITEMS {
+            break;
+        }
+        highest.retain(|&other| other != ancestor);
+        let Some(concept) = ancestor_concepts.remove(&ancestor) else {
+            continue;
+        };
+        for &index in &covered {
+            entries[index].kept = false;
+        }
+        let item = ConceptSetItem {
+            concept,
+            is_excluded: false,
+            include_descendants: true,
+            include_mapped: false,
+        };
+        changes.push(format!(
+            "Replaced {} items with {} and its descendants",
+            covered.len(),
+            describe_item(&item)
+        ));
+        entries.push(Entry {
+            item,
+            concepts: trees[&ancestor].clone(),
+            kept: true,
+        });
+    }
+    Ok(())
+}
+
+/// Drops the included or excluded items all of whose concepts other kept items of the same kind
+/// also cover, smallest first so broad items survive. For exclusions only the concepts in
+/// `relevant`, those actually included, count.
+fn drop_covered(
+    entries: &mut [Entry],
+    is_excluded: bool,
+    relevant: Option<&HashSet<i32>>,
+    changes: &mut Vec<String>,
+) {
+    let counts_towards = |concept: &i32| relevant.is_none_or(|relevant| relevant.contains(concept));
+    let mut counts: HashMap<i32, usize> = HashMap::new();
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.kept && entry.item.is_excluded == is_excluded)
+    {
+        for concept in entry.concepts.iter().filter(|c| counts_towards(c)) {
+            *counts.entry(*concept).or_default() += 1;
+        }
+    }
+
+    let mut order: Vec<usize> = (0..entries.len())
+        .filter(|&index| entries[index].kept && entries[index].item.is_excluded == is_excluded)
+        .collect();
+    order.sort_by_key(|&index| (entries[index].concepts.len(), Reverse(index)));
+    for index in order {
+        let entry = &entries[index];
+        let needed = entry
+            .concepts
+            .iter()
+            .filter(|c| counts_towards(c))
+            .any(|concept| counts[concept] < 2);
+        if needed {
+            continue;
+        }
+        let covering = entries.iter().enumerate().find(|(other, candidate)| {
+            *other != index
+                && candidate.kept
+                && candidate.item.is_excluded == is_excluded
+                && entry
+                    .concepts
+                    .iter()
+                    .filter(|c| counts_towards(c))
+                    .all(|concept| candidate.concepts.contains(concept))
+        });
+        let change = match (is_excluded, covering) {
+            (false, Some((_, covering))) => format!(
+                "Removed {}, already included by {}",
+                describe_item(&entry.item),
+                describe_item(&covering.item)
+            ),
+            (false, None) => format!(
+                "Removed {}, already included by other items",
+                describe_item(&entry.item)
+            ),
+            (true, _) if !entry.concepts.iter().any(counts_towards) => format!(
+                "Removed the exclusion of {}, which removes nothing that is included",
+                describe_item(&entry.item)
+            ),
+            (true, Some((_, covering))) => format!(
+                "Removed the exclusion of {}, already excluded by {}",
+                describe_item(&entry.item),
+                describe_item(&covering.item)
+            ),
+            (true, None) => format!(
+                "Removed the exclusion of {}, already excluded by other items",
+                describe_item(&entry.item)
+            ),
+        };
+        changes.push(change);
+        for concept in entry.concepts.iter().filter(|c| counts_towards(c)) {
+            *counts.get_mut(concept).unwrap() -= 1;
+        }
+        entries[index].kept = false;
+    }
+}
+
+#[utoipa::path(
+    tag = "concept sets",
+    summary = "Optimize a concept set",
+    description = "Returns a smaller expression that resolves to the same concepts. Explicit items are replaced \
+        by an ancestor with `includeDescendants` when the ancestor's whole tree is already in the resolved \
+        set, items and exclusions that other items already cover are removed. `changes` explains each step.",
+    responses(
+        (status = 200, description = "The optimized expression", body = OptimizedConceptSet),
+        (status = 400, description = "The concept set could not be parsed"),
+    )
+)]
+#[post("/api/conceptsets/optimize")]
+async fn optimize_concept_set(
+    request: Json<ConceptSetOptimizeRequest>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    info!("Received concept set optimization request");
+    let expression = match validation::parse_concept_set(&request.concept_set) {
+        Ok(expression) => expression,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+        }
+    };
+
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let optimized = optimize(expression, &pg_client).await?;
+    Ok(HttpResponse::Ok().json(optimized))
+}
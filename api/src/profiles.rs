@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use utoipa::ToSchema;
+
+/// The individual checks `analyze_concept_set` can run. Which ones run is decided by the
+/// selected `ValidationProfile`.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationRule {
+    /// A concept does not exist in the vocabulary tables, is no longer valid, or its vocabulary,
+    /// domain, class or standard flag differ from the vocabulary.
+    VocabularyConsistency,
+    /// The same concept appears in more than one item.
+    DuplicateConcepts,
+    /// An item is a descendant of another included item with `includeDescendants`, so it adds
+    /// nothing.
+    RedundantItems,
+    /// An excluded item removes concepts that another item includes explicitly.
+    ExclusionConflicts,
+    /// An included concept lies outside the domains the profile is about.
+    DomainConsistency,
+    /// An included concept has descendants but `includeDescendants` is not set.
+    DescendantsNotIncluded,
+    /// A drug item is not an ingredient, so brand and clinical drug variants are missed.
+    DrugIngredientLevel,
+    /// A classification concept is included without descendants and will match no records.
+    ClassificationWithoutDescendants,
+    /// `includeMapped` is set on a standard concept, or a non-standard concept is included
+    /// without it.
+    IncludeMappedSemantics,
+}
+
+/// Selectable rule sets and recommendation filters tailored to the kind of concept set being
+/// authored.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationProfile {
+    #[default]
+    General,
+    ConditionPhenotype,
+    DrugExposure,
+    LabMeasurement,
+}
+
+impl ValidationProfile {
+    pub const ALL: [ValidationProfile; 4] = [
+        ValidationProfile::General,
+        ValidationProfile::ConditionPhenotype,
+        ValidationProfile::DrugExposure,
+        ValidationProfile::LabMeasurement,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ValidationProfile::General => "general",
+            ValidationProfile::ConditionPhenotype => "condition_phenotype",
+            ValidationProfile::DrugExposure => "drug_exposure",
+            ValidationProfile::LabMeasurement => "lab_measurement",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ValidationProfile::General => {
+                "Structural and mapping checks only, suitable for any concept set"
+            }
+            ValidationProfile::ConditionPhenotype => {
+                "Condition phenotypes built from standard SNOMED concepts with descendants"
+            }
+            ValidationProfile::DrugExposure => {
+                "Drug exposures built from RxNorm ingredients with descendants"
+            }
+            ValidationProfile::LabMeasurement => "Laboratory measurements, usually LOINC",
+        }
+    }
+
+    pub fn rules(&self) -> &'static [ValidationRule] {
+        match self {
+            ValidationProfile::General => &[
+                ValidationRule::VocabularyConsistency,
+                ValidationRule::DuplicateConcepts,
+                ValidationRule::RedundantItems,
+                ValidationRule::ExclusionConflicts,
+                ValidationRule::IncludeMappedSemantics,
+            ],
+            ValidationProfile::ConditionPhenotype => &[
+                ValidationRule::VocabularyConsistency,
+                ValidationRule::DuplicateConcepts,
+                ValidationRule::RedundantItems,
+                ValidationRule::ExclusionConflicts,
+                ValidationRule::DomainConsistency,
+                ValidationRule::DescendantsNotIncluded,
+                ValidationRule::IncludeMappedSemantics,
+            ],
+            ValidationProfile::DrugExposure => &[
+                ValidationRule::VocabularyConsistency,
+                ValidationRule::DuplicateConcepts,
+                ValidationRule::RedundantItems,
+                ValidationRule::ExclusionConflicts,
+                ValidationRule::DomainConsistency,
+                ValidationRule::DescendantsNotIncluded,
+                ValidationRule::DrugIngredientLevel,
+                ValidationRule::IncludeMappedSemantics,
+            ],
+            ValidationProfile::LabMeasurement => &[
+                ValidationRule::VocabularyConsistency,
+                ValidationRule::DuplicateConcepts,
+                ValidationRule::RedundantItems,
+                ValidationRule::ExclusionConflicts,
+                ValidationRule::DomainConsistency,
+                ValidationRule::ClassificationWithoutDescendants,
+                ValidationRule::IncludeMappedSemantics,
+            ],
+        }
+    }
+
+    pub fn enables(&self, rule: ValidationRule) -> bool {
+        self.rules().contains(&rule)
+    }
+
+    /// Domains concept set items are expected to come from, `None` when any domain is fine.
+    pub fn expected_domains(&self) -> Option<&'static [&'static str]> {
+        match self {
+            ValidationProfile::General => None,
+            ValidationProfile::ConditionPhenotype => Some(&["Condition", "Observation"]),
+            ValidationProfile::DrugExposure => Some(&["Drug"]),
+            ValidationProfile::LabMeasurement => Some(&["Measurement"]),
+        }
+    }
+
+    /// Domains recommendations are restricted to. Without a profile-specific scope the domains
+    /// of the concept set itself are used.
+    pub fn recommendation_domains(&self, concept_set_domains: HashSet<String>) -> HashSet<String> {
+        match self.expected_domains() {
+            Some(domains) => domains.iter().map(|d| d.to_string()).collect(),
+            None => concept_set_domains,
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use crate::StateWrapper;
+use crate::boosting::{self, BoostingRules};
+use crate::curation;
+use crate::db;
+use crate::domain::{
+    ArchivedConceptSet, ArchivedConceptSetVersion, ArchivedRecommendationFeedback,
+    ArchivedSynonymOverride, ArchivedZeroResultQuery,
+};
+use crate::errors::PgError;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::web::{Data, Payload, Query};
+use actix_web::{Error, HttpResponse, get, post};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+/// Bumped whenever the archive layout changes incompatibly; older archives stay importable.
+/// Format 2 added concept sets, format 3 recommendation feedback.
+pub const ARCHIVE_FORMAT_VERSION: u32 = 3;
+
+/// Archives carry every zero-result query ever captured, so allow far more than the default
+/// payload limit.
+const MAX_ARCHIVE_BYTES: usize = 64 * 1024 * 1024;
+
+/// The curation state of an instance, for promoting it from one environment to the next.
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct HecateArchive {
+    pub format_version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub hecate_version: String,
+    pub synonym_overrides: Vec<ArchivedSynonymOverride>,
+    pub zero_result_queries: Vec<ArchivedZeroResultQuery>,
+    /// Missing from format 1 archives, which leave the target's concept sets untouched.
+    pub concept_sets: Option<Vec<ArchivedConceptSet>>,
+    pub concept_set_versions: Option<Vec<ArchivedConceptSetVersion>>,
+    /// Missing from archives before format 3, which leave the target's feedback untouched.
+    pub recommendation_feedback: Option<Vec<ArchivedRecommendationFeedback>>,
+    /// Left out to keep the target's rules untouched.
+    pub boosting_rules: Option<BoostingRules>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ImportParameters {
+    /// Replace the target's curation data instead of merging into it.
+    #[serde(default)]
+    replace: bool,
+}
+
+/// What an import wrote, per kind of curation data. Absent counts were missing from the archive
+/// and left the target untouched.
+#[derive(Serialize, ToSchema)]
+struct ImportedState {
+    synonym_overrides: usize,
+    zero_result_queries: usize,
+    concept_sets: Option<usize>,
+    recommendation_feedback: Option<usize>,
+    boosting_rules: Option<usize>,
+    replaced: bool,
+}
+
+/// Everything curated on this instance, as an archive `import_state` loads on another.
+#[utoipa::path(
+    tag = "admin",
+    summary = "Export the curation state",
+    description = "Synonym overrides, zero-result queries, concept sets with their versions, recommendation \
+        feedback and boosting rules, as an archive for promoting them to another instance.",
+    responses((status = 200, description = "The archive, as a download", body = HecateArchive))
+)]
+#[get("/api/admin/state/export")]
+async fn export_state(state: Data<StateWrapper>) -> Result<HttpResponse, Error> {
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let archive = HecateArchive {
+        format_version: ARCHIVE_FORMAT_VERSION,
+        exported_at: Utc::now(),
+        hecate_version: env!("CARGO_PKG_VERSION").to_string(),
+        synonym_overrides: db::get_archived_synonym_overrides(&pg_client).await?,
+        zero_result_queries: db::get_archived_zero_result_queries(&pg_client).await?,
+        concept_sets: Some(db::get_archived_concept_sets(&pg_client).await?),
+        concept_set_versions: Some(db::get_archived_concept_set_versions(&pg_client).await?),
+        recommendation_feedback: Some(db::get_archived_recommendation_feedback(&pg_client).await?),
+        boosting_rules: Some(boosting::current_rules(&state).as_ref().clone()),
+    };
+    info!(
+        "Exporting {} synonym overrides, {} zero-result queries, {} concept sets and {} recommendation verdicts",
+        archive.synonym_overrides.len(),
+        archive.zero_result_queries.len(),
+        archive.concept_sets.as_ref().map_or(0, Vec::len),
+        archive.recommendation_feedback.as_ref().map_or(0, Vec::len)
+    );
+
+    let filename = format!(
+        "hecate-state-{}.json",
+        archive.exported_at.format("%Y%m%dT%H%M%SZ")
+    );
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(filename)],
+        })
+        .json(archive))
+}
+
+/// Loads an archive produced by `export_state` on another instance. Curation data is written
+/// in a single transaction; boosting rules take effect immediately and are written back to
+/// `BOOSTING_RULES_PATH` so they survive a restart.
+#[utoipa::path(
+    tag = "admin",
+    summary = "Import the curation state",
+    description = "Loads an archive exported by another instance. Curation data is merged into this \
+        instance's, or replaces it with `replace=true`, in a single transaction. Boosting rules take \
+        effect immediately.",
+    params(ImportParameters),
+    request_body = HecateArchive,
+    responses(
+        (status = 200, description = "What was imported", body = ImportedState),
+        (status = 400, description = "The archive could not be parsed or its format is newer than supported"),
+        (status = 413, description = "The archive is larger than 64 MiB"),
+    )
+)]
+#[post("/api/admin/state/import")]
+async fn import_state(
+    parameters: Query<ImportParameters>,
+    payload: Payload,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let Ok(body) = payload.to_bytes_limited(MAX_ARCHIVE_BYTES).await else {
+        return Ok(HttpResponse::PayloadTooLarge().json(serde_json::json!({
+            "error": format!("Archives are limited to {} bytes", MAX_ARCHIVE_BYTES)
+        })));
+    };
+    let archive: HecateArchive = match serde_json::from_slice(&body?) {
+        Ok(archive) => archive,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid archive: {}", e)
+            })));
+        }
+    };
+    if archive.format_version > ARCHIVE_FORMAT_VERSION {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!(
+                "Archive format {} is newer than the supported format {}",
+                archive.format_version, ARCHIVE_FORMAT_VERSION
+            )
+        })));
+    }
+    info!(
+        "Importing archive exported at {} by Hecate {} (replace: {})",
+        archive.exported_at, archive.hecate_version, parameters.replace
+    );
+
+    let concept_sets = archive.concept_sets.as_deref().map(|concept_sets| {
+        let versions = archive.concept_set_versions.as_deref().unwrap_or_default();
+        (concept_sets, versions)
+    });
+    let mut pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    db::import_archived_curation(
+        &mut pg_client,
+        &archive.zero_result_queries,
+        &archive.synonym_overrides,
+        concept_sets,
+        archive.recommendation_feedback.as_deref(),
+        parameters.replace,
+    )
+    .await?;
+    curation::refresh_synonym_overrides(&state).await?;
+
+    let boosting_rules = match archive.boosting_rules {
+        Some(rules) => {
+            if let Some(path) = state.config.boosting_rules_path.as_deref()
+                && let Err(e) = rules.save(path)
+            {
+                warn!("Could not write imported boosting rules to {}: {}", path, e);
+            }
+            let imported = rules.rules.len();
+            *state.boosting_rules.write().unwrap() = Arc::new(rules);
+            state.search_cache.invalidate_all();
+            state.shared_cache.invalidate_searches().await;
+            Some(imported)
+        }
+        None => None,
+    };
+
+    Ok(HttpResponse::Ok().json(ImportedState {
+        synonym_overrides: archive.synonym_overrides.len(),
+        zero_result_queries: archive.zero_result_queries.len(),
+        concept_sets: archive.concept_sets.as_ref().map(Vec::len),
+        recommendation_feedback: archive.recommendation_feedback.as_ref().map(Vec::len),
+        boosting_rules,
+        replaced: parameters.replace,
+    }))
+}
@@ -1,12 +1,135 @@
-use crate::api::COLLECTION_NAME;
+use crate::config::QdrantReadConfig;
 use log::info;
-use qdrant_client::Qdrant;
 use qdrant_client::qdrant::point_id::PointIdOptions;
 use qdrant_client::qdrant::with_payload_selector::SelectorOptions;
-use qdrant_client::qdrant::{PayloadIncludeSelector, PointId, RetrievedPoint, ScrollPointsBuilder};
+use qdrant_client::qdrant::{
+    CreateCollectionBuilder, CreateFieldIndexCollectionBuilder, Distance, FieldType,
+    GetPointsBuilder, PayloadIncludeSelector, PointId, QueryBatchPointsBuilder, QueryPointsBuilder,
+    ReadConsistencyType, RetrievedPoint, ScrollPointsBuilder, SearchPointsBuilder,
+    ShardKeySelector, VectorParamsBuilder, read_consistency,
+};
+use qdrant_client::{Qdrant, QdrantError};
+use sha2::{Digest, Sha256};
 use std::fs;
 use uuid::Uuid;
 
+/// Consistency and shard selection for reads, parsed once from `QdrantReadConfig`. Writes only
+/// happen during ingest, see `ensure_collection`.
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    consistency: Option<read_consistency::Value>,
+    shard_keys: Option<ShardKeySelector>,
+}
+
+impl ReadOptions {
+    pub fn from_config(config: &QdrantReadConfig) -> Result<ReadOptions, String> {
+        let consistency = match config.consistency.as_deref().map(str::to_lowercase) {
+            None => None,
+            Some(value) => Some(match value.as_str() {
+                "all" => read_consistency::Value::Type(ReadConsistencyType::All as i32),
+                "majority" => read_consistency::Value::Type(ReadConsistencyType::Majority as i32),
+                "quorum" => read_consistency::Value::Type(ReadConsistencyType::Quorum as i32),
+                factor => match factor.parse::<u64>() {
+                    Ok(factor) if factor > 0 => read_consistency::Value::Factor(factor),
+                    _ => {
+                        return Err(format!(
+                            "Invalid Qdrant read consistency {:?}, expected all, majority, quorum or a replica count",
+                            value
+                        ));
+                    }
+                },
+            }),
+        };
+        let shard_keys = (!config.shard_keys.is_empty())
+            .then(|| ShardKeySelector::from(config.shard_keys.clone()));
+        Ok(ReadOptions {
+            consistency,
+            shard_keys,
+        })
+    }
+}
+
+/// Applies `ReadOptions` to the request builders Hecate reads points with.
+pub trait WithReadOptions {
+    fn with_read_options(self, options: &ReadOptions) -> Self;
+}
+
+macro_rules! impl_with_read_options {
+    ($($builder:ty),*) => {
+        $(
+            impl WithReadOptions for $builder {
+                fn with_read_options(self, options: &ReadOptions) -> Self {
+                    let mut builder = self;
+                    if let Some(consistency) = options.consistency {
+                        builder = builder.read_consistency(consistency);
+                    }
+                    if let Some(shard_keys) = &options.shard_keys {
+                        builder = builder.shard_key_selector(shard_keys.clone());
+                    }
+                    builder
+                }
+            }
+        )*
+    };
+}
+
+impl_with_read_options!(
+    GetPointsBuilder,
+    QueryPointsBuilder,
+    ScrollPointsBuilder,
+    SearchPointsBuilder
+);
+
+/// Batches carry the consistency, the shard keys go on each of their queries.
+impl WithReadOptions for QueryBatchPointsBuilder {
+    fn with_read_options(self, options: &ReadOptions) -> Self {
+        match options.consistency {
+            Some(consistency) => self.read_consistency(consistency),
+            None => self,
+        }
+    }
+}
+
+/// Creates the collection with the dimensions of the configured embedder, plus keyword
+/// indexes for exact lookups and filters. Returns whether it had to be created. The indexes are
+/// created on existing collections too, Qdrant keeps indexes that are already there, so
+/// collections from before an index was added get it on the next ingest.
+pub async fn ensure_collection(
+    client: &Qdrant,
+    collection: &str,
+    dimensions: u32,
+    keyword_fields: &[&str],
+) -> Result<bool, QdrantError> {
+    let created = !client.collection_exists(collection).await?;
+    if created {
+        info!("Creating collection {}", collection);
+        client
+            .create_collection(CreateCollectionBuilder::new(collection).vectors_config(
+                VectorParamsBuilder::new(dimensions as u64, Distance::Cosine),
+            ))
+            .await?;
+    }
+    for field in keyword_fields {
+        client
+            .create_field_index(CreateFieldIndexCollectionBuilder::new(
+                collection,
+                *field,
+                FieldType::Keyword,
+            ))
+            .await?;
+    }
+    Ok(created)
+}
+
+/// A point id derived from a natural key, so ingesting the same data again updates points in
+/// place instead of duplicating them.
+pub fn stable_point_id(key: &str) -> Uuid {
+    let digest = Sha256::digest(key.as_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    Uuid::from_bytes(bytes)
+}
+
 fn rem_first_and_last(value: &str) -> &str {
     let mut chars = value.chars();
     chars.next();
@@ -14,17 +137,20 @@ fn rem_first_and_last(value: &str) -> &str {
     chars.as_str()
 }
 
-pub async fn get_all_id_value_pairs(client: &Qdrant) -> Vec<(Uuid, String)> {
-    let points = get_all_points(client, COLLECTION_NAME).await;
+/// Every point of the collection with its lowercase concept name, the source of the concept
+/// index.
+pub async fn get_all_id_value_pairs(
+    client: &Qdrant,
+    collection: &str,
+) -> Result<Vec<(Uuid, String)>, QdrantError> {
+    let points = get_all_points(client, collection).await?;
     let mut pairs: Vec<(Uuid, String)> = Vec::new();
     for point in points {
         let index: PointIdOptions = point.clone().id.unwrap().point_id_options.unwrap();
-        let concept_name = point
-            .clone()
-            .payload
-            .get("concept_name_lower")
-            .unwrap()
-            .to_string();
+        let Some(concept_name) = point.payload.get("concept_name_lower") else {
+            continue;
+        };
+        let concept_name = concept_name.to_string();
         if let PointIdOptions::Uuid(id) = index {
             let v = rem_first_and_last(concept_name.as_str()).to_string();
             let uuid = Uuid::try_parse(id.as_str());
@@ -32,15 +158,17 @@ pub async fn get_all_id_value_pairs(client: &Qdrant) -> Vec<(Uuid, String)> {
             pairs.push((k, v));
         }
     }
-    pairs
+    Ok(pairs)
 }
 
-async fn get_all_points(client: &Qdrant, collection_name: &str) -> Vec<RetrievedPoint> {
+async fn get_all_points(
+    client: &Qdrant,
+    collection_name: &str,
+) -> Result<Vec<RetrievedPoint>, QdrantError> {
     let mut result: Vec<RetrievedPoint> = Vec::new();
     let mut has_next = true;
     let mut offset: Option<PointId> = None;
     while has_next {
-        // while result.len() < 150000 {
         let mut spb = ScrollPointsBuilder::new(collection_name)
             .limit(5000)
             .with_payload(SelectorOptions::Include(PayloadIncludeSelector::from(
@@ -50,7 +178,7 @@ async fn get_all_points(client: &Qdrant, collection_name: &str) -> Vec<Retrieved
             spb = spb.offset(offset.clone().unwrap())
         }
 
-        let resp = client.scroll(spb).await.unwrap();
+        let resp = client.scroll(spb).await?;
         resp.result.iter().for_each(|rp| result.push(rp.clone()));
         if resp.next_page_offset.is_some() {
             offset = resp.next_page_offset;
@@ -64,12 +192,14 @@ async fn get_all_points(client: &Qdrant, collection_name: &str) -> Vec<Retrieved
         result.len(),
         &collection_name
     );
-    result
+    Ok(result)
 }
 
 #[allow(dead_code)]
-async fn write_pairs_to_file(qdrant_client: &Qdrant) {
-    let id_value_pairs = get_all_id_value_pairs(qdrant_client).await;
+async fn write_pairs_to_file(qdrant_client: &Qdrant, collection: &str) {
+    let id_value_pairs = get_all_id_value_pairs(qdrant_client, collection)
+        .await
+        .unwrap();
     let asdf = serde_json::to_string(&id_value_pairs).unwrap();
     fs::write("/Users/rowan/code/hecate/hecate-api/all_pairs.txt", asdf).unwrap();
 }
@@ -0,0 +1,173 @@
+use serde::Serialize;
+
+/// Lexical constraints written into the search query: `"quoted phrases"`, terms joined by `AND`
+/// or `OR`, and `-excluded` terms. Concept names are matched case-insensitively on substrings.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct QueryOperators {
+    /// Phrases every concept name must contain: quoted phrases and terms joined by `AND`.
+    pub required: Vec<String>,
+    /// Groups of terms joined by `OR`, a concept name must contain one of each group.
+    pub any_of: Vec<Vec<String>>,
+    /// Terms no concept name may contain.
+    pub excluded: Vec<String>,
+}
+
+impl QueryOperators {
+    pub fn is_empty(&self) -> bool {
+        self.required.is_empty() && self.any_of.is_empty() && self.excluded.is_empty()
+    }
+
+    /// Whether a concept name satisfies every constraint.
+    pub fn accepts(&self, concept_name: &str) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        let name = concept_name.to_lowercase();
+        self.required.iter().all(|phrase| name.contains(phrase))
+            && self
+                .any_of
+                .iter()
+                .all(|group| group.iter().any(|term| name.contains(term)))
+            && !self.excluded.iter().any(|term| name.contains(term))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Term {
+        text: String,
+        quoted: bool,
+        negated: bool,
+    },
+    And,
+    Or,
+}
+
+fn tokenize(input: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut negated = false;
+        if c == '-' {
+            chars.next();
+            match chars.peek() {
+                Some(next) if !next.is_whitespace() => negated = true,
+                // A lone dash is not an operator
+                _ => continue,
+            }
+        }
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            let text: String = chars.by_ref().take_while(|c| *c != '"').collect();
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !text.is_empty() {
+                tokens.push(Token::Term {
+                    text,
+                    quoted: true,
+                    negated,
+                });
+            }
+            continue;
+        }
+        let mut text = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() || c == '"' {
+                break;
+            }
+            text.push(c);
+            chars.next();
+        }
+        tokens.push(match text.as_str() {
+            "AND" if !negated => Token::And,
+            "OR" if !negated => Token::Or,
+            _ => Token::Term {
+                text,
+                quoted: false,
+                negated,
+            },
+        });
+    }
+    tokens
+}
+
+/// Splits a search query into the text that is searched for and the lexical constraints on the
+/// results. Queries without operators are returned unchanged with no constraints, so does a
+/// query that would leave nothing to search for, such as a lone exclusion.
+pub fn parse(input: &str) -> (String, QueryOperators) {
+    let input = input.trim();
+    let tokens = tokenize(input);
+    let has_operators = tokens.iter().any(|token| match token {
+        Token::Term {
+            quoted, negated, ..
+        } => *quoted || *negated,
+        Token::And | Token::Or => true,
+    });
+    if !has_operators {
+        return (input.to_string(), QueryOperators::default());
+    }
+
+    // Positive terms as typed, with the operators that link them to their neighbours
+    let mut terms: Vec<(String, bool)> = Vec::new();
+    let mut links: Vec<Option<&Token>> = Vec::new();
+    let mut pending: Option<&Token> = None;
+    let mut operators = QueryOperators::default();
+    for token in &tokens {
+        match token {
+            Token::And | Token::Or => pending = Some(token),
+            Token::Term {
+                text,
+                negated: true,
+                ..
+            } => {
+                operators.excluded.push(text.to_lowercase());
+                pending = None;
+            }
+            Token::Term { text, quoted, .. } => {
+                if !terms.is_empty() {
+                    links.push(pending);
+                }
+                terms.push((text.clone(), *quoted));
+                pending = None;
+            }
+        }
+    }
+    if terms.is_empty() {
+        return (input.to_string(), QueryOperators::default());
+    }
+
+    // Terms chained by OR form a group, the others are required when quoted or joined by AND
+    let mut start = 0;
+    for end in 0..terms.len() {
+        let or_follows = links.get(end) == Some(&Some(&Token::Or));
+        if or_follows {
+            continue;
+        }
+        if end > start {
+            operators.any_of.push(
+                terms[start..=end]
+                    .iter()
+                    .map(|(term, _)| term.to_lowercase())
+                    .collect(),
+            );
+        } else {
+            let (term, quoted) = &terms[end];
+            let and_before = end > 0 && links[end - 1] == Some(&Token::And);
+            let and_after = links.get(end) == Some(&Some(&Token::And));
+            if *quoted || and_before || and_after {
+                operators.required.push(term.to_lowercase());
+            }
+        }
+        start = end + 1;
+    }
+
+    let text = terms
+        .into_iter()
+        .map(|(term, _)| term)
+        .collect::<Vec<_>>()
+        .join(" ");
+    (text, operators)
+}
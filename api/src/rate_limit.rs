@@ -0,0 +1,197 @@
+use crate::StateWrapper;
+use crate::auth::{self, Identity};
+use crate::config::RateLimitConfig;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::middleware::Next;
+use actix_web::web::Data;
+use actix_web::{Error, HttpMessage, HttpResponse};
+use log::info;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Buckets kept before idle ones, which would be full anyway, are dropped.
+const MAX_BUCKETS: usize = 10_000;
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// When the bucket will have refilled completely and can be forgotten.
+    full_at: Instant,
+}
+
+/// The quota a request counts against: its rate, its burst and the route prefix it applies to,
+/// empty for the default.
+#[derive(Debug, Clone, Copy)]
+struct Quota<'a> {
+    path: &'a str,
+    per_second: f64,
+    burst: f64,
+}
+
+/// Token buckets per client and quota, see `RateLimitConfig`.
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<(String, String), Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        RateLimiter {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn quota(&self, path: &str) -> Quota<'_> {
+        self.config
+            .routes
+            .iter()
+            .filter(|route| path.starts_with(&route.path))
+            .max_by_key(|route| route.path.len())
+            .map_or(
+                Quota {
+                    path: "",
+                    per_second: self.config.requests_per_minute as f64 / 60.0,
+                    burst: self.config.burst as f64,
+                },
+                |route| Quota {
+                    path: &route.path,
+                    per_second: route.requests_per_minute as f64 / 60.0,
+                    burst: route.burst as f64,
+                },
+            )
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Takes a token for the client, or returns how long until one is available.
+    pub fn acquire(&self, client: &str, path: &str) -> Result<(), Duration> {
+        let quota = self.quota(path);
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_BUCKETS {
+            buckets.retain(|_, bucket| bucket.full_at > now);
+        }
+        let bucket = buckets
+            .entry((client.to_string(), quota.path.to_string()))
+            .or_insert(Bucket {
+                tokens: quota.burst,
+                updated: now,
+                full_at: now,
+            });
+        let refilled = (now - bucket.updated).as_secs_f64() * quota.per_second;
+        bucket.tokens = (bucket.tokens + refilled).min(quota.burst);
+        bucket.updated = now;
+        if quota.per_second <= 0.0 {
+            return Err(Duration::from_secs(60));
+        }
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            bucket.full_at =
+                now + Duration::from_secs_f64((quota.burst - bucket.tokens) / quota.per_second);
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64(
+            (1.0 - bucket.tokens) / quota.per_second,
+        ))
+    }
+}
+
+/// The bucket of the authenticated caller, or of the client's address for anonymous requests.
+pub fn client_key_of(identity: Option<&Identity>, address: Option<String>) -> String {
+    match identity {
+        Some(identity) => format!("identity:{}", identity.name),
+        None => format!("ip:{}", address.unwrap_or_default()),
+    }
+}
+
+/// Seconds a client over its quota is told to wait, at least one.
+pub fn retry_after_secs(wait: Duration) -> u64 {
+    wait.as_secs_f64().ceil().max(1.0) as u64
+}
+
+/// The client's address: the `X-Forwarded-For` entry the outermost trusted proxy appended when
+/// forwarded addresses are trusted, the peer address otherwise. Entries further left are
+/// supplied by the client and could be rotated to get a fresh bucket with every request.
+fn client_address(req: &ServiceRequest, config: &RateLimitConfig) -> Option<String> {
+    let forwarded = config
+        .trust_forwarded_for
+        .then(|| req.headers().get("x-forwarded-for"))
+        .flatten()
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            value
+                .rsplit(',')
+                .nth(config.forwarded_for_hops.max(1) - 1)
+                .map(|address| address.trim().to_string())
+        })
+        .filter(|address| !address.is_empty());
+    forwarded.or_else(|| req.peer_addr().map(|peer| peer.ip().to_string()))
+}
+
+fn client_key(req: &ServiceRequest, config: &RateLimitConfig) -> String {
+    if let Some(identity) = req.extensions().get::<Identity>() {
+        return client_key_of(Some(identity), None);
+    }
+    client_key_of(None, client_address(req, config))
+}
+
+/// Takes a token for the client, or answers `429 Too Many Requests` with a `Retry-After` header.
+fn throttle(state: &StateWrapper, client: &str, path: &str) -> Option<HttpResponse> {
+    let wait = state.rate_limiter.acquire(client, path).err()?;
+    let retry_after = retry_after_secs(wait);
+    info!(
+        "Rate limiting {} on {}, retry in {}s",
+        client, path, retry_after
+    );
+    Some(
+        HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, retry_after.to_string()))
+            .json(serde_json::json!({
+                "error": "Too many requests, slow down",
+                "retry_after_secs": retry_after,
+            })),
+    )
+}
+
+/// Counts a request that failed authentication against the bucket of its address, which
+/// `limit_requests` never sees, so guessing keys is limited like anonymous requests. Returns the
+/// `429` to answer with instead of the `401` once the address is over its quota.
+pub fn throttle_unauthenticated(
+    state: &StateWrapper,
+    req: &ServiceRequest,
+) -> Option<HttpResponse> {
+    if !state.rate_limiter.enabled() {
+        return None;
+    }
+    let client = client_key_of(None, client_address(req, &state.rate_limiter.config));
+    throttle(state, &client, req.path())
+}
+
+/// Middleware answering clients over their quota with `429 Too Many Requests` and a
+/// `Retry-After` header. Runs after authentication so keys are limited rather than addresses.
+pub async fn limit_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let state = match req.app_data::<Data<StateWrapper>>() {
+        Some(state) if state.rate_limiter.enabled() => state.clone(),
+        _ => return next.call(req).await.map(|res| res.map_into_boxed_body()),
+    };
+    if req.method() == Method::OPTIONS || auth::is_public(req.path()) {
+        return next.call(req).await.map(|res| res.map_into_boxed_body());
+    }
+    let client = client_key(&req, &state.rate_limiter.config);
+    if let Some(response) = throttle(&state, &client, req.path()) {
+        return Ok(req.into_response(response));
+    }
+    next.call(req).await.map(|res| res.map_into_boxed_body())
+}
@@ -0,0 +1,331 @@
+use crate::StateWrapper;
+use crate::auth::Identity;
+use crate::concept_sets::author;
+use crate::db;
+use crate::domain::{NewRecommendationFeedback, RecommendationFeedback};
+use crate::errors::{ApiError, EmbeddingError, PgError};
+use crate::profiles::ValidationProfile;
+use crate::slo::SHED_RECOMMENDATIONS_WARNING;
+use crate::snapshot;
+use crate::validation::{
+    self, ConceptRecommendations, ConceptSetExpression, ConceptSetItem, RecommendationOptions,
+    TextExamples,
+};
+use actix_web::web::{Data, Json, ReqData};
+use actix_web::{Error, HttpResponse, ResponseError, post};
+use log::info;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+/// The most recommendations, and Qdrant points per query, a request may ask for.
+const MAX_RECOMMENDATIONS: u64 = 2000;
+/// The most phrases a request may steer by, each is an embedding and a query of its own.
+const MAX_TEXT_EXAMPLES: usize = 10;
+
+/// Either a concept set expression or the seed concepts to recommend from.
+#[derive(Deserialize, ToSchema)]
+struct RecommendationRequest {
+    /// The concept set as ATLAS JSON, instead of `concept_ids`.
+    concept_set: Option<String>,
+    /// Concepts to find similar ones for, as if they were the included items of a concept set
+    /// without descendants.
+    #[serde(default)]
+    #[schema(example = json!([201826]))]
+    concept_ids: Vec<i32>,
+    /// Scopes the recommendations to the profile's domains.
+    #[serde(default)]
+    profile: ValidationProfile,
+    /// Phrases to recommend concepts like, next to the concepts, e.g. "renal impairment".
+    #[serde(default)]
+    #[schema(max_items = 10)]
+    positive_texts: Vec<String>,
+    /// Phrases to steer recommendations away from, like excluded items.
+    #[serde(default)]
+    #[schema(max_items = 10)]
+    negative_texts: Vec<String>,
+    #[serde(flatten)]
+    options: RecommendationOptions,
+}
+
+/// What a reviewer made of a recommendation.
+#[derive(Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum Verdict {
+    Accepted,
+    Rejected,
+}
+
+impl Verdict {
+    fn as_str(self) -> &'static str {
+        match self {
+            Verdict::Accepted => "accepted",
+            Verdict::Rejected => "rejected",
+        }
+    }
+}
+
+/// A decision on one recommended concept, with the recommendation as it was shown.
+#[derive(Deserialize, ToSchema)]
+struct FeedbackRequest {
+    /// The stored concept set, or the ATLAS one, the concept was recommended for.
+    concept_set_id: Option<i64>,
+    #[schema(example = 4110056)]
+    concept_id: i32,
+    verdict: Verdict,
+    source_concept_id: Option<i32>,
+    source_text: Option<String>,
+    #[schema(minimum = 0, maximum = 1)]
+    similarity_score: Option<f32>,
+    /// The reviewer when the request is not authenticated.
+    created_by: Option<String>,
+}
+
+/// Rejects options outside what a similarity and Qdrant accept, and spells the domains as the
+/// vocabulary does since recommended concepts are matched on them exactly.
+fn checked_options(
+    state: &StateWrapper,
+    mut options: RecommendationOptions,
+) -> Result<RecommendationOptions, ApiError> {
+    if let Some(min_score) = options.min_score
+        && !(0.0..=1.0).contains(&min_score)
+    {
+        return Err(ApiError::OutOfRange {
+            parameter: "min_score",
+            min: 0.0,
+            max: 1.0,
+        });
+    }
+    if let Some(limit) = options.limit
+        && !(1..=MAX_RECOMMENDATIONS).contains(&limit)
+    {
+        return Err(ApiError::OutOfRange {
+            parameter: "limit",
+            min: 1.0,
+            max: MAX_RECOMMENDATIONS as f64,
+        });
+    }
+    if let Some(diversity) = options.diversity
+        && !(0.0..=1.0).contains(&diversity)
+    {
+        return Err(ApiError::OutOfRange {
+            parameter: "diversity",
+            min: 0.0,
+            max: 1.0,
+        });
+    }
+    let catalog = &state.vocabulary_catalog;
+    catalog.validate_domain_ids(options.domains.as_deref())?;
+    if let Some(domains) = &options.domains
+        && let Some(canonical) = catalog.canonical_domain_ids(domains)
+    {
+        options.domains = Some(canonical);
+    }
+    Ok(options)
+}
+
+/// The phrases with surrounding whitespace removed, leaving out empty ones.
+fn trimmed(texts: &[String]) -> Vec<String> {
+    texts
+        .iter()
+        .map(|text| text.trim())
+        .filter(|text| !text.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Embeds the phrases in one go. A mismatching embedder answers like it does for search,
+/// other failures as the bad gateway they are.
+async fn embed_texts(
+    state: &StateWrapper,
+    positive: Vec<String>,
+    negative: Vec<String>,
+) -> Result<TextExamples, HttpResponse> {
+    if positive.is_empty() && negative.is_empty() {
+        return Ok(TextExamples::default());
+    }
+    let inputs: Vec<String> = positive.iter().chain(&negative).cloned().collect();
+    let mut vectors =
+        state.embedder.embed_batch(inputs).await.map_err(|e| {
+            match e.downcast_ref::<EmbeddingError>() {
+                Some(mismatch) => mismatch.error_response(),
+                None => HttpResponse::BadGateway().json(serde_json::json!({
+                    "error": format!("Could not embed the phrases: {}", e)
+                })),
+            }
+        })?;
+    let negative_vectors = vectors.split_off(positive.len());
+    Ok(TextExamples {
+        positive: positive.into_iter().zip(vectors).collect(),
+        negative: negative_vectors,
+    })
+}
+
+/// The concept set expression of a request, or its seed concepts as included items without
+/// descendants. Requests passing neither or both are refused with the message.
+pub(crate) async fn seed_expression(
+    pg_client: &deadpool_postgres::Client,
+    concept_set: Option<&str>,
+    concept_ids: &[i32],
+) -> Result<Result<ConceptSetExpression, String>, PgError> {
+    Ok(match (concept_set, concept_ids.is_empty()) {
+        (Some(concept_set), true) => validation::parse_concept_set(concept_set),
+        (None, false) => Ok(ConceptSetExpression {
+            items: db::get_concepts_by_ids(pg_client, concept_ids)
+                .await?
+                .into_iter()
+                .map(|concept| ConceptSetItem {
+                    concept,
+                    is_excluded: false,
+                    include_descendants: false,
+                    include_mapped: false,
+                })
+                .collect(),
+        }),
+        _ => Err("Pass either concept_set or concept_ids".to_string()),
+    })
+}
+
+/// The recommendations of the analysis on their own, without the validation checks and the
+/// resolution of the concept set, for "suggest more concepts" in editors.
+#[utoipa::path(
+    tag = "concept sets",
+    summary = "Recommend concepts",
+    description = "The recommendations of the concept set analysis without its validation checks and \
+        resolution, for suggesting more concepts while editing. Takes a concept set expression, or seed \
+        concept IDs treated as included items without descendants. Concepts already in the set, including \
+        descendants of items that include them, are not recommended.",
+    responses(
+        (status = 200, description = "Recommended concepts in the blended order of the semantic and PHOEBE \
+            rankings", body = ConceptRecommendations),
+        (status = 400, description = "Neither or both of concept_set and concept_ids were passed, the concept \
+            set could not be parsed, there were no concepts to recommend from, there were more than 10 \
+            phrases, or an option is out of range or names an unknown domain"),
+        (status = 502, description = "The phrases could not be embedded"),
+        (status = 503, description = "Recommendations are shed because search is over its latency budget, or \
+            the embedder returns vectors the collections were not built with"),
+    )
+)]
+#[post("/api/recommendations")]
+async fn recommend_concepts(
+    request: Json<RecommendationRequest>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let request = request.into_inner();
+    info!("Received recommendation request");
+    let options = checked_options(&state, request.options)?;
+    if state.latency.shed_recommendations() {
+        return Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": SHED_RECOMMENDATIONS_WARNING
+        })));
+    }
+    let positive_texts = trimmed(&request.positive_texts);
+    let negative_texts = trimmed(&request.negative_texts);
+    if positive_texts.len() + negative_texts.len() > MAX_TEXT_EXAMPLES {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Pass at most {} phrases", MAX_TEXT_EXAMPLES)
+        })));
+    }
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let expression = match seed_expression(
+        &pg_client,
+        request.concept_set.as_deref(),
+        &request.concept_ids,
+    )
+    .await?
+    {
+        Ok(expression) => expression,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+        }
+    };
+    if expression.items.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "There are no concepts to recommend from"
+        })));
+    }
+    if state.config.demo.enabled && expression.items.len() > state.config.demo.max_concept_set_items
+    {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!(
+                "The demo instance recommends for at most {} concepts",
+                state.config.demo.max_concept_set_items
+            )
+        })));
+    }
+
+    let texts = match embed_texts(&state, positive_texts, negative_texts).await {
+        Ok(texts) => texts,
+        Err(response) => return Ok(response),
+    };
+
+    let recommendations = validation::get_concept_recommendations(
+        &expression,
+        &pg_client,
+        &state.qdrant_client,
+        &state.qdrant_read_options,
+        &snapshot::current(&state),
+        &options,
+        &texts,
+        request.profile,
+        state.config.recommendation_budget(),
+        &state.recommendation_cache,
+        &state.metrics.recommendation_cache,
+        state.vocabulary_catalog.vocabulary_version.as_deref(),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(recommendations))
+}
+
+/// Records a reviewer accepting or rejecting a recommended concept, for auditing reviews and
+/// for evaluating changes to the ranking against real decisions.
+#[utoipa::path(
+    tag = "concept sets",
+    summary = "Record a decision on a recommendation",
+    description = "Stores a reviewer accepting or rejecting a recommended concept, with the recommendation as \
+        it was shown and the vocabulary version. `created_by` is ignored for authenticated requests.",
+    responses(
+        (status = 201, description = "The recorded decision", body = RecommendationFeedback),
+        (status = 400, description = "Unknown verdict or a similarity score out of range"),
+    )
+)]
+#[post("/api/recommendations/feedback")]
+async fn record_feedback(
+    request: Json<FeedbackRequest>,
+    identity: Option<ReqData<Identity>>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let request = request.into_inner();
+    if let Some(similarity_score) = request.similarity_score
+        && !(0.0..=1.0).contains(&similarity_score)
+    {
+        return Err(ApiError::OutOfRange {
+            parameter: "similarity_score",
+            min: 0.0,
+            max: 1.0,
+        }
+        .into());
+    }
+    let created_by = author(identity, request.created_by.as_deref());
+    info!(
+        "Recording {} recommendation {} for concept set {:?}",
+        request.verdict.as_str(),
+        request.concept_id,
+        request.concept_set_id
+    );
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let feedback = db::insert_recommendation_feedback(
+        &pg_client,
+        &NewRecommendationFeedback {
+            concept_set_id: request.concept_set_id,
+            concept_id: request.concept_id,
+            source_concept_id: request.source_concept_id,
+            source_text: request.source_text.as_deref(),
+            similarity_score: request.similarity_score,
+            verdict: request.verdict.as_str(),
+            vocabulary_version: state.vocabulary_catalog.vocabulary_version.as_deref(),
+            created_by: created_by.as_deref(),
+        },
+    )
+    .await?;
+    Ok(HttpResponse::Created().json(feedback))
+}
@@ -0,0 +1,270 @@
+use crate::StateWrapper;
+use crate::db;
+use crate::errors::PgError;
+use crate::expansions;
+use crate::profiles::ValidationProfile;
+use crate::shared_cache::SharedCache;
+use crate::slo::SHED_RECOMMENDATIONS_WARNING;
+use crate::snapshot;
+use crate::validation::{
+    self, ConceptSetAnalysis, ConceptSetExpression, RecommendationOptions, TextExamples,
+    ValidationResult,
+};
+use actix_web::web::{Data, Json};
+use actix_web::{Error, HttpResponse, post};
+use deadpool_postgres::Client;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use utoipa::ToSchema;
+
+/// How many descendants are shown per item in the review screen.
+const PREVIEW_DESCENDANTS: usize = 5;
+
+#[derive(Deserialize, ToSchema)]
+struct ConceptSetReviewRequest {
+    /// The concept set expression, or a concept set with its expression, as JSON text.
+    concept_set: String,
+    #[serde(default)]
+    profile: ValidationProfile,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ConceptPreview {
+    pub concept_id: i32,
+    pub concept_name: String,
+}
+
+/// One concept set item as the review screen shows it: the concept itself, how far it expands
+/// and a few of the descendants it pulls in.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ItemPreview {
+    pub concept_id: i32,
+    pub concept_name: String,
+    pub vocabulary_id: String,
+    pub domain_id: String,
+    pub is_excluded: bool,
+    pub include_descendants: bool,
+    pub include_mapped: bool,
+    pub descendant_count: usize,
+    pub sample_descendants: Vec<ConceptPreview>,
+}
+
+/// The resolved concepts broken down by vocabulary and domain.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct CrosswalkSummary {
+    pub total: usize,
+    pub standard: usize,
+    pub non_standard: usize,
+    pub by_vocabulary: BTreeMap<String, usize>,
+    pub by_domain: BTreeMap<String, usize>,
+}
+
+/// The analysis with the item previews and the crosswalk of the resolved concepts.
+#[derive(Serialize, ToSchema)]
+struct ConceptSetReview<'a> {
+    #[serde(flatten)]
+    analysis: ConceptSetAnalysis<'a>,
+    items: Vec<ItemPreview>,
+    crosswalk: CrosswalkSummary,
+}
+
+async fn validate_and_resolve(
+    expression: &ConceptSetExpression,
+    pg_client: &Client,
+    profile: ValidationProfile,
+    vocabulary_version: Option<&str>,
+) -> (ValidationResult, CrosswalkSummary) {
+    let mut result = ValidationResult::new();
+    result.profile = profile;
+    validation::run_checks(&mut result, expression, pg_client, profile).await;
+    let concept_summary =
+        expansions::resolve_concept_set(expression, pg_client, vocabulary_version, &mut result)
+            .await;
+    let crosswalk =
+        match crosswalk_summary(&concept_summary.resolved_concept_ids(), pg_client).await {
+            Ok(crosswalk) => crosswalk,
+            Err(e) => {
+                result.add_warning(format!("Could not summarize resolved concepts: {}", e));
+                CrosswalkSummary::default()
+            }
+        };
+    result.concept_summary = Some(concept_summary);
+    (result, crosswalk)
+}
+
+async fn crosswalk_summary(
+    resolved: &[i32],
+    pg_client: &Client,
+) -> Result<CrosswalkSummary, PgError> {
+    let mut summary = CrosswalkSummary::default();
+    for concept in db::get_concepts_by_ids(pg_client, resolved).await? {
+        summary.total += 1;
+        match concept.standard_concept.as_deref() {
+            Some("S") => summary.standard += 1,
+            _ => summary.non_standard += 1,
+        }
+        *summary
+            .by_vocabulary
+            .entry(concept.vocabulary_id)
+            .or_default() += 1;
+        *summary.by_domain.entry(concept.domain_id).or_default() += 1;
+    }
+    Ok(summary)
+}
+
+async fn item_previews(
+    expression: &ConceptSetExpression,
+    pg_client: &Client,
+    shared_cache: &SharedCache,
+) -> Result<Vec<ItemPreview>, PgError> {
+    let with_descendants: Vec<i32> = expression
+        .items
+        .iter()
+        .filter(|item| item.include_descendants)
+        .map(|item| item.concept.concept_id)
+        .collect();
+    let descendants = shared_cache
+        .get_batch_descendant_concepts(pg_client, &with_descendants)
+        .await?;
+
+    let mut sample_ids: Vec<i32> = descendants
+        .values()
+        .flat_map(|ids| ids.iter().take(PREVIEW_DESCENDANTS))
+        .copied()
+        .collect();
+    sample_ids.sort();
+    sample_ids.dedup();
+    let names: HashMap<i32, String> = db::get_concepts_by_ids(pg_client, &sample_ids)
+        .await?
+        .into_iter()
+        .map(|concept| (concept.concept_id, concept.concept_name))
+        .collect();
+
+    let previews = expression
+        .items
+        .iter()
+        .map(|item| {
+            let item_descendants = descendants
+                .get(&item.concept.concept_id)
+                .filter(|_| item.include_descendants);
+            ItemPreview {
+                concept_id: item.concept.concept_id,
+                concept_name: item.concept.concept_name.clone(),
+                vocabulary_id: item.concept.vocabulary_id.clone(),
+                domain_id: item.concept.domain_id.clone(),
+                is_excluded: item.is_excluded,
+                include_descendants: item.include_descendants,
+                include_mapped: item.include_mapped,
+                descendant_count: item_descendants.map_or(0, |ids| ids.len()),
+                sample_descendants: item_descendants
+                    .into_iter()
+                    .flatten()
+                    .take(PREVIEW_DESCENDANTS)
+                    .map(|id| ConceptPreview {
+                        concept_id: *id,
+                        concept_name: names.get(id).cloned().unwrap_or_default(),
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+    Ok(previews)
+}
+
+/// Everything the concept set review screen needs in one round trip. Validation, item
+/// previews and recommendations are independent and run concurrently, each on its own
+/// connection.
+#[utoipa::path(
+    tag = "concept sets",
+    summary = "Review a concept set",
+    description = "Everything the concept set review screen needs in one call. Returns the same validation \
+        findings, resolution counts and recommendations as the analyze endpoint, plus a preview per item \
+        (`items`) and a breakdown of the resolved concepts by vocabulary and domain (`crosswalk`). The parts \
+        are assembled concurrently.",
+    responses(
+        (status = 200, description = "Review of the concept set", body = ConceptSetReview),
+        (status = 400, description = "The concept set could not be parsed or has no items, or is above the \
+            demo instance's size limit"),
+    )
+)]
+#[post("/api/conceptsets/review")]
+async fn review_concept_set(
+    request: Json<ConceptSetReviewRequest>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    info!("Received concept set review request");
+    let expression = match validation::parse_concept_set(&request.concept_set) {
+        Ok(expression) if !expression.items.is_empty() => expression,
+        Ok(_) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Concept set expression contains no items"
+            })));
+        }
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+        }
+    };
+    if state.config.demo.enabled && expression.items.len() > state.config.demo.max_concept_set_items
+    {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!(
+                "The demo instance reviews concept sets of at most {} items",
+                state.config.demo.max_concept_set_items
+            )
+        })));
+    }
+
+    let snapshot = snapshot::current(&state);
+    let shed_recommendations = state.latency.shed_recommendations();
+    let validation_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let preview_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let recommendation_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+
+    let ((mut result, crosswalk), items, recommendations) = futures::join!(
+        validate_and_resolve(
+            &expression,
+            &validation_client,
+            request.profile,
+            state.vocabulary_catalog.vocabulary_version.as_deref(),
+        ),
+        item_previews(&expression, &preview_client, &state.shared_cache),
+        async {
+            if shed_recommendations {
+                return Ok(None);
+            }
+            validation::get_concept_recommendations(
+                &expression,
+                &recommendation_client,
+                &state.qdrant_client,
+                &state.qdrant_read_options,
+                &snapshot,
+                &RecommendationOptions::default(),
+                &TextExamples::default(),
+                request.profile,
+                state.config.recommendation_budget(),
+                &state.recommendation_cache,
+                &state.metrics.recommendation_cache,
+                state.vocabulary_catalog.vocabulary_version.as_deref(),
+            )
+            .await
+            .map(Some)
+        },
+    );
+
+    let items = items.unwrap_or_else(|e| {
+        result.add_warning(format!("Could not build item previews: {}", e));
+        Vec::new()
+    });
+    match recommendations {
+        Ok(Some(recommendations)) => result.recommendations = Some(recommendations),
+        Ok(None) => result.add_warning(SHED_RECOMMENDATIONS_WARNING.to_string()),
+        Err(e) => result.add_warning(format!("Could not generate recommendations: {}", e)),
+    }
+
+    Ok(HttpResponse::Ok().json(ConceptSetReview {
+        analysis: result.analysis(),
+        items,
+        crosswalk,
+    }))
+}
@@ -0,0 +1,1506 @@
+use crate::StateWrapper;
+use crate::boosting;
+use crate::catalog::VocabularyCatalog;
+use crate::config::{CandidateSource, FusionStrategy, SearchConfig};
+use crate::db;
+use crate::debug::{SearchTrace, candidate_summary};
+use crate::domain::{Concept, SearchDiagnostics, SearchFacets, SearchResponse};
+use crate::errors::{ApiError, EmbeddingError, SearchError};
+use crate::qdrant::{ReadOptions, WithReadOptions};
+use crate::query_operators::{self, QueryOperators};
+use crate::snapshot::{self, IndexSnapshot};
+use async_trait::async_trait;
+use log::{info, warn};
+use qdrant_client::qdrant::condition::ConditionOneOf;
+use qdrant_client::qdrant::point_id::PointIdOptions;
+use qdrant_client::qdrant::{
+    Condition, Filter, GetPointsBuilder, PointId, QueryPointsBuilder, RecommendInputBuilder,
+    RetrievedPoint, ScoredPoint, ScrollPointsBuilder, SearchPointsBuilder,
+};
+use qdrant_client::{Qdrant, QdrantError, qdrant};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{Instrument, info_span, instrument};
+use utoipa::ToSchema;
+
+/// The most candidates a search may request from Qdrant through `candidates`.
+pub const MAX_CANDIDATES: u64 = 2000;
+/// Points read per page when looking up a name, and the pages read at most.
+const NAME_SCROLL_PAGE_SIZE: u32 = 100;
+const NAME_SCROLL_MAX_PAGES: usize = 100;
+const FULL_TEXT_CANDIDATES: i64 = 50;
+
+/// How the query is matched against concept names.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMode {
+    /// The configured pipeline: exact lookups, full-text and embedding candidates.
+    #[default]
+    Auto,
+    /// Only names, synonyms and codes equal to the query, without embeddings or neighbours.
+    Exact,
+    /// Names and synonyms starting with the query, from the concept index.
+    Prefix,
+    /// Only the nearest neighbours of the query embedding.
+    Semantic,
+}
+
+impl MatchMode {
+    fn is_auto(&self) -> bool {
+        *self == MatchMode::Auto
+    }
+}
+
+/// What a search result stands for.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    /// A concept name or synonym with every concept carrying it, across vocabularies.
+    #[default]
+    Name,
+    /// A single concept, under its own name.
+    Concept,
+}
+
+impl GroupBy {
+    fn is_name(&self) -> bool {
+        *self == GroupBy::Name
+    }
+}
+
+/// Orders grouped results can be sorted in instead of relevance, for reviewing them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    Score,
+    /// The name or synonym of the result, case-insensitively.
+    Name,
+    /// The smallest concept id of the result.
+    ConceptId,
+    /// The first vocabulary of the result's concepts, alphabetically.
+    Vocabulary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortKey {
+    /// Sorts the results, highest scores first and the other keys ascending unless `order`
+    /// says otherwise. Results that tie keep their relevance order.
+    pub fn sort(self, results: &mut [SearchResponse], order: Option<SortOrder>) {
+        let order = order.unwrap_or(match self {
+            SortKey::Score => SortOrder::Desc,
+            _ => SortOrder::Asc,
+        });
+        let ascending = |a: &SearchResponse, b: &SearchResponse| match self {
+            SortKey::Score => a
+                .score
+                .unwrap_or(f64::NEG_INFINITY)
+                .total_cmp(&b.score.unwrap_or(f64::NEG_INFINITY)),
+            SortKey::Name => a.concept_name_lower.cmp(&b.concept_name_lower),
+            SortKey::ConceptId => {
+                let first =
+                    |result: &SearchResponse| result.concepts.iter().map(|c| c.concept_id).min();
+                first(a).cmp(&first(b))
+            }
+            SortKey::Vocabulary => {
+                let first = |result: &SearchResponse| {
+                    result
+                        .concepts
+                        .iter()
+                        .map(|c| c.vocabulary_id.to_lowercase())
+                        .min()
+                };
+                first(a)
+                    .cmp(&first(b))
+                    .then_with(|| a.concept_name_lower.cmp(&b.concept_name_lower))
+            }
+        };
+        match order {
+            SortOrder::Asc => results.sort_by(ascending),
+            SortOrder::Desc => results.sort_by(|a, b| ascending(b, a)),
+        }
+    }
+}
+
+/// The filter parameters of `/api/search`.
+#[derive(Debug, Default, Clone, Serialize, ToSchema)]
+pub struct SearchFilters {
+    pub vocabulary_id: Option<Vec<String>>,
+    pub standard_concept: Option<String>,
+    pub domain_id: Option<Vec<String>>,
+    pub concept_class_id: Option<Vec<String>>,
+    /// Not a filter as such, but like the filters part of what identifies a search in caches
+    /// and cursors. Left out there when `auto`, so those keys did not change with it.
+    #[serde(rename = "match", skip_serializing_if = "MatchMode::is_auto")]
+    pub match_mode: MatchMode,
+    #[serde(skip_serializing_if = "GroupBy::is_name")]
+    pub group_by: GroupBy,
+    /// Overrides `SEARCH__SCORE_THRESHOLD`. Like `match_mode`, left out of keys when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score_threshold: Option<f32>,
+    /// Overrides both Qdrant candidate limits of the search configuration.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub candidates: Option<u64>,
+}
+
+impl SearchFilters {
+    /// Rejects a score threshold that is not a similarity and candidate limits Qdrant should
+    /// not be asked for.
+    pub fn validate_tuning(&self) -> Result<(), ApiError> {
+        if let Some(score_threshold) = self.score_threshold
+            && !(0.0..=1.0).contains(&score_threshold)
+        {
+            return Err(ApiError::OutOfRange {
+                parameter: "score_threshold",
+                min: 0.0,
+                max: 1.0,
+            });
+        }
+        if let Some(candidates) = self.candidates
+            && !(1..=MAX_CANDIDATES).contains(&candidates)
+        {
+            return Err(ApiError::OutOfRange {
+                parameter: "candidates",
+                min: 1.0,
+                max: MAX_CANDIDATES as f64,
+            });
+        }
+        Ok(())
+    }
+
+    /// The name of every filter, in the order they are checked, that rejects the concept.
+    pub fn rejections(&self, concept: &Concept) -> [Option<&'static str>; 4] {
+        let mut rejections = [None; 4];
+
+        // Filter by vocabulary_id
+        if let Some(vocab_ids) = &self.vocabulary_id
+            && !vocab_ids
+                .iter()
+                .any(|id| id.eq_ignore_ascii_case(&concept.vocabulary_id))
+        {
+            rejections[0] = Some("vocabulary_id");
+        }
+
+        // Filter by standard_concept
+        if let Some(std_concept) = &self.standard_concept {
+            match concept.standard_concept.as_ref() {
+                Some(sc) if sc == std_concept => {}
+                None if std_concept.is_empty() => {}
+                _ => rejections[1] = Some("standard_concept"),
+            }
+        }
+
+        // Filter by domain_id
+        if let Some(domain_ids) = &self.domain_id
+            && !domain_ids
+                .iter()
+                .any(|id| id.eq_ignore_ascii_case(&concept.domain_id))
+        {
+            rejections[2] = Some("domain_id");
+        }
+
+        // Filter by concept_class_id
+        if let Some(class_ids) = &self.concept_class_id
+            && !class_ids
+                .iter()
+                .any(|id| id.eq_ignore_ascii_case(&concept.concept_class_id))
+        {
+            rejections[3] = Some("concept_class_id");
+        }
+
+        rejections
+    }
+}
+
+/// The filters as a Qdrant condition on the concepts of a point, so narrow filters do not use
+/// up the candidates on points whose concepts are all filtered out afterwards. A point matches
+/// when one of its concepts meets every condition. Values the catalog does not know, and the
+/// empty `standard_concept` that stands for non-standard concepts, are only applied in
+/// `filter_concepts`, which checks every concept again either way.
+pub(crate) fn payload_filter(
+    filters: &SearchFilters,
+    catalog: &VocabularyCatalog,
+) -> Option<Filter> {
+    let mut conditions = Vec::new();
+    let keyword_filters = [
+        (
+            "vocabulary_id",
+            filters
+                .vocabulary_id
+                .as_deref()
+                .and_then(|ids| catalog.canonical_vocabulary_ids(ids)),
+        ),
+        (
+            "domain_id",
+            filters
+                .domain_id
+                .as_deref()
+                .and_then(|ids| catalog.canonical_domain_ids(ids)),
+        ),
+        (
+            "concept_class_id",
+            filters
+                .concept_class_id
+                .as_deref()
+                .and_then(|ids| catalog.canonical_concept_class_ids(ids)),
+        ),
+    ];
+    for (key, values) in keyword_filters {
+        if let Some(values) = values
+            && !values.is_empty()
+        {
+            conditions.push(Condition::matches(key, values));
+        }
+    }
+    if let Some(standard_concept) = &filters.standard_concept
+        && !standard_concept.is_empty()
+    {
+        conditions.push(Condition::matches(
+            "standard_concept",
+            standard_concept.clone(),
+        ));
+    }
+    (!conditions.is_empty())
+        .then(|| Filter::must([Condition::nested("concepts", Filter::must(conditions))]))
+}
+
+/// The filters that have facets, see `SearchFacets`.
+const FACETS: [&str; 4] = [
+    "vocabulary_id",
+    "standard_concept",
+    "domain_id",
+    "concept_class_id",
+];
+
+/// A search request as it travels through the pipeline.
+pub struct SearchQuery {
+    /// The query as typed, trimmed.
+    pub input: String,
+    /// The query as produced by the normalize stage, used for exact lookups.
+    pub normalized: String,
+    pub filters: SearchFilters,
+    /// Phrase, `AND`/`OR` and exclusion constraints taken out of the query, see `query_operators`.
+    pub operators: QueryOperators,
+    pub limit: u64,
+    /// Least similarity of the embedding neighbours.
+    pub score_threshold: f32,
+    /// Points requested from Qdrant for the query embedding, and names read for prefixes.
+    pub embedding_candidates: u64,
+    /// Points requested from Qdrant as neighbours of exact matches.
+    pub neighbour_candidates: u64,
+    /// The query embedding when it was computed ahead, such as once for a whole batch.
+    pub embedding: Option<Vec<f32>>,
+}
+
+pub struct SearchContext<'a> {
+    pub state: &'a StateWrapper,
+    /// Taken once per request, every stage reads the same collection and concept index.
+    pub snapshot: Arc<IndexSnapshot>,
+    pub query: &'a SearchQuery,
+    /// Set when a debug bundle is captured, stages record what they did into it.
+    pub trace: Option<&'a RefCell<SearchTrace>>,
+}
+
+impl SearchContext<'_> {
+    /// Records a trace event; `detail` is only built when the request is traced. Postgres, Qdrant
+    /// and embedding calls are also timed in the metrics, traced or not.
+    pub fn trace(
+        &self,
+        stage: &str,
+        kind: &'static str,
+        elapsed: Duration,
+        detail: impl FnOnce() -> Value,
+    ) {
+        let dependency = match kind {
+            "sql" => Some("postgres"),
+            "qdrant" => Some("qdrant"),
+            "embedding" => Some("embedding"),
+            _ => None,
+        };
+        if let Some(dependency) = dependency {
+            self.state
+                .metrics
+                .record_dependency(dependency, stage, elapsed);
+        }
+        if let Some(trace) = self.trace {
+            trace.borrow_mut().record(stage, kind, detail(), elapsed);
+        }
+    }
+}
+
+fn summarize(candidates: &[SearchResponse]) -> Value {
+    candidate_summary(
+        candidates
+            .iter()
+            .map(|candidate| (candidate.concept_name.as_str(), candidate.score)),
+    )
+}
+
+/// Turns the raw query into the form exact lookups are keyed on.
+pub trait Normalizer: Send + Sync {
+    fn normalize(&self, input: &str) -> String;
+}
+
+/// A source of candidate results: the curated overrides, the concept index, a lexical lookup in
+/// the vocabulary or the vector store.
+#[async_trait(?Send)]
+pub trait CandidateGenerator: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn generate(
+        &self,
+        ctx: &SearchContext<'_>,
+        diagnostics: &mut SearchDiagnostics,
+    ) -> Result<Vec<SearchResponse>, SearchError>;
+}
+
+/// Drops concepts that do not match the request, and candidates left without concepts.
+pub trait CandidateFilter: Send + Sync {
+    fn filter(
+        &self,
+        candidates: Vec<SearchResponse>,
+        query: &SearchQuery,
+        diagnostics: &mut SearchDiagnostics,
+    ) -> Vec<SearchResponse>;
+}
+
+/// Combines the candidate lists of the generators into one.
+pub trait Fusion: Send + Sync {
+    /// Whether candidate generation stops at the first generator that produced candidates.
+    fn short_circuits(&self) -> bool;
+    /// Candidate lists come labelled with the name of the generator that produced them.
+    fn fuse(
+        &self,
+        candidate_lists: Vec<(&'static str, Vec<SearchResponse>)>,
+    ) -> Vec<SearchResponse>;
+}
+
+/// Orders the fused candidates, best first.
+pub trait Reranker: Send + Sync {
+    fn rerank(&self, candidates: &mut Vec<SearchResponse>, ctx: &SearchContext<'_>);
+}
+
+/// Collapses candidates into the results returned to the client and applies the limit.
+pub trait Grouper: Send + Sync {
+    fn group(&self, candidates: Vec<SearchResponse>, limit: u64) -> Vec<SearchResponse>;
+}
+
+/// A complete, unpaged search result as kept in the search cache.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CachedSearch {
+    pub results: Vec<SearchResponse>,
+    /// Missing from entries cached before there were facets.
+    #[serde(default)]
+    pub facets: SearchFacets,
+    pub diagnostics: SearchDiagnostics,
+}
+
+/// normalize → candidate generation → filter → fuse → rerank → group, with every stage
+/// swappable. Built once at startup from `SearchConfig`.
+pub struct SearchPipeline {
+    normalizer: Box<dyn Normalizer>,
+    generators: Vec<Box<dyn CandidateGenerator>>,
+    /// The generators of the match modes other than `auto`, which ignore the configured ones.
+    exact_generators: Vec<Box<dyn CandidateGenerator>>,
+    prefix_generators: Vec<Box<dyn CandidateGenerator>>,
+    semantic_generators: Vec<Box<dyn CandidateGenerator>>,
+    filter: Box<dyn CandidateFilter>,
+    fusion: Box<dyn Fusion>,
+    reranker: Box<dyn Reranker>,
+    grouper: Box<dyn Grouper>,
+    /// The grouper of `group_by=concept`.
+    concept_grouper: Box<dyn Grouper>,
+}
+
+impl SearchPipeline {
+    pub fn from_config(config: &SearchConfig) -> SearchPipeline {
+        let generators = config
+            .candidate_generators
+            .iter()
+            .map(|source| -> Box<dyn CandidateGenerator> {
+                match source {
+                    CandidateSource::Override => Box::new(SynonymOverrideGenerator),
+                    CandidateSource::Index => Box::new(ConceptIndexGenerator),
+                    CandidateSource::Vocabulary => Box::new(VocabularyGenerator),
+                    CandidateSource::Embedding => Box::new(EmbeddingGenerator),
+                    CandidateSource::FullText => Box::new(FullTextGenerator),
+                }
+            })
+            .collect();
+        let fusion: Box<dyn Fusion> = match config.fusion {
+            FusionStrategy::Cascade => Box::new(CascadeFusion),
+            FusionStrategy::Union => Box::new(UnionFusion),
+            FusionStrategy::Hybrid => {
+                for source in [CandidateSource::FullText, CandidateSource::Embedding] {
+                    if !config.candidate_generators.contains(&source) {
+                        warn!(
+                            "Hybrid fusion without the {:?} candidate generator only sees half the blend",
+                            source
+                        );
+                    }
+                }
+                Box::new(HybridFusion {
+                    lexical_weight: config.lexical_weight.clamp(0.0, 1.0),
+                })
+            }
+        };
+        let reranker: Box<dyn Reranker> = if config.boosting {
+            Box::new(BoostingReranker)
+        } else {
+            Box::new(ScoreReranker)
+        };
+        info!(
+            "Search pipeline: {:?} candidates, {:?} fusion, boosting {}",
+            config.candidate_generators, config.fusion, config.boosting
+        );
+        SearchPipeline {
+            normalizer: Box::new(LowercaseNormalizer),
+            generators,
+            exact_generators: vec![
+                Box::new(SynonymOverrideGenerator),
+                Box::new(ConceptIndexGenerator),
+                Box::new(VocabularyGenerator),
+            ],
+            prefix_generators: vec![Box::new(PrefixGenerator)],
+            semantic_generators: vec![Box::new(EmbeddingGenerator)],
+            filter: Box::new(ParameterFilter),
+            fusion,
+            reranker,
+            grouper: Box::new(ConceptNameGrouper),
+            concept_grouper: Box::new(ConceptGrouper),
+        }
+    }
+
+    /// The search cache key: searches that normalize to the same query against the same index
+    /// snapshot with the same filters return the same results, until the shared cache
+    /// `generation` moves on.
+    pub fn cache_key(
+        &self,
+        state: &StateWrapper,
+        input: &str,
+        filters: &SearchFilters,
+        generation: i64,
+    ) -> String {
+        let snapshot = snapshot::current(state);
+        format!(
+            "{}\u{1f}{}\u{1f}{}\u{1f}{}\u{1f}{}",
+            snapshot.collection,
+            snapshot.activated_at.timestamp_millis(),
+            generation,
+            self.normalizer.normalize(input.trim()),
+            serde_json::to_string(filters).unwrap_or_default()
+        )
+    }
+
+    pub async fn run(
+        &self,
+        state: &StateWrapper,
+        input: &str,
+        filters: SearchFilters,
+        limit: u64,
+        diagnostics: &mut SearchDiagnostics,
+    ) -> Result<Vec<SearchResponse>, SearchError> {
+        self.execute(state, input, None, filters, limit, diagnostics, None)
+            .await
+    }
+
+    /// Like `run`, with the query embedding already computed.
+    pub async fn run_with_embedding(
+        &self,
+        state: &StateWrapper,
+        input: &str,
+        embedding: Option<Vec<f32>>,
+        filters: SearchFilters,
+        limit: u64,
+        diagnostics: &mut SearchDiagnostics,
+    ) -> Result<Vec<SearchResponse>, SearchError> {
+        self.execute(state, input, embedding, filters, limit, diagnostics, None)
+            .await
+    }
+
+    /// Like `run`, but also returns a trace of every stage, SQL statement and Qdrant call.
+    pub async fn run_traced(
+        &self,
+        state: &StateWrapper,
+        input: &str,
+        filters: SearchFilters,
+        limit: u64,
+        diagnostics: &mut SearchDiagnostics,
+    ) -> (Result<Vec<SearchResponse>, SearchError>, SearchTrace) {
+        let trace = RefCell::new(SearchTrace::new());
+        let results = self
+            .execute(
+                state,
+                input,
+                None,
+                filters,
+                limit,
+                diagnostics,
+                Some(&trace),
+            )
+            .await;
+        (results, trace.into_inner())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(skip_all, fields(limit = limit))]
+    async fn execute(
+        &self,
+        state: &StateWrapper,
+        input: &str,
+        embedding: Option<Vec<f32>>,
+        filters: SearchFilters,
+        limit: u64,
+        diagnostics: &mut SearchDiagnostics,
+        trace: Option<&RefCell<SearchTrace>>,
+    ) -> Result<Vec<SearchResponse>, SearchError> {
+        let input = input.trim();
+        info!("Received search request for {:?}", input);
+        let started = Instant::now();
+        let (input, operators) = query_operators::parse(input);
+        // An embedding computed ahead is of the query with its operators, not of what is searched
+        let embedding = embedding.filter(|_| operators.is_empty());
+        let config = &state.config.search;
+        let query = SearchQuery {
+            normalized: self.normalizer.normalize(&input),
+            input,
+            score_threshold: filters.score_threshold.unwrap_or(config.score_threshold),
+            embedding_candidates: filters.candidates.unwrap_or(config.embedding_candidates),
+            neighbour_candidates: filters.candidates.unwrap_or(config.neighbour_candidates),
+            filters,
+            operators,
+            limit,
+            embedding,
+        };
+        diagnostics.score_threshold = query.score_threshold;
+        let ctx = SearchContext {
+            state,
+            snapshot: snapshot::current(state),
+            query: &query,
+            trace,
+        };
+        ctx.trace("normalize", "stage", started.elapsed(), || {
+            json!({
+                "input": query.input,
+                "normalized": query.normalized,
+                "operators": query.operators,
+                "collection": ctx.snapshot.collection,
+                "snapshot_activated_at": ctx.snapshot.activated_at,
+            })
+        });
+
+        let generators = match query.filters.match_mode {
+            MatchMode::Auto => &self.generators,
+            MatchMode::Exact => &self.exact_generators,
+            MatchMode::Prefix => &self.prefix_generators,
+            MatchMode::Semantic => &self.semantic_generators,
+        };
+        let mut candidate_lists = Vec::new();
+        for generator in generators {
+            let started = Instant::now();
+            let candidates = generator
+                .generate(&ctx, diagnostics)
+                .instrument(info_span!("generate", source = generator.name()))
+                .await?;
+            let produced = candidates.len();
+            let raw = ctx.trace.is_some().then(|| summarize(&candidates));
+            let filtered = self.filter.filter(candidates, &query, diagnostics);
+            ctx.trace(generator.name(), "stage", started.elapsed(), || {
+                json!({
+                    "produced": produced,
+                    "after_filter": filtered.len(),
+                    "candidates": raw,
+                })
+            });
+            if produced > 0
+                && let Some(trace) = ctx.trace
+            {
+                trace.borrow_mut().retrieval_path.push(generator.name());
+            }
+            candidate_lists.push((generator.name(), filtered));
+            if produced > 0 && self.fusion.short_circuits() {
+                break;
+            }
+        }
+
+        let started = Instant::now();
+        let mut candidates = self.fusion.fuse(candidate_lists);
+        ctx.trace(
+            "fuse",
+            "stage",
+            started.elapsed(),
+            || json!({ "candidates": summarize(&candidates) }),
+        );
+        let started = Instant::now();
+        self.reranker.rerank(&mut candidates, &ctx);
+        ctx.trace(
+            "rerank",
+            "stage",
+            started.elapsed(),
+            || json!({ "candidates": summarize(&candidates) }),
+        );
+        let started = Instant::now();
+        let grouper = match query.filters.group_by {
+            GroupBy::Name => &self.grouper,
+            GroupBy::Concept => &self.concept_grouper,
+        };
+        let results = grouper.group(candidates, query.limit);
+        ctx.trace(
+            "group",
+            "stage",
+            started.elapsed(),
+            || json!({ "results": results.len() }),
+        );
+        Ok(results)
+    }
+}
+
+pub struct LowercaseNormalizer;
+
+impl Normalizer for LowercaseNormalizer {
+    fn normalize(&self, input: &str) -> String {
+        input.trim().to_lowercase()
+    }
+}
+
+/// Curated aliases, consulted before anything else.
+pub struct SynonymOverrideGenerator;
+
+#[async_trait(?Send)]
+impl CandidateGenerator for SynonymOverrideGenerator {
+    fn name(&self) -> &'static str {
+        "override"
+    }
+
+    async fn generate(
+        &self,
+        ctx: &SearchContext<'_>,
+        diagnostics: &mut SearchDiagnostics,
+    ) -> Result<Vec<SearchResponse>, SearchError> {
+        let overridden = ctx
+            .state
+            .synonym_overrides
+            .read()
+            .unwrap()
+            .get(ctx.query.normalized.as_str())
+            .cloned();
+        let Some(concept_ids) = overridden else {
+            return Ok(Vec::new());
+        };
+        info!("Using curated synonym override for {:?}", ctx.query.input);
+        diagnostics.override_hit = true;
+        let pg_client = ctx.state.pg_pool.get().await?;
+        let started = Instant::now();
+        let concepts = db::get_concepts_by_ids(&pg_client, &concept_ids).await?;
+        ctx.trace("override", "sql", started.elapsed(), || {
+            json!({
+                "statement": "select_concepts_by_ids.sql",
+                "params": [concept_ids],
+                "rows": concepts.len(),
+            })
+        });
+        let concepts = concepts
+            .into_iter()
+            .map(|concept| concept.concept_name)
+            .collect();
+        let ids = point_ids_for_concept_names(ctx, concepts).await?;
+        Ok(expand_seeds(ctx, ids, diagnostics).await)
+    }
+}
+
+/// Exact matches on concept names loaded from the vector store export, or found by scrolling the
+/// collection while the export is still loading.
+pub struct ConceptIndexGenerator;
+
+#[async_trait(?Send)]
+impl CandidateGenerator for ConceptIndexGenerator {
+    fn name(&self) -> &'static str {
+        "index"
+    }
+
+    async fn generate(
+        &self,
+        ctx: &SearchContext<'_>,
+        diagnostics: &mut SearchDiagnostics,
+    ) -> Result<Vec<SearchResponse>, SearchError> {
+        let existing = ctx
+            .snapshot
+            .point_ids(
+                &ctx.state.qdrant_client,
+                &ctx.state.qdrant_read_options,
+                &ctx.query.normalized,
+            )
+            .await?;
+        let Some(existing) = existing else {
+            info!("Nothing found in search index");
+            return Ok(Vec::new());
+        };
+        diagnostics.index_hit = true;
+        let ids = existing.iter().map(|id| id.to_string()).collect();
+        Ok(expand_seeds(ctx, ids, diagnostics).await)
+    }
+}
+
+/// Names and synonyms starting with the query, read from the concept index. Shorter names
+/// score higher, the exact name scores 1.
+pub struct PrefixGenerator;
+
+#[async_trait(?Send)]
+impl CandidateGenerator for PrefixGenerator {
+    fn name(&self) -> &'static str {
+        "prefix"
+    }
+
+    async fn generate(
+        &self,
+        ctx: &SearchContext<'_>,
+        diagnostics: &mut SearchDiagnostics,
+    ) -> Result<Vec<SearchResponse>, SearchError> {
+        let prefix = ctx.query.normalized.as_str();
+        let Some(concept_index) = ctx.snapshot.concept_index() else {
+            warn!(
+                "Prefix search for {:?} while the concept index is loading",
+                prefix
+            );
+            return Ok(Vec::new());
+        };
+        let names =
+            concept_index.names_with_prefix(prefix, ctx.query.embedding_candidates as usize);
+        let ids: Vec<String> = names
+            .iter()
+            .filter_map(|name| concept_index.get(name))
+            .flatten()
+            .map(|id| id.to_string())
+            .collect();
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        diagnostics.index_hit = true;
+        let points = ids.iter().map(|id| PointId::from(id.as_str())).collect();
+        let started = Instant::now();
+        let retrieved = retrieve_point_from_db(
+            &ctx.state.qdrant_client,
+            points,
+            ctx.snapshot.collection.as_str(),
+            &ctx.state.qdrant_read_options,
+        )
+        .await;
+        ctx.trace(
+            "get_points",
+            "qdrant",
+            started.elapsed(),
+            || json!({ "collection": ctx.snapshot.collection, "ids": ids.len(), "points": retrieved.len() }),
+        );
+        diagnostics.candidates_retrieved += retrieved.len();
+        let prefix_length = prefix.chars().count() as f64;
+        Ok(retrieved
+            .into_iter()
+            .map(|point| {
+                let mut candidate = SearchResponse::from(point);
+                let name_length = candidate.concept_name_lower.chars().count().max(1) as f64;
+                candidate.score = Some((prefix_length / name_length).min(1.0));
+                candidate
+            })
+            .collect())
+    }
+}
+
+/// Lexical lookup of concept names, or of a concept id when the query is numeric.
+pub struct VocabularyGenerator;
+
+#[async_trait(?Send)]
+impl CandidateGenerator for VocabularyGenerator {
+    fn name(&self) -> &'static str {
+        "vocabulary"
+    }
+
+    async fn generate(
+        &self,
+        ctx: &SearchContext<'_>,
+        diagnostics: &mut SearchDiagnostics,
+    ) -> Result<Vec<SearchResponse>, SearchError> {
+        let input = ctx.query.input.as_str();
+        let pg_client = ctx.state.pg_pool.get().await?;
+        let started = Instant::now();
+        let (statement, concepts) = match input.parse::<i32>() {
+            Ok(numeric_id) => (
+                "select_concept_for_numeric_input.sql",
+                db::get_concept_name_by_number(&pg_client, numeric_id).await?,
+            ),
+            Err(_) => (
+                "select_concept_for_non_numeric_input.sql",
+                db::get_concept_name_by_string(&pg_client, input.to_string()).await?,
+            ),
+        };
+        ctx.trace(
+            "vocabulary",
+            "sql",
+            started.elapsed(),
+            || json!({ "statement": statement, "params": [input], "rows": concepts }),
+        );
+        if concepts.is_empty() {
+            return Ok(Vec::new());
+        }
+        diagnostics.vocabulary_hit = true;
+        let ids = point_ids_for_concept_names(ctx, concepts).await?;
+        Ok(expand_seeds(ctx, ids, diagnostics).await)
+    }
+}
+
+const FULL_TEXT_SOURCE: &str = "full_text";
+const EMBEDDING_SOURCE: &str = "embedding";
+
+/// Postgres full-text matches on concept names, scored by their rank. Catches rare exact terms
+/// such as brand names that the embedding does not place near the query.
+pub struct FullTextGenerator;
+
+#[async_trait(?Send)]
+impl CandidateGenerator for FullTextGenerator {
+    fn name(&self) -> &'static str {
+        FULL_TEXT_SOURCE
+    }
+
+    async fn generate(
+        &self,
+        ctx: &SearchContext<'_>,
+        diagnostics: &mut SearchDiagnostics,
+    ) -> Result<Vec<SearchResponse>, SearchError> {
+        let input = ctx.query.input.as_str();
+        let pg_client = ctx.state.pg_pool.get().await?;
+        let started = Instant::now();
+        let matches =
+            db::get_concept_names_full_text(&pg_client, input, FULL_TEXT_CANDIDATES).await?;
+        ctx.trace(
+            "full_text",
+            "sql",
+            started.elapsed(),
+            || json!({ "statement": "select_concept_names_full_text.sql", "params": [input], "rows": matches.len() }),
+        );
+        if matches.is_empty() {
+            return Ok(Vec::new());
+        }
+        let ranks: HashMap<String, f32> = matches
+            .iter()
+            .map(|(name, rank)| (name.to_lowercase(), *rank))
+            .collect();
+        let names = matches.into_iter().map(|(name, _)| name).collect();
+        let ids = point_ids_for_concept_names(ctx, names).await?;
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let points = ids.iter().map(|id| PointId::from(id.as_str())).collect();
+        let started = Instant::now();
+        let retrieved = retrieve_point_from_db(
+            &ctx.state.qdrant_client,
+            points,
+            ctx.snapshot.collection.as_str(),
+            &ctx.state.qdrant_read_options,
+        )
+        .await;
+        ctx.trace(
+            "get_points",
+            "qdrant",
+            started.elapsed(),
+            || json!({ "collection": ctx.snapshot.collection, "ids": ids.len(), "points": retrieved.len() }),
+        );
+        diagnostics.candidates_retrieved += retrieved.len();
+        Ok(retrieved
+            .into_iter()
+            .map(|point| {
+                let mut candidate = SearchResponse::from(point);
+                candidate.score = ranks
+                    .get(&candidate.concept_name_lower)
+                    .map(|rank| *rank as f64);
+                candidate
+            })
+            .collect())
+    }
+}
+
+/// Nearest neighbours of the query embedding.
+pub struct EmbeddingGenerator;
+
+#[async_trait(?Send)]
+impl CandidateGenerator for EmbeddingGenerator {
+    fn name(&self) -> &'static str {
+        EMBEDDING_SOURCE
+    }
+
+    async fn generate(
+        &self,
+        ctx: &SearchContext<'_>,
+        diagnostics: &mut SearchDiagnostics,
+    ) -> Result<Vec<SearchResponse>, SearchError> {
+        let input = ctx.query.input.as_str();
+        diagnostics.embedding_attempted = true;
+        let recommendations =
+            match recommend(input.to_string(), ctx, ctx.query.embedding_candidates).await {
+                Ok(recommendations) => {
+                    diagnostics.embedding_succeeded = Some(true);
+                    recommendations
+                }
+                Err(e) => {
+                    diagnostics.embedding_succeeded = Some(false);
+                    // A mismatching embedder fails the request instead of ranking by bad vectors
+                    if let Some(mismatch) = e.downcast_ref::<EmbeddingError>() {
+                        return Err(mismatch.clone().into());
+                    }
+                    warn!("Embedding search failed for {:?}: {}", input, e);
+                    return Ok(Vec::new());
+                }
+            };
+        diagnostics.candidates_retrieved += recommendations.len();
+        Ok(recommendations
+            .into_iter()
+            .map(SearchResponse::from)
+            .collect())
+    }
+}
+
+/// Applies the request's filter parameters to every concept of every candidate.
+pub struct ParameterFilter;
+
+impl CandidateFilter for ParameterFilter {
+    fn filter(
+        &self,
+        candidates: Vec<SearchResponse>,
+        query: &SearchQuery,
+        diagnostics: &mut SearchDiagnostics,
+    ) -> Vec<SearchResponse> {
+        candidates
+            .into_iter()
+            .filter_map(|mut candidate| {
+                // Qdrant already applied the filters it could, see `payload_filter`, but seed
+                // points and points with several concepts still need every concept checked
+                candidate.concepts = filter_concepts(
+                    candidate.concepts,
+                    &query.filters,
+                    &query.operators,
+                    Some(diagnostics),
+                );
+                (!candidate.concepts.is_empty()).then_some(candidate)
+            })
+            .collect()
+    }
+}
+
+pub fn filter_concepts(
+    concepts: Vec<Concept>,
+    filters: &SearchFilters,
+    operators: &QueryOperators,
+    mut diagnostics: Option<&mut SearchDiagnostics>,
+) -> Vec<Concept> {
+    concepts
+        .into_iter()
+        .filter(|concept| {
+            let rejections = filters.rejections(concept);
+            let accepted_by_operators = operators.accepts(&concept.concept_name);
+            if accepted_by_operators && let Some(diagnostics) = diagnostics.as_deref_mut() {
+                // A facet counts the concepts that only its own filter could reject
+                for facet in FACETS {
+                    if rejections
+                        .iter()
+                        .flatten()
+                        .all(|rejection| *rejection == facet)
+                    {
+                        diagnostics.facets.record(facet, concept);
+                    }
+                }
+            }
+            let rejecting = rejections
+                .into_iter()
+                .flatten()
+                .next()
+                .or_else(|| (!accepted_by_operators).then_some("query_operators"));
+            match rejecting {
+                Some(filter) => {
+                    if let Some(diagnostics) = diagnostics.as_deref_mut() {
+                        diagnostics.record_rejection(filter);
+                    }
+                    false
+                }
+                None => true,
+            }
+        })
+        .collect()
+}
+
+/// Uses the candidates of the first generator that produced any, later generators are only
+/// consulted as fallbacks.
+pub struct CascadeFusion;
+
+impl Fusion for CascadeFusion {
+    fn short_circuits(&self) -> bool {
+        true
+    }
+
+    fn fuse(
+        &self,
+        candidate_lists: Vec<(&'static str, Vec<SearchResponse>)>,
+    ) -> Vec<SearchResponse> {
+        candidate_lists
+            .into_iter()
+            .flat_map(|(_, candidates)| candidates)
+            .collect()
+    }
+}
+
+/// Runs every generator and pools their candidates.
+pub struct UnionFusion;
+
+impl Fusion for UnionFusion {
+    fn short_circuits(&self) -> bool {
+        false
+    }
+
+    fn fuse(
+        &self,
+        candidate_lists: Vec<(&'static str, Vec<SearchResponse>)>,
+    ) -> Vec<SearchResponse> {
+        candidate_lists
+            .into_iter()
+            .flat_map(|(_, candidates)| candidates)
+            .collect()
+    }
+}
+
+/// Runs every generator and blends lexical and semantic relevance: full-text ranks are scaled
+/// so the best match scores 1, then combined with the embedding score of the same concept
+/// name as `lexical_weight * full_text + (1 - lexical_weight) * embedding`. Points sharing a
+/// name count once per generator, with their best score. Candidates of the other generators
+/// are exact matches and keep their scores.
+pub struct HybridFusion {
+    lexical_weight: f64,
+}
+
+impl Fusion for HybridFusion {
+    fn short_circuits(&self) -> bool {
+        false
+    }
+
+    fn fuse(
+        &self,
+        candidate_lists: Vec<(&'static str, Vec<SearchResponse>)>,
+    ) -> Vec<SearchResponse> {
+        let mut fused = Vec::new();
+        // Concept name to its blended candidate, in the order names were first seen
+        let mut blended: Vec<SearchResponse> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for (source, candidates) in candidate_lists {
+            let weight = match source {
+                FULL_TEXT_SOURCE => self.lexical_weight,
+                EMBEDDING_SOURCE => 1.0 - self.lexical_weight,
+                _ => {
+                    fused.extend(candidates);
+                    continue;
+                }
+            };
+            // One name maps to all of its points, which must not add up to a higher score
+            let mut by_name: Vec<SearchResponse> = Vec::new();
+            let mut name_positions: HashMap<String, usize> = HashMap::new();
+            for candidate in candidates {
+                match name_positions.get(&candidate.concept_name_lower) {
+                    Some(&position) => {
+                        let existing = &mut by_name[position];
+                        if candidate.score.unwrap_or(0.0) > existing.score.unwrap_or(0.0) {
+                            existing.score = candidate.score;
+                        }
+                        merge_concepts(existing, candidate);
+                    }
+                    None => {
+                        name_positions.insert(candidate.concept_name_lower.clone(), by_name.len());
+                        by_name.push(candidate);
+                    }
+                }
+            }
+            let best = by_name
+                .iter()
+                .filter_map(|candidate| candidate.score)
+                .fold(0.0, f64::max);
+            for mut candidate in by_name {
+                let mut score = candidate.score.unwrap_or(0.0);
+                if source == FULL_TEXT_SOURCE && best > 0.0 {
+                    score /= best;
+                }
+                let contribution = weight * score;
+                match positions.get(&candidate.concept_name_lower) {
+                    Some(&position) => {
+                        let existing = &mut blended[position];
+                        existing.score = Some(existing.score.unwrap_or(0.0) + contribution);
+                        merge_concepts(existing, candidate);
+                    }
+                    None => {
+                        candidate.score = Some(contribution);
+                        positions.insert(candidate.concept_name_lower.clone(), blended.len());
+                        blended.push(candidate);
+                    }
+                }
+            }
+        }
+        fused.extend(blended);
+        fused
+    }
+}
+
+/// Adds the concepts of a candidate merged into `existing` that it does not carry yet; the
+/// generators often return the same point.
+fn merge_concepts(existing: &mut SearchResponse, mut candidate: SearchResponse) {
+    let known: HashSet<i32> = existing
+        .concepts
+        .iter()
+        .map(|concept| concept.concept_id)
+        .collect();
+    candidate
+        .concepts
+        .retain(|concept| !known.contains(&concept.concept_id));
+    existing.append_concepts(&mut candidate.concepts);
+}
+
+fn sort_by_score(candidates: &mut [SearchResponse]) {
+    candidates.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+}
+
+/// Sorts by score descending.
+pub struct ScoreReranker;
+
+impl Reranker for ScoreReranker {
+    fn rerank(&self, candidates: &mut Vec<SearchResponse>, _ctx: &SearchContext<'_>) {
+        sort_by_score(candidates);
+    }
+}
+
+/// Applies the operator's boosting rules before sorting by score.
+pub struct BoostingReranker;
+
+impl Reranker for BoostingReranker {
+    fn rerank(&self, candidates: &mut Vec<SearchResponse>, ctx: &SearchContext<'_>) {
+        boosting::current_rules(ctx.state).apply(candidates);
+        sort_by_score(candidates);
+    }
+}
+
+/// Merges candidates whose names only differ in case, keeping the best scoring one first.
+pub struct ConceptNameGrouper;
+
+impl Grouper for ConceptNameGrouper {
+    fn group(&self, candidates: Vec<SearchResponse>, limit: u64) -> Vec<SearchResponse> {
+        let mut grouped: Vec<SearchResponse> = Vec::new();
+        for mut candidate in candidates {
+            match grouped
+                .iter_mut()
+                .find(|every| every.concept_name_lower == candidate.concept_name_lower)
+            {
+                Some(existing) => existing.append_concepts(&mut candidate.concepts),
+                None => grouped.push(candidate),
+            }
+        }
+        grouped.truncate(limit as usize);
+        grouped
+    }
+}
+
+/// One result per concept, named after the concept rather than the name or synonym it was
+/// found by, with the score of the best candidate carrying it.
+pub struct ConceptGrouper;
+
+impl Grouper for ConceptGrouper {
+    fn group(&self, candidates: Vec<SearchResponse>, limit: u64) -> Vec<SearchResponse> {
+        let mut seen = HashSet::new();
+        let mut grouped: Vec<SearchResponse> = Vec::new();
+        // Candidates arrive best first, so the first one carrying a concept scored it best
+        for candidate in candidates {
+            for concept in candidate.concepts {
+                if grouped.len() as u64 >= limit {
+                    return grouped;
+                }
+                if !seen.insert(concept.concept_id) {
+                    continue;
+                }
+                grouped.push(SearchResponse {
+                    system: candidate.system.clone(),
+                    concept_name: concept.concept_name.clone(),
+                    concept_name_lower: concept.concept_name.to_lowercase(),
+                    score: candidate.score,
+                    concepts: vec![concept],
+                });
+            }
+        }
+        grouped
+    }
+}
+
+/// Resolve concept names to vector point ids, via the concept index where possible and a
+/// Qdrant payload scroll otherwise.
+async fn point_ids_for_concept_names(
+    ctx: &SearchContext<'_>,
+    concept_names: Vec<String>,
+) -> Result<Vec<String>, SearchError> {
+    let mut ids: Vec<String> = Vec::new();
+    for c in concept_names {
+        let lower = c.to_lowercase();
+        info!("{}", lower);
+        let res = ctx
+            .snapshot
+            .concept_index()
+            .and_then(|concept_index| concept_index.get(lower.as_str()));
+        if let Some(item) = res {
+            item.iter().for_each(|x| ids.push(x.to_string()))
+        } else {
+            let started = Instant::now();
+            let results: Vec<RetrievedPoint> = find_by_concept_name_lower(
+                &ctx.state.qdrant_client,
+                lower.clone(),
+                &ctx.snapshot.collection,
+                &ctx.state.qdrant_read_options,
+            )
+            .await?;
+            ctx.trace("scroll", "qdrant", started.elapsed(), || {
+                json!({
+                    "collection": ctx.snapshot.collection,
+                    "concept_name_lower": lower,
+                    "points": results.len(),
+                })
+            });
+            results.iter().for_each(|x| {
+                if let PointIdOptions::Uuid(id) = x.clone().id.unwrap().point_id_options.unwrap() {
+                    ids.push(id.to_string());
+                }
+            });
+        }
+    }
+    Ok(ids)
+}
+
+/// The seed points themselves plus their neighbours in the vector store.
+#[instrument(skip_all, fields(db.system = "qdrant", seeds = ids.len()))]
+async fn expand_seeds(
+    ctx: &SearchContext<'_>,
+    ids: Vec<String>,
+    diagnostics: &mut SearchDiagnostics,
+) -> Vec<SearchResponse> {
+    let client = &ctx.state.qdrant_client;
+    let collection = ctx.snapshot.collection.as_str();
+    if ids.is_empty() {
+        return Vec::new();
+    }
+    let seeds = ids.len();
+    let mut points: Vec<PointId> = Vec::new();
+    let mut recs = RecommendInputBuilder::default();
+    for id in ids {
+        points.push(PointId::from(id.as_str()));
+        recs = recs.add_positive(PointId::from(id.as_str()));
+    }
+
+    let started = Instant::now();
+    let read_options = &ctx.state.qdrant_read_options;
+    let search_result = retrieve_point_from_db(client, points, collection, read_options).await;
+    ctx.trace(
+        "get_points",
+        "qdrant",
+        started.elapsed(),
+        || json!({ "collection": collection, "ids": seeds, "points": search_result.len() }),
+    );
+    if ctx.query.filters.match_mode == MatchMode::Exact {
+        diagnostics.candidates_retrieved += search_result.len();
+        return search_result
+            .into_iter()
+            .map(SearchResponse::from)
+            .collect();
+    }
+    let recommend_input = recs.build();
+    let filter = payload_filter(&ctx.query.filters, &ctx.state.vocabulary_catalog);
+    // Request more results from qdrant to account for the filters it cannot apply
+    let mut query_points_builder = QueryPointsBuilder::new(collection)
+        .with_payload(true)
+        .score_threshold(ctx.query.score_threshold)
+        .limit(ctx.query.neighbour_candidates)
+        .query(recommend_input.clone())
+        .with_read_options(read_options);
+    if let Some(filter) = &filter {
+        query_points_builder = query_points_builder.filter(filter.clone());
+    }
+    let started = Instant::now();
+    let neighbours = client.query(query_points_builder).await.unwrap().result;
+    ctx.trace("recommend", "qdrant", started.elapsed(), || {
+        json!({
+            "collection": collection,
+            "positives": seeds,
+            "limit": ctx.query.neighbour_candidates,
+            "score_threshold": ctx.query.score_threshold,
+            "filtered": filter.is_some(),
+            "points": neighbours.len(),
+        })
+    });
+    diagnostics.candidates_retrieved += search_result.len() + neighbours.len();
+    if neighbours.is_empty() {
+        diagnostics.best_below_threshold_score =
+            best_unthresholded_score(client, collection, recommend_input, filter, read_options)
+                .await;
+    }
+
+    search_result
+        .into_iter()
+        .map(SearchResponse::from)
+        .chain(neighbours.into_iter().map(SearchResponse::from))
+        .collect()
+}
+
+/// Re-issue the neighbour query without a score threshold to find out how close the best
+/// candidate came, so an empty result can explain itself.
+#[instrument(skip_all, fields(db.system = "qdrant"))]
+async fn best_unthresholded_score(
+    client: &Qdrant,
+    collection: &str,
+    recommend_input: qdrant::RecommendInput,
+    filter: Option<Filter>,
+    read_options: &ReadOptions,
+) -> Option<f32> {
+    let mut query_points_builder = QueryPointsBuilder::new(collection)
+        .with_payload(false)
+        .limit(1)
+        .query(recommend_input)
+        .with_read_options(read_options);
+    if let Some(filter) = filter {
+        query_points_builder = query_points_builder.filter(filter);
+    }
+    match client.query(query_points_builder).await {
+        Ok(response) => response.result.first().map(|point| point.score),
+        Err(e) => {
+            warn!("Could not compute best below-threshold score: {}", e);
+            None
+        }
+    }
+}
+
+/// Every point stored under the name, page by page, as names embedded more than once have
+/// several. The page cap only guards against scrolling a broken collection forever.
+#[instrument(skip_all, fields(db.system = "qdrant"))]
+pub async fn find_by_concept_name_lower(
+    client: &Qdrant,
+    concept_name_lower: String,
+    collection: &str,
+    read_options: &ReadOptions,
+) -> Result<Vec<RetrievedPoint>, QdrantError> {
+    let filter = Filter::must([Condition {
+        condition_one_of: Some(ConditionOneOf::Field(qdrant::FieldCondition {
+            key: "concept_name_lower".to_string(),
+            r#match: Some(qdrant::Match {
+                match_value: Some(concept_name_lower.to_string().into()),
+            }),
+            range: None,
+            geo_bounding_box: None,
+            geo_radius: None,
+            values_count: None,
+            geo_polygon: None,
+            datetime_range: None,
+            is_empty: None,
+            is_null: None,
+        })),
+    }]);
+    let mut points = Vec::new();
+    let mut offset: Option<PointId> = None;
+    for _ in 0..NAME_SCROLL_MAX_PAGES {
+        let mut scroll_points_builder = ScrollPointsBuilder::new(collection)
+            .with_read_options(read_options)
+            .filter(filter.clone())
+            .limit(NAME_SCROLL_PAGE_SIZE);
+        if let Some(offset) = offset.take() {
+            scroll_points_builder = scroll_points_builder.offset(offset);
+        }
+        let response = client.scroll(scroll_points_builder).await?;
+        points.extend(response.result);
+        match response.next_page_offset {
+            Some(next) => offset = Some(next),
+            None => return Ok(points),
+        }
+    }
+    warn!(
+        "Stopped collecting points named {:?} in {} after {}",
+        concept_name_lower,
+        collection,
+        points.len()
+    );
+    Ok(points)
+}
+
+#[instrument(skip_all, fields(db.system = "qdrant", points = points.len()))]
+async fn retrieve_point_from_db(
+    client: &Qdrant,
+    points: Vec<PointId>,
+    collection: &str,
+    read_options: &ReadOptions,
+) -> Vec<RetrievedPoint> {
+    client
+        .get_points(
+            GetPointsBuilder::new(collection, points)
+                .with_vectors(false)
+                .with_payload(true)
+                .with_read_options(read_options),
+        )
+        .await
+        .unwrap()
+        .result
+}
+
+#[instrument(skip_all, fields(db.system = "qdrant", limit = limit))]
+async fn recommend(
+    input: String,
+    ctx: &SearchContext<'_>,
+    limit: u64,
+) -> Result<Vec<ScoredPoint>, Box<dyn std::error::Error>> {
+    let started = Instant::now();
+    let precomputed = ctx.query.embedding.is_some();
+    let vector = match &ctx.query.embedding {
+        Some(vector) => vector.clone(),
+        None => ctx.state.embedder.embed(input).await?,
+    };
+    // A precomputed vector made no embedding call and is kept out of its timings.
+    let kind = if precomputed { "stage" } else { "embedding" };
+    ctx.trace(
+        "embedding",
+        kind,
+        started.elapsed(),
+        || json!({ "dimensions": vector.len(), "precomputed": precomputed }),
+    );
+    let started = Instant::now();
+    let filter = payload_filter(&ctx.query.filters, &ctx.state.vocabulary_catalog);
+    let filtered = filter.is_some();
+    let mut search_points_builder =
+        SearchPointsBuilder::new(ctx.snapshot.collection.as_str(), vector, limit)
+            .with_payload(true)
+            .with_read_options(&ctx.state.qdrant_read_options);
+    if let Some(filter) = filter {
+        search_points_builder = search_points_builder.filter(filter);
+    }
+    // Without a threshold of its own the query always gets its nearest concepts
+    if let Some(score_threshold) = ctx.query.filters.score_threshold {
+        search_points_builder = search_points_builder.score_threshold(score_threshold);
+    }
+    let response = ctx
+        .state
+        .qdrant_client
+        .search_points(search_points_builder)
+        .await?;
+    ctx.trace("search_points", "qdrant", started.elapsed(), || {
+        json!({
+            "collection": ctx.snapshot.collection,
+            "limit": limit,
+            "filtered": filtered,
+            "score_threshold": ctx.query.filters.score_threshold,
+            "points": response.result.len(),
+        })
+    });
+    Ok(response.result)
+}
@@ -0,0 +1,279 @@
+use crate::StateWrapper;
+use crate::config::CacheConfig;
+use crate::db;
+use crate::errors::PgError;
+use actix_web::web::Data;
+use deadpool_postgres::Client;
+use log::{info, warn};
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use redis::{AsyncCommands, RedisError, RedisResult};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::time::Duration;
+use tokio::sync::OnceCell;
+use tokio::time::timeout;
+
+/// Bumped to invalidate every replica's search results at once.
+const SEARCH_GENERATION_KEY: &str = "search-generation";
+/// How often the search generation is read from Redis. Searches use the last value read, so
+/// an invalidation on another replica takes effect here within this time.
+const SEARCH_GENERATION_REFRESH: Duration = Duration::from_secs(2);
+
+struct Redis {
+    client: redis::Client,
+    /// Connected on first use, so the API starts while Redis is down. Reconnects on its own
+    /// afterwards.
+    connection: OnceCell<ConnectionManager>,
+    timeout: Duration,
+    /// Whether the last command went through, so an outage is logged once rather than per call.
+    available: AtomicBool,
+}
+
+impl Redis {
+    async fn connection(&self) -> RedisResult<ConnectionManager> {
+        let config = ConnectionManagerConfig::new()
+            .set_connection_timeout(self.timeout)
+            .set_response_timeout(self.timeout)
+            .set_number_of_retries(1);
+        self.connection
+            .get_or_try_init(|| ConnectionManager::new_with_config(self.client.clone(), config))
+            .await
+            .cloned()
+    }
+
+    /// Runs the commands, or returns `None` when Redis fails or is slower than the timeout.
+    async fn run<T, F, Fut>(&self, commands: F) -> Option<T>
+    where
+        F: FnOnce(ConnectionManager) -> Fut,
+        Fut: Future<Output = RedisResult<T>>,
+    {
+        let result = timeout(self.timeout, async {
+            commands(self.connection().await?).await
+        })
+        .await
+        .unwrap_or_else(|_| Err(RedisError::from(io::Error::from(io::ErrorKind::TimedOut))));
+        let address = &self.client.get_connection_info().addr;
+        match result {
+            Ok(value) => {
+                if !self.available.swap(true, Ordering::Relaxed) {
+                    info!("Redis cache at {} is available", address);
+                }
+                Some(value)
+            }
+            Err(e) => {
+                if self.available.swap(false, Ordering::Relaxed) {
+                    warn!(
+                        "Redis cache at {} failed, serving from the local cache: {}",
+                        address, e
+                    );
+                }
+                None
+            }
+        }
+    }
+}
+
+/// A cache shared by all replicas through Redis, in front of searches, UMLS definitions and
+/// descendant lookups. Without `CACHE__REDIS_URL` every lookup misses and nothing is stored.
+pub struct SharedCache {
+    redis: Option<Redis>,
+    prefix: String,
+    ttl: Duration,
+    /// Part of the descendant keys, so a new vocabulary release does not reuse the hierarchy
+    /// of the previous one.
+    vocabulary_version: String,
+    /// The last search generation read from Redis, see `watch_search_generation`.
+    search_generation: AtomicI64,
+}
+
+impl SharedCache {
+    pub fn from_config(
+        config: &CacheConfig,
+        vocabulary_version: Option<&str>,
+    ) -> Result<SharedCache, RedisError> {
+        let redis = match &config.redis_url {
+            Some(url) if config.enabled => {
+                let client = redis::Client::open(url.as_str())?;
+                info!(
+                    "Sharing caches through Redis at {}",
+                    client.get_connection_info().addr
+                );
+                Some(Redis {
+                    client,
+                    connection: OnceCell::new(),
+                    timeout: Duration::from_millis(config.redis_timeout_ms),
+                    available: AtomicBool::new(true),
+                })
+            }
+            _ => None,
+        };
+        Ok(SharedCache {
+            redis,
+            prefix: config.redis_key_prefix.clone(),
+            ttl: Duration::from_secs(config.ttl_secs),
+            vocabulary_version: vocabulary_version.unwrap_or_default().to_string(),
+            search_generation: AtomicI64::new(0),
+        })
+    }
+
+    /// Keys are hashed so long queries and filters stay within reasonable key sizes.
+    fn key(&self, namespace: &str, key: &str) -> String {
+        let hash: String = Sha256::digest(key.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        format!("{}{}:{}", self.prefix, namespace, hash)
+    }
+
+    fn descendants_key(&self, concept_id: i32) -> String {
+        self.key(
+            "descendants",
+            &format!("{}:{}", self.vocabulary_version, concept_id),
+        )
+    }
+
+    fn ttl_secs(&self) -> u64 {
+        self.ttl.as_secs().max(1)
+    }
+
+    pub async fn get<T: DeserializeOwned>(&self, namespace: &str, key: &str) -> Option<T> {
+        let redis = self.redis.as_ref()?;
+        let key = self.key(namespace, key);
+        let value = redis
+            .run(|mut connection| async move { connection.get::<_, Option<Vec<u8>>>(key).await })
+            .await??;
+        serde_json::from_slice(&value).ok()
+    }
+
+    pub async fn insert<T: Serialize>(&self, namespace: &str, key: &str, value: &T) {
+        let Some(redis) = &self.redis else {
+            return;
+        };
+        let Ok(value) = serde_json::to_vec(value) else {
+            return;
+        };
+        let key = self.key(namespace, key);
+        let ttl = self.ttl_secs();
+        redis
+            .run(|mut connection| async move { connection.set_ex::<_, _, ()>(key, value, ttl).await })
+            .await;
+    }
+
+    /// Changes whenever `invalidate_searches` is called on any replica. Part of the search
+    /// cache key, so replicas stop serving results computed before the change.
+    pub fn search_generation(&self) -> i64 {
+        self.search_generation.load(Ordering::Relaxed)
+    }
+
+    async fn refresh_search_generation(&self) {
+        let Some(redis) = &self.redis else {
+            return;
+        };
+        let key = format!("{}{}", self.prefix, SEARCH_GENERATION_KEY);
+        if let Some(generation) = redis
+            .run(|mut connection| async move { connection.get::<_, Option<i64>>(key).await })
+            .await
+        {
+            self.search_generation
+                .store(generation.unwrap_or_default(), Ordering::Relaxed);
+        }
+    }
+
+    pub async fn invalidate_searches(&self) {
+        let Some(redis) = &self.redis else {
+            return;
+        };
+        let key = format!("{}{}", self.prefix, SEARCH_GENERATION_KEY);
+        if let Some(generation) = redis
+            .run(|mut connection| async move { connection.incr::<_, _, i64>(key, 1).await })
+            .await
+        {
+            self.search_generation.store(generation, Ordering::Relaxed);
+            info!("Shared search cache now at generation {}", generation);
+        }
+    }
+
+    /// `db::get_batch_descendant_concepts` with each concept's descendants cached on its own,
+    /// so overlapping lookups from different requests share entries.
+    pub async fn get_batch_descendant_concepts(
+        &self,
+        client: &Client,
+        concept_ids: &[i32],
+    ) -> Result<HashMap<i32, Vec<i32>>, PgError> {
+        let Some(redis) = &self.redis else {
+            return db::get_batch_descendant_concepts(client, concept_ids).await;
+        };
+        if concept_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let keys: Vec<String> = concept_ids
+            .iter()
+            .map(|concept_id| self.descendants_key(*concept_id))
+            .collect();
+        let cached: Vec<Option<Vec<u8>>> = redis
+            .run(|mut connection| async move {
+                redis::cmd("MGET")
+                    .arg(keys)
+                    .query_async(&mut connection)
+                    .await
+            })
+            .await
+            .unwrap_or_default();
+
+        // Like the database lookup, every requested concept gets an entry, also those without
+        // descendants
+        let mut found = HashMap::new();
+        let mut missing = Vec::new();
+        for (index, concept_id) in concept_ids.iter().enumerate() {
+            let descendants = cached
+                .get(index)
+                .and_then(Option::as_ref)
+                .and_then(|value| serde_json::from_slice::<Vec<i32>>(value).ok());
+            match descendants {
+                Some(descendants) => {
+                    found.insert(*concept_id, descendants);
+                }
+                None => missing.push(*concept_id),
+            }
+        }
+        if missing.is_empty() {
+            return Ok(found);
+        }
+
+        let looked_up = db::get_batch_descendant_concepts(client, &missing).await?;
+        let mut pipeline = redis::pipe();
+        for concept_id in &missing {
+            let descendants = looked_up.get(concept_id).cloned().unwrap_or_default();
+            if let Ok(value) = serde_json::to_vec(&descendants) {
+                pipeline
+                    .set_ex(self.descendants_key(*concept_id), value, self.ttl_secs())
+                    .ignore();
+            }
+        }
+        redis
+            .run(|mut connection| async move { pipeline.query_async::<()>(&mut connection).await })
+            .await;
+        found.extend(looked_up);
+        Ok(found)
+    }
+}
+
+/// Keeps `SharedCache::search_generation` current in the background, so searches do not wait
+/// on Redis for it.
+pub fn watch_search_generation(state: Data<StateWrapper>) {
+    if state.shared_cache.redis.is_none() {
+        return;
+    }
+    actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(SEARCH_GENERATION_REFRESH);
+        loop {
+            interval.tick().await;
+            state.shared_cache.refresh_search_generation().await;
+        }
+    });
+}
@@ -0,0 +1,332 @@
+use crate::StateWrapper;
+use crate::config::SloConfig;
+use crate::embeddings::EmbeddingStatus;
+use crate::snapshot;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::web::Data;
+use actix_web::{Error, HttpResponse, get};
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+use utoipa::ToSchema;
+
+/// Percentiles over fewer requests than this are too noisy to call a budget violated.
+const MIN_SAMPLES: usize = 20;
+const SEARCH_PATH: &str = "/api/search";
+
+pub const SHED_RECOMMENDATIONS_WARNING: &str =
+    "Recommendations were skipped because search is over its latency budget, try again later";
+
+/// Latencies of the most recent requests to one route plus lifetime totals.
+#[derive(Debug, Default)]
+struct LatencyWindow {
+    recent_ms: VecDeque<f64>,
+    count: u64,
+    sum_ms: f64,
+    over_budget: u64,
+}
+
+impl LatencyWindow {
+    fn percentile(sorted_ms: &[f64], quantile: f64) -> f64 {
+        if sorted_ms.is_empty() {
+            return 0.0;
+        }
+        let rank = (quantile * sorted_ms.len() as f64).ceil() as usize;
+        sorted_ms[rank.clamp(1, sorted_ms.len()) - 1]
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct EndpointLatency {
+    pub endpoint: String,
+    pub samples: usize,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub budget_p95_ms: u64,
+    /// The p95 is over budget, judged once the window holds enough samples.
+    pub violated: bool,
+    pub requests: u64,
+    pub requests_over_budget: u64,
+    pub total_ms: f64,
+}
+
+/// Rolling latency percentiles per route, fed by `track_latency`.
+#[derive(Debug)]
+pub struct LatencyTracker {
+    config: SloConfig,
+    windows: Mutex<BTreeMap<String, LatencyWindow>>,
+}
+
+impl LatencyTracker {
+    pub fn new(config: SloConfig) -> Self {
+        LatencyTracker {
+            config,
+            windows: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn record(&self, endpoint: &str, elapsed_ms: f64) {
+        let budget_ms = self.config.budget_ms(endpoint) as f64;
+        let mut windows = self.windows.lock().unwrap();
+        let window = windows.entry(endpoint.to_string()).or_default();
+        if window.recent_ms.len() >= self.config.window.max(1) {
+            window.recent_ms.pop_front();
+        }
+        window.recent_ms.push_back(elapsed_ms);
+        window.count += 1;
+        window.sum_ms += elapsed_ms;
+        if elapsed_ms > budget_ms {
+            window.over_budget += 1;
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<EndpointLatency> {
+        let windows = self.windows.lock().unwrap();
+        windows
+            .iter()
+            .map(|(endpoint, window)| {
+                let mut sorted: Vec<f64> = window.recent_ms.iter().copied().collect();
+                sorted.sort_by(f64::total_cmp);
+                let p95_ms = LatencyWindow::percentile(&sorted, 0.95);
+                let budget_p95_ms = self.config.budget_ms(endpoint);
+                EndpointLatency {
+                    endpoint: endpoint.clone(),
+                    samples: sorted.len(),
+                    p50_ms: LatencyWindow::percentile(&sorted, 0.50),
+                    p95_ms,
+                    p99_ms: LatencyWindow::percentile(&sorted, 0.99),
+                    budget_p95_ms,
+                    violated: sorted.len() >= MIN_SAMPLES && p95_ms > budget_p95_ms as f64,
+                    requests: window.count,
+                    requests_over_budget: window.over_budget,
+                    total_ms: window.sum_ms,
+                }
+            })
+            .collect()
+    }
+
+    pub fn is_violated(&self, endpoint: &str) -> bool {
+        let windows = self.windows.lock().unwrap();
+        let Some(window) = windows.get(endpoint) else {
+            return false;
+        };
+        if window.recent_ms.len() < MIN_SAMPLES {
+            return false;
+        }
+        let mut sorted: Vec<f64> = window.recent_ms.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        LatencyWindow::percentile(&sorted, 0.95) > self.config.budget_ms(endpoint) as f64
+    }
+
+    /// Whether concept set recommendations should be skipped to protect interactive search.
+    pub fn shed_recommendations(&self) -> bool {
+        self.config.shed_recommendations && self.is_violated(SEARCH_PATH)
+    }
+}
+
+/// Middleware recording the latency and status of every routed request under its route pattern.
+pub async fn track_latency(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if req.method() == Method::OPTIONS {
+        return next.call(req).await.map(|res| res.map_into_boxed_body());
+    }
+    let state = req.app_data::<Data<StateWrapper>>().cloned();
+    let started = Instant::now();
+    let res = next.call(req).await?;
+    if let Some(state) = state
+        && let Some(endpoint) = res.request().match_pattern()
+    {
+        let elapsed = started.elapsed();
+        state
+            .latency
+            .record(&endpoint, elapsed.as_secs_f64() * 1000.0);
+        state
+            .metrics
+            .record_request(&endpoint, res.status().as_u16(), elapsed);
+    }
+    Ok(res.map_into_boxed_body())
+}
+
+#[derive(Serialize, ToSchema)]
+struct SloStatus {
+    /// Search is over its latency budget, so recommendations are skipped.
+    shedding_recommendations: bool,
+    endpoints: Vec<EndpointLatency>,
+}
+
+/// Per-route latency percentiles against their budgets, violations first.
+#[utoipa::path(
+    tag = "admin",
+    summary = "Latency budgets",
+    description = "The p50, p95 and p99 latency of the recent requests per route against the route's p95 \
+        budget, violations first.",
+    responses((status = 200, description = "Latency per route", body = SloStatus))
+)]
+#[get("/api/admin/slo")]
+async fn get_slo_status(state: Data<StateWrapper>) -> Result<HttpResponse, Error> {
+    let mut endpoints = state.latency.snapshot();
+    endpoints.sort_by_key(|endpoint| !endpoint.violated);
+    Ok(HttpResponse::Ok().json(SloStatus {
+        shedding_recommendations: state.latency.shed_recommendations(),
+        endpoints,
+    }))
+}
+
+/// Appends the latency percentiles and embedding health in the Prometheus text format.
+pub fn render_metrics(state: &StateWrapper, body: &mut String) {
+    let endpoints = state.latency.snapshot();
+    let _ = writeln!(
+        body,
+        "# HELP hecate_request_duration_seconds Request latency over the recent window per route."
+    );
+    let _ = writeln!(body, "# TYPE hecate_request_duration_seconds summary");
+    for endpoint in &endpoints {
+        let name = &endpoint.endpoint;
+        for (quantile, value_ms) in [
+            ("0.5", endpoint.p50_ms),
+            ("0.95", endpoint.p95_ms),
+            ("0.99", endpoint.p99_ms),
+        ] {
+            let _ = writeln!(
+                body,
+                "hecate_request_duration_seconds{{endpoint=\"{}\",quantile=\"{}\"}} {}",
+                name,
+                quantile,
+                value_ms / 1000.0
+            );
+        }
+        let _ = writeln!(
+            body,
+            "hecate_request_duration_seconds_sum{{endpoint=\"{}\"}} {}",
+            name,
+            endpoint.total_ms / 1000.0
+        );
+        let _ = writeln!(
+            body,
+            "hecate_request_duration_seconds_count{{endpoint=\"{}\"}} {}",
+            name, endpoint.requests
+        );
+    }
+    let _ = writeln!(
+        body,
+        "# HELP hecate_slo_budget_seconds p95 latency budget per route."
+    );
+    let _ = writeln!(body, "# TYPE hecate_slo_budget_seconds gauge");
+    for endpoint in &endpoints {
+        let _ = writeln!(
+            body,
+            "hecate_slo_budget_seconds{{endpoint=\"{}\"}} {}",
+            endpoint.endpoint,
+            endpoint.budget_p95_ms as f64 / 1000.0
+        );
+    }
+    let _ = writeln!(
+        body,
+        "# HELP hecate_slo_requests_over_budget_total Requests slower than the route's budget."
+    );
+    let _ = writeln!(body, "# TYPE hecate_slo_requests_over_budget_total counter");
+    for endpoint in &endpoints {
+        let _ = writeln!(
+            body,
+            "hecate_slo_requests_over_budget_total{{endpoint=\"{}\"}} {}",
+            endpoint.endpoint, endpoint.requests_over_budget
+        );
+    }
+    let _ = writeln!(
+        body,
+        "# HELP hecate_embedding_degraded Whether the embedder returns vectors the collections were not built with."
+    );
+    let _ = writeln!(body, "# TYPE hecate_embedding_degraded gauge");
+    let _ = writeln!(
+        body,
+        "hecate_embedding_degraded {}",
+        u8::from(state.embedder.is_degraded())
+    );
+    let _ = writeln!(
+        body,
+        "# HELP hecate_embedding_mismatches_total Embedding responses rejected for their model or dimensions."
+    );
+    let _ = writeln!(body, "# TYPE hecate_embedding_mismatches_total counter");
+    let _ = writeln!(
+        body,
+        "hecate_embedding_mismatches_total {}",
+        state.embedder.mismatches()
+    );
+}
+
+#[derive(Serialize, ToSchema)]
+struct Health {
+    #[schema(example = "ok")]
+    status: &'static str,
+    embedding: EmbeddingStatus,
+    #[schema(example = "ready")]
+    concept_index: &'static str,
+}
+
+#[derive(Serialize, ToSchema)]
+struct Readiness {
+    ready: bool,
+    #[schema(example = "ready")]
+    concept_index: &'static str,
+}
+
+/// Overall service status. Stays 200 while degraded so orchestrators do not restart an instance
+/// whose lexical search still works; alerting should key on `status` or the metrics.
+#[utoipa::path(
+    tag = "operations",
+    summary = "Service health",
+    description = "Reports `degraded` while the embedding service returns vectors that do not match the \
+        collections or the concept index is not loaded. Always 200 so a degraded instance keeps serving \
+        lexical lookups.",
+    responses((status = 200, description = "`ok` or `degraded`", body = Health))
+)]
+#[get("/api/health")]
+async fn get_health(state: Data<StateWrapper>) -> Result<HttpResponse, Error> {
+    let embedding = state.embedder.status();
+    let snapshot = snapshot::current(&state);
+    let ready = snapshot.concept_index().is_some();
+    let status = if embedding.degraded || !ready {
+        "degraded"
+    } else {
+        "ok"
+    };
+    Ok(HttpResponse::Ok().json(Health {
+        status,
+        embedding,
+        concept_index: snapshot.concept_index.as_str(),
+    }))
+}
+
+/// 503 until the concept index is loaded, so load balancers hold traffic back from an instance
+/// still answering exact-name lookups through Qdrant.
+#[utoipa::path(
+    tag = "operations",
+    summary = "Readiness",
+    description = "503 until the concept index has loaded. Until then exact-name lookups go to Qdrant \
+        instead, which is slower.",
+    responses(
+        (status = 200, description = "Ready", body = Readiness),
+        (status = 503, description = "The concept index is loading or failed to load", body = Readiness),
+    )
+)]
+#[get("/api/ready")]
+async fn get_readiness(state: Data<StateWrapper>) -> Result<HttpResponse, Error> {
+    let snapshot = snapshot::current(&state);
+    let body = Readiness {
+        ready: snapshot.concept_index().is_some(),
+        concept_index: snapshot.concept_index.as_str(),
+    };
+    if snapshot.concept_index().is_some() {
+        Ok(HttpResponse::Ok().json(body))
+    } else {
+        Ok(HttpResponse::ServiceUnavailable().json(body))
+    }
+}
@@ -0,0 +1,381 @@
+use crate::StateWrapper;
+use crate::concept_index::{ConceptIndex, ConceptIndexBuilder};
+use crate::qdrant::{ReadOptions, get_all_id_value_pairs};
+use crate::search::find_by_concept_name_lower;
+use crate::spelling::SpellingDictionary;
+use actix_web::web::{self, Data, Json};
+use actix_web::{Error, HttpResponse, get, post};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use qdrant_client::qdrant::point_id::PointIdOptions;
+use qdrant_client::{Qdrant, QdrantError};
+use serde::{Deserialize, Serialize};
+use std::error::Error as StdError;
+use std::sync::Arc;
+use std::time::Instant;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Whether the concept index of a snapshot can be used yet.
+#[derive(Debug)]
+pub enum ConceptIndexState {
+    Loading,
+    Ready(ConceptIndex),
+    Failed(String),
+}
+
+impl ConceptIndexState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConceptIndexState::Loading => "loading",
+            ConceptIndexState::Ready(_) => "ready",
+            ConceptIndexState::Failed(_) => "failed",
+        }
+    }
+}
+
+/// A Qdrant collection together with the concept index built from it. Requests take the
+/// current snapshot once and use it throughout, so a blue/green swap never mixes the old and
+/// the new collection within one response.
+#[derive(Debug)]
+pub struct IndexSnapshot {
+    pub collection: String,
+    /// Where the concept index is read from and rebuilt to.
+    pub vectordb_data_path: String,
+    pub concept_index: ConceptIndexState,
+    /// "Did you mean" suggestions for searches without results, see `spelling`.
+    pub spelling: Option<SpellingDictionary>,
+    pub activated_at: DateTime<Utc>,
+}
+
+impl IndexSnapshot {
+    pub fn load(collection: &str, vectordb_data_path: &str) -> Result<Self, Box<dyn StdError>> {
+        info!(
+            "Load all concept-vector_ids map from file: {}",
+            vectordb_data_path
+        );
+        let concept_index = ConceptIndex::load(vectordb_data_path)?;
+        if concept_index.is_empty() {
+            warn!("The concept index at {} is empty", vectordb_data_path);
+        }
+        info!(
+            "{} concept-vector_ids loaded for collection {}",
+            concept_index.len(),
+            collection
+        );
+        // Searches work without suggestions, a broken dictionary must not keep the index out
+        let spelling = SpellingDictionary::load(vectordb_data_path).unwrap_or_else(|e| {
+            warn!("Could not load the spelling dictionary: {}", e);
+            None
+        });
+        Ok(IndexSnapshot {
+            collection: collection.to_string(),
+            vectordb_data_path: vectordb_data_path.to_string(),
+            concept_index: ConceptIndexState::Ready(concept_index),
+            spelling,
+            activated_at: Utc::now(),
+        })
+    }
+
+    /// A snapshot whose concept index is still being loaded, see `load_in_background`.
+    pub fn loading(collection: &str, vectordb_data_path: &str) -> Self {
+        IndexSnapshot {
+            collection: collection.to_string(),
+            vectordb_data_path: vectordb_data_path.to_string(),
+            concept_index: ConceptIndexState::Loading,
+            spelling: None,
+            activated_at: Utc::now(),
+        }
+    }
+
+    pub fn concept_index(&self) -> Option<&ConceptIndex> {
+        match &self.concept_index {
+            ConceptIndexState::Ready(concept_index) => Some(concept_index),
+            _ => None,
+        }
+    }
+
+    /// The points of a lowercase concept name from the concept index, or from a Qdrant scroll
+    /// while the index is not available.
+    pub async fn point_ids(
+        &self,
+        qdrant_client: &Qdrant,
+        read_options: &ReadOptions,
+        concept_name_lower: &str,
+    ) -> Result<Option<Vec<Uuid>>, QdrantError> {
+        if let Some(concept_index) = self.concept_index() {
+            return Ok(concept_index.get(concept_name_lower));
+        }
+        let ids: Vec<Uuid> = find_by_concept_name_lower(
+            qdrant_client,
+            concept_name_lower.to_string(),
+            &self.collection,
+            read_options,
+        )
+        .await?
+        .into_iter()
+        .filter_map(|point| match point.id?.point_id_options? {
+            PointIdOptions::Uuid(id) => Uuid::parse_str(&id).ok(),
+            PointIdOptions::Num(_) => None,
+        })
+        .collect();
+        Ok((!ids.is_empty()).then_some(ids))
+    }
+}
+
+pub fn current(state: &StateWrapper) -> Arc<IndexSnapshot> {
+    state.index_snapshot.read().unwrap().clone()
+}
+
+/// Loads the concept index of the current, still loading snapshot off the request path. The
+/// server answers meanwhile, looking names up in Qdrant, and reports not ready.
+pub fn load_in_background(state: Data<StateWrapper>) {
+    actix_web::rt::spawn(async move {
+        let snapshot = current(&state);
+        let collection = snapshot.collection.clone();
+        let path = snapshot.vectordb_data_path.clone();
+        let started = Instant::now();
+        let loaded =
+            web::block(move || IndexSnapshot::load(&collection, &path).map_err(|e| e.to_string()))
+                .await
+                .unwrap_or_else(|e| Err(e.to_string()));
+        let mut snapshot = state.index_snapshot.write().unwrap();
+        // An index swapped in meanwhile wins over the one loaded at startup
+        if !matches!(snapshot.concept_index, ConceptIndexState::Loading) {
+            return;
+        }
+        match loaded {
+            Ok(loaded) => {
+                info!(
+                    "Concept index ready after {:.1}s",
+                    started.elapsed().as_secs_f64()
+                );
+                *snapshot = Arc::new(loaded);
+            }
+            Err(e) => {
+                error!("Could not load the concept index: {}", e);
+                *snapshot = Arc::new(IndexSnapshot {
+                    collection: snapshot.collection.clone(),
+                    vectordb_data_path: snapshot.vectordb_data_path.clone(),
+                    concept_index: ConceptIndexState::Failed(e),
+                    spelling: None,
+                    activated_at: snapshot.activated_at,
+                });
+            }
+        }
+    });
+}
+
+/// What the admin endpoints report about a snapshot.
+#[derive(Serialize, ToSchema)]
+struct SnapshotStatus {
+    collection: String,
+    vectordb_data_path: String,
+    /// `loading`, `ready` or `failed`.
+    #[schema(example = "ready")]
+    status: &'static str,
+    /// Names in the concept index, once it is loaded.
+    concepts: Option<usize>,
+    /// Why the concept index failed to load.
+    error: Option<String>,
+    activated_at: DateTime<Utc>,
+}
+
+fn describe(snapshot: &IndexSnapshot) -> SnapshotStatus {
+    let error = match &snapshot.concept_index {
+        ConceptIndexState::Failed(error) => Some(error.clone()),
+        _ => None,
+    };
+    SnapshotStatus {
+        collection: snapshot.collection.clone(),
+        vectordb_data_path: snapshot.vectordb_data_path.clone(),
+        status: snapshot.concept_index.as_str(),
+        concepts: snapshot.concept_index().map(ConceptIndex::len),
+        error,
+        activated_at: snapshot.activated_at,
+    }
+}
+
+/// What `POST /api/admin/reindex` rebuilds the concept index from.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum ReindexSource {
+    /// Reread the index file, e.g. after running `ingest` elsewhere.
+    #[default]
+    File,
+    /// Scan every point of the collection and write a new index file.
+    Qdrant,
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+struct ReindexRequest {
+    #[serde(default)]
+    source: ReindexSource,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct SwapRequest {
+    collection: String,
+    vectordb_data_path: String,
+}
+
+/// The active collection and the state of its concept index.
+#[utoipa::path(
+    tag = "admin",
+    summary = "Active index snapshot",
+    description = "The collection searches run against and whether its concept index is loaded.",
+    responses((status = 200, description = "The active index snapshot", body = SnapshotStatus))
+)]
+#[get("/api/admin/index")]
+async fn get_index_snapshot(state: Data<StateWrapper>) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok().json(describe(&current(&state))))
+}
+
+/// Switches searches over to another collection and its concept index. The new snapshot is
+/// fully loaded before it replaces the old one; requests already running finish on the old.
+#[utoipa::path(
+    tag = "admin",
+    summary = "Swap the index snapshot",
+    description = "Switches searches over to another Qdrant collection and the concept index at \
+        `vectordb_data_path`. The new snapshot is fully loaded before it replaces the active one.",
+    responses(
+        (status = 200, description = "The new index snapshot", body = SnapshotStatus),
+        (status = 400, description = "The collection does not exist or its concept index could not be loaded"),
+        (status = 502, description = "Qdrant could not be reached"),
+    )
+)]
+#[post("/api/admin/index/swap")]
+async fn swap_index_snapshot(
+    request: Json<SwapRequest>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    match state
+        .qdrant_client
+        .collection_exists(&request.collection)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Collection {} does not exist", request.collection)
+            })));
+        }
+        Err(e) => {
+            return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                "error": format!("Could not reach Qdrant: {}", e)
+            })));
+        }
+    }
+    // Loading reads and converts the index file, off the worker so other requests go on
+    let loaded = web::block({
+        let collection = request.collection.clone();
+        let path = request.vectordb_data_path.clone();
+        move || IndexSnapshot::load(&collection, &path).map_err(|e| e.to_string())
+    })
+    .await
+    .unwrap_or_else(|e| Err(e.to_string()));
+    let snapshot = match loaded {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Could not load concept index: {}", e)
+            })));
+        }
+    };
+    let response = describe(&snapshot);
+    let previous = std::mem::replace(
+        &mut *state.index_snapshot.write().unwrap(),
+        Arc::new(snapshot),
+    );
+    info!(
+        "Swapped index snapshot from {} to {}",
+        previous.collection, request.collection
+    );
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Rebuilds the concept index of the active collection and swaps it in, so vocabulary updates
+/// ingested into the collection do not need a restart. Requests already running finish on the
+/// previous index.
+#[utoipa::path(
+    tag = "admin",
+    summary = "Rebuild the concept index",
+    description = "Reloads the concept index of the active collection from its index file, or with `source` \
+        set to `qdrant` rebuilds the file from every point in the collection first. The new index is \
+        swapped in once it is fully loaded.",
+    request_body = Option<ReindexRequest>,
+    responses(
+        (status = 200, description = "The new index snapshot", body = SnapshotStatus),
+        (status = 400, description = "The index file could not be loaded"),
+        (status = 409, description = "Another collection was swapped in during the rebuild"),
+        (status = 500, description = "The index file could not be written"),
+        (status = 502, description = "Qdrant could not be scanned"),
+    )
+)]
+#[post("/api/admin/reindex")]
+async fn reindex(
+    request: Option<Json<ReindexRequest>>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let source = request
+        .map(|request| request.into_inner().source)
+        .unwrap_or_default();
+    let previous = current(&state);
+    let collection = previous.collection.clone();
+    let path = previous.vectordb_data_path.clone();
+    info!(
+        "Rebuilding the concept index of {} from {:?}",
+        collection, source
+    );
+
+    if let ReindexSource::Qdrant = source {
+        let pairs = match get_all_id_value_pairs(&state.qdrant_client, &collection).await {
+            Ok(pairs) => pairs,
+            Err(e) => {
+                return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                    "error": format!("Could not scan {}: {}", collection, e)
+                })));
+            }
+        };
+        let target = path.clone();
+        let written = web::block(move || {
+            let mut builder = ConceptIndexBuilder::default();
+            for (id, name) in pairs {
+                builder.insert(&name, id);
+            }
+            builder.write(&target).map_err(|e| e.to_string())
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+        if let Err(e) = written {
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Could not write concept index to {}: {}", path, e)
+            })));
+        }
+    }
+
+    let loaded = web::block({
+        let collection = collection.clone();
+        move || IndexSnapshot::load(&collection, &path).map_err(|e| e.to_string())
+    })
+    .await
+    .unwrap_or_else(|e| Err(e.to_string()));
+    let snapshot = match loaded {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Could not load concept index: {}", e)
+            })));
+        }
+    };
+    let response = describe(&snapshot);
+    let mut active = state.index_snapshot.write().unwrap();
+    if active.collection != collection {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("The index was swapped to {} meanwhile", active.collection)
+        })));
+    }
+    *active = Arc::new(snapshot);
+    info!("Swapped in the rebuilt concept index of {}", collection);
+    Ok(HttpResponse::Ok().json(response))
+}
@@ -0,0 +1,900 @@
+use crate::auth::Identity;
+use crate::domain::{MappingJob, MappingJobRow, MappingReview, SearchDiagnostics, SearchResponse};
+use crate::errors::{ApiError, EmbeddingError, PgError};
+use crate::mapping_reviews::ReviewStatus;
+use crate::profiles::ValidationProfile;
+use crate::search::SearchFilters;
+use crate::tabular::{self, FormatParameter};
+use crate::utils::deserialize_string_or_vec;
+use crate::validation::{
+    self, ConceptSetExpression, RecommendationOptions, RecommendedConcept, TextExamples,
+};
+use crate::{StateWrapper, db, snapshot};
+use actix_web::rt::time::timeout;
+use actix_web::web::{Data, Payload, Query, ReqData};
+use actix_web::{Error, HttpRequest, HttpResponse, delete, get, post, web};
+use chrono::{NaiveDate, Utc};
+use futures::{StreamExt, stream};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::convert::Infallible;
+use std::time::{Duration, Instant};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+/// Source code lists are small next to vocabularies, this still fits a full EHR extract.
+const MAX_UPLOAD_BYTES: usize = 16 * 1024 * 1024;
+pub(crate) const MAX_SOURCE_CODES: usize = 50_000;
+const DEFAULT_CANDIDATES: usize = 5;
+const MAX_CANDIDATES: usize = 50;
+/// Source codes embedded together and searched before the progress is recorded.
+const CHUNK_SIZE: usize = 200;
+/// Searches running at once per job, like a batch search.
+const SEARCH_CONCURRENCY: usize = 8;
+
+const RESULT_COLUMNS: &[&str] = &[
+    "row_number",
+    "source_code",
+    "source_name",
+    "frequency",
+    "rank",
+    "score",
+    "recommendation_score",
+    "matched_name",
+    "concept_id",
+    "concept_name",
+    "domain_id",
+    "vocabulary_id",
+    "concept_class_id",
+    "standard_concept",
+    "concept_code",
+    "review_status",
+    "approved_concept_id",
+    "assigned_to",
+    "error",
+];
+
+/// Where and how many candidates to look for, passed in the query string next to the CSV body.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct MappingParameters {
+    /// Only concepts of these vocabularies, comma-separated.
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    #[param(value_type = Option<String>)]
+    vocabulary_id: Option<Vec<String>>,
+    /// Only concepts of these domains, comma-separated.
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    #[param(value_type = Option<String>)]
+    domain_id: Option<Vec<String>>,
+    /// Only concepts of these classes, comma-separated.
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    #[param(value_type = Option<String>)]
+    concept_class_id: Option<Vec<String>>,
+    /// `S` when omitted, source codes are mapped to standard concepts. `any` lifts the filter.
+    standard_concept: Option<String>,
+    /// Candidates kept per source code.
+    #[param(default = 5, minimum = 1, maximum = 50)]
+    limit: Option<usize>,
+}
+
+impl MappingParameters {
+    fn filters(&self) -> SearchFilters {
+        let standard_concept = match self.standard_concept.as_deref() {
+            None => Some("S".to_string()),
+            Some(value) if value.eq_ignore_ascii_case("any") => None,
+            Some(value) => Some(value.to_string()),
+        };
+        SearchFilters {
+            vocabulary_id: self.vocabulary_id.clone(),
+            standard_concept,
+            domain_id: self.domain_id.clone(),
+            concept_class_id: self.concept_class_id.clone(),
+            ..SearchFilters::default()
+        }
+    }
+}
+
+/// A row of the uploaded source code list.
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+struct SourceCode {
+    source_code: String,
+    source_name: String,
+    frequency: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+struct Candidate {
+    /// 1 for the best candidate of the source code.
+    rank: usize,
+    /// The search score, `None` for concepts only the recommender found.
+    score: Option<f64>,
+    /// The similarity the recommender gives the concept for the source name, `None` when it
+    /// did not recommend it.
+    recommendation_score: Option<f32>,
+    /// The concept name or synonym the source name matched.
+    matched_name: String,
+    concept_id: i32,
+    concept_name: String,
+    domain_id: String,
+    vocabulary_id: String,
+    concept_class_id: String,
+    standard_concept: Option<String>,
+    concept_code: String,
+    valid_start_date: Option<NaiveDate>,
+    valid_end_date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+struct MappedCode {
+    /// The row of the results, from 1, that reviews and comments refer to.
+    row_number: i32,
+    #[serde(flatten)]
+    source: SourceCode,
+    candidates: Vec<Candidate>,
+    /// Why the search of the source code failed, the row has no candidates then.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// Merged in when the results are read, never stored with them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    review: Option<MappingReview>,
+}
+
+impl MappedCode {
+    fn review_status(&self) -> &str {
+        self.review
+            .as_ref()
+            .map_or(ReviewStatus::Unreviewed.as_str(), |review| {
+                review.status.as_str()
+            })
+    }
+}
+
+impl MappedCode {
+    fn into_row(self) -> MappingJobRow {
+        MappingJobRow {
+            row_number: self.row_number,
+            source_code: self.source.source_code,
+            source_name: self.source.source_name,
+            frequency: self.source.frequency,
+            candidates: serde_json::to_value(self.candidates).unwrap_or_default(),
+            error: self.error,
+        }
+    }
+
+    fn from_row(row: MappingJobRow) -> Result<MappedCode, serde_json::Error> {
+        Ok(MappedCode {
+            row_number: row.row_number,
+            source: SourceCode {
+                source_code: row.source_code,
+                source_name: row.source_name,
+                frequency: row.frequency,
+            },
+            candidates: serde_json::from_value(row.candidates)?,
+            error: row.error,
+            review: None,
+        })
+    }
+}
+
+/// The results of a succeeded job, the source codes in the order they were mapped.
+#[derive(Debug, Serialize, ToSchema)]
+struct MappingResult {
+    codes: Vec<MappedCode>,
+    /// Source codes without a single candidate.
+    unmatched: i32,
+    /// Source codes whose search failed.
+    failed: i32,
+    /// Lines of the upload that were skipped.
+    warnings: Vec<String>,
+}
+
+/// Narrows the results to the rows a reviewer works on.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ResultParameters {
+    /// Only rows with this review status, rows never reviewed are `unreviewed`.
+    status: Option<ReviewStatus>,
+    /// Only rows assigned to this reviewer.
+    assigned_to: Option<String>,
+}
+
+/// One line of the downloaded results: a candidate, or the bare source code if it has none.
+#[derive(Clone, Serialize)]
+struct ResultRow {
+    row_number: i32,
+    source_code: String,
+    source_name: String,
+    frequency: Option<i64>,
+    rank: Option<usize>,
+    score: Option<f64>,
+    recommendation_score: Option<f32>,
+    matched_name: Option<String>,
+    concept_id: Option<i32>,
+    concept_name: Option<String>,
+    domain_id: Option<String>,
+    vocabulary_id: Option<String>,
+    concept_class_id: Option<String>,
+    standard_concept: Option<String>,
+    concept_code: Option<String>,
+    review_status: String,
+    approved_concept_id: Option<i32>,
+    assigned_to: Option<String>,
+    error: Option<String>,
+}
+
+impl ResultRow {
+    fn bare(code: &MappedCode) -> ResultRow {
+        let review = code.review.as_ref();
+        ResultRow {
+            row_number: code.row_number,
+            source_code: code.source.source_code.clone(),
+            source_name: code.source.source_name.clone(),
+            frequency: code.source.frequency,
+            rank: None,
+            score: None,
+            recommendation_score: None,
+            matched_name: None,
+            concept_id: None,
+            concept_name: None,
+            domain_id: None,
+            vocabulary_id: None,
+            concept_class_id: None,
+            standard_concept: None,
+            concept_code: None,
+            review_status: code.review_status().to_string(),
+            approved_concept_id: review.and_then(|review| review.concept_id),
+            assigned_to: review.and_then(|review| review.assigned_to.clone()),
+            error: code.error.clone(),
+        }
+    }
+}
+
+/// The result flattened to one row per candidate.
+fn result_rows(result: MappingResult) -> Vec<ResultRow> {
+    result
+        .codes
+        .into_iter()
+        .flat_map(|code| {
+            let bare = ResultRow::bare(&code);
+            if code.candidates.is_empty() {
+                return vec![bare];
+            }
+            code.candidates
+                .into_iter()
+                .map(|candidate| ResultRow {
+                    rank: Some(candidate.rank),
+                    score: candidate.score,
+                    recommendation_score: candidate.recommendation_score,
+                    matched_name: Some(candidate.matched_name),
+                    concept_id: Some(candidate.concept_id),
+                    concept_name: Some(candidate.concept_name),
+                    domain_id: Some(candidate.domain_id),
+                    vocabulary_id: Some(candidate.vocabulary_id),
+                    concept_class_id: Some(candidate.concept_class_id),
+                    standard_concept: candidate.standard_concept,
+                    concept_code: Some(candidate.concept_code),
+                    ..bare.clone()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Maps the usual Usagi and spreadsheet headers onto the columns of a source code list.
+fn canonical_column(header: &str) -> Option<&'static str> {
+    let normalized: String = header
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    match normalized.as_str() {
+        "code" | "sourcecode" | "localcode" => Some("source_code"),
+        "name" | "term" | "description" | "sourcename" | "sourceterm" | "sourcedescription" => {
+            Some("source_name")
+        }
+        "frequency" | "count" | "sourcefrequency" | "occurrences" => Some("frequency"),
+        _ => None,
+    }
+}
+
+/// Reads a CSV or TSV with a name column and optional code and frequency columns. Rows without
+/// a name are skipped with a warning, as are frequencies that are not whole numbers. The codes
+/// are returned most frequent first, like Usagi lists them for review.
+fn parse_source_codes(content: &str) -> Result<(Vec<SourceCode>, Vec<String>), String> {
+    let header_line = content.lines().next().unwrap_or_default();
+    let delimiter = if header_line.contains('\t') {
+        b'\t'
+    } else {
+        b','
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(content.as_bytes());
+
+    let columns: Vec<Option<&'static str>> = reader
+        .headers()
+        .map_err(|e| format!("Could not read CSV header: {}", e))?
+        .iter()
+        .map(canonical_column)
+        .collect();
+    if !columns.contains(&Some("source_name")) {
+        return Err("CSV needs a source_name column".to_string());
+    }
+
+    let mut codes = Vec::new();
+    let mut warnings = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        // Header is line 1
+        let line = index + 2;
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                warnings.push(format!("Skipping line {}: {}", line, e));
+                continue;
+            }
+        };
+        let mut code = SourceCode {
+            source_code: String::new(),
+            source_name: String::new(),
+            frequency: None,
+        };
+        for (column, value) in columns.iter().zip(record.iter()) {
+            match column {
+                Some("source_code") => code.source_code = value.to_string(),
+                Some("source_name") => code.source_name = value.to_string(),
+                Some("frequency") if !value.is_empty() => match value.parse() {
+                    Ok(frequency) => code.frequency = Some(frequency),
+                    Err(_) => {
+                        warnings.push(format!("Ignoring frequency {:?} on line {}", value, line))
+                    }
+                },
+                _ => {}
+            }
+        }
+        if code.source_name.is_empty() {
+            warnings.push(format!("Skipping line {}: missing source_name", line));
+            continue;
+        }
+        codes.push(code);
+    }
+    codes.sort_by_key(|code| std::cmp::Reverse(code.frequency));
+    Ok((codes, warnings))
+}
+
+/// How well a candidate fits the source name, the better of its search score and the
+/// similarity the recommender gives it.
+fn relevance(candidate: &Candidate) -> f64 {
+    let score = candidate.score.unwrap_or(0.0);
+    let recommendation_score = candidate.recommendation_score.map_or(0.0, f64::from);
+    score.max(recommendation_score)
+}
+
+/// The best `limit` concepts of a search and of the recommendations for the same source name,
+/// each once with the best score it was found with, ranked by their relevance. Recommended
+/// concepts outside the vocabularies or concept classes asked for are left out, as the search
+/// leaves them out.
+fn candidates(
+    results: Vec<SearchResponse>,
+    recommendations: Vec<RecommendedConcept>,
+    filters: &SearchFilters,
+    limit: usize,
+) -> Vec<Candidate> {
+    let recommendation_scores: HashMap<i32, f32> = recommendations
+        .iter()
+        .map(|recommended| (recommended.concept_id, recommended.similarity_score))
+        .collect();
+    let mut seen = HashSet::new();
+    let searched = results
+        .into_iter()
+        .flat_map(|result| {
+            let SearchResponse {
+                concept_name: matched_name,
+                score,
+                concepts,
+                ..
+            } = result;
+            concepts
+                .into_iter()
+                .map(move |concept| (matched_name.clone(), score, concept))
+        })
+        .filter(|(_, _, concept)| seen.insert(concept.concept_id))
+        .map(|(matched_name, score, concept)| Candidate {
+            rank: 0,
+            score,
+            recommendation_score: recommendation_scores.get(&concept.concept_id).copied(),
+            matched_name,
+            concept_id: concept.concept_id,
+            concept_name: concept.concept_name,
+            domain_id: concept.domain_id,
+            vocabulary_id: concept.vocabulary_id,
+            concept_class_id: concept.concept_class_id,
+            standard_concept: concept.standard_concept,
+            concept_code: concept.concept_code,
+            valid_start_date: concept.valid_start_date,
+            valid_end_date: concept.valid_end_date,
+        })
+        .collect::<Vec<_>>();
+    let allowed = |allowed: &Option<Vec<String>>, value: &str| {
+        allowed
+            .as_ref()
+            .is_none_or(|allowed| allowed.iter().any(|id| id.eq_ignore_ascii_case(value)))
+    };
+    let recommended = recommendations
+        .into_iter()
+        .filter(|recommended| {
+            allowed(&filters.vocabulary_id, &recommended.vocabulary_id)
+                && allowed(&filters.concept_class_id, &recommended.concept_class_id)
+        })
+        .filter(|recommended| seen.insert(recommended.concept_id))
+        .map(|recommended| Candidate {
+            rank: 0,
+            score: None,
+            recommendation_score: Some(recommended.similarity_score),
+            matched_name: recommended.concept_name.clone(),
+            concept_id: recommended.concept_id,
+            concept_name: recommended.concept_name,
+            domain_id: recommended.domain_id,
+            vocabulary_id: recommended.vocabulary_id,
+            concept_class_id: recommended.concept_class_id,
+            standard_concept: Some(recommended.standard_concept).filter(|s| !s.is_empty()),
+            concept_code: recommended.concept_code,
+            valid_start_date: None,
+            valid_end_date: None,
+        });
+
+    let mut candidates: Vec<Candidate> = searched.into_iter().chain(recommended).collect();
+    // Stable, so the search order decides between equally relevant candidates
+    candidates.sort_by(|a, b| relevance(b).total_cmp(&relevance(a)));
+    candidates.truncate(limit);
+    for (index, candidate) in candidates.iter_mut().enumerate() {
+        candidate.rank = index + 1;
+    }
+    candidates
+}
+
+/// Concepts the recommender finds for the source name from its embedding, like recommendations
+/// steered by a phrase. They are looked for in the domains asked for or, without those, in the
+/// domains of the search candidates.
+async fn recommendations(
+    state: &StateWrapper,
+    source_name: &str,
+    embedding: Vec<f32>,
+    filters: &SearchFilters,
+    results: &[SearchResponse],
+    limit: usize,
+) -> Result<Vec<RecommendedConcept>, PgError> {
+    let domains: Vec<String> = match &filters.domain_id {
+        Some(domain_ids) => state
+            .vocabulary_catalog
+            .canonical_domain_ids(domain_ids)
+            .unwrap_or_else(|| domain_ids.clone()),
+        None => results
+            .iter()
+            .flat_map(|result| &result.concepts)
+            .map(|concept| concept.domain_id.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect(),
+    };
+    if domains.is_empty() {
+        return Ok(Vec::new());
+    }
+    let options = RecommendationOptions {
+        limit: Some(limit as u64),
+        domains: Some(domains),
+        standard_only: filters.standard_concept.as_deref() == Some("S"),
+        ..RecommendationOptions::default()
+    };
+    let texts = TextExamples {
+        positive: vec![(source_name.to_string(), embedding)],
+        negative: Vec::new(),
+    };
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let recommended = validation::get_concept_recommendations(
+        &ConceptSetExpression { items: Vec::new() },
+        &pg_client,
+        &state.qdrant_client,
+        &state.qdrant_read_options,
+        &snapshot::current(state),
+        &options,
+        &texts,
+        ValidationProfile::default(),
+        state.config.recommendation_budget(),
+        &state.recommendation_cache,
+        &state.metrics.recommendation_cache,
+        state.vocabulary_catalog.vocabulary_version.as_deref(),
+    )
+    .await?;
+    Ok(recommended.recommendations)
+}
+
+/// Searches the source names of one chunk, embedding the distinct ones in a single batch, and
+/// scores the candidates with the recommendations for the same names. A failed search is
+/// recorded on its row; only an embedder that does not match the collection fails the chunk.
+async fn map_chunk(
+    state: &StateWrapper,
+    chunk: &[SourceCode],
+    filters: &SearchFilters,
+    limit: usize,
+) -> Result<Vec<MappedCode>, Error> {
+    let names: Vec<String> = chunk
+        .iter()
+        .map(|code| code.source_name.clone())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let started = Instant::now();
+    let embedded = state.embedder.embed_batch(names.clone()).await;
+    state
+        .metrics
+        .record_dependency("embedding", "embed_batch", started.elapsed());
+    let embeddings: HashMap<String, Vec<f32>> = match embedded {
+        Ok(vectors) => names.into_iter().zip(vectors).collect(),
+        Err(e) => {
+            if let Some(mismatch) = e.downcast_ref::<EmbeddingError>() {
+                return Err(mismatch.clone().into());
+            }
+            // Each search embeds its own name again
+            warn!(
+                "Batch embedding failed, falling back to per-term embedding: {}",
+                e
+            );
+            HashMap::new()
+        }
+    };
+
+    let mapped = stream::iter(chunk)
+        .map(|code| {
+            let embedding = embeddings.get(&code.source_name).cloned();
+            async move {
+                let mut diagnostics = SearchDiagnostics::new(state.config.search.score_threshold);
+                let mut mapped = MappedCode {
+                    // Numbered by the job
+                    row_number: 0,
+                    source: code.clone(),
+                    candidates: Vec::new(),
+                    error: None,
+                    review: None,
+                };
+                let results = match state
+                    .search_pipeline
+                    .run_with_embedding(
+                        state,
+                        &code.source_name,
+                        embedding.clone(),
+                        filters.clone(),
+                        limit as u64,
+                        &mut diagnostics,
+                    )
+                    .await
+                {
+                    Ok(results) => results,
+                    Err(e) => {
+                        warn!("Could not map source name {:?}: {}", code.source_name, e);
+                        mapped.error = Some(e.to_string());
+                        return mapped;
+                    }
+                };
+                // The search candidates stand on their own without recommendations
+                let recommended = match embedding {
+                    Some(embedding) => recommendations(
+                        state,
+                        &code.source_name,
+                        embedding,
+                        filters,
+                        &results,
+                        limit,
+                    )
+                    .await
+                    .unwrap_or_else(|e| {
+                        warn!(
+                            "Could not get recommendations for source name {:?}: {}",
+                            code.source_name, e
+                        );
+                        Vec::new()
+                    }),
+                    None => Vec::new(),
+                };
+                mapped.candidates = candidates(results, recommended, filters, limit);
+                mapped
+            }
+        })
+        .buffered(SEARCH_CONCURRENCY)
+        .collect()
+        .await;
+    Ok(mapped)
+}
+
+/// Maps the source codes chunk by chunk once a job slot is free, storing the rows of every
+/// chunk and recording the progress so large uploads can be followed.
+async fn run_job(
+    state: Data<StateWrapper>,
+    id: Uuid,
+    codes: Vec<SourceCode>,
+    filters: SearchFilters,
+    limit: usize,
+) {
+    let budget = Duration::from_secs(state.config.jobs.timeout_secs);
+    let mapping = async {
+        let _slot = state.job_slots.acquire().await;
+        let (mut mapped_codes, mut unmatched, mut failed) = (0, 0, 0);
+        for chunk in codes.chunks(CHUNK_SIZE) {
+            let mut mapped = map_chunk(&state, chunk, &filters, limit).await?;
+            for code in &mut mapped {
+                mapped_codes += 1;
+                code.row_number = mapped_codes;
+                if code.error.is_some() {
+                    failed += 1;
+                } else if code.candidates.is_empty() {
+                    unmatched += 1;
+                }
+            }
+            let rows: Vec<MappingJobRow> = mapped.into_iter().map(MappedCode::into_row).collect();
+            let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+            db::insert_mapping_job_rows(&pg_client, id, &rows).await?;
+            let progress = mapped_codes as f64 / codes.len() as f64;
+            if let Err(e) =
+                db::update_mapping_job_progress(&pg_client, id, progress, mapped_codes).await
+            {
+                warn!("Could not record the progress of mapping job {}: {}", id, e);
+            }
+        }
+        Ok::<_, Error>((unmatched, failed))
+    };
+
+    let (status, counts, error) = match timeout(budget, mapping).await {
+        Ok(Ok(counts)) => ("succeeded", counts, None),
+        Ok(Err(e)) => ("failed", (0, 0), Some(e.to_string())),
+        Err(_) => (
+            "failed",
+            (0, 0),
+            Some(format!(
+                "The job did not finish within {}s",
+                budget.as_secs()
+            )),
+        ),
+    };
+    info!("Mapping job {} {}", id, status);
+    let finished = match state.pg_pool.get().await {
+        Ok(pg_client) => {
+            db::finish_mapping_job(&pg_client, id, status, counts, error.as_deref()).await
+        }
+        Err(e) => Err(PgError::PoolError(e)),
+    };
+    if let Err(e) = finished {
+        warn!("Could not record the outcome of mapping job {}: {}", id, e);
+    }
+}
+
+/// Queues the mapping of an uploaded source code list, Usagi's input on the server: a CSV or
+/// TSV with a `source_name` column and optional `source_code` and `frequency` columns. Answers
+/// `202 Accepted` with the job to poll.
+#[utoipa::path(
+    tag = "mapping",
+    summary = "Queue a source code mapping",
+    description = "Maps an uploaded list of source codes to candidate concepts in the background, like Usagi. \
+        Names are searched like a batch search and the best candidates kept per source code. Poll the job at the \
+        `Location` header until its status is `succeeded` or `failed`.",
+    params(MappingParameters),
+    request_body(
+        description = "A header row with a `source_name` column and optional `source_code` and `frequency` \
+            columns. At most 16 MB and 50,000 source codes.",
+        content((String = "text/csv"), (String = "text/tab-separated-values"))
+    ),
+    responses(
+        (status = 202, description = "The queued job", body = MappingJob,
+            headers(("Location" = String, description = "The job to poll"))),
+        (status = 400, description = "The upload has no source names, too many source codes or an unknown filter value"),
+        (status = 413, description = "The upload is too large"),
+    )
+)]
+#[post("/api/mapping/jobs")]
+async fn create_mapping_job(
+    parameters: Query<MappingParameters>,
+    payload: Payload,
+    identity: Option<ReqData<Identity>>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let limit = parameters.limit.unwrap_or(DEFAULT_CANDIDATES);
+    if !(1..=MAX_CANDIDATES).contains(&limit) {
+        return Err(ApiError::OutOfRange {
+            parameter: "limit",
+            min: 1.0,
+            max: MAX_CANDIDATES as f64,
+        }
+        .into());
+    }
+    let catalog = &state.vocabulary_catalog;
+    catalog.validate_vocabulary_ids(parameters.vocabulary_id.as_deref())?;
+    catalog.validate_domain_ids(parameters.domain_id.as_deref())?;
+    let Ok(body) = payload.to_bytes_limited(MAX_UPLOAD_BYTES).await else {
+        return Ok(HttpResponse::PayloadTooLarge().json(serde_json::json!({
+            "error": format!("Source code lists are limited to {} bytes", MAX_UPLOAD_BYTES)
+        })));
+    };
+    let body = body?;
+    let (codes, warnings) = match std::str::from_utf8(&body)
+        .map_err(|e| format!("Source code list is not valid UTF-8: {}", e))
+        .and_then(parse_source_codes)
+    {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+        }
+    };
+    if codes.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "There are no source codes to map",
+            "warnings": warnings,
+        })));
+    }
+    if codes.len() > MAX_SOURCE_CODES {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("At most {} source codes can be mapped per job", MAX_SOURCE_CODES)
+        })));
+    }
+
+    let created_by = identity.map(|identity| identity.into_inner().name);
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    match db::delete_expired_mapping_jobs(&pg_client, state.config.jobs.retention_hours).await {
+        Ok(0) => {}
+        Ok(deleted) => info!("Removed {} expired mapping jobs", deleted),
+        Err(e) => warn!("Could not remove expired mapping jobs: {}", e),
+    }
+    let id = Uuid::new_v4();
+    let job = db::insert_mapping_job(
+        &pg_client,
+        id,
+        codes.len() as i32,
+        &warnings,
+        created_by.as_deref(),
+    )
+    .await?;
+    info!("Queued mapping job {} for {} source codes", id, codes.len());
+    actix_web::rt::spawn(run_job(
+        state.clone(),
+        id,
+        codes,
+        parameters.filters(),
+        limit,
+    ));
+    Ok(HttpResponse::Accepted()
+        .insert_header(("Location", format!("/api/mapping/jobs/{}", id)))
+        .json(job))
+}
+
+/// The job's status and how many source codes it mapped. Jobs left unfinished past the
+/// timeout, e.g. because their replica restarted, are reported as failed.
+#[utoipa::path(
+    tag = "mapping",
+    summary = "Get a mapping job",
+    description = "The job's status and how many source codes it mapped. Jobs are kept for \
+        `JOBS__RETENTION_HOURS`.",
+    params(("id" = Uuid, Path, description = "Job id")),
+    responses(
+        (status = 200, description = "The job", body = MappingJob),
+        (status = 404, description = "No such job"),
+    )
+)]
+#[get("/api/mapping/jobs/{id}")]
+async fn get_mapping_job(
+    path: web::Path<Uuid>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let mut job: MappingJob = db::get_mapping_job(&pg_client, path.into_inner()).await?;
+    let deadline = job.created_at
+        + chrono::Duration::seconds(state.config.jobs.timeout_secs as i64)
+        + chrono::Duration::minutes(1);
+    if job.finished_at.is_none() && Utc::now() > deadline {
+        job.status = "failed".to_string();
+        job.error = Some("The job was interrupted before it finished".to_string());
+    }
+    Ok(HttpResponse::Ok().json(job))
+}
+
+/// The ranked candidates of a succeeded job with the review state of each row, as JSON or as
+/// one CSV or TSV line per candidate for review in a spreadsheet. Source codes without
+/// candidates, e.g. because their search failed, keep a line of their own.
+#[utoipa::path(
+    tag = "mapping",
+    summary = "Download the candidates of a mapping job",
+    description = "The source codes, most frequent first, with their ranked candidate concepts and review state. \
+        `format=csv` or `format=tsv`, or the matching Accept header, returns one line per candidate, source codes \
+        without candidates keep a line of their own.",
+    params(("id" = Uuid, Path, description = "Job id"), ResultParameters, FormatParameter),
+    responses(
+        (status = 200, description = "The candidates",
+            content(
+                (MappingResult = "application/json"),
+                (String = "text/csv"),
+                (String = "text/tab-separated-values"),
+            )
+        ),
+        (status = 404, description = "No such job"),
+        (status = 409, description = "The job has not succeeded (yet)"),
+    )
+)]
+#[get("/api/mapping/jobs/{id}/results")]
+async fn get_mapping_job_results(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    parameters: Query<ResultParameters>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let job = db::get_mapping_job(&pg_client, id).await?;
+    if job.status != "succeeded" {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "The job has no results, poll it until it succeeded"
+        })));
+    }
+    let rows = db::get_mapping_job_rows(
+        &pg_client,
+        id,
+        parameters.status.map(ReviewStatus::as_str),
+        parameters.assigned_to.as_deref(),
+    )
+    .await?;
+    let mut codes = rows
+        .into_iter()
+        .map(MappedCode::from_row)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| {
+            actix_web::error::ErrorInternalServerError(format!("Unreadable mapping result: {}", e))
+        })?;
+    let mut reviews: HashMap<i32, MappingReview> = db::get_mapping_reviews(&pg_client, id)
+        .await?
+        .into_iter()
+        .map(|review| (review.row_number, review))
+        .collect();
+    for code in &mut codes {
+        code.review = reviews.remove(&code.row_number);
+    }
+    let result = MappingResult {
+        codes,
+        unmatched: job.unmatched_codes,
+        failed: job.failed_codes,
+        warnings: job.warnings,
+    };
+
+    let Some(format) = tabular::requested(&req) else {
+        return Ok(HttpResponse::Ok().json(result));
+    };
+    let rows = result_rows(result).into_iter();
+    Ok(tabular::respond(
+        HttpResponse::Ok(),
+        format,
+        RESULT_COLUMNS,
+        stream::iter(rows.map(Ok::<_, Infallible>)),
+    ))
+}
+
+/// Removes a job with its review state and comments. Jobs whose review started are kept past
+/// `JOBS__RETENTION_HOURS` until they are deleted.
+#[utoipa::path(
+    tag = "mapping",
+    summary = "Delete a mapping job",
+    description = "Removes the job with its review state and comments. Jobs whose review started are kept past \
+        `JOBS__RETENTION_HOURS` until deleted.",
+    params(("id" = Uuid, Path, description = "Job id")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 404, description = "No such job"),
+    )
+)]
+#[delete("/api/mapping/jobs/{id}")]
+async fn delete_mapping_job(
+    path: web::Path<Uuid>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    let pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    db::delete_mapping_job(&pg_client, id).await?;
+    info!("Deleted mapping job {}", id);
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,145 @@
+use fst::automaton::Levenshtein;
+use fst::{IntoStreamer, Map, MapBuilder, Streamer};
+use log::info;
+use memmap2::Mmap;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use uuid::Uuid;
+
+/// Words shorter than this are left alone, at one or two letters almost anything is a typo of
+/// something.
+const MIN_CORRECTED_LENGTH: usize = 4;
+/// Words from this length on are corrected across two edits, shorter ones across one.
+const TWO_EDITS_FROM_LENGTH: usize = 7;
+
+/// The spelling dictionary sits next to the concept index it was built with.
+fn dictionary_path(index_path: &str) -> String {
+    format!("{}.spelling", index_path)
+}
+
+/// Words of the concept names and synonyms with the number of names they occur in, for "did you
+/// mean" suggestions. Like SymSpell, candidates are ranked by edit distance first and then by
+/// how common the word is, but candidates are found by running a Levenshtein automaton over the
+/// memory-mapped word FST rather than by precomputing deletes.
+pub struct SpellingDictionary {
+    words: Map<Mmap>,
+}
+
+impl fmt::Debug for SpellingDictionary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpellingDictionary")
+            .field("words", &self.words.len())
+            .finish()
+    }
+}
+
+impl SpellingDictionary {
+    /// The dictionary written alongside the concept index at `index_path`, `None` for indexes
+    /// written before there were dictionaries.
+    pub fn load(index_path: &str) -> Result<Option<SpellingDictionary>, Box<dyn Error>> {
+        let path = dictionary_path(index_path);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                info!(
+                    "No spelling dictionary at {}, rerun `ingest` or reindex to get suggestions",
+                    path
+                );
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        // Safety: like the concept index, the dictionary is only ever replaced by renaming
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(Some(SpellingDictionary {
+            words: Map::new(mmap)?,
+        }))
+    }
+
+    /// The query with its unknown words replaced by the closest known ones, `None` when every
+    /// word is known or nothing close enough was found.
+    pub fn suggest(&self, query: &str) -> Option<String> {
+        let query = query.trim().to_lowercase();
+        let mut suggested = String::with_capacity(query.len());
+        let mut word = String::new();
+        let mut corrected = false;
+        for c in query.chars().chain(std::iter::once(' ')) {
+            if c.is_alphanumeric() {
+                word.push(c);
+                continue;
+            }
+            if let Some(correction) = self.correct(&word) {
+                suggested.push_str(&correction);
+                corrected = true;
+            } else {
+                suggested.push_str(&word);
+            }
+            word.clear();
+            suggested.push(c);
+        }
+        suggested.pop();
+        corrected.then_some(suggested)
+    }
+
+    /// The most common word one edit away, or two for longer words.
+    fn correct(&self, word: &str) -> Option<String> {
+        let length = word.chars().count();
+        if length < MIN_CORRECTED_LENGTH
+            || !word.chars().all(char::is_alphabetic)
+            || self.words.contains_key(word)
+        {
+            return None;
+        }
+        let max_distance = if length >= TWO_EDITS_FROM_LENGTH {
+            2
+        } else {
+            1
+        };
+        (1..=max_distance).find_map(|distance| self.most_common_within(word, distance))
+    }
+
+    fn most_common_within(&self, word: &str, distance: u32) -> Option<String> {
+        // Automatons of long words can exceed the state limit, those get no suggestion
+        let automaton = Levenshtein::new(word, distance).ok()?;
+        let mut stream = self.words.search(automaton).into_stream();
+        let mut best: Option<(u64, Vec<u8>)> = None;
+        while let Some((candidate, count)) = stream.next() {
+            if best
+                .as_ref()
+                .is_none_or(|(best_count, _)| count > *best_count)
+            {
+                best = Some((count, candidate.to_vec()));
+            }
+        }
+        best.and_then(|(_, candidate)| String::from_utf8(candidate).ok())
+    }
+}
+
+/// Writes the dictionary of the lowercase `names` next to the concept index at `index_path`,
+/// renaming it into place like the index.
+pub fn write_dictionary<'a>(
+    index_path: &str,
+    names: impl Iterator<Item = &'a str>,
+) -> Result<usize, Box<dyn Error>> {
+    let mut counts: BTreeMap<&str, u64> = BTreeMap::new();
+    for name in names {
+        for word in name.split(|c: char| !c.is_alphanumeric()) {
+            if word.chars().count() >= MIN_CORRECTED_LENGTH && word.chars().all(char::is_alphabetic)
+            {
+                *counts.entry(word).or_default() += 1;
+            }
+        }
+    }
+    let path = dictionary_path(index_path);
+    let temporary = format!("{}.{}.tmp", path, Uuid::new_v4().simple());
+    let mut builder = MapBuilder::new(BufWriter::new(File::create(&temporary)?))?;
+    for (word, count) in &counts {
+        builder.insert(word, *count)?;
+    }
+    builder.finish()?;
+    fs::rename(&temporary, &path)?;
+    Ok(counts.len())
+}
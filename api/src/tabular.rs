@@ -0,0 +1,182 @@
+use crate::code_systems::CodeSystemMatch;
+use crate::domain::{Concept, SearchResponse};
+use actix_web::http::header::{ACCEPT, CONTENT_TYPE};
+use actix_web::web::{Bytes, Query};
+use actix_web::{HttpRequest, HttpResponse, HttpResponseBuilder};
+use chrono::NaiveDate;
+use futures::{Stream, StreamExt, stream};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use utoipa::IntoParams;
+
+/// Delimited text for spreadsheets and R, one flattened concept per row.
+#[derive(Clone, Copy, Debug)]
+pub enum Format {
+    Csv,
+    Tsv,
+}
+
+impl Format {
+    fn media_type(self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv; charset=utf-8",
+            Format::Tsv => "text/tab-separated-values; charset=utf-8",
+        }
+    }
+
+    fn delimiter(self) -> u8 {
+        match self {
+            Format::Csv => b',',
+            Format::Tsv => b'\t',
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FormatParameter {
+    /// `csv` or `tsv` for delimited text instead of JSON, one row per concept. Same as Accept
+    /// `text/csv` or `text/tab-separated-values`.
+    format: Option<String>,
+}
+
+/// The delimited format the client asked for, through `?format=` or the Accept header. The
+/// query parameter wins so links pasted into a spreadsheet work without custom headers.
+pub fn requested(req: &HttpRequest) -> Option<Format> {
+    if let Ok(parameter) = Query::<FormatParameter>::from_query(req.query_string())
+        && let Some(format) = &parameter.format
+    {
+        return match format.to_ascii_lowercase().as_str() {
+            "csv" => Some(Format::Csv),
+            "tsv" => Some(Format::Tsv),
+            _ => None,
+        };
+    }
+    req.headers()
+        .get_all(ACCEPT)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|media| match media.split(';').next().unwrap_or("").trim() {
+            "text/csv" => Some(Format::Csv),
+            "text/tab-separated-values" => Some(Format::Tsv),
+            _ => None,
+        })
+}
+
+/// A search result flattened to one row per concept, with the label it matched on.
+#[derive(Serialize)]
+pub struct SearchRow {
+    matched_name: String,
+    score: Option<f64>,
+    concept_id: i32,
+    concept_name: String,
+    domain_id: String,
+    vocabulary_id: String,
+    concept_class_id: String,
+    standard_concept: Option<String>,
+    concept_code: String,
+    invalid_reason: Option<String>,
+    valid_start_date: Option<NaiveDate>,
+    valid_end_date: Option<NaiveDate>,
+}
+
+pub const SEARCH_COLUMNS: &[&str] = &[
+    "matched_name",
+    "score",
+    "concept_id",
+    "concept_name",
+    "domain_id",
+    "vocabulary_id",
+    "concept_class_id",
+    "standard_concept",
+    "concept_code",
+    "invalid_reason",
+    "valid_start_date",
+    "valid_end_date",
+];
+
+pub const CODE_SYSTEM_COLUMNS: &[&str] = &["system", "code", "name", "score"];
+
+pub const RESOLVED_COLUMNS: &[&str] = &["concept_id", "concept_name", "vocabulary_id", "domain_id"];
+
+pub fn search_rows(results: Vec<SearchResponse>) -> Vec<SearchRow> {
+    results
+        .into_iter()
+        .flat_map(|result| {
+            let SearchResponse {
+                concept_name: matched_name,
+                score,
+                concepts,
+                ..
+            } = result;
+            concepts.into_iter().map(move |concept: Concept| SearchRow {
+                matched_name: matched_name.clone(),
+                score,
+                concept_id: concept.concept_id,
+                concept_name: concept.concept_name,
+                domain_id: concept.domain_id,
+                vocabulary_id: concept.vocabulary_id,
+                concept_class_id: concept.concept_class_id,
+                standard_concept: concept.standard_concept,
+                concept_code: concept.concept_code,
+                invalid_reason: concept.invalid_reason,
+                valid_start_date: concept.valid_start_date,
+                valid_end_date: concept.valid_end_date,
+            })
+        })
+        .collect()
+}
+
+/// Code system matches without their catalog attributes, which differ between systems.
+pub fn code_system_rows(
+    matches: Vec<CodeSystemMatch>,
+) -> Vec<(String, String, String, Option<f64>)> {
+    matches
+        .into_iter()
+        .map(|code| (code.system, code.code, code.name, code.score))
+        .collect()
+}
+
+/// Streams the header and then one delimited line per row as they are produced. Like NDJSON,
+/// a row that fails ends the response early.
+pub fn respond<T, E, S>(
+    mut response: HttpResponseBuilder,
+    format: Format,
+    columns: &[&str],
+    rows: S,
+) -> HttpResponse
+where
+    T: Serialize,
+    E: Error + 'static,
+    S: Stream<Item = Result<T, E>> + 'static,
+{
+    let header = encode(format, |writer| writer.write_record(columns));
+    let lines = rows.map(move |row| {
+        let row = row.inspect_err(|e| warn!("Ending {:?} response early: {}", format, e))?;
+        Ok::<_, E>(encode(format, |writer| writer.serialize(&row)))
+    });
+    response
+        .insert_header((CONTENT_TYPE, format.media_type()))
+        .streaming(stream::once(async move { Ok(header) }).chain(lines))
+}
+
+/// The delimited line(s) `write` produces, empty when they could not be written.
+fn encode(
+    format: Format,
+    write: impl FnOnce(&mut csv::Writer<Vec<u8>>) -> csv::Result<()>,
+) -> Bytes {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(format.delimiter())
+        .has_headers(false)
+        .from_writer(Vec::new());
+    let written =
+        write(&mut writer).and_then(|_| writer.into_inner().map_err(|e| e.into_error().into()));
+    match written {
+        Ok(line) => Bytes::from(line),
+        Err(e) => {
+            warn!("Could not write {:?} row: {}", format, e);
+            Bytes::new()
+        }
+    }
+}
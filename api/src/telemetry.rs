@@ -0,0 +1,127 @@
+use crate::config::TelemetryConfig;
+use actix_web::Error;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::HeaderMap;
+use actix_web::middleware::Next;
+use log::{info, warn};
+use opentelemetry::propagation::{Extractor, TextMapPropagator};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{Protocol, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{BatchConfigBuilder, BatchSpanProcessor, SdkTracerProvider};
+use std::time::Duration;
+use tracing::{Instrument, field};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{Layer, registry};
+
+/// Finished spans kept while the collector is unreachable, newer ones are dropped beyond this.
+const MAX_QUEUED_SPANS: usize = 10_000;
+
+/// Request headers as seen by the W3C trace context propagator.
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+/// Installs the span collector and starts exporting to `TELEMETRY__OTLP_ENDPOINT` over
+/// OTLP/HTTP. Does nothing when no endpoint is configured, so the instrumentation costs nothing
+/// by default. Only the spans of this crate are exported, logging goes through `log`.
+pub fn init(config: &TelemetryConfig) {
+    let Some(endpoint) = &config.otlp_endpoint else {
+        return;
+    };
+    let exporter = match SpanExporter::builder()
+        .with_http()
+        .with_protocol(Protocol::HttpJson)
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build()
+    {
+        Ok(exporter) => exporter,
+        Err(e) => {
+            warn!("Could not create the trace exporter: {}", e);
+            return;
+        }
+    };
+    let batches = BatchConfigBuilder::default()
+        .with_scheduled_delay(Duration::from_millis(config.export_interval_ms.max(100)))
+        .with_max_queue_size(MAX_QUEUED_SPANS)
+        .build();
+    let provider = SdkTracerProvider::builder()
+        .with_span_processor(
+            BatchSpanProcessor::builder(exporter)
+                .with_batch_config(batches)
+                .build(),
+        )
+        .with_resource(
+            Resource::builder()
+                .with_service_name(config.service_name.clone())
+                .build(),
+        )
+        .build();
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+    let spans = tracing_opentelemetry::layer()
+        .with_tracer(tracer)
+        .with_filter(filter_fn(|metadata| {
+            metadata.is_span() && metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+        }));
+    if let Err(e) = registry().with(spans).try_init() {
+        warn!("Could not install the trace collector: {}", e);
+        return;
+    }
+    // Kept by the global so the batches are exported for the lifetime of the process
+    opentelemetry::global::set_tracer_provider(provider);
+    info!("Exporting traces to {}", endpoint);
+}
+
+/// Middleware opening the root span of every request, named after its route once routed and
+/// joined to the caller's trace when a `traceparent` header is sent.
+pub async fn trace_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let span = tracing::info_span!(
+        "request",
+        otel.name = field::Empty,
+        otel.kind = "server",
+        otel.status_code = field::Empty,
+        http.request.method = %req.method(),
+        url.path = req.path(),
+        http.route = field::Empty,
+        http.response.status_code = field::Empty,
+    );
+    let _ = span.set_parent(TraceContextPropagator::new().extract(&HeaderExtractor(req.headers())));
+    let res = next
+        .call(req)
+        .instrument(span.clone())
+        .await?
+        .map_into_boxed_body();
+    let route = res.request().match_pattern();
+    if let Some(route) = &route {
+        span.record("http.route", route.as_str());
+    }
+    span.record(
+        "otel.name",
+        format!(
+            "{} {}",
+            res.request().method(),
+            route.as_deref().unwrap_or(res.request().path())
+        ),
+    );
+    span.record("http.response.status_code", res.status().as_u16());
+    if res.status().is_server_error() {
+        span.record("otel.status_code", "ERROR");
+    }
+    Ok(res)
+}
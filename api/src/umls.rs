@@ -37,7 +37,10 @@ pub struct NLMConcept {
 }
 
 pub async fn get_umls_definition_from_nlm(concept: String) -> Result<Option<String>, Unauthorized> {
-    let api_key = env::var("UMLS_API_KEY").expect("UMLS_API_KEY must be set");
+    let Ok(api_key) = env::var("UMLS_API_KEY") else {
+        warn!("UMLS_API_KEY is not set, definitions cannot be fetched");
+        return Err(Unauthorized);
+    };
     let url = format!(
         "https://uts-ws.nlm.nih.gov/esearch/es/current?apiKey={}",
         api_key
@@ -54,7 +57,13 @@ pub async fn get_umls_definition_from_nlm(concept: String) -> Result<Option<Stri
     let body: NLMResponse = match response {
         Ok(r) => {
             if r.status() == 200 {
-                r.json().await.unwrap()
+                match r.json().await {
+                    Ok(body) => body,
+                    Err(e) => {
+                        warn!("Could not read the NLM response for {}: {}", concept, e);
+                        return Ok(None);
+                    }
+                }
             } else if r.status() == 401 {
                 return Err(Unauthorized);
             } else {
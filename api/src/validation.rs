@@ -1,41 +1,34 @@
 use crate::db;
-use crate::domain::SearchResponse;
+use crate::domain::{Concept, LinkedConcept, SearchResponse};
 use crate::errors::PgError;
+use crate::expansions;
+use crate::metrics::CacheStats;
+use crate::profiles::{ValidationProfile, ValidationRule};
+use crate::qdrant::{ReadOptions, WithReadOptions};
+use crate::snapshot::IndexSnapshot;
+use actix_web::rt::time::timeout;
 use deadpool_postgres::Client;
 use log::{info, warn};
 use qdrant_client::Qdrant;
-use qdrant_client::qdrant::{PointId, QueryPointsBuilder, RecommendInputBuilder};
+use qdrant_client::qdrant::point_id::PointIdOptions;
+use qdrant_client::qdrant::vector_output::Vector;
+use qdrant_client::qdrant::{
+    PointId, QueryBatchPointsBuilder, QueryPoints, QueryPointsBuilder, RecommendInputBuilder,
+    VectorInput,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::{HashMap, HashSet};
-use uuid::Uuid;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::instrument;
+use utoipa::ToSchema;
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct Concept {
-    #[serde(rename = "CONCEPT_ID")]
-    pub concept_id: i32,
-    #[serde(rename = "CONCEPT_NAME")]
-    pub concept_name: String,
-    #[serde(rename = "VOCABULARY_ID")]
-    pub vocabulary_id: String,
-    #[serde(rename = "DOMAIN_ID")]
-    pub domain_id: String,
-    #[serde(rename = "CONCEPT_CLASS_ID")]
-    pub concept_class_id: String,
-    #[serde(rename = "STANDARD_CONCEPT")]
-    pub standard_concept: Option<String>,
-    #[serde(rename = "STANDARD_CONCEPT_CAPTION")]
-    pub standard_concept_caption: Option<String>,
-    #[serde(rename = "INVALID_REASON")]
-    pub invalid_reason: Option<String>,
-    #[serde(rename = "INVALID_REASON_CAPTION")]
-    pub invalid_reason_caption: Option<String>,
-    #[serde(rename = "CONCEPT_CODE")]
-    pub concept_code: Option<String>,
-}
-
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct ConceptSetItem {
+    #[serde(with = "crate::domain::atlas_concept")]
+    #[schema(value_type = crate::domain::atlas_concept::AtlasConcept)]
     pub concept: Concept,
     #[serde(rename = "isExcluded")]
     pub is_excluded: bool,
@@ -45,19 +38,19 @@ pub struct ConceptSetItem {
     pub include_mapped: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct ConceptSetExpression {
     pub items: Vec<ConceptSetItem>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct ConceptSetWithMetadata {
     pub id: Option<i32>,
     pub name: Option<String>,
     pub expression: ConceptSetExpression,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct ConceptGatheringResult {
     pub included_concepts: Vec<i32>,
     pub included_descendants: Vec<i32>, // Will store actual descendant concept IDs
@@ -78,25 +71,182 @@ impl ConceptGatheringResult {
             excluded_mapped: Vec::new(),
         }
     }
+
+    /// The distinct concept IDs the concept set resolves to, in ascending order.
+    pub fn resolved_concept_ids(&self) -> Vec<i32> {
+        let mut resolved: Vec<i32> = self
+            .included_concepts
+            .iter()
+            .chain(&self.included_descendants)
+            .chain(&self.included_mapped)
+            .copied()
+            .collect();
+        sort_and_dedup_vec(&mut resolved);
+        resolved
+    }
+}
+
+/// Steps of concept set analysis, reported through `Progress` as they start.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AnalysisStage {
+    Parsing,
+    Checks,
+    DescendantExpansion,
+    MappedExpansion,
+    Recommendations,
+}
+
+impl AnalysisStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnalysisStage::Parsing => "parsing",
+            AnalysisStage::Checks => "checks",
+            AnalysisStage::DescendantExpansion => "descendant_expansion",
+            AnalysisStage::MappedExpansion => "mapped_expansion",
+            AnalysisStage::Recommendations => "recommendations",
+        }
+    }
+
+    /// Rough share of the analysis done when the stage starts, for progress bars.
+    pub fn fraction(&self) -> f64 {
+        match self {
+            AnalysisStage::Parsing => 0.0,
+            AnalysisStage::Checks => 0.05,
+            AnalysisStage::DescendantExpansion => 0.3,
+            AnalysisStage::MappedExpansion => 0.5,
+            AnalysisStage::Recommendations => 0.6,
+        }
+    }
+}
+
+/// Where analysis reports the stage it is in, nowhere unless created with `channel`.
+#[derive(Debug, Default, Clone)]
+pub struct Progress(Option<mpsc::UnboundedSender<AnalysisStage>>);
+
+impl Progress {
+    /// A progress reporter and the receiving end of its stages, which ends once every clone of
+    /// the reporter is dropped.
+    pub fn channel() -> (Progress, mpsc::UnboundedReceiver<AnalysisStage>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (Progress(Some(sender)), receiver)
+    }
+
+    pub fn report(&self, stage: AnalysisStage) {
+        if let Some(sender) = &self.0 {
+            // Nobody listening any more is not a reason to stop the analysis
+            let _ = sender.send(stage);
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct ValidationResult {
     pub valid: bool,
+    pub profile: ValidationProfile,
     pub errors: Vec<String>,
     pub warnings: Vec<String>,
     pub concept_summary: Option<ConceptGatheringResult>,
     pub recommendations: Option<ConceptRecommendations>,
+    /// Address of the stored expansion, see `expansions::get_expansion`.
+    pub expansion_hash: Option<String>,
+    /// Included concepts that are not standard, see `check_include_mapped`.
+    pub non_standard_concepts: Vec<NonStandardConcept>,
+    /// Items that can be removed without changing the resolved set, see `check_redundant_items`.
+    pub redundant_items: Vec<RedundantItem>,
+    /// Excluded items that remove explicitly included concepts, see `check_exclusion_conflicts`.
+    pub exclusion_conflicts: Vec<ExclusionConflict>,
+    /// Receives the stages of the analysis filling in this result.
+    pub progress: Progress,
+}
+
+/// An excluded item whose concept, descendants or mapped concepts take out concepts that other
+/// items include explicitly.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ExclusionConflict {
+    pub concept_id: i32,
+    pub concept_name: String,
+    pub removed_concept_ids: Vec<i32>,
+}
+
+/// An included item that another item already covers through its descendants.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RedundantItem {
+    pub concept_id: i32,
+    pub concept_name: String,
+    pub covered_by_concept_id: i32,
+    pub covered_by_concept_name: String,
+}
+
+/// An included non-standard concept with the standard concepts it maps to, which are what the
+/// clinical tables record instead.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct NonStandardConcept {
+    pub concept_id: i32,
+    pub concept_name: String,
+    pub vocabulary_id: String,
+    pub maps_to: Vec<StandardEquivalent>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct StandardEquivalent {
+    pub concept_id: i32,
+    pub concept_name: String,
+    pub vocabulary_id: String,
+    pub domain_id: String,
+}
+
+/// How many concepts each kind of item contributes to the resolved set.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ConceptSummary {
+    pub included_concepts_count: usize,
+    pub included_descendants_count: usize,
+    pub included_mapped_count: usize,
+    pub excluded_concepts_count: usize,
+    pub excluded_descendants_count: usize,
+    pub excluded_mapped_count: usize,
+    pub total_included: usize,
+    pub total_excluded: usize,
+}
+
+/// A `ValidationResult` as the analysis endpoints return it. The checks' findings are left out
+/// when there are none.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ConceptSetAnalysis<'a> {
+    pub valid: bool,
+    pub profile: ValidationProfile,
+    pub errors: &'a [String],
+    pub warnings: &'a [String],
+    /// Absent when the concept set could not be parsed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concept_summary: Option<ConceptSummary>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub non_standard_concepts: &'a [NonStandardConcept],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub redundant_items: &'a [RedundantItem],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub exclusion_conflicts: &'a [ExclusionConflict],
+    /// Address of the stored expansion, see `/api/expansions/{hash}`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expansion_hash: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recommendations: Option<&'a ConceptRecommendations>,
 }
 
 impl ValidationResult {
     pub fn new() -> Self {
         Self {
             valid: true,
+            profile: ValidationProfile::default(),
             errors: Vec::new(),
             warnings: Vec::new(),
             concept_summary: None,
             recommendations: None,
+            expansion_hash: None,
+            non_standard_concepts: Vec::new(),
+            redundant_items: Vec::new(),
+            exclusion_conflicts: Vec::new(),
+            progress: Progress::default(),
         }
     }
 
@@ -109,36 +259,41 @@ impl ValidationResult {
         self.warnings.push(warning);
     }
 
-    pub fn to_json(&self) -> Value {
-        let mut result = serde_json::json!({
-            "valid": self.valid,
-            "errors": self.errors,
-            "warnings": self.warnings
+    pub fn analysis(&self) -> ConceptSetAnalysis<'_> {
+        let concept_summary = self.concept_summary.as_ref().map(|summary| ConceptSummary {
+            included_concepts_count: summary.included_concepts.len(),
+            included_descendants_count: summary.included_descendants.len(),
+            included_mapped_count: summary.included_mapped.len(),
+            excluded_concepts_count: summary.excluded_concepts.len(),
+            excluded_descendants_count: summary.excluded_descendants.len(),
+            excluded_mapped_count: summary.excluded_mapped.len(),
+            total_included: summary.included_concepts.len()
+                + summary.included_descendants.len()
+                + summary.included_mapped.len(),
+            total_excluded: summary.excluded_concepts.len()
+                + summary.excluded_descendants.len()
+                + summary.excluded_mapped.len(),
         });
-
-        if let Some(summary) = &self.concept_summary {
-            result["concept_summary"] = serde_json::json!({
-                "included_concepts_count": summary.included_concepts.len(),
-                "included_descendants_count": summary.included_descendants.len(),
-                "included_mapped_count": summary.included_mapped.len(),
-                "excluded_concepts_count": summary.excluded_concepts.len(),
-                "excluded_descendants_count": summary.excluded_descendants.len(),
-                "excluded_mapped_count": summary.excluded_mapped.len(),
-                "total_included": summary.included_concepts.len() + summary.included_descendants.len() + summary.included_mapped.len(),
-                "total_excluded": summary.excluded_concepts.len() + summary.excluded_descendants.len() + summary.excluded_mapped.len()
-            });
-        }
-
-        if let Some(recommendations) = &self.recommendations {
-            result["recommendations"] =
-                serde_json::to_value(recommendations).unwrap_or(serde_json::json!(null));
+        ConceptSetAnalysis {
+            valid: self.valid,
+            profile: self.profile,
+            errors: &self.errors,
+            warnings: &self.warnings,
+            concept_summary,
+            non_standard_concepts: &self.non_standard_concepts,
+            redundant_items: &self.redundant_items,
+            exclusion_conflicts: &self.exclusion_conflicts,
+            expansion_hash: self.expansion_hash.as_deref(),
+            recommendations: self.recommendations.as_ref(),
         }
+    }
 
-        result
+    pub fn to_json(&self) -> Value {
+        serde_json::to_value(self.analysis()).unwrap_or_default()
     }
 }
 
-fn parse_concept_set(concept_set: &str) -> Result<ConceptSetExpression, String> {
+pub fn parse_concept_set(concept_set: &str) -> Result<ConceptSetExpression, String> {
     // Try to parse as direct expression format first
     if let Ok(expression) = serde_json::from_str::<ConceptSetExpression>(concept_set) {
         return Ok(expression);
@@ -199,14 +354,27 @@ fn sort_and_dedup_vec(vec: &mut Vec<i32>) {
     vec.dedup();
 }
 
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip_all, fields(profile = ?profile))]
 pub async fn analyze_concept_set(
     concept_set: &str,
     pg_client: &Client,
+    recommendation_client: &Client,
     qdrant_client: Option<&Qdrant>,
-    concept_index: Option<&HashMap<String, Vec<Uuid>>>,
+    read_options: &ReadOptions,
+    snapshot: Option<&IndexSnapshot>,
+    profile: ValidationProfile,
+    recommendation_budget: Option<Duration>,
+    recommendation_cache: &RecommendationCache,
+    cache_stats: &CacheStats,
+    vocabulary_version: Option<&str>,
+    progress: Progress,
 ) -> Result<ValidationResult, PgError> {
-    info!("Starting concept set analysis");
+    info!("Starting concept set analysis with profile {:?}", profile);
     let mut result = ValidationResult::new();
+    result.profile = profile;
+    result.progress = progress;
+    result.progress.report(AnalysisStage::Parsing);
 
     // Basic validation checks
     if concept_set.trim().is_empty() {
@@ -229,18 +397,163 @@ pub async fn analyze_concept_set(
         return Ok(result);
     }
 
-    // Gather concepts from the expression
-    let mut concept_summary = gather_concepts_from_expression(&expression);
+    run_checks(&mut result, &expression, pg_client, profile).await;
+
+    // Recommendations only need the expression, so they are generated on their own connection
+    // while the concept set is resolved
+    let recommend = async {
+        let (Some(qdrant), Some(snapshot)) = (qdrant_client, snapshot) else {
+            return None;
+        };
+        let recommendations = get_concept_recommendations(
+            &expression,
+            recommendation_client,
+            qdrant,
+            read_options,
+            snapshot,
+            &RecommendationOptions::default(),
+            &TextExamples::default(),
+            profile,
+            recommendation_budget,
+            recommendation_cache,
+            cache_stats,
+            vocabulary_version,
+        )
+        .await;
+        Some(recommendations)
+    };
+    let recommending = qdrant_client.is_some() && snapshot.is_some();
+    let resolve = async {
+        let concept_summary = expansions::resolve_concept_set(
+            &expression,
+            pg_client,
+            vocabulary_version,
+            &mut result,
+        )
+        .await;
+        if recommending {
+            result.progress.report(AnalysisStage::Recommendations);
+        }
+        concept_summary
+    };
+    let (concept_summary, recommendations) = futures::join!(resolve, recommend);
+    result.concept_summary = Some(concept_summary);
+    match recommendations {
+        Some(Ok(recommendations)) => result.recommendations = Some(recommendations),
+        Some(Err(e)) => {
+            result.add_warning(format!("Could not generate recommendations: {}", e));
+        }
+        None => {}
+    }
 
+    info!("Concept set analysis completed");
+    Ok(result)
+}
+
+/// Runs the checks enabled by the profile against a parsed, non-empty expression.
+#[instrument(skip_all)]
+pub async fn run_checks(
+    result: &mut ValidationResult,
+    expression: &ConceptSetExpression,
+    pg_client: &Client,
+    profile: ValidationProfile,
+) {
+    result.progress.report(AnalysisStage::Checks);
     // Basic logical validation
-    if concept_summary.included_concepts.is_empty()
-        && concept_summary.included_descendants.is_empty()
-        && concept_summary.included_mapped.is_empty()
-    {
+    if expression.items.iter().all(|item| item.is_excluded) {
         result.add_warning("No concepts are included in this concept set".to_string());
     }
 
-    check_for_duplicates(&mut result, &expression);
+    if profile.enables(ValidationRule::VocabularyConsistency) {
+        check_vocabulary_consistency(result, expression, pg_client).await;
+    }
+    if profile.enables(ValidationRule::DuplicateConcepts) {
+        check_for_duplicates(result, expression);
+    }
+    if profile.enables(ValidationRule::RedundantItems) {
+        check_redundant_items(result, expression, pg_client).await;
+    }
+    if profile.enables(ValidationRule::ExclusionConflicts) {
+        check_exclusion_conflicts(result, expression, pg_client).await;
+    }
+    if profile.enables(ValidationRule::DomainConsistency) {
+        check_domain_consistency(result, expression, profile);
+    }
+    if profile.enables(ValidationRule::DrugIngredientLevel) {
+        check_drug_ingredient_level(result, expression);
+    }
+    if profile.enables(ValidationRule::ClassificationWithoutDescendants) {
+        check_classification_without_descendants(result, expression);
+    }
+    if profile.enables(ValidationRule::IncludeMappedSemantics) {
+        check_include_mapped(result, expression, pg_client).await;
+    }
+    if profile.enables(ValidationRule::DescendantsNotIncluded) {
+        check_descendants_not_included(result, expression, pg_client).await;
+    }
+}
+
+/// How a single concept relates to a concept set expression, see `concept_membership`.
+#[derive(Debug)]
+pub struct ConceptMembership {
+    pub included: bool,
+    /// The item that brings the concept in, directly, as a descendant or as a mapped concept.
+    pub included_by: Option<i32>,
+    /// The excluded item that removes it.
+    pub excluded_by: Option<i32>,
+}
+
+/// Whether the expression resolves to `concept_id`, decided from the links between the concept
+/// and the items instead of expanding the whole set. Follows `expand_concept_set`: an item
+/// reaches the concept itself, its descendants with `include_descendants` and the concepts
+/// mapping to it with `include_mapped`, and exclusions win over inclusions.
+pub async fn concept_membership(
+    expression: &ConceptSetExpression,
+    concept_id: i32,
+    pg_client: &Client,
+) -> Result<ConceptMembership, PgError> {
+    let item_ids: Vec<i32> = expression
+        .items
+        .iter()
+        .map(|item| item.concept.concept_id)
+        .collect();
+    let (ancestors, maps_to) =
+        db::get_concept_links_among(pg_client, concept_id, &item_ids).await?;
+    let reaches = |item: &&ConceptSetItem| {
+        let item_id = item.concept.concept_id;
+        item_id == concept_id
+            || (item.include_descendants && ancestors.contains(&item_id))
+            || (item.include_mapped && maps_to.contains(&item_id))
+    };
+    let included_by = expression
+        .items
+        .iter()
+        .filter(|item| !item.is_excluded)
+        .find(reaches)
+        .map(|item| item.concept.concept_id);
+    let excluded_by = expression
+        .items
+        .iter()
+        .filter(|item| item.is_excluded)
+        .find(reaches)
+        .map(|item| item.concept.concept_id);
+    Ok(ConceptMembership {
+        included: included_by.is_some() && excluded_by.is_none(),
+        included_by,
+        excluded_by,
+    })
+}
+
+/// Resolves the descendants and mapped concepts of every item and removes everything that is
+/// excluded, recording lookup failures as warnings on `result`.
+#[instrument(skip_all, fields(items = expression.items.len()))]
+pub async fn expand_concept_set(
+    expression: &ConceptSetExpression,
+    pg_client: &Client,
+    result: &mut ValidationResult,
+) -> ConceptGatheringResult {
+    // Gather concepts from the expression
+    let mut concept_summary = gather_concepts_from_expression(expression);
 
     // Collect all concept IDs that need descendant expansion
     let concepts_needing_descendants: Vec<i32> = expression
@@ -250,39 +563,6 @@ pub async fn analyze_concept_set(
         .map(|item| item.concept.concept_id)
         .collect();
 
-    // Batch fetch all descendants if needed
-    if !concepts_needing_descendants.is_empty() {
-        match db::get_batch_descendant_concepts(pg_client, &concepts_needing_descendants).await {
-            Ok(descendants_map) => {
-                // Process each item and add descendants to appropriate lists
-                for item in &expression.items {
-                    let concept_id = item.concept.concept_id;
-
-                    if item.include_descendants {
-                        if let Some(descendants) = descendants_map.get(&concept_id) {
-                            info!(
-                                "Found {} descendants for concept {}",
-                                descendants.len(),
-                                concept_id
-                            );
-
-                            if item.is_excluded {
-                                // Add descendants to excluded list
-                                concept_summary.excluded_descendants.extend(descendants);
-                            } else {
-                                // Add descendants to included list
-                                concept_summary.included_descendants.extend(descendants);
-                            }
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                result.add_warning(format!("Could not get descendants for concepts: {}", e));
-            }
-        }
-    }
-
     // Collect all concept IDs that need mapped expansion
     let concepts_needing_mapped: Vec<i32> = expression
         .items
@@ -291,37 +571,92 @@ pub async fn analyze_concept_set(
         .map(|item| item.concept.concept_id)
         .collect();
 
-    // Batch fetch all mapped concepts if needed
-    if !concepts_needing_mapped.is_empty() {
-        match db::get_batch_mapped_concepts(pg_client, &concepts_needing_mapped).await {
-            Ok(mapped_map) => {
-                // Process each item and add mapped concepts to appropriate lists
-                for item in &expression.items {
-                    let concept_id = item.concept.concept_id;
-
-                    if item.include_mapped {
-                        if let Some(mapped) = mapped_map.get(&concept_id) {
-                            info!(
-                                "Found {} mapped concepts for concept {}",
-                                mapped.len(),
-                                concept_id
-                            );
-
-                            if item.is_excluded {
-                                // Add mapped concepts to excluded list
-                                concept_summary.excluded_mapped.extend(mapped);
-                            } else {
-                                // Add mapped concepts to included list
-                                concept_summary.included_mapped.extend(mapped);
-                            }
-                        }
+    // Both lookups are sent at once and overlap on the connection
+    let progress = result.progress.clone();
+    let (descendants, mapped) = futures::join!(
+        async {
+            if concepts_needing_descendants.is_empty() {
+                return None;
+            }
+            progress.report(AnalysisStage::DescendantExpansion);
+            let descendants =
+                db::get_batch_descendant_concepts(pg_client, &concepts_needing_descendants).await;
+            if !concepts_needing_mapped.is_empty() {
+                progress.report(AnalysisStage::MappedExpansion);
+            }
+            Some(descendants)
+        },
+        async {
+            if concepts_needing_mapped.is_empty() {
+                return None;
+            }
+            if concepts_needing_descendants.is_empty() {
+                progress.report(AnalysisStage::MappedExpansion);
+            }
+            Some(db::get_batch_mapped_concepts(pg_client, &concepts_needing_mapped).await)
+        },
+    );
+
+    match descendants {
+        Some(Ok(descendants_map)) => {
+            // Process each item and add descendants to appropriate lists
+            for item in &expression.items {
+                let concept_id = item.concept.concept_id;
+
+                if item.include_descendants
+                    && let Some(descendants) = descendants_map.get(&concept_id)
+                {
+                    info!(
+                        "Found {} descendants for concept {}",
+                        descendants.len(),
+                        concept_id
+                    );
+
+                    if item.is_excluded {
+                        // Add descendants to excluded list
+                        concept_summary.excluded_descendants.extend(descendants);
+                    } else {
+                        // Add descendants to included list
+                        concept_summary.included_descendants.extend(descendants);
                     }
                 }
             }
-            Err(e) => {
-                result.add_warning(format!("Could not get mapped concepts for concepts: {}", e));
+        }
+        Some(Err(e)) => {
+            result.add_warning(format!("Could not get descendants for concepts: {}", e));
+        }
+        None => {}
+    }
+
+    match mapped {
+        Some(Ok(mapped_map)) => {
+            // Process each item and add mapped concepts to appropriate lists
+            for item in &expression.items {
+                let concept_id = item.concept.concept_id;
+
+                if item.include_mapped
+                    && let Some(mapped) = mapped_map.get(&concept_id)
+                {
+                    info!(
+                        "Found {} mapped concepts for concept {}",
+                        mapped.len(),
+                        concept_id
+                    );
+
+                    if item.is_excluded {
+                        // Add mapped concepts to excluded list
+                        concept_summary.excluded_mapped.extend(mapped);
+                    } else {
+                        // Add mapped concepts to included list
+                        concept_summary.included_mapped.extend(mapped);
+                    }
+                }
             }
         }
+        Some(Err(e)) => {
+            result.add_warning(format!("Could not get mapped concepts for concepts: {}", e));
+        }
+        None => {}
     }
 
     // Remove duplicates from descendant and mapped lists
@@ -357,28 +692,160 @@ pub async fn analyze_concept_set(
         concept_summary.excluded_mapped.len()
     );
 
-    result.concept_summary = Some(concept_summary);
+    concept_summary
+}
 
-    // Generate recommendations if qdrant client and concept index are available
-    if let (Some(qdrant), Some(index)) = (qdrant_client, concept_index) {
-        match get_concept_recommendations(&expression, pg_client, qdrant, index, 50).await {
-            Ok(recommendations) => {
-                result.recommendations = Some(recommendations);
-            }
-            Err(e) => {
-                result.add_warning(format!("Could not generate recommendations: {}", e));
+const REPLACED_BY: &str = "Concept replaced by";
+const MAPS_TO: &str = "Maps to";
+/// Replacements named per invalid concept, the rest are left out of the warning.
+const MAX_REPLACEMENTS: usize = 5;
+
+/// `invalid_reason` when the concept is deleted or replaced, `None` while it is valid.
+fn invalid_reason(concept: &Concept) -> Option<&str> {
+    concept
+        .invalid_reason
+        .as_deref()
+        .filter(|reason| !reason.is_empty() && *reason != "V")
+}
+
+/// Names the successors of an invalid concept: its `Concept replaced by` targets, or the
+/// concepts it maps to when it has none.
+fn describe_replacements(linked: &[LinkedConcept]) -> String {
+    let replaced_by = linked.iter().any(|l| l.relationship_id == REPLACED_BY);
+    let mut seen = HashSet::new();
+    let names: Vec<String> = linked
+        .iter()
+        .filter(|l| !replaced_by || l.relationship_id == REPLACED_BY)
+        .filter(|l| seen.insert(l.concept_id))
+        .take(MAX_REPLACEMENTS)
+        .map(|l| {
+            format!(
+                "{} ({}, {} {})",
+                l.concept_name, l.concept_id, l.vocabulary_id, l.relationship_id
+            )
+        })
+        .collect();
+    names.join(" or ")
+}
+
+/// `standard_concept` as stored in the vocabulary, where ATLAS exports write `N` for NULL.
+fn normalize_standard_concept(value: Option<&str>) -> Option<&str> {
+    value.filter(|value| !value.is_empty() && *value != "N")
+}
+
+/// Compares every item with its row in `cdm.concept`: unknown concepts are errors, invalid ones
+/// and metadata that disagrees with the vocabulary are warnings.
+async fn check_vocabulary_consistency(
+    result: &mut ValidationResult,
+    expression: &ConceptSetExpression,
+    pg_client: &Client,
+) {
+    let mut concept_ids: Vec<i32> = expression
+        .items
+        .iter()
+        .map(|item| item.concept.concept_id)
+        .collect();
+    sort_and_dedup_vec(&mut concept_ids);
+    let stored: HashMap<i32, Concept> = match db::get_concepts_by_ids(pg_client, &concept_ids).await
+    {
+        Ok(concepts) => concepts
+            .into_iter()
+            .map(|concept| (concept.concept_id, concept))
+            .collect(),
+        Err(e) => {
+            result.add_warning(format!(
+                "Could not verify the concepts against the vocabulary: {}",
+                e
+            ));
+            return;
+        }
+    };
+
+    let invalid_ids: Vec<i32> = stored
+        .values()
+        .filter(|concept| invalid_reason(concept).is_some())
+        .map(|concept| concept.concept_id)
+        .collect();
+    let mut replacements: HashMap<i32, Vec<LinkedConcept>> = HashMap::new();
+    match db::get_linked_concepts(pg_client, &invalid_ids, &[REPLACED_BY, MAPS_TO]).await {
+        Ok(linked) => {
+            for linked in linked {
+                replacements
+                    .entry(linked.source_concept_id)
+                    .or_default()
+                    .push(linked);
             }
         }
+        Err(e) => warn!("Could not look up replacements of invalid concepts: {}", e),
     }
 
-    // TODO: Add more database validation
-    // - Verify concept IDs exist in the vocabulary
-    // - Check for invalid standard_concept values
-    // - Validate vocabulary_id, domain_id, concept_class_id
-    // - Get mapped concepts using concept_relationship table
-
-    info!("Concept set analysis completed");
-    Ok(result)
+    for item in &expression.items {
+        let Some(concept) = stored.get(&item.concept.concept_id) else {
+            result.add_error(format!(
+                "Concept {} does not exist in the vocabulary",
+                describe_item(item)
+            ));
+            continue;
+        };
+        if let Some(invalid_reason) = invalid_reason(concept) {
+            let reason = match invalid_reason {
+                "D" => "deleted",
+                "U" => "replaced by another concept",
+                _ => "invalid",
+            };
+            let suggestion = match replacements.get(&concept.concept_id) {
+                Some(linked) => format!("; replace it with {}", describe_replacements(linked)),
+                None => String::new(),
+            };
+            result.add_warning(format!(
+                "Concept {} is {} in the vocabulary (invalid_reason {}) and will not match newly recorded data{}",
+                describe_item(item),
+                reason,
+                invalid_reason,
+                suggestion
+            ));
+        }
+        let mismatches: Vec<String> = [
+            (
+                "vocabulary_id",
+                Some(item.concept.vocabulary_id.as_str()),
+                Some(concept.vocabulary_id.as_str()),
+            ),
+            (
+                "domain_id",
+                Some(item.concept.domain_id.as_str()),
+                Some(concept.domain_id.as_str()),
+            ),
+            (
+                "concept_class_id",
+                Some(item.concept.concept_class_id.as_str()),
+                Some(concept.concept_class_id.as_str()),
+            ),
+            (
+                "standard_concept",
+                normalize_standard_concept(item.concept.standard_concept.as_deref()),
+                normalize_standard_concept(concept.standard_concept.as_deref()),
+            ),
+        ]
+        .into_iter()
+        .filter(|(_, given, stored)| given != stored)
+        .map(|(field, given, stored)| {
+            format!(
+                "{} is {} but {} in the vocabulary",
+                field,
+                given.unwrap_or("empty"),
+                stored.unwrap_or("empty")
+            )
+        })
+        .collect();
+        if !mismatches.is_empty() {
+            result.add_warning(format!(
+                "Concept {} does not match the vocabulary: {}",
+                describe_item(item),
+                mismatches.join(", ")
+            ));
+        }
+    }
 }
 
 fn check_for_duplicates(result: &mut ValidationResult, expression: &ConceptSetExpression) {
@@ -411,7 +878,350 @@ fn check_for_duplicates(result: &mut ValidationResult, expression: &ConceptSetEx
     }
 }
 
-#[derive(Debug, Serialize)]
+pub(crate) fn describe_item(item: &ConceptSetItem) -> String {
+    format!(
+        "{} ({})",
+        item.concept.concept_name, item.concept.concept_id
+    )
+}
+
+fn check_domain_consistency(
+    result: &mut ValidationResult,
+    expression: &ConceptSetExpression,
+    profile: ValidationProfile,
+) {
+    let Some(expected_domains) = profile.expected_domains() else {
+        return;
+    };
+    for item in expression.items.iter().filter(|item| !item.is_excluded) {
+        if !expected_domains.contains(&item.concept.domain_id.as_str()) {
+            result.add_warning(format!(
+                "Concept {} is in the {} domain, expected one of {} for this profile",
+                describe_item(item),
+                item.concept.domain_id,
+                expected_domains.join(", ")
+            ));
+        }
+    }
+}
+
+fn check_drug_ingredient_level(result: &mut ValidationResult, expression: &ConceptSetExpression) {
+    for item in expression.items.iter().filter(|item| !item.is_excluded) {
+        let is_rxnorm = item.concept.vocabulary_id.starts_with("RxNorm");
+        if is_rxnorm
+            && item.concept.domain_id == "Drug"
+            && item.concept.concept_class_id != "Ingredient"
+            && !item.include_descendants
+        {
+            result.add_warning(format!(
+                "Drug concept {} is a {} without descendants; drug exposure sets are usually built from ingredients with descendants so that all formulations are captured",
+                describe_item(item),
+                item.concept.concept_class_id
+            ));
+        }
+    }
+}
+
+fn check_classification_without_descendants(
+    result: &mut ValidationResult,
+    expression: &ConceptSetExpression,
+) {
+    for item in expression.items.iter().filter(|item| !item.is_excluded) {
+        if item.concept.standard_concept.as_deref() == Some("C") && !item.include_descendants {
+            result.add_warning(format!(
+                "Classification concept {} is included without descendants and will not match any records",
+                describe_item(item)
+            ));
+        }
+    }
+}
+
+/// `includeMapped` adds the concepts that map *to* an item, which for a standard concept are its
+/// source codes. Clinical tables record standard concepts, so the flag only changes which
+/// `*_source_concept_id` values match; a non-standard concept on the other hand never appears
+/// in the standard concept columns at all. Non-standard items are listed in
+/// `non_standard_concepts` with the standard concepts they map to.
+async fn check_include_mapped(
+    result: &mut ValidationResult,
+    expression: &ConceptSetExpression,
+    pg_client: &Client,
+) {
+    let non_standard: Vec<&ConceptSetItem> = expression
+        .items
+        .iter()
+        .filter(|item| !item.is_excluded)
+        .filter(|item| {
+            normalize_standard_concept(item.concept.standard_concept.as_deref()).is_none()
+        })
+        .collect();
+    let concept_ids: Vec<i32> = non_standard
+        .iter()
+        .map(|item| item.concept.concept_id)
+        .collect();
+    let mut maps_to: HashMap<i32, Vec<StandardEquivalent>> = HashMap::new();
+    match db::get_linked_concepts(pg_client, &concept_ids, &[MAPS_TO]).await {
+        Ok(linked) => {
+            for linked in linked
+                .into_iter()
+                .filter(|linked| linked.standard_concept.as_deref() == Some("S"))
+            {
+                maps_to
+                    .entry(linked.source_concept_id)
+                    .or_default()
+                    .push(StandardEquivalent {
+                        concept_id: linked.concept_id,
+                        concept_name: linked.concept_name,
+                        vocabulary_id: linked.vocabulary_id,
+                        domain_id: linked.domain_id,
+                    });
+            }
+        }
+        Err(e) => warn!("Could not look up standard equivalents: {}", e),
+    }
+
+    for item in expression.items.iter().filter(|item| !item.is_excluded) {
+        if item.concept.standard_concept.as_deref() == Some("S") && item.include_mapped {
+            result.add_warning(format!(
+                "Standard concept {} has includeMapped set, which adds the source (non-standard) codes that map to it; these only match *_source_concept_id columns, so leave it unset unless the cohort queries source codes",
+                describe_item(item)
+            ));
+        }
+    }
+    for item in non_standard {
+        let equivalents = maps_to.remove(&item.concept.concept_id).unwrap_or_default();
+        if !item.include_mapped {
+            let instead = match equivalents.as_slice() {
+                [] => "the standard concept it maps to".to_string(),
+                equivalents => equivalents
+                    .iter()
+                    .map(|e| format!("{} ({})", e.concept_name, e.concept_id))
+                    .collect::<Vec<_>>()
+                    .join(" or "),
+            };
+            result.add_warning(format!(
+                "Concept {} is non-standard and will not match the standard concept columns of the clinical tables; include {} instead, with includeMapped to keep its source codes",
+                describe_item(item),
+                instead
+            ));
+        }
+        result.non_standard_concepts.push(NonStandardConcept {
+            concept_id: item.concept.concept_id,
+            concept_name: item.concept.concept_name.clone(),
+            vocabulary_id: item.concept.vocabulary_id.clone(),
+            maps_to: equivalents,
+        });
+    }
+}
+
+/// Included items that are descendants of another included item with `includeDescendants`.
+/// They are only kept when they bring mapped concepts the covering item does not.
+async fn check_redundant_items(
+    result: &mut ValidationResult,
+    expression: &ConceptSetExpression,
+    pg_client: &Client,
+) {
+    let included: Vec<&ConceptSetItem> = expression
+        .items
+        .iter()
+        .filter(|item| !item.is_excluded)
+        .collect();
+    let ancestors: Vec<&ConceptSetItem> = included
+        .iter()
+        .filter(|item| item.include_descendants)
+        .copied()
+        .collect();
+    if ancestors.is_empty() || included.len() < 2 {
+        return;
+    }
+    let ancestor_ids: Vec<i32> = ancestors
+        .iter()
+        .map(|item| item.concept.concept_id)
+        .collect();
+    let descendants_map = match db::get_batch_descendant_concepts(pg_client, &ancestor_ids).await {
+        Ok(descendants_map) => descendants_map,
+        Err(e) => {
+            result.add_warning(format!("Could not check for redundant items: {}", e));
+            return;
+        }
+    };
+    let descendants: HashMap<i32, HashSet<i32>> = descendants_map
+        .into_iter()
+        .map(|(ancestor, descendants)| (ancestor, descendants.into_iter().collect()))
+        .collect();
+
+    for item in &included {
+        let covering = ancestors.iter().find(|ancestor| {
+            ancestor.concept.concept_id != item.concept.concept_id
+                && (ancestor.include_mapped || !item.include_mapped)
+                && descendants
+                    .get(&ancestor.concept.concept_id)
+                    .is_some_and(|descendants| descendants.contains(&item.concept.concept_id))
+        });
+        if let Some(ancestor) = covering {
+            result.add_warning(format!(
+                "Concept {} is already included as a descendant of {} and can be removed",
+                describe_item(item),
+                describe_item(ancestor)
+            ));
+            result.redundant_items.push(RedundantItem {
+                concept_id: item.concept.concept_id,
+                concept_name: item.concept.concept_name.clone(),
+                covered_by_concept_id: ancestor.concept.concept_id,
+                covered_by_concept_name: ancestor.concept.concept_name.clone(),
+            });
+        }
+    }
+}
+
+/// The concepts each item adds, or removes when excluded: its own concept, plus its descendants
+/// and mapped concepts when the item asks for them.
+pub async fn item_concepts(
+    items: &[&ConceptSetItem],
+    pg_client: &Client,
+) -> Result<Vec<HashSet<i32>>, PgError> {
+    let with_descendants: Vec<i32> = items
+        .iter()
+        .filter(|item| item.include_descendants)
+        .map(|item| item.concept.concept_id)
+        .collect();
+    let descendants_map = db::get_batch_descendant_concepts(pg_client, &with_descendants).await?;
+    let with_mapped: Vec<i32> = items
+        .iter()
+        .filter(|item| item.include_mapped)
+        .map(|item| item.concept.concept_id)
+        .collect();
+    let mapped_map = db::get_batch_mapped_concepts(pg_client, &with_mapped).await?;
+
+    Ok(items
+        .iter()
+        .map(|item| {
+            let concept_id = item.concept.concept_id;
+            let mut concepts = HashSet::from([concept_id]);
+            if item.include_descendants
+                && let Some(descendants) = descendants_map.get(&concept_id)
+            {
+                concepts.extend(descendants);
+            }
+            if item.include_mapped
+                && let Some(mapped) = mapped_map.get(&concept_id)
+            {
+                concepts.extend(mapped);
+            }
+            concepts
+        })
+        .collect())
+}
+
+/// Exclusions win over inclusions during expansion, so an excluded item whose tree covers an
+/// explicitly included concept silently drops it from the resolved set.
+async fn check_exclusion_conflicts(
+    result: &mut ValidationResult,
+    expression: &ConceptSetExpression,
+    pg_client: &Client,
+) {
+    let included: HashSet<i32> = expression
+        .items
+        .iter()
+        .filter(|item| !item.is_excluded)
+        .map(|item| item.concept.concept_id)
+        .collect();
+    let excluded: Vec<&ConceptSetItem> = expression
+        .items
+        .iter()
+        .filter(|item| item.is_excluded)
+        .collect();
+    if included.is_empty() || excluded.is_empty() {
+        return;
+    }
+    let removed_by_item = match item_concepts(&excluded, pg_client).await {
+        Ok(removed_by_item) => removed_by_item,
+        Err(e) => {
+            result.add_warning(format!("Could not check exclusions for conflicts: {}", e));
+            return;
+        }
+    };
+
+    for (item, removed) in excluded.into_iter().zip(removed_by_item) {
+        let mut removed: Vec<i32> = removed.intersection(&included).copied().collect();
+        if removed.is_empty() {
+            continue;
+        }
+        sort_and_dedup_vec(&mut removed);
+        result.add_warning(format!(
+            "Excluded concept {} removes explicitly included concept IDs {}",
+            describe_item(item),
+            removed
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+        result.exclusion_conflicts.push(ExclusionConflict {
+            concept_id: item.concept.concept_id,
+            concept_name: item.concept.concept_name.clone(),
+            removed_concept_ids: removed,
+        });
+    }
+}
+
+async fn check_descendants_not_included(
+    result: &mut ValidationResult,
+    expression: &ConceptSetExpression,
+    pg_client: &Client,
+) {
+    let candidates: Vec<&ConceptSetItem> = expression
+        .items
+        .iter()
+        .filter(|item| !item.is_excluded && !item.include_descendants)
+        .collect();
+    if candidates.is_empty() {
+        return;
+    }
+    let concept_ids: Vec<i32> = candidates
+        .iter()
+        .map(|item| item.concept.concept_id)
+        .collect();
+    match db::get_batch_descendant_concepts(pg_client, &concept_ids).await {
+        Ok(descendants_map) => {
+            for item in candidates {
+                let count = descendants_map
+                    .get(&item.concept.concept_id)
+                    .map_or(0, |descendants| descendants.len());
+                if count > 0 {
+                    result.add_warning(format!(
+                        "Concept {} has {} descendants that are not included because includeDescendants is not set",
+                        describe_item(item),
+                        count
+                    ));
+                }
+            }
+        }
+        Err(e) => {
+            result.add_warning(format!("Could not check for missing descendants: {}", e));
+        }
+    }
+}
+
+/// The recommender that suggested a concept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RecommendationSource {
+    /// Similar in the embedding space to an item or phrase.
+    Semantic,
+    /// Recommended by PHOEBE for an item, from co-occurrence in observational data.
+    Phoebe,
+}
+
+impl RecommendationSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RecommendationSource::Semantic => "semantic",
+            RecommendationSource::Phoebe => "phoebe",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct RecommendedConcept {
     pub concept_id: i32,
     pub concept_name: String,
@@ -421,20 +1231,103 @@ pub struct RecommendedConcept {
     pub concept_code: String,
     pub standard_concept: String,
     pub invalid_reason: Option<String>,
+    /// 0 for concepts only PHOEBE recommends.
     pub similarity_score: f32,
     pub source_concept_id: i32, // The top-level concept that led to this recommendation
+    /// The phrase that led to this recommendation, when it was not a concept, whose
+    /// `source_concept_id` is then 0.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_text: Option<String>,
+    pub sources: Vec<RecommendationSource>,
+    /// Position in the blend of the semantic and the PHOEBE ranking, from 1.
+    pub rank: usize,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct ConceptRecommendations {
     pub recommendations: Vec<RecommendedConcept>,
     pub total_count: usize,
     pub used_vocabularies: Vec<String>,
+    /// The time budget ran out, the recommendations are those computed until then.
+    pub truncated: bool,
+}
+
+/// Least similarity and number of points of a recommendation query, unless the request says
+/// otherwise.
+const DEFAULT_RECOMMENDATION_MIN_SCORE: f32 = 0.50;
+const DEFAULT_RECOMMENDATION_POINTS: u64 = 500;
+
+/// How wide recommendations cast their net. The analysis uses the defaults, the
+/// recommendation endpoint takes them from the request.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, ToSchema)]
+pub struct RecommendationOptions {
+    /// Least similarity of a recommended concept, 0.5 when omitted.
+    #[schema(minimum = 0, maximum = 1)]
+    pub min_score: Option<f32>,
+    /// Most recommendations returned, and points requested per Qdrant query. All found among
+    /// 500 points per query when omitted.
+    #[schema(minimum = 1, maximum = 2000)]
+    pub limit: Option<u64>,
+    /// Domains to recommend concepts of, instead of the domains of the concept set or the
+    /// profile.
+    pub domains: Option<Vec<String>>,
+    /// Only recommend standard concepts.
+    #[serde(default)]
+    pub standard_only: bool,
+    /// Trade-off between similarity to the concept set and novelty among the recommendations,
+    /// from 0 (by similarity alone) to 1. Ranks by similarity alone when omitted.
+    #[schema(minimum = 0, maximum = 1)]
+    pub diversity: Option<f32>,
+}
+
+/// Phrases embedded on the fly that steer recommendations next to the concept set: positive
+/// ones are recommended from like the included items, negative ones are avoided like the
+/// excluded items.
+#[derive(Debug, Default)]
+pub struct TextExamples {
+    pub positive: Vec<(String, Vec<f32>)>,
+    pub negative: Vec<Vec<f32>>,
+}
+
+/// What a recommendation query starts from, and what its recommendations are attributed to.
+struct Seed {
+    input: VectorInput,
+    source_concept_id: i32,
+    source_text: Option<String>,
+}
+
+/// Recommendations re-ranked when diversifying, those further down follow them by similarity.
+/// Every pick is compared with every candidate left, so this bounds the work per request.
+const DIVERSITY_WINDOW: usize = 200;
+
+/// A recommendation with the vector of the point it was found as, for diversifying.
+struct Candidate {
+    recommendation: RecommendedConcept,
+    vector: Option<Vec<f32>>,
+}
+
+/// Positive examples sent to Qdrant per batch request, each as a query of its own. Batches are
+/// queried one after the other so a time budget can stop between them and keep what was found
+/// so far.
+const RECOMMENDATION_BATCH_SIZE: usize = 10;
+
+/// Time left until `deadline`, `None` when there is no deadline.
+fn remaining(deadline: Option<Instant>) -> Option<Duration> {
+    deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
 }
 
-fn process_concepts_from_cache(
+/// Where the points of concept names are looked up: the snapshot's concept index, or Qdrant
+/// while it is loading.
+#[derive(Clone, Copy)]
+struct ConceptLookup<'a> {
+    snapshot: &'a IndexSnapshot,
+    qdrant_client: &'a Qdrant,
+    read_options: &'a ReadOptions,
+}
+
+async fn process_concepts_from_cache(
     concepts: &[&ConceptSetItem],
-    concept_index: &HashMap<String, Vec<Uuid>>,
+    lookup: ConceptLookup<'_>,
     mut source_concept_map: Option<&mut HashMap<String, i32>>,
     log_prefix: &str,
 ) -> Vec<PointId> {
@@ -450,7 +1343,25 @@ fn process_concepts_from_cache(
             log_prefix, concept_name, concept_id
         );
 
-        if let Some(cached_ids) = concept_index.get(concept_name_lower.as_str()) {
+        let cached_ids = match lookup
+            .snapshot
+            .point_ids(
+                lookup.qdrant_client,
+                lookup.read_options,
+                &concept_name_lower,
+            )
+            .await
+        {
+            Ok(cached_ids) => cached_ids,
+            Err(e) => {
+                warn!(
+                    "Could not look up the points of concept '{}': {}",
+                    concept_name, e
+                );
+                continue;
+            }
+        };
+        if let Some(cached_ids) = cached_ids {
             // For recommendations, use only the first cached vector per concept to avoid redundancy
             if let Some(first_uuid) = cached_ids.first() {
                 let point_id = PointId::from(first_uuid.to_string().as_str());
@@ -471,6 +1382,21 @@ fn process_concepts_from_cache(
     point_ids
 }
 
+/// The point of each item's concept name, looked up like the examples of recommendations.
+pub async fn item_point_ids(
+    items: &[&ConceptSetItem],
+    snapshot: &IndexSnapshot,
+    qdrant_client: &Qdrant,
+    read_options: &ReadOptions,
+) -> Vec<PointId> {
+    let lookup = ConceptLookup {
+        snapshot,
+        qdrant_client,
+        read_options,
+    };
+    process_concepts_from_cache(items, lookup, None, "Getting examples").await
+}
+
 fn limit_point_ids(point_ids: Vec<PointId>, limit: usize, collection_type: &str) -> Vec<PointId> {
     let original_count = point_ids.len();
     let limited: Vec<_> = point_ids.into_iter().take(limit).collect();
@@ -487,22 +1413,23 @@ fn limit_point_ids(point_ids: Vec<PointId>, limit: usize, collection_type: &str)
     limited
 }
 
-fn collect_positive_point_ids(
+async fn collect_positive_point_ids(
     top_level_included: &[&ConceptSetItem],
-    concept_index: &HashMap<String, Vec<Uuid>>,
+    lookup: ConceptLookup<'_>,
     source_concept_map: &mut HashMap<String, i32>,
 ) -> Vec<PointId> {
     process_concepts_from_cache(
         top_level_included,
-        concept_index,
+        lookup,
         Some(source_concept_map),
         "Getting positive recommendations",
     )
+    .await
 }
 
-fn collect_negative_point_ids(
+async fn collect_negative_point_ids(
     expression: &ConceptSetExpression,
-    concept_index: &HashMap<String, Vec<Uuid>>,
+    lookup: ConceptLookup<'_>,
 ) -> Vec<PointId> {
     let excluded_concepts: Vec<&ConceptSetItem> = expression
         .items
@@ -512,84 +1439,136 @@ fn collect_negative_point_ids(
 
     process_concepts_from_cache(
         &excluded_concepts,
-        concept_index,
+        lookup,
         None,
         "Getting negative examples",
     )
+    .await
+}
+
+/// The key a point is stored under in `source_concept_map`.
+fn point_key(point_id: &PointId) -> Option<String> {
+    match point_id.point_id_options.as_ref()? {
+        PointIdOptions::Uuid(uuid) => Some(uuid.clone()),
+        PointIdOptions::Num(num) => Some(num.to_string()),
+    }
 }
 
+/// Recommends from each positive on its own, in one batch request, so every recommendation
+/// can name the concept set item or phrase it is similar to.
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip_all, fields(db.system = "qdrant", collection = collection, positives = positives.len()))]
 async fn query_and_process_recommendations(
     qdrant_client: &Qdrant,
-    recommend_query: qdrant_client::qdrant::Query,
+    read_options: &ReadOptions,
+    collection: &str,
+    positives: &[Seed],
+    negatives: &[VectorInput],
     existing_concepts: &HashSet<i32>,
-    top_level_included: &[&ConceptSetItem],
     allowed_domains: &HashSet<String>,
-    concept_set_vocabularies: HashSet<String>,
-    limit_per_concept: u64,
-) -> ConceptRecommendations {
-    const COLLECTION_NAME: &str = "meddra";
+    options: &RecommendationOptions,
+) -> Vec<Candidate> {
     let mut all_recommendations = Vec::new();
+    // Vectors are only needed to tell near-duplicates apart
+    let with_vectors = options.diversity.is_some_and(|diversity| diversity > 0.0);
 
-    let query_points_builder = QueryPointsBuilder::new(COLLECTION_NAME)
-        .with_payload(true)
-        .score_threshold(0.50)
-        .limit(500)
-        .query(recommend_query);
+    let queries: Vec<QueryPoints> = positives
+        .iter()
+        .map(|positive| {
+            let mut recs = RecommendInputBuilder::default().add_positive(positive.input.clone());
+            for negative in negatives {
+                recs = recs.add_negative(negative.clone());
+            }
+            QueryPointsBuilder::new(collection)
+                .with_payload(true)
+                .with_vectors(with_vectors)
+                .score_threshold(
+                    options
+                        .min_score
+                        .unwrap_or(DEFAULT_RECOMMENDATION_MIN_SCORE),
+                )
+                .limit(options.limit.unwrap_or(DEFAULT_RECOMMENDATION_POINTS))
+                .query(recs.build())
+                .with_read_options(read_options)
+                .build()
+        })
+        .collect();
+    let batch = QueryBatchPointsBuilder::new(collection, queries).with_read_options(read_options);
 
-    match qdrant_client.query(query_points_builder).await {
-        Ok(query_result) => {
+    match qdrant_client.query_batch(batch).await {
+        Ok(batch_result) => {
             info!(
-                "Qdrant query returned {} results",
-                query_result.result.len()
+                "Qdrant batch query returned {} results",
+                batch_result
+                    .result
+                    .iter()
+                    .map(|result| result.result.len())
+                    .sum::<usize>()
             );
             let mut passed_filters_count = 0;
             let mut already_in_set_count = 0;
             let mut wrong_domain_count = 0;
-
-            for scored_point in query_result.result {
-                // Use the same approach as the search endpoint
-                let search_response = SearchResponse::from(scored_point.clone());
-
-                // Process each concept in the concepts array
-                for concept in search_response.concepts {
-                    let concept_id = concept.concept_id;
-
-                    // Filter: only not already in set and in allowed domains (let UI handle standard/vocabulary filtering)
-                    if !existing_concepts.contains(&concept_id)
-                        && allowed_domains.contains(&concept.domain_id)
-                    {
-                        passed_filters_count += 1;
-                        // Use first source concept as default (could be improved to track actual source)
-                        let source_concept_id = top_level_included
-                            .first()
-                            .map(|item| item.concept.concept_id)
-                            .unwrap_or(0);
-
-                        all_recommendations.push(RecommendedConcept {
-                            concept_id,
-                            concept_name: concept.concept_name,
-                            vocabulary_id: concept.vocabulary_id,
-                            domain_id: concept.domain_id,
-                            concept_class_id: concept.concept_class_id,
-                            concept_code: concept.concept_code,
-                            standard_concept: concept
-                                .standard_concept
-                                .unwrap_or_else(|| "".to_string()),
-                            invalid_reason: concept.invalid_reason,
-                            similarity_score: scored_point.score,
-                            source_concept_id,
+            let mut non_standard_count = 0;
+
+            // Results come back in the order of the queries, one per positive
+            for (positive, query_result) in positives.iter().zip(batch_result.result) {
+                for scored_point in query_result.result {
+                    let vector = scored_point
+                        .vectors
+                        .as_ref()
+                        .and_then(|vectors| vectors.get_vector())
+                        .and_then(|vector| match vector {
+                            Vector::Dense(dense) => Some(dense.data),
+                            _ => None,
                         });
-                    } else if existing_concepts.contains(&concept_id) {
-                        already_in_set_count += 1;
-                    } else if !allowed_domains.contains(&concept.domain_id) {
-                        wrong_domain_count += 1;
+                    // Use the same approach as the search endpoint
+                    let search_response = SearchResponse::from(scored_point.clone());
+
+                    // Process each concept in the concepts array
+                    for concept in search_response.concepts {
+                        let concept_id = concept.concept_id;
+
+                        // Filter: only not already in set and in allowed domains (let UI handle vocabulary filtering)
+                        let standard = concept.standard_concept.as_deref() == Some("S");
+                        if !existing_concepts.contains(&concept_id)
+                            && allowed_domains.contains(&concept.domain_id)
+                            && (standard || !options.standard_only)
+                        {
+                            passed_filters_count += 1;
+                            all_recommendations.push(Candidate {
+                                recommendation: RecommendedConcept {
+                                    concept_id,
+                                    concept_name: concept.concept_name,
+                                    vocabulary_id: concept.vocabulary_id,
+                                    domain_id: concept.domain_id,
+                                    concept_class_id: concept.concept_class_id,
+                                    concept_code: concept.concept_code,
+                                    standard_concept: concept
+                                        .standard_concept
+                                        .unwrap_or_else(|| "".to_string()),
+                                    invalid_reason: concept.invalid_reason,
+                                    similarity_score: scored_point.score,
+                                    source_concept_id: positive.source_concept_id,
+                                    source_text: positive.source_text.clone(),
+                                    sources: vec![RecommendationSource::Semantic],
+                                    rank: 0,
+                                },
+                                vector: vector.clone(),
+                            });
+                        } else if existing_concepts.contains(&concept_id) {
+                            already_in_set_count += 1;
+                        } else if !allowed_domains.contains(&concept.domain_id) {
+                            wrong_domain_count += 1;
+                        } else {
+                            non_standard_count += 1;
+                        }
                     }
                 }
             }
 
             info!(
-                "Filtering results: {} passed filters, {} already in set, {} wrong domain",
-                passed_filters_count, already_in_set_count, wrong_domain_count
+                "Filtering results: {} passed filters, {} already in set, {} wrong domain, {} non-standard",
+                passed_filters_count, already_in_set_count, wrong_domain_count, non_standard_count
             );
         }
         Err(e) => {
@@ -597,30 +1576,263 @@ async fn query_and_process_recommendations(
         }
     }
 
-    // Sort by similarity score (descending) and limit results
     all_recommendations
-        .sort_by(|a, b| b.similarity_score.partial_cmp(&a.similarity_score).unwrap());
-    let total_count = all_recommendations.len();
+}
 
-    // Get vocabularies from the original concept set (not from recommendations)
-    let used_vocabularies: Vec<String> = concept_set_vocabularies.into_iter().collect();
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let (mut dot, mut norm_a, mut norm_b) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
 
-    ConceptRecommendations {
-        recommendations: all_recommendations,
-        total_count,
-        used_vocabularies,
+/// Re-ranks candidates sorted by similarity with maximal marginal relevance, so the first
+/// recommendations are not dozens of near-identical terms, such as the MedDRA LLTs of one PT.
+/// Each pick maximizes `(1 - diversity) * similarity - diversity * closeness`, where closeness
+/// is the cosine similarity to the nearest recommendation picked before it. Candidates without
+/// a vector are never considered close to anything.
+fn diversify(mut candidates: Vec<Candidate>, diversity: f32) -> Vec<RecommendedConcept> {
+    let rest = candidates.split_off(candidates.len().min(DIVERSITY_WINDOW));
+    let mut closeness = vec![0.0f32; candidates.len()];
+    let mut remaining: Vec<Option<Candidate>> = candidates.into_iter().map(Some).collect();
+    let mut picked = Vec::with_capacity(remaining.len() + rest.len());
+    while picked.len() < remaining.len() {
+        let marginal = |index: usize, candidate: &Candidate| {
+            (1.0 - diversity) * candidate.recommendation.similarity_score
+                - diversity * closeness[index]
+        };
+        let Some(best) = remaining
+            .iter()
+            .enumerate()
+            .filter_map(|(index, candidate)| {
+                candidate
+                    .as_ref()
+                    .map(|candidate| (index, marginal(index, candidate)))
+            })
+            // The first of equal candidates is the most similar one
+            .reduce(|best, next| if next.1 > best.1 { next } else { best })
+            .map(|(index, _)| index)
+        else {
+            break;
+        };
+        let Some(pick) = remaining[best].take() else {
+            break;
+        };
+        if let Some(picked_vector) = &pick.vector {
+            for (index, candidate) in remaining.iter().enumerate() {
+                if let Some(vector) = candidate.as_ref().and_then(|c| c.vector.as_ref()) {
+                    closeness[index] =
+                        closeness[index].max(cosine_similarity(picked_vector, vector));
+                }
+            }
+        }
+        picked.push(pick.recommendation);
+    }
+    picked.extend(rest.into_iter().map(|candidate| candidate.recommendation));
+    picked
+}
+
+/// Recommendations of recently analyzed concept sets. Validating the same ATLAS concept set
+/// over and over is the common workflow, and the recommendations are its slowest part.
+pub type RecommendationCache = moka::sync::Cache<String, Arc<ConceptRecommendations>>;
+
+/// What recommendations are computed from: the expression by its canonical hash, so item order
+/// and the names sent along do not matter, the options and profile, the collection of the index
+/// snapshot and the vocabulary version.
+fn recommendation_cache_key(
+    expression: &ConceptSetExpression,
+    snapshot: &IndexSnapshot,
+    options: &RecommendationOptions,
+    profile: ValidationProfile,
+    vocabulary_version: Option<&str>,
+) -> String {
+    format!(
+        "{}|{}|{}|{}|{}",
+        expansions::expression_hash(expression),
+        snapshot.collection,
+        profile.as_str(),
+        serde_json::to_string(options).unwrap_or_default(),
+        vocabulary_version.unwrap_or_default()
+    )
+}
+
+/// Smoothing of reciprocal rank fusion, the usual 60: a concept ranked 1st by one recommender
+/// and not at all by the other is on a par with one both rank around 20th.
+const RANK_FUSION_K: f32 = 60.0;
+
+/// Concepts PHOEBE recommends for the items, those recommended for the most items first, with
+/// the filters of the semantic recommendations.
+async fn phoebe_recommendations(
+    pg_client: &Client,
+    item_ids: &[i32],
+    existing_concepts: &HashSet<i32>,
+    allowed_domains: &HashSet<String>,
+    options: &RecommendationOptions,
+) -> Result<Vec<RecommendedConcept>, PgError> {
+    let mut recommended: HashMap<i32, (Concept, i32, usize)> = HashMap::new();
+    for (source_concept_id, concept) in db::get_batch_phoebe_concepts(pg_client, item_ids).await? {
+        let standard = concept.standard_concept.as_deref() == Some("S");
+        if existing_concepts.contains(&concept.concept_id)
+            || !allowed_domains.contains(&concept.domain_id)
+            || (options.standard_only && !standard)
+        {
+            continue;
+        }
+        recommended
+            .entry(concept.concept_id)
+            .or_insert((concept, source_concept_id, 0))
+            .2 += 1;
+    }
+    let mut recommended: Vec<(Concept, i32, usize)> = recommended.into_values().collect();
+    recommended.sort_by(|a, b| {
+        b.2.cmp(&a.2)
+            .then_with(|| a.0.concept_name.cmp(&b.0.concept_name))
+    });
+    Ok(recommended
+        .into_iter()
+        .map(|(concept, source_concept_id, _)| RecommendedConcept {
+            concept_id: concept.concept_id,
+            concept_name: concept.concept_name,
+            vocabulary_id: concept.vocabulary_id,
+            domain_id: concept.domain_id,
+            concept_class_id: concept.concept_class_id,
+            concept_code: concept.concept_code,
+            standard_concept: concept.standard_concept.unwrap_or_default(),
+            invalid_reason: concept.invalid_reason,
+            similarity_score: 0.0,
+            source_concept_id,
+            source_text: None,
+            sources: vec![RecommendationSource::Phoebe],
+            rank: 0,
+        })
+        .collect())
+}
+
+/// Blends the semantic and the PHOEBE ranking by reciprocal rank fusion. Concepts both
+/// recommend keep the semantic recommendation, its similarity and source, and are tagged with
+/// both sources.
+fn blend(
+    semantic: Vec<RecommendedConcept>,
+    phoebe: Vec<RecommendedConcept>,
+) -> Vec<RecommendedConcept> {
+    let mut fused: HashMap<i32, (RecommendedConcept, f32)> = HashMap::new();
+    for ranking in [semantic, phoebe] {
+        for (rank, recommendation) in (1..).zip(ranking) {
+            let score = 1.0 / (RANK_FUSION_K + rank as f32);
+            match fused.get_mut(&recommendation.concept_id) {
+                Some((existing, fused_score)) => {
+                    existing.sources.extend(recommendation.sources);
+                    *fused_score += score;
+                }
+                None => {
+                    fused.insert(recommendation.concept_id, (recommendation, score));
+                }
+            }
+        }
     }
+    let mut blended: Vec<(RecommendedConcept, f32)> = fused.into_values().collect();
+    blended.sort_by(|a, b| {
+        b.1.total_cmp(&a.1)
+            .then_with(|| b.0.similarity_score.total_cmp(&a.0.similarity_score))
+            .then_with(|| a.0.concept_id.cmp(&b.0.concept_id))
+    });
+    (1..)
+        .zip(blended)
+        .map(|(rank, (mut recommendation, _))| {
+            recommendation.rank = rank;
+            recommendation
+        })
+        .collect()
 }
 
+/// Recommendations for the concept set, reused from `cache` when the same set was recommended
+/// for with the same options before. Recommendations steered by phrases are not cached, nor
+/// are those the time budget cut short.
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip_all, fields(limit = options.limit))]
 pub async fn get_concept_recommendations(
     expression: &ConceptSetExpression,
     pg_client: &Client,
     qdrant_client: &Qdrant,
-    concept_index: &HashMap<String, Vec<Uuid>>,
-    limit_per_concept: u64,
+    read_options: &ReadOptions,
+    snapshot: &IndexSnapshot,
+    options: &RecommendationOptions,
+    texts: &TextExamples,
+    profile: ValidationProfile,
+    budget: Option<Duration>,
+    cache: &RecommendationCache,
+    cache_stats: &CacheStats,
+    vocabulary_version: Option<&str>,
 ) -> Result<ConceptRecommendations, PgError> {
-    // Get all concepts that are already in the set (direct, descendants, excluded)
-    let existing_concepts = get_all_concepts_in_set(expression, pg_client).await?;
+    let cache_key = (texts.positive.is_empty() && texts.negative.is_empty()).then(|| {
+        recommendation_cache_key(expression, snapshot, options, profile, vocabulary_version)
+    });
+    if let Some(cache_key) = &cache_key
+        && let Some(cached) = cache_stats.record(cache.get(cache_key))
+    {
+        info!("Recommendations served from the cache");
+        return Ok(ConceptRecommendations::clone(&cached));
+    }
+    let recommendations = recommend(
+        expression,
+        pg_client,
+        qdrant_client,
+        read_options,
+        snapshot,
+        options,
+        texts,
+        profile,
+        budget,
+    )
+    .await?;
+    if let Some(cache_key) = cache_key
+        && !recommendations.truncated
+    {
+        cache.insert(cache_key, Arc::new(recommendations.clone()));
+    }
+    Ok(recommendations)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn recommend(
+    expression: &ConceptSetExpression,
+    pg_client: &Client,
+    qdrant_client: &Qdrant,
+    read_options: &ReadOptions,
+    snapshot: &IndexSnapshot,
+    options: &RecommendationOptions,
+    texts: &TextExamples,
+    profile: ValidationProfile,
+    budget: Option<Duration>,
+) -> Result<ConceptRecommendations, PgError> {
+    let deadline = budget.map(|budget| Instant::now() + budget);
+    let mut truncated = false;
+
+    // Get all concepts that are already in the set (direct, descendants, excluded). Without
+    // time for the expansion, only the items themselves are left out of the recommendations.
+    let existing_concepts = match remaining(deadline) {
+        None => get_all_concepts_in_set(expression, pg_client).await?,
+        Some(remaining) => {
+            match timeout(remaining, get_all_concepts_in_set(expression, pg_client)).await {
+                Ok(existing_concepts) => existing_concepts?,
+                Err(_) => {
+                    warn!("Time budget exhausted while expanding the concept set");
+                    truncated = true;
+                    expression
+                        .items
+                        .iter()
+                        .map(|item| item.concept.concept_id)
+                        .collect()
+                }
+            }
+        }
+    };
     info!(
         "Found {} existing concepts in set to exclude from recommendations",
         existing_concepts.len()
@@ -638,12 +1850,18 @@ pub async fn get_concept_recommendations(
         top_level_included.len()
     );
 
-    // Collect allowed domain IDs from all concepts in the expression
-    let allowed_domains: HashSet<String> = expression
-        .items
-        .iter()
-        .map(|item| item.concept.domain_id.clone())
-        .collect();
+    // Collect allowed domain IDs from all concepts in the expression, unless the request or
+    // the profile scopes recommendations to other domains
+    let allowed_domains: HashSet<String> = match &options.domains {
+        Some(domains) => domains.iter().cloned().collect(),
+        None => profile.recommendation_domains(
+            expression
+                .items
+                .iter()
+                .map(|item| item.concept.domain_id.clone())
+                .collect(),
+        ),
+    };
 
     // Collect vocabulary IDs from the concept set (for UI pre-selection, not filtering)
     let concept_set_vocabularies: HashSet<String> = expression
@@ -661,44 +1879,137 @@ pub async fn get_concept_recommendations(
     let mut source_concept_map: HashMap<String, i32> = HashMap::new();
 
     // Collect positive and negative point IDs
+    let lookup = ConceptLookup {
+        snapshot,
+        qdrant_client,
+        read_options,
+    };
     let all_positive_point_ids =
-        collect_positive_point_ids(&top_level_included, concept_index, &mut source_concept_map);
-    let all_negative_point_ids = collect_negative_point_ids(expression, concept_index);
-
-    if all_positive_point_ids.is_empty() {
-        return Ok(ConceptRecommendations {
-            recommendations: Vec::new(),
-            total_count: 0,
-            used_vocabularies: Vec::new(),
-        });
-    }
+        collect_positive_point_ids(&top_level_included, lookup, &mut source_concept_map).await;
+    let all_negative_point_ids = collect_negative_point_ids(expression, lookup).await;
 
     // Limit to 50 points for performance (Qdrant performance scales linearly with number of examples)
     let limited_positive_point_ids = limit_point_ids(all_positive_point_ids, 50, "positive");
     let limited_negative_point_ids = limit_point_ids(all_negative_point_ids, 50, "negative");
 
-    // Use Qdrant's recommendation API with the cached point IDs
-    let mut recs = RecommendInputBuilder::default();
-    for point_id in &limited_positive_point_ids {
-        recs = recs.add_positive(point_id.clone());
-    }
-    for point_id in &limited_negative_point_ids {
-        recs = recs.add_negative(point_id.clone());
+    // Phrases are queried like the items, as vectors instead of points
+    let seeds: Vec<Seed> = limited_positive_point_ids
+        .into_iter()
+        .map(|point_id| Seed {
+            source_concept_id: point_key(&point_id)
+                .and_then(|key| source_concept_map.get(&key).copied())
+                .unwrap_or(0),
+            source_text: None,
+            input: point_id.into(),
+        })
+        .chain(texts.positive.iter().map(|(text, vector)| Seed {
+            input: vector.clone().into(),
+            source_concept_id: 0,
+            source_text: Some(text.clone()),
+        }))
+        .collect();
+    let negatives: Vec<VectorInput> = limited_negative_point_ids
+        .into_iter()
+        .map(VectorInput::from)
+        .chain(texts.negative.iter().cloned().map(VectorInput::from))
+        .collect();
+
+    // Use Qdrant's recommendation API with the cached point IDs, one batch of positives at a
+    // time, keeping the best score per concept, and the positive it came from, across batches
+    let mut best: HashMap<i32, Candidate> = HashMap::new();
+    for batch in seeds.chunks(RECOMMENDATION_BATCH_SIZE) {
+        let query = query_and_process_recommendations(
+            qdrant_client,
+            read_options,
+            &snapshot.collection,
+            batch,
+            &negatives,
+            &existing_concepts,
+            &allowed_domains,
+            options,
+        );
+        let batch_recommendations = match remaining(deadline) {
+            None => query.await,
+            Some(remaining) => match timeout(remaining, query).await {
+                Ok(batch_recommendations) => batch_recommendations,
+                Err(_) => {
+                    warn!("Time budget exhausted, returning partial recommendations");
+                    truncated = true;
+                    break;
+                }
+            },
+        };
+        for candidate in batch_recommendations {
+            let concept_id = candidate.recommendation.concept_id;
+            match best.get(&concept_id) {
+                Some(existing)
+                    if existing.recommendation.similarity_score
+                        >= candidate.recommendation.similarity_score => {}
+                _ => {
+                    best.insert(concept_id, candidate);
+                }
+            }
+        }
     }
 
-    // Query Qdrant and process results
-    let all_recommendations = query_and_process_recommendations(
-        qdrant_client,
-        recs.build().into(),
+    // Sort by similarity score (descending), then make room for novelty if asked to
+    let mut candidates: Vec<Candidate> = best.into_values().collect();
+    candidates.sort_by(|a, b| {
+        b.recommendation
+            .similarity_score
+            .partial_cmp(&a.recommendation.similarity_score)
+            .unwrap()
+    });
+    let semantic = match options.diversity {
+        Some(diversity) if diversity > 0.0 => diversify(candidates, diversity),
+        _ => candidates
+            .into_iter()
+            .map(|candidate| candidate.recommendation)
+            .collect(),
+    };
+
+    // PHOEBE is optional, without the table or the time for it the semantic ranking stands
+    let item_ids: Vec<i32> = top_level_included
+        .iter()
+        .map(|item| item.concept.concept_id)
+        .collect();
+    let phoebe = phoebe_recommendations(
+        pg_client,
+        &item_ids,
         &existing_concepts,
-        &top_level_included,
         &allowed_domains,
-        concept_set_vocabularies,
-        limit_per_concept,
-    )
-    .await;
+        options,
+    );
+    let phoebe = match remaining(deadline) {
+        // Out of time for the semantic recommendations already
+        Some(_) if truncated => Ok(Vec::new()),
+        None => phoebe.await,
+        Some(remaining) => timeout(remaining, phoebe).await.unwrap_or_else(|_| {
+            warn!("Time budget exhausted before the PHOEBE recommendations");
+            truncated = true;
+            Ok(Vec::new())
+        }),
+    };
+    let phoebe = phoebe.unwrap_or_else(|e| {
+        warn!("Could not get PHOEBE recommendations: {}", e);
+        Vec::new()
+    });
+    info!("PHOEBE recommends {} concepts", phoebe.len());
+    let mut all_recommendations = blend(semantic, phoebe);
+    if let Some(limit) = options.limit {
+        all_recommendations.truncate(limit as usize);
+    }
+    let total_count = all_recommendations.len();
 
-    Ok(all_recommendations)
+    // Get vocabularies from the original concept set (not from recommendations)
+    let used_vocabularies: Vec<String> = concept_set_vocabularies.into_iter().collect();
+
+    Ok(ConceptRecommendations {
+        recommendations: all_recommendations,
+        total_count,
+        used_vocabularies,
+        truncated,
+    })
 }
 
 async fn get_all_concepts_in_set(
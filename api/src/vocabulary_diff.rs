@@ -0,0 +1,213 @@
+use crate::StateWrapper;
+use crate::catalog::release_version;
+use crate::config::Configs;
+use crate::db;
+use crate::domain::VocabularyChange;
+use crate::errors::{ApiError, PgError};
+use crate::utils::deserialize_string_or_vec;
+use actix_web::web::{Data, Query};
+use actix_web::{Error, HttpResponse, get};
+use deadpool_postgres::Client;
+use log::info;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+/// The vocabulary the API serves, in the `cdm` schema.
+const CURRENT_SNAPSHOT: &str = "current";
+const DEFAULT_DIFF_LIMIT: i64 = 100;
+const MAX_DIFF_LIMIT: i64 = 1000;
+
+const NEW_CONCEPTS: &str = include_str!("../sql/insert_vocabulary_diff_new.sql");
+const DEPRECATED_CONCEPTS: &str = include_str!("../sql/insert_vocabulary_diff_deprecated.sql");
+const REMAPPED_CONCEPTS: &str = include_str!("../sql/insert_vocabulary_diff_remapped.sql");
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DiffParameters {
+    /// The earlier release, a configured snapshot.
+    #[param(example = "2024-02")]
+    from: String,
+    /// The later release, the current vocabulary unless given.
+    #[param(default = "current")]
+    to: Option<String>,
+    /// Only concepts of these vocabularies, comma-separated.
+    #[serde(default, deserialize_with = "deserialize_string_or_vec")]
+    #[param(value_type = Option<String>)]
+    vocabulary_id: Option<Vec<String>>,
+    #[serde(default)]
+    #[param(default = 0, minimum = 0)]
+    offset: i64,
+    #[param(default = 100, minimum = 1, maximum = 1000)]
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = VocabularyChangePage)]
+struct ChangePage {
+    total: i64,
+    has_more: bool,
+    concepts: Vec<VocabularyChange>,
+}
+
+/// Concepts added, deprecated and mapped to other standard concepts between two releases, each
+/// paged with the same `offset` and `limit`.
+#[derive(Debug, Serialize, ToSchema)]
+struct VocabularyDiff {
+    from: String,
+    to: String,
+    offset: i64,
+    new: ChangePage,
+    deprecated: ChangePage,
+    remapped: ChangePage,
+}
+
+/// The schema a snapshot name refers to. Schema names end up in the SQL, so only plain
+/// identifiers are accepted.
+fn snapshot_schema<'a>(
+    config: &'a Configs,
+    parameter: &'static str,
+    name: &str,
+) -> Result<&'a str, ApiError> {
+    if name == CURRENT_SNAPSHOT {
+        return Ok("cdm");
+    }
+    config
+        .vocabulary_snapshots
+        .iter()
+        .find(|snapshot| snapshot.name == name)
+        .map(|snapshot| snapshot.schema.as_str())
+        .filter(|schema| {
+            schema.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+                && schema
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        })
+        .ok_or_else(|| ApiError::InvalidFilter {
+            parameter,
+            invalid: vec![name.to_string()],
+            valid: std::iter::once(CURRENT_SNAPSHOT.to_string())
+                .chain(
+                    config
+                        .vocabulary_snapshots
+                        .iter()
+                        .map(|snapshot| snapshot.name.clone()),
+                )
+                .collect(),
+        })
+}
+
+/// The release in `schema`, as `/api/version` reports it for the current vocabulary, so a stored
+/// diff is computed again once either side is reloaded with another release.
+async fn snapshot_version(pg_client: &Client, schema: &str) -> Result<String, PgError> {
+    let vocabularies = db::get_snapshot_vocabularies(pg_client, schema).await?;
+    Ok(release_version(&vocabularies).unwrap_or_default())
+}
+
+/// Stores the diff between the `(from, to)` schemas unless it is stored already, and returns its
+/// ID.
+async fn stored_diff(
+    pg_client: &mut Client,
+    (from_schema, to_schema): (&str, &str),
+) -> Result<i64, PgError> {
+    let from_version = snapshot_version(pg_client, from_schema).await?;
+    let to_version = snapshot_version(pg_client, to_schema).await?;
+    let queries: Vec<String> = [NEW_CONCEPTS, DEPRECATED_CONCEPTS, REMAPPED_CONCEPTS]
+        .iter()
+        .map(|query| {
+            query
+                .replace("{from}", from_schema)
+                .replace("{to}", to_schema)
+        })
+        .collect();
+    db::materialize_vocabulary_diff(
+        pg_client,
+        (from_schema, &from_version),
+        (to_schema, &to_version),
+        &queries,
+    )
+    .await
+}
+
+/// A page of one kind of change of a stored diff.
+async fn change_page(
+    pg_client: &Client,
+    diff_id: i64,
+    change: &str,
+    vocabulary_ids: Option<&[String]>,
+    offset: i64,
+    limit: i64,
+) -> Result<ChangePage, PgError> {
+    let (total, concepts) =
+        db::get_vocabulary_changes(pg_client, diff_id, change, vocabulary_ids, offset, limit)
+            .await?;
+    Ok(ChangePage {
+        total,
+        has_more: offset + (concepts.len() as i64) < total,
+        concepts,
+    })
+}
+
+/// What changed between two vocabulary releases, for judging the impact of an Athena update on
+/// stored concept sets before switching to it. Releases are the current vocabulary and the
+/// snapshots configured in `VOCABULARY_SNAPSHOTS`. The first request for two releases stores
+/// their diff in `hecate.vocabulary_diff_change`, and every page is read from there.
+#[utoipa::path(
+    tag = "vocabularies",
+    summary = "Compare two vocabulary releases",
+    description = "Concepts added (`new`), deprecated (valid before, invalid now) and `remapped` (different \
+        `Maps to` targets) between an earlier release and a later one. Releases are `current`, the served \
+        vocabulary, and the snapshots configured in `VOCABULARY_SNAPSHOTS`. Each list is paged with the same \
+        `offset` and `limit`.",
+    params(DiffParameters),
+    responses(
+        (status = 200, description = "Changes between the releases", body = VocabularyDiff),
+        (status = 400, description = "Unknown release"),
+    )
+)]
+#[get("/api/vocabulary/diff")]
+async fn get_vocabulary_diff(
+    parameters: Query<DiffParameters>,
+    state: Data<StateWrapper>,
+) -> Result<HttpResponse, Error> {
+    let to = parameters.to.as_deref().unwrap_or(CURRENT_SNAPSHOT);
+    let from_schema = snapshot_schema(&state.config, "from", &parameters.from)?;
+    let to_schema = snapshot_schema(&state.config, "to", to)?;
+    let offset = parameters.offset.max(0);
+    let limit = parameters
+        .limit
+        .unwrap_or(DEFAULT_DIFF_LIMIT)
+        .clamp(1, MAX_DIFF_LIMIT);
+    info!("Comparing vocabulary {} to {}", parameters.from, to);
+
+    let vocabulary_ids = parameters.vocabulary_id.as_deref();
+    let mut pg_client = state.pg_pool.get().await.map_err(PgError::PoolError)?;
+    let diff_id = stored_diff(&mut pg_client, (from_schema, to_schema)).await?;
+    let new = change_page(&pg_client, diff_id, "new", vocabulary_ids, offset, limit).await?;
+    let deprecated = change_page(
+        &pg_client,
+        diff_id,
+        "deprecated",
+        vocabulary_ids,
+        offset,
+        limit,
+    )
+    .await?;
+    let remapped = change_page(
+        &pg_client,
+        diff_id,
+        "remapped",
+        vocabulary_ids,
+        offset,
+        limit,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(VocabularyDiff {
+        from: parameters.from.clone(),
+        to: to.to_string(),
+        offset,
+        new,
+        deprecated,
+        remapped,
+    }))
+}
@@ -0,0 +1,214 @@
+use crate::domain::Concept;
+use crate::validation::{ConceptGatheringResult, ValidationResult};
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
+use std::collections::HashMap;
+
+pub const XLSX: &str = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+
+const CONCEPT_COLUMNS: &[&str] = &[
+    "concept_id",
+    "concept_name",
+    "domain_id",
+    "vocabulary_id",
+    "concept_class_id",
+    "standard_concept",
+    "concept_code",
+    "invalid_reason",
+    "valid_start_date",
+    "valid_end_date",
+];
+
+const RECOMMENDATION_COLUMNS: &[&str] = &[
+    "concept_id",
+    "concept_name",
+    "domain_id",
+    "vocabulary_id",
+    "concept_class_id",
+    "standard_concept",
+    "concept_code",
+    "invalid_reason",
+    "similarity_score",
+    "source_concept_id",
+    "sources",
+    "rank",
+];
+
+enum Cell<'a> {
+    Text(&'a str),
+    Number(f64),
+    Empty,
+}
+
+impl<'a> From<Option<&'a str>> for Cell<'a> {
+    fn from(value: Option<&'a str>) -> Self {
+        value.map_or(Cell::Empty, Cell::Text)
+    }
+}
+
+/// Renders an analysis as a workbook for coders who review concept sets in Excel: a sheet each
+/// for the included concepts, their descendants, the mapped concepts, the exclusions and the
+/// recommendations, then the warnings if there are any. `concepts` holds the rows of every
+/// concept ID in the analysis, IDs missing from it are written with the ID alone.
+pub fn render(
+    result: &ValidationResult,
+    concepts: &HashMap<i32, Concept>,
+) -> Result<Vec<u8>, XlsxError> {
+    let header = Format::new().set_bold();
+    let empty = ConceptGatheringResult::new();
+    let summary = result.concept_summary.as_ref().unwrap_or(&empty);
+    let mut workbook = Workbook::new();
+
+    let sections = [
+        ("Included", &summary.included_concepts),
+        ("Descendants", &summary.included_descendants),
+        ("Mapped", &summary.included_mapped),
+    ];
+    for (name, concept_ids) in sections {
+        let sheet = add_sheet(&mut workbook, name, CONCEPT_COLUMNS, &header)?;
+        for (row, concept_id) in (1..).zip(concept_ids) {
+            write_concept(sheet, row, 0, *concept_id, concepts.get(concept_id))?;
+        }
+        finish(sheet, concept_ids.len(), CONCEPT_COLUMNS.len())?;
+    }
+
+    let mut excluded_columns = vec!["excluded_as"];
+    excluded_columns.extend_from_slice(CONCEPT_COLUMNS);
+    let sheet = add_sheet(&mut workbook, "Excluded", &excluded_columns, &header)?;
+    let excluded = [
+        ("concept", &summary.excluded_concepts),
+        ("descendant", &summary.excluded_descendants),
+        ("mapped", &summary.excluded_mapped),
+    ];
+    let mut row = 0;
+    for (excluded_as, concept_ids) in excluded {
+        for concept_id in concept_ids {
+            row += 1;
+            sheet.write_string(row, 0, excluded_as)?;
+            write_concept(sheet, row, 1, *concept_id, concepts.get(concept_id))?;
+        }
+    }
+    finish(sheet, row as usize, excluded_columns.len())?;
+
+    let sheet = add_sheet(
+        &mut workbook,
+        "Recommendations",
+        RECOMMENDATION_COLUMNS,
+        &header,
+    )?;
+    let recommendations = result
+        .recommendations
+        .as_ref()
+        .map(|recommendations| recommendations.recommendations.as_slice())
+        .unwrap_or_default();
+    for (row, concept) in (1..).zip(recommendations) {
+        let sources = concept
+            .sources
+            .iter()
+            .map(|source| source.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        write_row(
+            sheet,
+            row,
+            0,
+            [
+                Cell::Number(concept.concept_id.into()),
+                Cell::Text(&concept.concept_name),
+                Cell::Text(&concept.domain_id),
+                Cell::Text(&concept.vocabulary_id),
+                Cell::Text(&concept.concept_class_id),
+                Cell::Text(&concept.standard_concept),
+                Cell::Text(&concept.concept_code),
+                concept.invalid_reason.as_deref().into(),
+                Cell::Number(concept.similarity_score.into()),
+                Cell::Number(concept.source_concept_id.into()),
+                Cell::Text(&sources),
+                Cell::Number(concept.rank as f64),
+            ],
+        )?;
+    }
+    finish(sheet, recommendations.len(), RECOMMENDATION_COLUMNS.len())?;
+
+    if !result.errors.is_empty() || !result.warnings.is_empty() {
+        let sheet = add_sheet(&mut workbook, "Warnings", &["severity", "message"], &header)?;
+        let messages = result
+            .errors
+            .iter()
+            .map(|message| ("error", message))
+            .chain(result.warnings.iter().map(|message| ("warning", message)));
+        for (row, (severity, message)) in (1..).zip(messages) {
+            write_row(sheet, row, 0, [Cell::Text(severity), Cell::Text(message)])?;
+        }
+        sheet.autofit();
+    }
+
+    workbook.save_to_buffer()
+}
+
+fn add_sheet<'a>(
+    workbook: &'a mut Workbook,
+    name: &str,
+    columns: &[&str],
+    header: &Format,
+) -> Result<&'a mut Worksheet, XlsxError> {
+    let sheet = workbook.add_worksheet();
+    sheet.set_name(name)?;
+    sheet.write_row_with_format(0, 0, columns.iter().copied(), header)?;
+    sheet.set_freeze_panes(1, 0)?;
+    Ok(sheet)
+}
+
+/// Filters on the header row and sizes the columns to their content.
+fn finish(sheet: &mut Worksheet, rows: usize, columns: usize) -> Result<(), XlsxError> {
+    sheet.autofilter(0, 0, rows as u32, columns.saturating_sub(1) as u16)?;
+    sheet.autofit();
+    Ok(())
+}
+
+fn write_concept(
+    sheet: &mut Worksheet,
+    row: u32,
+    column: u16,
+    concept_id: i32,
+    concept: Option<&Concept>,
+) -> Result<(), XlsxError> {
+    let Some(concept) = concept else {
+        sheet.write_number(row, column, concept_id)?;
+        return Ok(());
+    };
+    let valid_start_date = concept.valid_start_date.map(|date| date.to_string());
+    let valid_end_date = concept.valid_end_date.map(|date| date.to_string());
+    write_row(
+        sheet,
+        row,
+        column,
+        [
+            Cell::Number(concept_id.into()),
+            Cell::Text(&concept.concept_name),
+            Cell::Text(&concept.domain_id),
+            Cell::Text(&concept.vocabulary_id),
+            Cell::Text(&concept.concept_class_id),
+            concept.standard_concept.as_deref().into(),
+            Cell::Text(&concept.concept_code),
+            concept.invalid_reason.as_deref().into(),
+            valid_start_date.as_deref().into(),
+            valid_end_date.as_deref().into(),
+        ],
+    )
+}
+
+fn write_row<'a>(
+    sheet: &mut Worksheet,
+    row: u32,
+    first_column: u16,
+    cells: impl IntoIterator<Item = Cell<'a>>,
+) -> Result<(), XlsxError> {
+    for (column, cell) in (first_column..).zip(cells) {
+        match cell {
+            Cell::Text(text) => sheet.write_string(row, column, text)?,
+            Cell::Number(number) => sheet.write_number(row, column, number)?,
+            Cell::Empty => sheet,
+        };
+    }
+    Ok(())
+}
@@ -98,6 +98,12 @@ const ConceptRecommendationsComponent: React.FC<
         <Text type="secondary">
           These are standard concepts similar to your included concepts.
         </Text>
+        {recommendations.truncated && (
+          <Text type="secondary">
+            {" "}
+            The time limit was reached, so this list may be incomplete.
+          </Text>
+        )}
       </div>
     </div>
   );
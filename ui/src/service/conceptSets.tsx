@@ -17,6 +17,7 @@ export interface ConceptRecommendations {
   recommendations: RecommendedConcept[];
   total_count: number;
   used_vocabularies: string[];
+  truncated?: boolean;
 }
 
 export interface AnalysisResult {